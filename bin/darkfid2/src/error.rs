@@ -28,12 +28,16 @@ pub enum RpcError {
     // State-related errors,
     NotSynced = -32120,
     UnknownSlot = -32121,
+    UnknownTxEvents = -32122,
+    StateHeightMismatch = -32123,
 
     // Parsing errors
     ParseError = -32190,
 
     // Contract-related errors
     ContractZkasDbNotFound = -32200,
+    ContractNotFound = -32201,
+    ContractStateNotFound = -32202,
 
     // Misc errors
     PingFailed = -32300,
@@ -47,10 +51,14 @@ fn to_tuple(e: RpcError) -> (i32, String) {
         // State-related errors
         RpcError::NotSynced => "Blockchain is not synced",
         RpcError::UnknownSlot => "Did not find slot",
+        RpcError::UnknownTxEvents => "Did not find events for transaction",
+        RpcError::StateHeightMismatch => "Contracts state is not at requested height",
         // Parsing errors
         RpcError::ParseError => "Parse error",
         // Contract-related errors
         RpcError::ContractZkasDbNotFound => "zkas database not found for given contract",
+        RpcError::ContractNotFound => "Did not find contract",
+        RpcError::ContractStateNotFound => "Did not find contract state tree",
         // Misc errors
         RpcError::PingFailed => "Miner daemon ping error",
     };
@@ -29,7 +29,7 @@ use url::Url;
 
 use darkfi::{
     async_daemonize,
-    blockchain::BlockInfo,
+    blockchain::{export_snapshot, import_snapshot, BlockInfo, Blockchain},
     cli_desc,
     net::{settings::SettingsOpt, P2pPtr},
     rpc::{
@@ -37,11 +37,13 @@ use darkfi::{
         jsonrpc::JsonSubscriber,
         server::{listen_and_serve, RequestHandler},
     },
+    runtime::module_cache::MODULE_CACHE,
     system::{StoppableTask, StoppableTaskPtr},
     util::{path::expand_path, time::TimeKeeper},
     validator::{utils::genesis_txs_total, Validator, ValidatorConfig, ValidatorPtr},
     Error, Result,
 };
+use darkfi_money_contract::client::call_decoder as money_call_decoder;
 use darkfi_sdk::crypto::PublicKey;
 use darkfi_serial::deserialize_async;
 
@@ -54,15 +56,20 @@ use error::{server_error, RpcError};
 /// JSON-RPC requests handler and methods
 mod rpc;
 mod rpc_blockchain;
+mod rpc_mempool;
 mod rpc_tx;
 
 /// Validator async tasks
 mod task;
-use task::{miner_task, sync_task};
+use task::{events_task, miner_task, prune_task, stratum_task, sync_task};
 
 /// P2P net protocols
 mod proto;
 
+/// Stratum mining server
+mod stratum;
+use stratum::StratumServer;
+
 /// Utility functions
 mod utils;
 use utils::{parse_blockchain_config, spawn_consensus_p2p, spawn_sync_p2p};
@@ -99,6 +106,18 @@ struct Args {
     #[structopt(short, parse(from_occurrences))]
     /// Increase verbosity (-vvv supported)
     verbose: u8,
+
+    #[structopt(long)]
+    /// Export the canonical state into a snapshot file at given path and exit
+    export_snapshot: Option<String>,
+
+    #[structopt(long)]
+    /// Bootstrap an empty database from the snapshot file at given path
+    import_snapshot: Option<String>,
+
+    #[structopt(long)]
+    /// Trusted state hash the imported snapshot must match (required with --import-snapshot)
+    snapshot_hash: Option<String>,
 }
 
 /// Defines a blockchain network configuration.
@@ -110,6 +129,10 @@ pub struct BlockchainNetwork {
     /// Path to blockchain database
     pub database: String,
 
+    #[structopt(long)]
+    /// Optional path to persist compiled WASM contracts to
+    pub wasm_cache: Option<String>,
+
     #[structopt(long, default_value = "3")]
     /// Finalization threshold, denominated by number of blocks
     pub threshold: usize,
@@ -118,6 +141,14 @@ pub struct BlockchainNetwork {
     /// minerd JSON-RPC endpoint
     pub minerd_endpoint: Url,
 
+    #[structopt(long)]
+    /// Optional Stratum server endpoint, used by miners instead of minerd
+    pub stratum_listen: Option<Url>,
+
+    #[structopt(long, default_value = "1000")]
+    /// Difficulty of the shares Stratum miners have to submit
+    pub stratum_difficulty: u64,
+
     #[structopt(long, default_value = "10")]
     /// PoW block production target, in seconds
     pub pow_target: usize,
@@ -154,6 +185,11 @@ pub struct BlockchainNetwork {
     /// Enable PoS testing mode for local testing
     pub pos_testing_mode: bool,
 
+    #[structopt(long)]
+    /// Optional depth, denominated by number of blocks, after which
+    /// blocks transactions get pruned
+    pub pruning_depth: Option<u64>,
+
     /// Syncing network settings
     #[structopt(flatten)]
     pub sync_net: SettingsOpt,
@@ -163,6 +199,10 @@ pub struct BlockchainNetwork {
     pub consensus_net: SettingsOpt,
 }
 
+/// Filter of a contract events subscription, in the form of
+/// (contract ID bytes, optional topic)
+pub type EventFilter = ([u8; 32], Option<[u8; 32]>);
+
 /// Daemon structure
 pub struct Darkfid {
     /// Syncing P2P network pointer
@@ -173,6 +213,8 @@ pub struct Darkfid {
     validator: ValidatorPtr,
     /// A map of various subscribers exporting live info from the blockchain
     subscribers: HashMap<&'static str, JsonSubscriber>,
+    /// Contract event subscribers, keyed by their (contract ID, optional topic) filter
+    event_subscribers: Mutex<HashMap<EventFilter, JsonSubscriber>>,
     /// JSON-RPC connection tracker
     rpc_connections: Mutex<HashSet<StoppableTaskPtr>>,
     /// JSON-RPC client to execute requests to the miner daemon
@@ -192,6 +234,7 @@ impl Darkfid {
             consensus_p2p,
             validator,
             subscribers,
+            event_subscribers: Mutex::new(HashMap::new()),
             rpc_connections: Mutex::new(HashSet::new()),
             rpc_client,
         }
@@ -231,6 +274,28 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
     let db_path = expand_path(&blockchain_config.database)?;
     let sled_db = sled::open(&db_path)?;
 
+    // Bootstrap database from a snapshot, if requested
+    if let Some(path) = &args.import_snapshot {
+        let Some(hash) = &args.snapshot_hash else {
+            error!(target: "darkfid", "A trusted snapshot state hash must be provided using --snapshot-hash");
+            return Err(Error::ParseFailed("Missing snapshot state hash"))
+        };
+        let Ok(state_hash) = blake3::Hash::from_hex(hash) else {
+            return Err(Error::ParseFailed("Invalid snapshot state hash"))
+        };
+
+        // The snapshot must belong to the configured network
+        let blockchain = Blockchain::new(&sled_db)?;
+        let header =
+            import_snapshot(&blockchain, &expand_path(path)?, &state_hash, &genesis_block.hash()?)?;
+        info!(target: "darkfid", "Database bootstrapped from snapshot at height {}", header.height);
+    }
+
+    // Persist compiled contracts across restarts, if configured
+    if let Some(wasm_cache) = &blockchain_config.wasm_cache {
+        MODULE_CACHE.set_disk_path(expand_path(wasm_cache)?)?;
+    }
+
     // Initialize validator configuration
     let genesis_txs_total = genesis_txs_total(&genesis_block.txs).await?;
 
@@ -258,14 +323,23 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
         vec![],
         blockchain_config.pos_testing_mode,
         false, // TODO: Make configurable
+        vec![money_call_decoder()],
     );
 
     // Initialize validator
     let validator = Validator::new(&sled_db, config).await?;
 
+    // Export canonical state snapshot, if requested
+    if let Some(path) = &args.export_snapshot {
+        let header = export_snapshot(&validator.blockchain, &expand_path(path)?)?;
+        info!(target: "darkfid", "Snapshot state hash: {}", header.state_hash);
+        return Ok(())
+    }
+
     // Here we initialize various subscribers that can export live blockchain/consensus data.
     let mut subscribers = HashMap::new();
     subscribers.insert("blocks", JsonSubscriber::new("blockchain.subscribe_blocks"));
+    subscribers.insert("best_chain", JsonSubscriber::new("blockchain.subscribe_best_chain"));
     subscribers.insert("txs", JsonSubscriber::new("blockchain.subscribe_txs"));
     if blockchain_config.consensus {
         subscribers.insert("proposals", JsonSubscriber::new("blockchain.subscribe_proposals"));
@@ -278,10 +352,17 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
 
     // Initialize consensus P2P network
     let (consensus_p2p, rpc_client) = if blockchain_config.consensus {
-        let Ok(rpc_client) = RpcClient::new(blockchain_config.minerd_endpoint, ex.clone()).await
-        else {
-            error!(target: "darkfid", "Failed to initialize miner daemon rpc client, check if minerd is running");
-            return Err(Error::RpcClientStopped)
+        // Miners connect to our Stratum server when it's configured
+        let rpc_client = if blockchain_config.stratum_listen.is_some() {
+            None
+        } else {
+            let Ok(rpc_client) =
+                RpcClient::new(blockchain_config.minerd_endpoint, ex.clone()).await
+            else {
+                error!(target: "darkfid", "Failed to initialize miner daemon rpc client, check if minerd is running");
+                return Err(Error::RpcClientStopped)
+            };
+            Some(rpc_client)
         };
         (
             Some(
@@ -293,7 +374,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                 )
                 .await,
             ),
-            Some(rpc_client),
+            rpc_client,
         )
     } else {
         (None, None)
@@ -312,7 +393,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
     info!(target: "darkfid", "Node initialized successfully!");
 
     // Pinging minerd daemon to verify it listens
-    if blockchain_config.consensus {
+    if darkfid.rpc_client.is_some() {
         if let Err(e) = darkfid.ping_miner_daemon().await {
             error!(target: "darkfid", "Failed to ping miner daemon: {}", e);
             return Err(Error::RpcClientStopped)
@@ -351,6 +432,22 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
         info!(target: "darkfid", "Not starting consensus P2P network");
     }
 
+    // Contract events notifications
+    info!(target: "darkfid", "Starting contract events task");
+    let events_notif_task = StoppableTask::new();
+    let darkfid_ = darkfid.clone();
+    events_notif_task.clone().start(
+        async move { events_task(&darkfid_).await },
+        |res| async {
+            match res {
+                Ok(()) | Err(Error::EventsTaskStopped) => { /* Do nothing */ }
+                Err(e) => error!(target: "darkfid", "Failed starting events task: {}", e),
+            }
+        },
+        Error::EventsTaskStopped,
+        ex.clone(),
+    );
+
     // Sync blockchain
     if !blockchain_config.skip_sync {
         sync_task(&darkfid).await?;
@@ -361,7 +458,29 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
     // Clean node pending transactions
     darkfid.validator.purge_pending_txs().await?;
 
+    // Blockchain pruning
+    let prune_task = if let Some(depth) = blockchain_config.pruning_depth {
+        info!(target: "darkfid", "Starting blockchain pruning task, keeping the last {} blocks", depth);
+        let task = StoppableTask::new();
+        let darkfid_ = darkfid.clone();
+        task.clone().start(
+            async move { prune_task(&darkfid_, depth).await },
+            |res| async {
+                match res {
+                    Ok(()) | Err(Error::PruneTaskStopped) => { /* Do nothing */ }
+                    Err(e) => error!(target: "darkfid", "Failed starting prune task: {}", e),
+                }
+            },
+            Error::PruneTaskStopped,
+            ex.clone(),
+        );
+        Some(task)
+    } else {
+        None
+    };
+
     // Consensus protocol
+    let mut stratum_server_task = None;
     let consensus_task = if blockchain_config.consensus {
         info!(target: "darkfid", "Starting consensus protocol task");
         // Grab rewards recipient public key(address)
@@ -374,18 +493,54 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
         };
 
         let task = StoppableTask::new();
-        task.clone().start(
-            // Weird hack to prevent lifetimes hell
-            async move { miner_task(&darkfid, &recipient).await },
-            |res| async {
-                match res {
-                    Ok(()) | Err(Error::MinerTaskStopped) => { /* Do nothing */ }
-                    Err(e) => error!(target: "darkfid", "Failed starting miner task: {}", e),
-                }
-            },
-            Error::MinerTaskStopped,
-            ex.clone(),
-        );
+        if let Some(endpoint) = blockchain_config.stratum_listen {
+            info!(target: "darkfid", "Starting Stratum server on {}", endpoint);
+            let server = StratumServer::new(blockchain_config.stratum_difficulty);
+            let server_task = StoppableTask::new();
+            let server_ = server.clone();
+            server_task.clone().start(
+                server.clone().listen(endpoint, ex.clone()),
+                |res| async move {
+                    match res {
+                        Ok(()) | Err(Error::StratumServerStopped) => {
+                            server_.stop_connections().await
+                        }
+                        Err(e) => {
+                            error!(target: "darkfid", "Failed starting Stratum server: {}", e)
+                        }
+                    }
+                },
+                Error::StratumServerStopped,
+                ex.clone(),
+            );
+            stratum_server_task = Some(server_task);
+
+            task.clone().start(
+                // Weird hack to prevent lifetimes hell
+                async move { stratum_task(&darkfid, &recipient, &server).await },
+                |res| async {
+                    match res {
+                        Ok(()) | Err(Error::MinerTaskStopped) => { /* Do nothing */ }
+                        Err(e) => error!(target: "darkfid", "Failed starting stratum task: {}", e),
+                    }
+                },
+                Error::MinerTaskStopped,
+                ex.clone(),
+            );
+        } else {
+            task.clone().start(
+                // Weird hack to prevent lifetimes hell
+                async move { miner_task(&darkfid, &recipient).await },
+                |res| async {
+                    match res {
+                        Ok(()) | Err(Error::MinerTaskStopped) => { /* Do nothing */ }
+                        Err(e) => error!(target: "darkfid", "Failed starting miner task: {}", e),
+                    }
+                },
+                Error::MinerTaskStopped,
+                ex.clone(),
+            );
+        }
         Some(task)
     } else {
         info!(target: "darkfid", "Not participating in consensus");
@@ -400,6 +555,14 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
     info!(target: "darkfid", "Stopping JSON-RPC server...");
     rpc_task.stop().await;
 
+    info!(target: "darkfid", "Stopping contract events task...");
+    events_notif_task.stop().await;
+
+    if let Some(task) = prune_task {
+        info!(target: "darkfid", "Stopping prune task...");
+        task.stop().await;
+    }
+
     info!(target: "darkfid", "Stopping syncing P2P network...");
     sync_p2p.stop().await;
 
@@ -411,6 +574,11 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
         consensus_task.unwrap().stop().await;
     }
 
+    if let Some(server_task) = stratum_server_task {
+        info!(target: "darkfid", "Stopping Stratum server...");
+        server_task.stop().await;
+    }
+
     info!(target: "darkfid", "Flushing sled database...");
     let flushed_bytes = sled_db.flush_async().await?;
     info!(target: "darkfid", "Flushed {} bytes", flushed_bytes);
@@ -26,7 +26,10 @@ pub use protocol_proposal::ProtocolProposal;
 
 /// Validator blockchain sync protocol
 mod protocol_sync;
-pub use protocol_sync::{ProtocolSync, SyncRequest, SyncResponse};
+pub use protocol_sync::{
+    HeaderSyncRequest, HeaderSyncResponse, ProtocolSync, PrunedHeightRequest, PrunedHeightResponse,
+    SyncRequest, SyncResponse, BATCH, HEADERS_BATCH,
+};
 
 /// Transaction broadcast protocol
 mod protocol_tx;
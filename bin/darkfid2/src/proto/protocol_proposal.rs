@@ -37,6 +37,8 @@ use darkfi::{
 };
 use darkfi_serial::{serialize_async, SerialDecodable, SerialEncodable};
 
+use crate::utils::notify_best_chain;
+
 /// Auxiliary [`Proposal`] wrapper structure used for messaging.
 #[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
 struct ProposalMessage(Proposal);
@@ -50,6 +52,7 @@ pub struct ProtocolProposal {
     p2p: P2pPtr,
     channel_address: Url,
     subscriber: JsonSubscriber,
+    best_chain_subscriber: JsonSubscriber,
 }
 
 impl ProtocolProposal {
@@ -58,6 +61,7 @@ impl ProtocolProposal {
         validator: ValidatorPtr,
         p2p: P2pPtr,
         subscriber: JsonSubscriber,
+        best_chain_subscriber: JsonSubscriber,
     ) -> Result<ProtocolBasePtr> {
         debug!(
             target: "validator::protocol_proposal::init",
@@ -75,6 +79,7 @@ impl ProtocolProposal {
             p2p,
             channel_address: channel.address().clone(),
             subscriber,
+            best_chain_subscriber,
         }))
     }
 
@@ -120,6 +125,7 @@ impl ProtocolProposal {
                     let enc_prop =
                         JsonValue::String(base64::encode(&serialize_async(&proposal_copy).await));
                     self.subscriber.notify(vec![enc_prop].into()).await;
+                    notify_best_chain(&self.validator, &self.best_chain_subscriber).await;
                 }
                 Err(e) => {
                     debug!(
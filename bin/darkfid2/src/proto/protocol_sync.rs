@@ -23,7 +23,7 @@ use log::{debug, error};
 use smol::Executor;
 
 use darkfi::{
-    blockchain::BlockInfo,
+    blockchain::{BlockInfo, Header},
     impl_p2p_message,
     net::{
         ChannelPtr, Message, MessageSubscription, ProtocolBase, ProtocolBasePtr,
@@ -35,11 +35,16 @@ use darkfi::{
 use darkfi_serial::{SerialDecodable, SerialEncodable};
 
 // Constant defining how many blocks we send during syncing.
-const BATCH: u64 = 10;
+pub const BATCH: u64 = 10;
+
+// Constant defining how many headers we send during syncing.
+pub const HEADERS_BATCH: u64 = 100;
 
 /// Auxiliary structure used for blockchain syncing.
 #[derive(Debug, SerialEncodable, SerialDecodable)]
 pub struct SyncRequest {
+    /// Request identifier, echoed back in the response
+    pub id: u64,
     /// Slot UID
     pub slot: u64,
     /// Block headerhash of that slot
@@ -51,14 +56,65 @@ impl_p2p_message!(SyncRequest, "syncrequest");
 /// Auxiliary structure used for blockchain syncing.
 #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
 pub struct SyncResponse {
+    /// Identifier of the request this response is for
+    pub id: u64,
     /// Response blocks
     pub blocks: Vec<BlockInfo>,
+    /// Set when the request was refused because the requested blocks
+    /// have been pruned, containing the height below which they were
+    pub pruned: Option<u64>,
 }
 
 impl_p2p_message!(SyncResponse, "syncresponse");
 
+/// Auxiliary structure used for blockchain syncing, to ask a peer
+/// whether it has pruned its blockchain.
+#[derive(Debug, SerialEncodable, SerialDecodable)]
+pub struct PrunedHeightRequest {
+    /// Request identifier, echoed back in the response
+    pub id: u64,
+}
+
+impl_p2p_message!(PrunedHeightRequest, "prunedheightrequest");
+
+/// Auxiliary structure used for blockchain syncing, advertising
+/// the height below which the node has pruned its blocks.
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct PrunedHeightResponse {
+    /// Identifier of the request this response is for
+    pub id: u64,
+    /// Height below which blocks have been pruned, if any
+    pub height: Option<u64>,
+}
+
+impl_p2p_message!(PrunedHeightResponse, "prunedheightresponse");
+
+/// Auxiliary structure used for headers-first blockchain syncing.
+#[derive(Debug, SerialEncodable, SerialDecodable)]
+pub struct HeaderSyncRequest {
+    /// Request identifier, echoed back in the response
+    pub id: u64,
+    /// Block height to retrieve headers after
+    pub height: u64,
+}
+
+impl_p2p_message!(HeaderSyncRequest, "headersyncrequest");
+
+/// Auxiliary structure used for headers-first blockchain syncing.
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct HeaderSyncResponse {
+    /// Identifier of the request this response is for
+    pub id: u64,
+    /// Response headers
+    pub headers: Vec<Header>,
+}
+
+impl_p2p_message!(HeaderSyncResponse, "headersyncresponse");
+
 pub struct ProtocolSync {
     request_sub: MessageSubscription<SyncRequest>,
+    header_request_sub: MessageSubscription<HeaderSyncRequest>,
+    pruned_height_request_sub: MessageSubscription<PrunedHeightRequest>,
     jobsman: ProtocolJobsManagerPtr,
     validator: ValidatorPtr,
     channel: ChannelPtr,
@@ -72,11 +128,17 @@ impl ProtocolSync {
         );
         let msg_subsystem = channel.message_subsystem();
         msg_subsystem.add_dispatch::<SyncRequest>().await;
+        msg_subsystem.add_dispatch::<HeaderSyncRequest>().await;
+        msg_subsystem.add_dispatch::<PrunedHeightRequest>().await;
 
         let request_sub = channel.subscribe_msg::<SyncRequest>().await?;
+        let header_request_sub = channel.subscribe_msg::<HeaderSyncRequest>().await?;
+        let pruned_height_request_sub = channel.subscribe_msg::<PrunedHeightRequest>().await?;
 
         Ok(Arc::new(Self {
             request_sub,
+            header_request_sub,
+            pruned_height_request_sub,
             jobsman: ProtocolJobsManager::new("SyncProtocol", channel.clone()),
             validator,
             channel,
@@ -107,20 +169,46 @@ impl ProtocolSync {
                 continue
             }
 
+            // Refuse requests for ranges we have pruned, so the peer
+            // retrieves them from an archive node
             let key = request.slot;
-            let blocks = match self.validator.blockchain.get_blocks_after(key, BATCH) {
+            let pruned = match self.validator.blockchain.pruned_height() {
                 Ok(v) => v,
                 Err(e) => {
                     error!(
                         target: "validator::protocol_sync::handle_receive_request",
-                        "get_blocks_after fail: {}",
+                        "pruned_height fail: {}",
                         e
                     );
                     continue
                 }
             };
 
-            let response = SyncResponse { blocks };
+            let response = match pruned {
+                Some(pruned) if key + 1 < pruned => {
+                    debug!(
+                        target: "validator::protocol_sync::handle_receive_request",
+                        "Requested blocks after {} are pruned, refusing...",
+                        key
+                    );
+                    SyncResponse { id: request.id, blocks: vec![], pruned: Some(pruned) }
+                }
+                _ => {
+                    let blocks = match self.validator.blockchain.get_blocks_after(key, BATCH) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            error!(
+                                target: "validator::protocol_sync::handle_receive_request",
+                                "get_blocks_after fail: {}",
+                                e
+                            );
+                            continue
+                        }
+                    };
+                    SyncResponse { id: request.id, blocks, pruned: None }
+                }
+            };
+
             if let Err(e) = self.channel.send(&response).await {
                 error!(
                     target: "validator::protocol_sync::handle_receive_request",
@@ -130,6 +218,92 @@ impl ProtocolSync {
             };
         }
     }
+
+    async fn handle_receive_header_request(self: Arc<Self>) -> Result<()> {
+        debug!(target: "validator::protocol_sync::handle_receive_header_request", "START");
+        loop {
+            let request = match self.header_request_sub.receive().await {
+                Ok(v) => v,
+                Err(e) => {
+                    debug!(
+                        target: "validator::protocol_sync::handle_receive_header_request",
+                        "recv fail: {}",
+                        e
+                    );
+                    continue
+                }
+            };
+
+            // Check if node has finished syncing its blockchain
+            if !*self.validator.synced.read().await {
+                debug!(
+                    target: "validator::protocol_sync::handle_receive_header_request",
+                    "Node still syncing blockchain, skipping..."
+                );
+                continue
+            }
+
+            let headers =
+                match self.validator.blockchain.get_headers_after(request.height, HEADERS_BATCH) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!(
+                            target: "validator::protocol_sync::handle_receive_header_request",
+                            "get_headers_after fail: {}",
+                            e
+                        );
+                        continue
+                    }
+                };
+
+            let response = HeaderSyncResponse { id: request.id, headers };
+            if let Err(e) = self.channel.send(&response).await {
+                error!(
+                    target: "validator::protocol_sync::handle_receive_header_request",
+                    "channel send fail: {}",
+                    e
+                )
+            };
+        }
+    }
+
+    async fn handle_receive_pruned_height_request(self: Arc<Self>) -> Result<()> {
+        debug!(target: "validator::protocol_sync::handle_receive_pruned_height_request", "START");
+        loop {
+            let request = match self.pruned_height_request_sub.receive().await {
+                Ok(v) => v,
+                Err(e) => {
+                    debug!(
+                        target: "validator::protocol_sync::handle_receive_pruned_height_request",
+                        "recv fail: {}",
+                        e
+                    );
+                    continue
+                }
+            };
+
+            let height = match self.validator.blockchain.pruned_height() {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(
+                        target: "validator::protocol_sync::handle_receive_pruned_height_request",
+                        "pruned_height fail: {}",
+                        e
+                    );
+                    continue
+                }
+            };
+
+            let response = PrunedHeightResponse { id: request.id, height };
+            if let Err(e) = self.channel.send(&response).await {
+                error!(
+                    target: "validator::protocol_sync::handle_receive_pruned_height_request",
+                    "channel send fail: {}",
+                    e
+                )
+            };
+        }
+    }
 }
 
 #[async_trait]
@@ -138,6 +312,14 @@ impl ProtocolBase for ProtocolSync {
         debug!(target: "validator::protocol_sync::start", "START");
         self.jobsman.clone().start(executor.clone());
         self.jobsman.clone().spawn(self.clone().handle_receive_request(), executor.clone()).await;
+        self.jobsman
+            .clone()
+            .spawn(self.clone().handle_receive_header_request(), executor.clone())
+            .await;
+        self.jobsman
+            .clone()
+            .spawn(self.clone().handle_receive_pruned_height_request(), executor.clone())
+            .await;
         debug!(target: "validator::protocol_sync::start", "END");
         Ok(())
     }
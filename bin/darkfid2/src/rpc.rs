@@ -61,18 +61,32 @@ impl RequestHandler for Darkfid {
             "blockchain.get_tx" => return self.blockchain_get_tx(req.id, req.params).await,
             "blockchain.last_known_slot" => return self.blockchain_last_known_slot(req.id, req.params).await,
             "blockchain.lookup_zkas" => return self.blockchain_lookup_zkas(req.id, req.params).await,
+            "blockchain.get_contract_state_size" => return self.blockchain_get_contract_state_size(req.id, req.params).await,
+            "blockchain.get_headers" => return self.blockchain_get_headers(req.id, req.params).await,
+            "blockchain.get_state_proof" => return self.blockchain_get_state_proof(req.id, req.params).await,
             "blockchain.subscribe_blocks" => return self.blockchain_subscribe_blocks(req.id, req.params).await,
+            "blockchain.subscribe_best_chain" => return self.blockchain_subscribe_best_chain(req.id, req.params).await,
             "blockchain.subscribe_txs" =>  return self.blockchain_subscribe_txs(req.id, req.params).await,
             "blockchain.subscribe_proposals" => return self.blockchain_subscribe_proposals(req.id, req.params).await,
+            "blockchain.get_tx_events" => return self.blockchain_get_tx_events(req.id, req.params).await,
+            "blockchain.get_events" => return self.blockchain_get_events(req.id, req.params).await,
+            "blockchain.subscribe_events" => return self.blockchain_subscribe_events(req.id, req.params).await,
             "merge_mining_get_chain_id" => return self.merge_mining_get_chain_id(req.id, req.params).await,
 
             // ===================
             // Transaction methods
             // ===================
             "tx.simulate" => return self.tx_simulate(req.id, req.params).await,
+            "tx.trace" => return self.tx_trace(req.id, req.params).await,
             "tx.broadcast" => return self.tx_broadcast(req.id, req.params).await,
             "tx.pending" => return self.tx_pending(req.id, req.params).await,
-            "tx.clean_pending" => return self.tx_pending(req.id, req.params).await,
+            "tx.clean_pending" => return self.tx_clean_pending(req.id, req.params).await,
+
+            // ===============
+            // Mempool methods
+            // ===============
+            "mempool.stats" => return self.mempool_stats(req.id, req.params).await,
+            "mempool.list" => return self.mempool_list(req.id, req.params).await,
 
             // ==============
             // Invalid method
@@ -24,16 +24,54 @@ use log::{debug, error};
 use tinyjson::JsonValue;
 
 use darkfi::{
-    blockchain::contract_store::SMART_CONTRACT_ZKAS_DB_NAME,
+    blockchain::{contract_store::SMART_CONTRACT_ZKAS_DB_NAME, BlockchainOverlay, ContractEvent},
     rpc::jsonrpc::{
         ErrorCode::{InternalError, InvalidParams, ParseError},
-        JsonError, JsonResponse, JsonResult,
+        JsonError, JsonResponse, JsonResult, JsonSubscriber,
     },
     util::encoding::base64,
+    validator::consensus::Reorg,
+    Error,
 };
 
 use crate::{server_error, Darkfid, RpcError};
 
+/// Maximum number of headers returned by a single `blockchain.get_headers` query
+const MAX_HEADERS_QUERY: u64 = 1000;
+
+/// Maximum number of transactions returned by `blockchain.get_events`
+const MAX_EVENTS_QUERY: usize = 1000;
+
+/// Maximum block height range scanned by a single `blockchain.get_events` query
+const MAX_EVENTS_HEIGHT_RANGE: u64 = 10000;
+
+/// Auxiliary function to convert a [`Reorg`] into a JSON object
+pub fn reorg_json(reorg: &Reorg) -> JsonValue {
+    let hex = |hash: &blake3::Hash| JsonValue::String(hash.to_hex().to_string());
+    JsonValue::Object(HashMap::from([
+        ("old_tip".to_string(), hex(&reorg.old_tip)),
+        ("new_tip".to_string(), hex(&reorg.new_tip)),
+        ("rolled_back".to_string(), JsonValue::Array(reorg.rolled_back.iter().map(hex).collect())),
+    ]))
+}
+
+/// Auxiliary function to convert a [`ContractEvent`] into a JSON object
+pub fn contract_event_json(event: &ContractEvent) -> JsonValue {
+    JsonValue::Object(HashMap::from([
+        ("contract_id".to_string(), JsonValue::String(event.contract_id.to_string())),
+        (
+            "topic".to_string(),
+            JsonValue::String(blake3::Hash::from(event.topic).to_hex().to_string()),
+        ),
+        ("data".to_string(), JsonValue::String(base64::encode(&event.data))),
+    ]))
+}
+
+/// Auxiliary function to parse a hex-encoded 32 byte event topic
+fn parse_topic(topic: &str) -> Option<[u8; 32]> {
+    blake3::Hash::from_hex(topic).ok().map(|x| *x.as_bytes())
+}
+
 impl Darkfid {
     // RPCAPI:
     // Queries the blockchain database for a block in the given slot.
@@ -157,6 +195,27 @@ impl Darkfid {
         self.subscribers.get("blocks").unwrap().clone().into()
     }
 
+    // RPCAPI:
+    // Initializes a subscription to the node's best fork changes, asuming node participates
+    // in consensus. Once a subscription is established, `darkfid` will send JSON-RPC
+    // notifications of blocks entering the best fork, before they get finalized. If the
+    // best fork switches, dropping already sent blocks, a reorg object is sent first,
+    // containing the old and new fork tips and the dropped block hashes in chain order,
+    // so subscribers can roll them back. Finalized blocks are still sent to the blocks
+    // subscription, and never get rolled back.
+    //
+    // --> {"jsonrpc": "2.0", "method": "blockchain.subscribe_best_chain", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "method": "blockchain.subscribe_best_chain", "params": [`blockinfo`]}
+    // <-- {"jsonrpc": "2.0", "method": "blockchain.subscribe_best_chain", "params": [{"old_tip": "abcd...", "new_tip": "ef01...", "rolled_back": ["abcd..."]}]}
+    pub async fn blockchain_subscribe_best_chain(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if !params.is_empty() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        self.subscribers.get("best_chain").unwrap().clone().into()
+    }
+
     // RPCAPI:
     // Initializes a subscription to new incoming transactions.
     // Once a subscription is established, `darkfid` will send JSON-RPC notifications of
@@ -197,6 +256,181 @@ impl Darkfid {
         proposals_subscriber.unwrap().clone().into()
     }
 
+    // RPCAPI:
+    // Queries the blockchain database for the events emitted by a given transaction.
+    //
+    // **Params:**
+    // * `array[0]`: Hex-encoded transaction hash string
+    //
+    // **Returns:**
+    // * `array[n]`: Event objects with the base58-encoded `contract_id`, the hex-encoded
+    //   `topic` and the base64-encoded `data` of each event
+    //
+    // --> {"jsonrpc": "2.0", "method": "blockchain.get_tx_events", "params": ["TxHash"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"contract_id": "...", "topic": "...", "data": "..."}], "id": 1}
+    pub async fn blockchain_get_tx_events(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let tx_hash = params[0].get::<String>().unwrap();
+        let tx_hash = match blake3::Hash::from_hex(tx_hash) {
+            Ok(v) => v,
+            Err(_) => return JsonError::new(ParseError, None, id).into(),
+        };
+
+        let events = match self.validator.blockchain.events.get(&[tx_hash], false) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "darkfid::rpc::blockchain_get_tx_events", "Failed fetching tx events: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        let Some(events) = &events[0] else {
+            return server_error(RpcError::UnknownTxEvents, id, None)
+        };
+
+        let events = events.iter().map(contract_event_json).collect();
+        JsonResponse::new(JsonValue::Array(events), id).into()
+    }
+
+    // RPCAPI:
+    // Queries the blockchain database for the events emitted by a given contract,
+    // optionally filtered by topic, in the given block height range. The range
+    // spans at most 10000 blocks, and at most 1000 transactions are returned,
+    // ordered by block height.
+    //
+    // **Params:**
+    // * `array[0]`: base58-encoded contract ID string
+    // * `array[1]`: Hex-encoded topic string, or `null` for all topics
+    // * `array[2]`: `u64` block height to start from (as string)
+    // * `array[3]`: (Optional) `u64` last block height to include (as string)
+    //
+    // **Returns:**
+    // * `array[n]`: Tuples of: `u64` block height (as string), hex-encoded transaction
+    //   hash string, and the matching event objects of that transaction
+    //
+    // --> {"jsonrpc": "2.0", "method": "blockchain.get_events", "params": ["6Ef42L1KLZXBoxBuCDto7coi9DA2D2SRtegNqNU4sd74", null, "0"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [["42", "TxHash", [{"contract_id": "...", "topic": "...", "data": "..."}]]], "id": 1}
+    pub async fn blockchain_get_events(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() < 3 ||
+            params.len() > 4 ||
+            !params[0].is_string() ||
+            !(params[1].is_string() || params[1].is_null()) ||
+            !params[2].is_string() ||
+            !params[3..].iter().all(|x| x.is_string())
+        {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let Ok(contract_id) = ContractId::from_str(params[0].get::<String>().unwrap()) else {
+            return JsonError::new(InvalidParams, None, id).into()
+        };
+
+        let topic = match params[1].get::<String>() {
+            Some(topic) => match parse_topic(topic) {
+                Some(v) => Some(v),
+                None => return JsonError::new(ParseError, None, id).into(),
+            },
+            None => None,
+        };
+
+        let Ok(from) = params[2].get::<String>().unwrap().parse::<u64>() else {
+            return JsonError::new(ParseError, None, id).into()
+        };
+
+        let max_to = from.saturating_add(MAX_EVENTS_HEIGHT_RANGE - 1);
+        let to = match params.get(3) {
+            Some(to) => match to.get::<String>().unwrap().parse::<u64>() {
+                Ok(v) if v >= from => v.min(max_to),
+                _ => return JsonError::new(InvalidParams, None, id).into(),
+            },
+            None => max_to,
+        };
+
+        let blockchain = &self.validator.blockchain;
+        let records = match blockchain.events.get_by_topic(
+            &contract_id,
+            topic.as_ref(),
+            from,
+            to,
+            MAX_EVENTS_QUERY,
+        ) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "darkfid::rpc::blockchain_get_events", "Failed fetching events: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        let mut ret = Vec::with_capacity(records.len());
+        for (height, tx_hash) in records {
+            let events = match blockchain.events.get(&[tx_hash], true) {
+                Ok(v) => v[0].clone().unwrap(),
+                Err(e) => {
+                    error!(target: "darkfid::rpc::blockchain_get_events", "Failed fetching tx events: {}", e);
+                    return JsonError::new(InternalError, None, id).into()
+                }
+            };
+
+            let events = events
+                .iter()
+                .filter(|x| {
+                    x.contract_id == contract_id && topic.map_or(true, |topic| x.topic == topic)
+                })
+                .map(contract_event_json)
+                .collect();
+
+            ret.push(JsonValue::Array(vec![
+                JsonValue::String(height.to_string()),
+                JsonValue::String(tx_hash.to_hex().to_string()),
+                JsonValue::Array(events),
+            ]));
+        }
+
+        JsonResponse::new(JsonValue::Array(ret), id).into()
+    }
+
+    // RPCAPI:
+    // Initializes a subscription to the events emitted by a given contract, optionally
+    // filtered by topic. Once a subscription is established, `darkfid` will send JSON-RPC
+    // notifications of matching events included in new incoming blocks to the subscriber.
+    //
+    // **Params:**
+    // * `array[0]`: base58-encoded contract ID string
+    // * `array[1]`: (Optional) Hex-encoded topic string
+    //
+    // --> {"jsonrpc": "2.0", "method": "blockchain.subscribe_events", "params": ["6Ef42L1KLZXBoxBuCDto7coi9DA2D2SRtegNqNU4sd74"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "method": "blockchain.subscribe_events", "params": [`height`, `tx_hash`, `event`]}
+    pub async fn blockchain_subscribe_events(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.is_empty() || params.len() > 2 || !params.iter().all(|x| x.is_string()) {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let Ok(contract_id) = ContractId::from_str(params[0].get::<String>().unwrap()) else {
+            return JsonError::new(InvalidParams, None, id).into()
+        };
+
+        let topic = match params.get(1) {
+            Some(topic) => match parse_topic(topic.get::<String>().unwrap()) {
+                Some(v) => Some(v),
+                None => return JsonError::new(ParseError, None, id).into(),
+            },
+            None => None,
+        };
+
+        let mut subscribers = self.event_subscribers.lock().await;
+        let subscriber = subscribers
+            .entry((contract_id.to_bytes(), topic))
+            .or_insert_with(|| JsonSubscriber::new("blockchain.subscribe_events"));
+
+        subscriber.clone().into()
+    }
+
     // RPCAPI:
     // Performs a lookup of zkas bincodes for a given contract ID and returns all of
     // them, including their namespace.
@@ -263,6 +497,181 @@ impl Darkfid {
         JsonResponse::new(JsonValue::Array(ret), id).into()
     }
 
+    // RPCAPI:
+    // Queries the amount of bytes a contract holds in its state trees, which
+    // it has paid storage fees for.
+    //
+    // **Params:**
+    // * `array[0]`: base58-encoded contract ID string
+    //
+    // **Returns:**
+    // * `u64`: Size of the contract state keys and values in bytes
+    //
+    // --> {"jsonrpc": "2.0", "method": "blockchain.get_contract_state_size", "params": ["6Ef42L1KLZXBoxBuCDto7coi9DA2D2SRtegNqNU4sd74"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": 1234, "id": 1}
+    pub async fn blockchain_get_contract_state_size(
+        &self,
+        id: u16,
+        params: JsonValue,
+    ) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let contract_id = params[0].get::<String>().unwrap();
+        let contract_id = match ContractId::from_str(contract_id) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "darkfid::rpc::blockchain_get_contract_state_size", "Error decoding string to ContractId: {}", e);
+                return JsonError::new(InvalidParams, None, id).into()
+            }
+        };
+
+        let blockchain = self.validator.blockchain.clone();
+
+        if blockchain.wasm_bincode.get(contract_id).is_err() {
+            return server_error(RpcError::ContractNotFound, id, None)
+        }
+
+        let size = match blockchain.state_sizes.get(&contract_id) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    target: "darkfid::rpc::blockchain_get_contract_state_size",
+                    "[RPC] Error looking up state size of {}: {}", contract_id, e,
+                );
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        JsonResponse::new(JsonValue::Number(size as f64), id).into()
+    }
+
+    // RPCAPI:
+    // Queries the blockchain database for a sequence of block headers, starting
+    // from the given height, so light clients can sync and verify them.
+    // Returns at most `MAX_HEADERS_QUERY` headers.
+    //
+    // **Params:**
+    // * `array[0]`: `u64` start height (as string)
+    // * `array[1]`: `u64` headers count (as string)
+    //
+    // **Returns:**
+    // * `array[n]`: [`Header`](https://darkrenaissance.github.io/darkfi/development/darkfi/blockchain/header_store/struct.Header.html)
+    //   structs serialized into base64.
+    //
+    // --> {"jsonrpc": "2.0", "method": "blockchain.get_headers", "params": ["0", "10"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": ["ABCD...", "EFGH..."], "id": 1}
+    pub async fn blockchain_get_headers(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 2 || !params[0].is_string() || !params[1].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let Ok(start) = params[0].get::<String>().unwrap().parse::<u64>() else {
+            return JsonError::new(ParseError, None, id).into()
+        };
+        let Ok(count) = params[1].get::<String>().unwrap().parse::<u64>() else {
+            return JsonError::new(ParseError, None, id).into()
+        };
+
+        let blockchain = &self.validator.blockchain;
+        let heights: Vec<u64> =
+            (start..start.saturating_add(count.min(MAX_HEADERS_QUERY))).collect();
+        let headers = match blockchain.order.get(&heights, false).and_then(|hashes| {
+            let hashes: Vec<blake3::Hash> = hashes.into_iter().map_while(|x| x).collect();
+            blockchain.headers.get(&hashes, true)
+        }) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "darkfid::rpc::blockchain_get_headers", "Failed fetching headers: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        let mut ret = Vec::with_capacity(headers.len());
+        for header in headers.into_iter().flatten() {
+            ret.push(JsonValue::String(base64::encode(&serialize_async(&header).await)));
+        }
+
+        JsonResponse::new(JsonValue::Array(ret), id).into()
+    }
+
+    // RPCAPI:
+    // Generates a proof of a key membership, or absence, in a contract state
+    // tree, against the block header at given height, which light clients
+    // have synced. Since only the current contracts state is kept, the height
+    // must be the one of the last block, otherwise an error is returned.
+    //
+    // **Params:**
+    // * `array[0]`: base58-encoded contract ID string
+    // * `array[1]`: Contract state tree name string
+    // * `array[2]`: base64-encoded record key
+    // * `array[3]`: Block height, as string
+    //
+    // **Returns:**
+    // * [`StateProof`](https://darkrenaissance.github.io/darkfi/development/darkfi/blockchain/state_tree/struct.StateProof.html)
+    //   struct serialized into base64.
+    //
+    // --> {"jsonrpc": "2.0", "method": "blockchain.get_state_proof", "params": ["BZHK...", "coins", "ABCD...", "42"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "ABCD...", "id": 1}
+    pub async fn blockchain_get_state_proof(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 4 || !params.iter().all(|x| x.is_string()) {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let contract_id = params[0].get::<String>().unwrap();
+        let contract_id = match ContractId::from_str(contract_id) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "darkfid::rpc::blockchain_get_state_proof", "Error decoding string to ContractId: {}", e);
+                return JsonError::new(InvalidParams, None, id).into()
+            }
+        };
+
+        let tree_name = params[1].get::<String>().unwrap();
+
+        let Some(key) = base64::decode(params[2].get::<String>().unwrap()) else {
+            return JsonError::new(ParseError, None, id).into()
+        };
+
+        let Ok(height) = params[3].get::<String>().unwrap().parse::<u64>() else {
+            return JsonError::new(ParseError, None, id).into()
+        };
+
+        // Only the current contracts state is kept, so it must be the requested one
+        match self.validator.blockchain.last() {
+            Ok((last, _)) if last == height => {}
+            Ok(_) => return server_error(RpcError::StateHeightMismatch, id, None),
+            Err(e) => {
+                error!(target: "darkfid::rpc::blockchain_get_state_proof", "Failed retrieving last block: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        }
+
+        let proof = match BlockchainOverlay::new(&self.validator.blockchain).and_then(|overlay| {
+            let overlay = overlay.lock().unwrap();
+            overlay.contracts.state_proof(&contract_id, tree_name, &key, height)
+        }) {
+            Ok(v) => v,
+            Err(Error::ContractNotFound(_)) => {
+                return server_error(RpcError::ContractNotFound, id, None)
+            }
+            Err(Error::ContractStateNotFound) => {
+                return server_error(RpcError::ContractStateNotFound, id, None)
+            }
+            Err(e) => {
+                error!(target: "darkfid::rpc::blockchain_get_state_proof", "Failed generating state proof: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        let proof = base64::encode(&serialize_async(&proof).await);
+        JsonResponse::new(JsonValue::String(proof), id).into()
+    }
+
     // RPCAPI:
     // Returns the `chain_id` used for merge mining. A 32-byte hash of the genesis block.
     //
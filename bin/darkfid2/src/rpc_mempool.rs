@@ -0,0 +1,116 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+
+use log::error;
+use tinyjson::JsonValue;
+
+use darkfi::{
+    rpc::jsonrpc::{ErrorCode::InvalidParams, JsonError, JsonResponse, JsonResult},
+    validator::mempool::{FeeRate, MempoolEntry},
+};
+
+use super::Darkfid;
+use crate::{server_error, RpcError};
+
+/// Maximum number of entries returned by `mempool.list`
+const MAX_MEMPOOL_QUERY: usize = 1000;
+
+/// Auxiliary function to convert an optional [`FeeRate`] into a JSON value
+fn fee_rate_json(rate: &Option<FeeRate>) -> JsonValue {
+    match rate {
+        Some(rate) => JsonValue::Number(rate.as_f64()),
+        None => JsonValue::Null,
+    }
+}
+
+/// Auxiliary function to convert a [`MempoolEntry`] into a JSON object
+fn mempool_entry_json(entry: &MempoolEntry) -> JsonValue {
+    JsonValue::Object(HashMap::from([
+        ("hash".to_string(), JsonValue::String(entry.hash.to_string())),
+        ("fee".to_string(), JsonValue::Number(entry.rate.fee as f64)),
+        ("gas".to_string(), JsonValue::Number(entry.rate.gas as f64)),
+        ("fee_rate".to_string(), JsonValue::Number(entry.rate.as_f64())),
+        ("size".to_string(), JsonValue::Number(entry.size as f64)),
+    ]))
+}
+
+impl Darkfid {
+    // RPCAPI:
+    // Queries the node mempool for its current statistics.
+    // Returns the number of pending transactions, their total serialized
+    // size, the configured maximum size and the lowest and highest fee rates,
+    // `null` if the mempool is empty.
+    //
+    // --> {"jsonrpc": "2.0", "method": "mempool.stats", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"count": 2, "size": 4096, "max_size": 33554432, "min_fee_rate": 0.5, "max_fee_rate": 1.2}, "id": 1}
+    pub async fn mempool_stats(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if !params.is_empty() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        if !*self.validator.synced.read().await {
+            error!(target: "darkfid::rpc::mempool_stats", "Blockchain is not synced");
+            return server_error(RpcError::NotSynced, id, None)
+        }
+
+        let stats = self.validator.consensus.mempool.read().await.stats();
+
+        let result = JsonValue::Object(HashMap::from([
+            ("count".to_string(), JsonValue::Number(stats.count as f64)),
+            ("size".to_string(), JsonValue::Number(stats.size as f64)),
+            ("max_size".to_string(), JsonValue::Number(stats.max_size as f64)),
+            ("min_fee_rate".to_string(), fee_rate_json(&stats.min_rate)),
+            ("max_fee_rate".to_string(), fee_rate_json(&stats.max_rate)),
+        ]));
+
+        JsonResponse::new(result, id).into()
+    }
+
+    // RPCAPI:
+    // Queries the node mempool for its pending transactions, ordered by
+    // priority, highest fee rate first. Optionally takes the maximum number
+    // of entries to return, capped at 1000.
+    //
+    // --> {"jsonrpc": "2.0", "method": "mempool.list", "params": [10], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"hash": "TxHash", "fee": 100, "gas": 80, "fee_rate": 1.25, "size": 2048}, ...], "id": 1}
+    pub async fn mempool_list(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() > 1 {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let limit = match params.first() {
+            Some(JsonValue::Number(n)) if *n >= 0.0 => (*n as usize).min(MAX_MEMPOOL_QUERY),
+            Some(_) => return JsonError::new(InvalidParams, None, id).into(),
+            None => MAX_MEMPOOL_QUERY,
+        };
+
+        if !*self.validator.synced.read().await {
+            error!(target: "darkfid::rpc::mempool_list", "Blockchain is not synced");
+            return server_error(RpcError::NotSynced, id, None)
+        }
+
+        let mempool = self.validator.consensus.mempool.read().await;
+        let entries: Vec<JsonValue> = mempool.iter().take(limit).map(mempool_entry_json).collect();
+
+        JsonResponse::new(JsonValue::Array(entries), id).into()
+    }
+}
@@ -16,6 +16,8 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::collections::HashMap;
+
 use darkfi_serial::deserialize_async;
 use log::error;
 use tinyjson::JsonValue;
@@ -83,6 +85,68 @@ impl Darkfid {
         JsonResponse::new(JsonValue::Boolean(true), id).into()
     }
 
+    // RPCAPI:
+    // Execute the given transaction against the current state with tracing
+    // enabled, without writing anything to the database. Returns the execution
+    // trace of every contract runtime instantiated, along with the error the
+    // execution failed with, or `null` if it succeeded. Signatures, ZK proofs
+    // and fees are not verified.
+    //
+    // --> {"jsonrpc": "2.0", "method": "tx.trace", "params": ["base64encodedTX"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"traces": [...], "error": null}, "id": 1}
+    pub async fn tx_trace(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        if !*self.validator.synced.read().await {
+            error!(target: "darkfid::rpc::tx_trace", "Blockchain is not synced");
+            return server_error(RpcError::NotSynced, id, None)
+        }
+
+        // Try to deserialize the transaction
+        let tx_enc = params[0].get::<String>().unwrap().trim();
+        let tx_bytes = match base64::decode(tx_enc) {
+            Some(v) => v,
+            None => {
+                error!(target: "darkfid::rpc::tx_trace", "Failed decoding base64 transaction");
+                return server_error(RpcError::ParseError, id, None)
+            }
+        };
+
+        let tx: Transaction = match deserialize_async(&tx_bytes).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "darkfid::rpc::tx_trace", "Failed deserializing bytes into Transaction: {}", e);
+                return server_error(RpcError::ParseError, id, None)
+            }
+        };
+
+        // Trace the state transition
+        let current_slot = self.validator.consensus.time_keeper.current_slot();
+        let (traces, err) = match self.validator.trace_transaction(&tx, current_slot).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "darkfid::rpc::tx_trace", "Failed to trace transaction: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        let traces = traces.iter().map(|trace| trace.to_json()).collect();
+        let err = match err {
+            Some(e) => JsonValue::String(e.to_string()),
+            None => JsonValue::Null,
+        };
+
+        let result = HashMap::from([
+            ("traces".to_string(), JsonValue::Array(traces)),
+            ("error".to_string(), err),
+        ]);
+
+        JsonResponse::new(JsonValue::Object(result), id).into()
+    }
+
     // RPCAPI:
     // Broadcast a given transaction to the P2P network.
     // The function will first simulate the state transition in order to see
@@ -207,7 +271,7 @@ impl Darkfid {
             }
         };
 
-        if let Err(e) = self.validator.blockchain.remove_pending_txs(&pending_txs) {
+        if let Err(e) = self.validator.remove_pending_txs(&pending_txs).await {
             error!(target: "darkfid::rpc::tx_clean_pending", "Failed fetching pending txs: {}", e);
             return JsonError::new(InternalError, None, id).into()
         };
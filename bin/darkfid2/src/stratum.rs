@@ -0,0 +1,531 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Stratum v1 mining server, enabling standard pool software and miners
+//! to mine blocks for the node.
+//!
+//! Messages are newline delimited JSON objects, following the Stratum v1
+//! conventions. Jobs carry the unsigned block header, so miners can
+//! set its nonce and compute its RandomX hash, keyed by the previous
+//! block hash:
+//!
+//! --> {"id": 1, "method": "mining.subscribe", "params": []}
+//! <-- {"id": 1, "result": [[["mining.set_difficulty", "00000000"], ["mining.notify", "00000000"]], "00000000", 4], "error": null}
+//!
+//! --> {"id": 2, "method": "mining.authorize", "params": ["worker", "password"]}
+//! <-- {"id": 2, "result": true, "error": null}
+//!
+//! <-- {"id": null, "method": "mining.set_difficulty", "params": [1000]}
+//! <-- {"id": null, "method": "mining.notify", "params": ["job_id", "seed_hash", "base64_header", true]}
+//!
+//! --> {"id": 3, "method": "mining.submit", "params": ["worker", "job_id", "0000000000000042"]}
+//! <-- {"id": 3, "result": true, "error": null}
+//!
+//! Submitted nonces are 8 bytes, big-endian hex encoded, where the first
+//! 4 bytes must be the extranonce the miner got on subscription and the
+//! rest are iterated by the miner. Shares meeting the job network target
+//! are full solutions, which get signed and appended as proposals.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::ErrorKind,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use darkfi::{
+    blockchain::BlockInfo,
+    net::transport::{Listener, PtStream},
+    system::{timeout::timeout, StoppableTask, StoppableTaskPtr},
+    util::encoding::base64,
+    validator::pow::RandomXHasher,
+    Error, Result,
+};
+use darkfi_sdk::{crypto::SecretKey, pasta::pallas};
+use darkfi_serial::serialize_async;
+use log::{debug, error, info, warn};
+use num_bigint::BigUint;
+use smol::{
+    channel::{Receiver, Sender},
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf},
+    lock::Mutex,
+};
+use tinyjson::JsonValue;
+use url::Url;
+
+/// Number of most recent jobs miners can submit shares for
+const MAX_JOBS: usize = 4;
+/// Size of the nonce part miners iterate over, in bytes
+const EXTRANONCE2_SIZE: usize = 4;
+/// Maximum length of a miner request line, in bytes
+const MAX_REQUEST_LEN: u64 = 4096;
+/// Time to wait for a miner to receive a job notification, in seconds
+const SEND_TIMEOUT: u64 = 10;
+
+/// Stratum v1 errors, as used by common pool software
+#[derive(Clone, Copy, Debug)]
+pub enum StratumError {
+    Other = 20,
+    JobNotFound = 21,
+    DuplicateShare = 22,
+    LowDifficultyShare = 23,
+    UnauthorizedWorker = 24,
+    NotSubscribed = 25,
+}
+
+impl StratumError {
+    fn to_json(&self) -> JsonValue {
+        let msg = match self {
+            Self::Other => "Invalid request",
+            Self::JobNotFound => "Job not found",
+            Self::DuplicateShare => "Duplicate share",
+            Self::LowDifficultyShare => "Low difficulty share",
+            Self::UnauthorizedWorker => "Unauthorized worker",
+            Self::NotSubscribed => "Not subscribed",
+        };
+
+        JsonValue::Array(vec![
+            JsonValue::Number(*self as i32 as f64),
+            JsonValue::String(msg.to_string()),
+            JsonValue::Null,
+        ])
+    }
+}
+
+/// A block template handed out to miners
+struct StratumJob {
+    id: String,
+    /// Unsigned block to mine
+    block: BlockInfo,
+    /// Secret key the block reward transaction was built with
+    secret: SecretKey,
+    /// Network mine target
+    target: BigUint,
+    /// Share target, based on the pool difficulty
+    share_target: BigUint,
+    /// Difficulty of the shares miners have to submit
+    difficulty: u64,
+    /// Base64 encoded serialized block header
+    blob: String,
+    /// Nonces of the shares submitted for this job
+    shares: HashSet<u64>,
+    /// RandomX VM keyed by the block previous hash, verifying the shares
+    hasher: Arc<RandomXHasher>,
+}
+
+impl StratumJob {
+    /// Generate the notifications informing miners of this job
+    fn notifications(&self, clean: bool) -> [JsonValue; 2] {
+        let difficulty = vec![JsonValue::Number(self.difficulty as f64)];
+        let notify = vec![
+            JsonValue::String(self.id.clone()),
+            JsonValue::String(self.block.header.previous.to_hex().to_string()),
+            JsonValue::String(self.blob.clone()),
+            JsonValue::Boolean(clean),
+        ];
+
+        [notification("mining.set_difficulty", difficulty), notification("mining.notify", notify)]
+    }
+}
+
+/// Writing half of a miner connection
+type MinerWriter = Arc<Mutex<WriteHalf<Box<dyn PtStream>>>>;
+
+/// Atomic pointer to the Stratum server
+pub type StratumServerPtr = Arc<StratumServer>;
+
+/// Stratum server handing out block templates to miners and
+/// collecting their shares
+pub struct StratumServer {
+    /// Difficulty of the shares miners have to submit
+    share_difficulty: BigUint,
+    /// Most recent jobs, newest last
+    jobs: Mutex<VecDeque<StratumJob>>,
+    /// Counter used to generate job IDs
+    next_job: AtomicU64,
+    /// Counter used to generate miners extranonces
+    next_extranonce: AtomicU32,
+    /// Subscribed miners, keyed by their extranonce
+    miners: Mutex<HashMap<u32, MinerWriter>>,
+    /// Miner connections tracker
+    connections: Mutex<HashSet<StoppableTaskPtr>>,
+    /// Channel transmitting found solutions, along with the
+    /// secret key their block has to be signed with
+    solutions: (Sender<(BlockInfo, SecretKey)>, Receiver<(BlockInfo, SecretKey)>),
+}
+
+impl StratumServer {
+    pub fn new(share_difficulty: u64) -> StratumServerPtr {
+        Arc::new(Self {
+            share_difficulty: BigUint::from(std::cmp::max(share_difficulty, 1)),
+            jobs: Mutex::new(VecDeque::new()),
+            next_job: AtomicU64::new(0),
+            next_extranonce: AtomicU32::new(0),
+            miners: Mutex::new(HashMap::new()),
+            connections: Mutex::new(HashSet::new()),
+            solutions: smol::channel::unbounded(),
+        })
+    }
+
+    /// Hand out a new job to all subscribed miners. If `clean` is set, shares
+    /// of previous jobs are no longer accepted, since their blocks are stale.
+    pub async fn new_job(
+        &self,
+        block: BlockInfo,
+        secret: SecretKey,
+        target: BigUint,
+        clean: bool,
+    ) -> Result<()> {
+        // Shares difficulty can't exceed the network one
+        let max = BigUint::from_bytes_be(&[0xFF; 32]);
+        let network_difficulty = std::cmp::max(&max / &target, BigUint::from(1_u8));
+        let difficulty = std::cmp::min(self.share_difficulty.clone(), network_difficulty);
+        let share_target = &max / &difficulty;
+
+        // Jobs extending the same block can reuse its RandomX VM
+        let hasher = self
+            .jobs
+            .lock()
+            .await
+            .iter()
+            .find(|j| j.hasher.seed() == &block.header.previous)
+            .map(|j| j.hasher.clone());
+        let hasher = match hasher {
+            Some(hasher) => hasher,
+            None => {
+                let seed = block.header.previous;
+                Arc::new(smol::unblock(move || RandomXHasher::new(seed)).await?)
+            }
+        };
+
+        let job = StratumJob {
+            id: format!("{:x}", self.next_job.fetch_add(1, Ordering::SeqCst)),
+            blob: base64::encode(&serialize_async(&block.header).await),
+            block,
+            secret,
+            target,
+            share_target,
+            difficulty: u64::try_from(&difficulty).unwrap_or(u64::MAX),
+            shares: HashSet::new(),
+            hasher,
+        };
+        debug!(
+            target: "darkfid::stratum::new_job",
+            "New job {} for height {}, with shares difficulty: {}",
+            job.id, job.block.header.height, job.difficulty,
+        );
+        let notifications = job.notifications(clean);
+
+        let mut jobs = self.jobs.lock().await;
+        if clean {
+            jobs.clear();
+        }
+        while jobs.len() >= MAX_JOBS {
+            jobs.pop_front();
+        }
+        jobs.push_back(job);
+        drop(jobs);
+
+        // Snapshot the miners, so slow ones don't block the others while notified
+        let miners: Vec<(u32, MinerWriter)> =
+            self.miners.lock().await.iter().map(|(k, v)| (*k, v.clone())).collect();
+        for (extranonce, writer) in miners {
+            for notification in &notifications {
+                let result =
+                    match timeout(Duration::from_secs(SEND_TIMEOUT), send(&writer, notification))
+                        .await
+                    {
+                        Ok(result) => result,
+                        Err(e) => Err(e.into()),
+                    };
+                if let Err(e) = result {
+                    warn!(
+                        target: "darkfid::stratum::new_job",
+                        "Failed notifying miner {:08x}: {}", extranonce, e,
+                    );
+                    break
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Wait for the next full difficulty solution miners found, along with
+    /// the secret key its block has to be signed with.
+    pub async fn solution(&self) -> Result<(BlockInfo, SecretKey)> {
+        Ok(self.solutions.1.recv().await?)
+    }
+
+    /// Accept miner connections on provided endpoint.
+    pub async fn listen(
+        self: Arc<Self>,
+        endpoint: Url,
+        ex: Arc<smol::Executor<'static>>,
+    ) -> Result<()> {
+        let listener = Listener::new(endpoint).await?.listen().await?;
+        loop {
+            let (stream, url) = match listener.next().await {
+                Ok(v) => v,
+                Err(e) => match e.kind() {
+                    ErrorKind::ConnectionAborted |
+                    ErrorKind::Interrupted |
+                    ErrorKind::WouldBlock |
+                    ErrorKind::UnexpectedEof => continue,
+                    _ => {
+                        error!(target: "darkfid::stratum::listen", "Stratum server failed listening: {}", e);
+                        return Err(e.into())
+                    }
+                },
+            };
+            info!(target: "darkfid::stratum::listen", "Stratum server accepted conn from {}", url);
+
+            let task = StoppableTask::new();
+            let task_ = task.clone();
+            let server = self.clone();
+            task.clone().start(
+                self.clone().handle_connection(stream),
+                move |_| async move {
+                    info!(target: "darkfid::stratum::listen", "Stratum server closed conn from {}", url);
+                    server.connections.lock().await.remove(&task_);
+                },
+                Error::ChannelStopped,
+                ex.clone(),
+            );
+            self.connections.lock().await.insert(task);
+        }
+    }
+
+    /// Close all miner connections.
+    pub async fn stop_connections(&self) {
+        let connections: Vec<StoppableTaskPtr> =
+            self.connections.lock().await.iter().cloned().collect();
+        for task in connections {
+            task.stop().await;
+        }
+    }
+
+    /// Handle the requests of a miner connection, until it closes.
+    async fn handle_connection(self: Arc<Self>, stream: Box<dyn PtStream>) -> Result<()> {
+        let (reader, writer) = smol::io::split(stream);
+        let mut reader = BufReader::new(reader);
+        let writer = Arc::new(Mutex::new(writer));
+        let extranonce = self.next_extranonce.fetch_add(1, Ordering::SeqCst);
+
+        let result = self.connection_loop(&mut reader, &writer, extranonce).await;
+        self.miners.lock().await.remove(&extranonce);
+        result
+    }
+
+    /// Reply to the miner requests, until its connection closes.
+    async fn connection_loop(
+        &self,
+        reader: &mut BufReader<ReadHalf<Box<dyn PtStream>>>,
+        writer: &MinerWriter,
+        extranonce: u32,
+    ) -> Result<()> {
+        let mut subscribed = false;
+        let mut worker: Option<String> = None;
+
+        loop {
+            let mut line = String::new();
+            let len = (&mut *reader).take(MAX_REQUEST_LEN).read_line(&mut line).await?;
+            if len == 0 {
+                return Ok(())
+            }
+            if len as u64 >= MAX_REQUEST_LEN && !line.ends_with('\n') {
+                return Err(Error::ParseFailed("Stratum request too long"))
+            }
+
+            let request: JsonValue = line.trim().parse()?;
+            let Some(request) = request.get::<HashMap<String, JsonValue>>() else {
+                return Err(Error::ParseFailed("Stratum request is not an object"))
+            };
+            let id = request.get("id").cloned().unwrap_or(JsonValue::Null);
+            let method = request.get("method").and_then(|m| m.get::<String>()).cloned();
+            let params = match request.get("params").and_then(|p| p.get::<Vec<JsonValue>>()) {
+                Some(params) => params.clone(),
+                None => vec![],
+            };
+            debug!(target: "darkfid::stratum", "{:08x} --> {}", extranonce, line.trim());
+
+            // Newly subscribed miners get the current job right away
+            let mut fresh = false;
+            let result = match method.as_deref() {
+                Some("mining.subscribe") => {
+                    fresh = !subscribed;
+                    subscribed = true;
+                    let id = JsonValue::String(format!("{:08x}", extranonce));
+                    Ok(JsonValue::Array(vec![
+                        JsonValue::Array(vec![
+                            JsonValue::Array(vec![
+                                JsonValue::String("mining.set_difficulty".to_string()),
+                                id.clone(),
+                            ]),
+                            JsonValue::Array(vec![
+                                JsonValue::String("mining.notify".to_string()),
+                                id.clone(),
+                            ]),
+                        ]),
+                        id,
+                        JsonValue::Number(EXTRANONCE2_SIZE as f64),
+                    ]))
+                }
+
+                Some("mining.authorize") => match params.first().and_then(|w| w.get::<String>()) {
+                    Some(name) => {
+                        info!(target: "darkfid::stratum", "Miner {:08x} authorized as {}", extranonce, name);
+                        worker = Some(name.clone());
+                        Ok(JsonValue::Boolean(true))
+                    }
+                    None => Err(StratumError::Other),
+                },
+
+                Some("mining.submit") => {
+                    if !subscribed {
+                        Err(StratumError::NotSubscribed)
+                    } else if worker.is_none() ||
+                        params.first().and_then(|w| w.get::<String>()) != worker.as_ref()
+                    {
+                        Err(StratumError::UnauthorizedWorker)
+                    } else {
+                        self.submit(extranonce, &params).await.map(|_| JsonValue::Boolean(true))
+                    }
+                }
+
+                _ => Err(StratumError::Other),
+            };
+
+            let reply = match result {
+                Ok(result) => response(id, result, JsonValue::Null),
+                Err(e) => {
+                    debug!(target: "darkfid::stratum", "Miner {:08x} request failed: {:?}", extranonce, e);
+                    response(id, JsonValue::Null, e.to_json())
+                }
+            };
+            send(writer, &reply).await?;
+
+            if fresh {
+                let notifications =
+                    self.jobs.lock().await.back().map(|job| job.notifications(true));
+                for notification in notifications.iter().flatten() {
+                    send(writer, notification).await?;
+                }
+                self.miners.lock().await.insert(extranonce, writer.clone());
+            }
+        }
+    }
+
+    /// Validate a share submitted for one of the current jobs, and
+    /// transmit it as a solution if it meets the network target.
+    async fn submit(
+        &self,
+        extranonce: u32,
+        params: &[JsonValue],
+    ) -> std::result::Result<(), StratumError> {
+        if params.len() != 3 {
+            return Err(StratumError::Other)
+        }
+        let (Some(job_id), Some(nonce)) = (params[1].get::<String>(), params[2].get::<String>())
+        else {
+            return Err(StratumError::Other)
+        };
+
+        // Miners can only iterate over their own nonces space
+        let Ok(nonce) = u64::from_str_radix(nonce, 16) else { return Err(StratumError::Other) };
+        if (nonce >> 32) as u32 != extranonce {
+            return Err(StratumError::Other)
+        }
+
+        // Grab the job block with the submitted nonce
+        let jobs = self.jobs.lock().await;
+        let Some(job) = jobs.iter().find(|j| &j.id == job_id) else {
+            return Err(StratumError::JobNotFound)
+        };
+        if job.shares.contains(&nonce) {
+            return Err(StratumError::DuplicateShare)
+        }
+        let mut block = job.block.clone();
+        block.header.nonce = pallas::Base::from(nonce);
+        let (secret, target, share_target, hasher) =
+            (job.secret, job.target.clone(), job.share_target.clone(), job.hasher.clone());
+        drop(jobs);
+
+        // Verify the share meets the pool difficulty
+        let header = block.header.clone();
+        let out_hash = match smol::unblock(move || hasher.header_hash(&header)).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: "darkfid::stratum::submit", "Failed computing share hash: {}", e);
+                return Err(StratumError::Other)
+            }
+        };
+        if out_hash > share_target {
+            return Err(StratumError::LowDifficultyShare)
+        }
+
+        if let Some(job) = self.jobs.lock().await.iter_mut().find(|j| &j.id == job_id) {
+            job.shares.insert(nonce);
+        }
+        debug!(target: "darkfid::stratum::submit", "Miner {:08x} share accepted for job {}", extranonce, job_id);
+
+        // Transmit full difficulty solutions
+        if out_hash <= target {
+            info!(
+                target: "darkfid::stratum::submit",
+                "Miner {:08x} found block for height {}", extranonce, block.header.height,
+            );
+            if let Err(e) = self.solutions.0.send((block, secret)).await {
+                error!(target: "darkfid::stratum::submit", "Failed transmitting solution: {}", e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Auxiliary function to build a Stratum response
+fn response(id: JsonValue, result: JsonValue, error: JsonValue) -> JsonValue {
+    JsonValue::Object(HashMap::from([
+        ("id".to_string(), id),
+        ("result".to_string(), result),
+        ("error".to_string(), error),
+    ]))
+}
+
+/// Auxiliary function to build a Stratum notification
+fn notification(method: &str, params: Vec<JsonValue>) -> JsonValue {
+    JsonValue::Object(HashMap::from([
+        ("id".to_string(), JsonValue::Null),
+        ("method".to_string(), JsonValue::String(method.to_string())),
+        ("params".to_string(), JsonValue::Array(params)),
+    ]))
+}
+
+/// Auxiliary function to write a Stratum message to a miner connection
+async fn send(writer: &MinerWriter, message: &JsonValue) -> Result<()> {
+    let mut line = message.stringify()?;
+    line.push('\n');
+    let mut writer = writer.lock().await;
+    writer.write_all(line.as_bytes()).await?;
+    writer.flush().await?;
+    Ok(())
+}
@@ -0,0 +1,87 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi::{blockchain::BlockInfo, rpc::util::JsonValue, util::encoding::base64, Result};
+use darkfi_serial::deserialize_async;
+use log::{debug, error};
+
+use crate::{rpc_blockchain::contract_event_json, Darkfid};
+
+/// async task used for notifying contract events subscribers of the
+/// events emitted by the transactions of new blocks.
+pub async fn events_task(node: &Darkfid) -> Result<()> {
+    // We follow the blocks notifications, so events are published for
+    // exactly the blocks the blocks subscribers get.
+    let blocks_sub = node.subscribers.get("blocks").unwrap().sub.clone().subscribe().await;
+
+    loop {
+        let notification = blocks_sub.receive().await;
+        let Some(params) = notification.params.get::<Vec<JsonValue>>() else { continue };
+
+        for param in params {
+            let Some(encoded_block) = param.get::<String>() else { continue };
+            let Some(bytes) = base64::decode(encoded_block) else { continue };
+            let block: BlockInfo = match deserialize_async(&bytes).await {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(target: "darkfid::task::events_task", "Failed decoding block: {}", e);
+                    continue
+                }
+            };
+
+            notify_block_events(node, &block).await;
+        }
+    }
+}
+
+/// Notify the contract events subscribers whose filter matches any of the
+/// events emitted by the given block transactions.
+async fn notify_block_events(node: &Darkfid, block: &BlockInfo) {
+    let subscribers = node.event_subscribers.lock().await;
+    if subscribers.is_empty() {
+        return
+    }
+
+    for tx in &block.txs {
+        let Ok(tx_hash) = tx.hash() else { continue };
+        let events = match node.validator.blockchain.events.get(&[tx_hash], false) {
+            Ok(v) => v[0].clone().unwrap_or_default(),
+            Err(e) => {
+                error!(target: "darkfid::task::events_task", "Failed fetching tx events: {}", e);
+                continue
+            }
+        };
+
+        for event in &events {
+            let contract_id = event.contract_id.to_bytes();
+            for filter in [(contract_id, None), (contract_id, Some(event.topic))] {
+                let Some(subscriber) = subscribers.get(&filter) else { continue };
+                debug!(
+                    target: "darkfid::task::events_task",
+                    "Notifying event of tx {} for contract {}", tx_hash, event.contract_id,
+                );
+                let params = vec![
+                    JsonValue::String(block.header.height.to_string()),
+                    JsonValue::String(tx_hash.to_hex().to_string()),
+                    contract_event_json(event),
+                ];
+                subscriber.notify(params.into()).await;
+            }
+        }
+    }
+}
@@ -38,12 +38,12 @@ use darkfi_sdk::{
     pasta::pallas,
     ContractCall,
 };
-use darkfi_serial::{deserialize, serialize, Encodable};
+use darkfi_serial::{deserialize, serialize, serialize_async, Encodable};
 use log::info;
 use num_bigint::BigUint;
 use rand::rngs::OsRng;
 
-use crate::{proto::BlockInfoMessage, Darkfid};
+use crate::{proto::BlockInfoMessage, utils::notify_best_chain, Darkfid};
 
 // TODO: handle all ? so the task don't stop on errors
 
@@ -77,15 +77,7 @@ pub async fn miner_task(node: &Darkfid, recipient: &PublicKey) -> Result<()> {
 /// Miner loop
 async fn miner_loop(node: &Darkfid, recipient: &PublicKey) -> Result<()> {
     // Grab zkas proving keys and bin for PoWReward transaction
-    info!(target: "darkfid::task::miner_task", "Generating zkas bin and proving keys...");
-    let blockchain = node.validator.blockchain.clone();
-    let (zkbin, _) = blockchain.contracts.get_zkas(
-        &blockchain.sled_db,
-        &MONEY_CONTRACT_ID,
-        MONEY_CONTRACT_ZKAS_MINT_NS_V1,
-    )?;
-    let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
-    let pk = ProvingKey::build(zkbin.k, &circuit);
+    let (zkbin, pk) = reward_zkas(node)?;
 
     // Generate a random master secret key, to derive all signing keys from.
     // This enables us to deanonimize proposals from reward recipient(miner).
@@ -113,29 +105,64 @@ async fn miner_loop(node: &Darkfid, recipient: &PublicKey) -> Result<()> {
         let nonce_bytes = base64::decode(response.get::<String>().unwrap()).unwrap();
         next_block.header.nonce = deserialize::<pallas::Base>(&nonce_bytes)?;
 
-        // Sign the mined block
-        next_block.sign(&secret)?;
+        // Sign, verify and append the mined block
+        append_mined_block(node, next_block, &secret).await?;
+    }
+}
 
-        // Verify it
-        node.validator.consensus.module.read().await.verify_current_block(&next_block)?;
+/// Auxiliary function to grab the zkas bin and generate the proving key
+/// used to build PoWReward transactions
+pub fn reward_zkas(node: &Darkfid) -> Result<(ZkBinary, ProvingKey)> {
+    info!(target: "darkfid::task::miner_task", "Generating zkas bin and proving keys...");
+    let blockchain = node.validator.blockchain.clone();
+    let (zkbin, _) = blockchain.contracts.get_zkas(
+        &blockchain.sled_db,
+        &MONEY_CONTRACT_ID,
+        MONEY_CONTRACT_ZKAS_MINT_NS_V1,
+    )?;
+    let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
+    let pk = ProvingKey::build(zkbin.k, &circuit);
 
-        // Append the mined block as a proposal
-        let proposal = Proposal::new(next_block)?;
-        node.validator.consensus.append_proposal(&proposal).await?;
+    Ok((zkbin, pk))
+}
 
-        // Check if we can finalize anything and broadcast them
-        let finalized = node.validator.finalization().await?;
-        if !finalized.is_empty() {
-            for block in finalized {
-                let message = BlockInfoMessage::from(&block);
-                node.sync_p2p.broadcast(&message).await;
-            }
+/// Auxiliary function to sign a mined block with the secret key its reward
+/// transaction was built with, verify it and append it as a proposal
+pub async fn append_mined_block(
+    node: &Darkfid,
+    mut block: BlockInfo,
+    secret: &SecretKey,
+) -> Result<()> {
+    // Sign the mined block
+    block.sign(secret)?;
+
+    // Verify it
+    node.validator.consensus.module.read().await.verify_current_block(&block)?;
+
+    // Append the mined block as a proposal
+    let proposal = Proposal::new(block)?;
+    node.validator.consensus.append_proposal(&proposal).await?;
+
+    // Notify best chain subscribers of our best fork changes
+    notify_best_chain(&node.validator, node.subscribers.get("best_chain").unwrap()).await;
+
+    // Check if we can finalize anything, broadcast them and notify blocks subscribers
+    let finalized = node.validator.finalization().await?;
+    if !finalized.is_empty() {
+        let notif_sub = node.subscribers.get("blocks").unwrap();
+        for block in finalized {
+            let message = BlockInfoMessage::from(&block);
+            node.sync_p2p.broadcast(&message).await;
+            let encoded_block = JsonValue::String(base64::encode(&serialize_async(&block).await));
+            notif_sub.notify(vec![encoded_block].into()).await;
         }
     }
+
+    Ok(())
 }
 
 /// Auxiliary function to generate next block in an atomic manner
-async fn generate_next_block(
+pub async fn generate_next_block(
     node: &Darkfid,
     secret: &mut SecretKey,
     recipient: &PublicKey,
@@ -23,3 +23,12 @@ pub use sync::sync_task;
 
 pub mod miner;
 pub use miner::miner_task;
+
+pub mod events;
+pub use events::events_task;
+
+pub mod stratum;
+pub use stratum::stratum_task;
+
+pub mod prune;
+pub use prune::prune_task;
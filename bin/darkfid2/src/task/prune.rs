@@ -0,0 +1,58 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi::{system::sleep, Result};
+use log::{debug, error, info};
+
+use crate::Darkfid;
+
+/// Seconds to wait between pruning runs
+const PRUNE_INTERVAL: u64 = 60;
+
+/// async task used for periodically pruning the transactions of
+/// blocks older than provided depth from the blockchain.
+pub async fn prune_task(node: &Darkfid, depth: u64) -> Result<()> {
+    loop {
+        sleep(PRUNE_INTERVAL).await;
+
+        // Don't prune while the node is still syncing
+        if !*node.validator.synced.read().await {
+            continue
+        }
+
+        let height = match node.validator.blockchain.last() {
+            Ok((height, _)) => height,
+            Err(e) => {
+                error!(target: "darkfid::task::prune_task", "Failed retrieving last block: {}", e);
+                continue
+            }
+        };
+        if height <= depth {
+            continue
+        }
+
+        debug!(target: "darkfid::task::prune_task", "Pruning blocks before height {}", height - depth);
+        match node.validator.blockchain.prune(height - depth) {
+            Ok(0) => { /* Nothing got pruned */ }
+            Ok(pruned) => {
+                info!(target: "darkfid::task::prune_task", "Pruned {} blocks transactions", pruned)
+            }
+            Err(e) => error!(target: "darkfid::task::prune_task", "Pruning failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::Duration;
+
+use darkfi::{system::timeout::timeout, Result};
+use darkfi_sdk::crypto::{PublicKey, SecretKey};
+use log::{debug, error, info};
+use rand::rngs::OsRng;
+use smol::future::or;
+
+use crate::{
+    stratum::StratumServerPtr,
+    task::miner::{append_mined_block, generate_next_block, reward_zkas},
+    Darkfid,
+};
+
+/// Seconds after which a job is refreshed, so its block timestamp
+/// and transactions stay current
+const JOB_REFRESH: u64 = 30;
+
+/// async task used for participating in the PoW consensus protocol by
+/// handing out block templates to miners connected to the Stratum server
+pub async fn stratum_task(
+    node: &Darkfid,
+    recipient: &PublicKey,
+    server: &StratumServerPtr,
+) -> Result<()> {
+    info!(target: "darkfid::task::stratum_task", "Starting stratum task...");
+
+    // Grab zkas proving keys and bin for PoWReward transaction
+    let (zkbin, pk) = reward_zkas(node)?;
+
+    // Generate a random master secret key, to derive all signing keys from
+    let mut secret = SecretKey::random(&mut OsRng);
+
+    // Generate a new fork to be able to extend
+    node.validator.consensus.generate_pow_slot().await?;
+
+    // We follow the best chain notifications, to know when our best fork changes
+    let best_chain_sub = node.subscribers.get("best_chain").unwrap().sub.clone().subscribe().await;
+
+    let mut clean = true;
+    loop {
+        // Hand out next block template
+        let (target, block) =
+            generate_next_block(node, &mut secret, recipient, &zkbin, &pk).await?;
+        server.new_job(block, secret, target, clean).await?;
+
+        // Wait for a solution, a best fork change or the job refresh
+        let solution = async { server.solution().await.map(Some) };
+        let best_fork_changed = async {
+            best_chain_sub.receive().await;
+            Ok(None)
+        };
+        clean = match timeout(Duration::from_secs(JOB_REFRESH), or(solution, best_fork_changed))
+            .await
+        {
+            Ok(Ok(Some((block, secret)))) => {
+                if let Err(e) = append_mined_block(node, block, &secret).await {
+                    error!(target: "darkfid::task::stratum_task", "Failed appending mined block: {}", e);
+                }
+                // Our own block changed the best fork, so we skip its notification,
+                // since the next job already extends it
+                while best_chain_sub.try_receive().is_some() {}
+                true
+            }
+            Ok(Ok(None)) => {
+                debug!(target: "darkfid::task::stratum_task", "Best fork changed, refreshing job");
+                true
+            }
+            Ok(Err(e)) => {
+                best_chain_sub.unsubscribe().await;
+                return Err(e)
+            }
+            Err(_) => false,
+        };
+    }
+}
@@ -16,77 +16,578 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use darkfi::{system::sleep, util::encoding::base64, Result};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use darkfi::{
+    blockchain::{BlockInfo, Header},
+    net::{ChannelPtr, Message, MessageSubscription},
+    rpc::jsonrpc::JsonSubscriber,
+    system::{sleep, timeout::timeout},
+    util::encoding::base64,
+    Error, Result,
+};
 use darkfi_serial::serialize_async;
 use log::{debug, info, warn};
+use num_bigint::BigUint;
+use rand::{rngs::OsRng, seq::SliceRandom};
 use tinyjson::JsonValue;
 
 use crate::{
-    proto::{SyncRequest, SyncResponse},
+    proto::{
+        HeaderSyncRequest, HeaderSyncResponse, PrunedHeightRequest, PrunedHeightResponse,
+        SyncRequest, SyncResponse, BATCH, HEADERS_BATCH,
+    },
     Darkfid,
 };
 
-/// async task used for block syncing
-pub async fn sync_task(node: &Darkfid) -> Result<()> {
-    info!(target: "darkfid::task::sync_task", "Starting blockchain sync...");
-    // Block until at least node is connected to at least one peer
+/// Max number of peers to sync from in parallel
+const SYNC_PEERS: usize = 8;
+/// Max number of headers to retrieve from a peer in a single round
+const MAX_SYNC_HEADERS: usize = 10000;
+/// Seconds to wait for a peer to respond to a request
+const REPLY_TIMEOUT: u64 = 30;
+/// Max attempts to retrieve a block range, before restarting the sync round
+const MAX_RETRIES: usize = 5;
+/// Number of failed requests after which a peer is no longer used for syncing
+const MAX_PEER_FAILURES: usize = 3;
+
+/// Identifier of the last sync request sent. Shared between sync rounds,
+/// so late responses to a previous round's requests are never mistaken
+/// for responses to the current ones.
+static LAST_REQUEST: AtomicU64 = AtomicU64::new(0);
+
+/// Sync protocol responses, tagged with the identifier of the request they respond to
+pub trait SyncReply: Message {
+    fn request_id(&self) -> u64;
+}
+
+impl SyncReply for HeaderSyncResponse {
+    fn request_id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl SyncReply for SyncResponse {
+    fn request_id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl SyncReply for PrunedHeightResponse {
+    fn request_id(&self) -> u64 {
+        self.id
+    }
+}
+
+/// Wait for the response to the request with provided identifier, for up to
+/// `wait` duration. Responses to previous requests, that arrived after those
+/// requests timed out, are dropped.
+pub async fn receive_reply<M: SyncReply>(
+    sub: &MessageSubscription<M>,
+    request_id: u64,
+    wait: Duration,
+) -> Result<Arc<M>> {
+    let deadline = Instant::now() + wait;
     loop {
-        if !node.sync_p2p.channels().await.is_empty() {
-            break
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let reply = match timeout(remaining, sub.receive()).await {
+            Ok(reply) => reply?,
+            Err(_) => return Err(Error::ChannelTimeout),
+        };
+
+        if reply.request_id() == request_id {
+            return Ok(reply)
         }
-        warn!(target: "darkfid::task::sync_task", "Node is not connected to other nodes, waiting to retry...");
-        sleep(10).await;
+
+        debug!(
+            target: "darkfid::task::sync_task",
+            "Dropping stale {} response for request {}, expected {}",
+            M::NAME, reply.request_id(), request_id,
+        );
     }
+}
 
-    // Getting a random connected channel to ask from peers
-    let channel = node.sync_p2p.random_channel().await.unwrap();
+/// A peer we are syncing from, along with its communication setup
+struct SyncPeer {
+    channel: ChannelPtr,
+    header_sub: MessageSubscription<HeaderSyncResponse>,
+    block_sub: MessageSubscription<SyncResponse>,
+    pruned_sub: MessageSubscription<PrunedHeightResponse>,
+    /// Header hashes of the validated chain the peer extends our blockchain with
+    hashes: Vec<blake3::Hash>,
+    /// Height below which the peer has pruned its blocks, if any
+    pruned_height: Option<u64>,
+    /// Number of requests the peer failed to respond to
+    failures: usize,
+    /// Flag indicating the peer must not be used anymore
+    dropped: bool,
+}
 
-    // Communication setup
-    let msg_subsystem = channel.message_subsystem();
-    msg_subsystem.add_dispatch::<SyncResponse>().await;
-    let block_response_sub = channel.subscribe_msg::<SyncResponse>().await?;
-    let notif_sub = node.subscribers.get("blocks").unwrap();
+impl SyncPeer {
+    async fn new(channel: ChannelPtr) -> Result<Self> {
+        let msg_subsystem = channel.message_subsystem();
+        msg_subsystem.add_dispatch::<HeaderSyncResponse>().await;
+        msg_subsystem.add_dispatch::<SyncResponse>().await;
+        msg_subsystem.add_dispatch::<PrunedHeightResponse>().await;
+        let header_sub = channel.subscribe_msg::<HeaderSyncResponse>().await?;
+        let block_sub = channel.subscribe_msg::<SyncResponse>().await?;
+        let pruned_sub = channel.subscribe_msg::<PrunedHeightResponse>().await?;
 
-    // TODO: make this parallel and use a head selection method,
-    // for example use a manual known head and only connect to nodes
-    // that follow that. Also use a random peer on every block range
-    // we sync.
+        Ok(Self {
+            channel,
+            header_sub,
+            block_sub,
+            pruned_sub,
+            hashes: vec![],
+            pruned_height: None,
+            failures: 0,
+            dropped: false,
+        })
+    }
 
-    // Node sends the last known block hash of the canonical blockchain
-    // and loops until the response is the same block (used to utilize
-    // batch requests).
-    let mut last = node.validator.blockchain.last()?;
-    info!(target: "darkfid::task::sync_task", "Last known block: {:?} - {:?}", last.0, last.1);
-    loop {
-        // Node creates a `SyncRequest` and sends it
-        let request = SyncRequest { slot: last.0, block: last.1 };
-        channel.send(&request).await?;
+    /// Request the headers after provided height, returning the request identifier.
+    async fn request_headers(&self, height: u64) -> Result<u64> {
+        let id = LAST_REQUEST.fetch_add(1, Ordering::Relaxed) + 1;
+        let request = HeaderSyncRequest { id, height };
+        self.channel.send(&request).await?;
+        Ok(request.id)
+    }
+
+    /// Request the blocks after provided one, returning the request identifier.
+    async fn request_blocks(&self, slot: u64, block: blake3::Hash) -> Result<u64> {
+        let id = LAST_REQUEST.fetch_add(1, Ordering::Relaxed) + 1;
+        let request = SyncRequest { id, slot, block };
+        self.channel.send(&request).await?;
+        Ok(request.id)
+    }
+
+    /// Request the height below which the peer has pruned its blocks,
+    /// returning the request identifier.
+    async fn request_pruned_height(&self) -> Result<u64> {
+        let id = LAST_REQUEST.fetch_add(1, Ordering::Relaxed) + 1;
+        let request = PrunedHeightRequest { id };
+        self.channel.send(&request).await?;
+        Ok(request.id)
+    }
+
+    /// Wait for the headers response to provided request, for up to `REPLY_TIMEOUT` seconds.
+    async fn receive_headers(&self, request_id: u64) -> Result<Vec<Header>> {
+        let wait = Duration::from_secs(REPLY_TIMEOUT);
+        Ok(receive_reply(&self.header_sub, request_id, wait).await?.headers.clone())
+    }
+
+    /// Wait for the blocks response to provided request, for up to `REPLY_TIMEOUT` seconds.
+    async fn receive_blocks(&self, request_id: u64) -> Result<Arc<SyncResponse>> {
+        let wait = Duration::from_secs(REPLY_TIMEOUT);
+        receive_reply(&self.block_sub, request_id, wait).await
+    }
+
+    /// Wait for the pruned height response to provided request, for up to
+    /// `REPLY_TIMEOUT` seconds.
+    async fn receive_pruned_height(&self, request_id: u64) -> Result<Option<u64>> {
+        let wait = Duration::from_secs(REPLY_TIMEOUT);
+        Ok(receive_reply(&self.pruned_sub, request_id, wait).await?.height)
+    }
+
+    /// Check if the peer can serve the header at given index of provided chain.
+    fn follows(&self, hashes: &[blake3::Hash], index: usize) -> bool {
+        !self.dropped && self.hashes.get(index) == Some(&hashes[index])
+    }
+
+    /// Check if the peer can serve the blocks of provided chain, from given
+    /// height up to the header at given end index. Pruning peers only keep
+    /// the blocks after the height they have pruned.
+    fn serves(&self, hashes: &[blake3::Hash], height: u64, end: usize) -> bool {
+        self.follows(hashes, end - 1) && self.pruned_height.map_or(true, |pruned| height >= pruned)
+    }
+
+    /// Register a failed request. Once the peer reaches `MAX_PEER_FAILURES`,
+    /// it is no longer used in this sync round.
+    fn failed(&mut self) {
+        self.failures += 1;
+        if self.failures >= MAX_PEER_FAILURES {
+            warn!(
+                target: "darkfid::task::sync_task",
+                "Peer {} failed too many requests, dropping it", self.channel.address(),
+            );
+            self.dropped = true;
+        }
+    }
+
+    /// Penalise a peer that sent us invalid data, by downgrading
+    /// its host entry and disconnecting from it.
+    async fn penalise(&mut self, node: &Darkfid) {
+        warn!(
+            target: "darkfid::task::sync_task",
+            "Peer {} sent invalid data, disconnecting", self.channel.address(),
+        );
+        node.sync_p2p.hosts().downgrade_host(self.channel.address()).await;
+        self.channel.stop().await;
+        self.dropped = true;
+    }
+
+    async fn close(&self) {
+        self.header_sub.unsubscribe().await;
+        self.block_sub.unsubscribe().await;
+        self.pruned_sub.unsubscribe().await;
+    }
+}
+
+/// Retrieve the height below which each of provided peers has pruned its
+/// blocks. Peers that don't respond, i.e. ones not supporting the request,
+/// are considered archive nodes. If they actually have pruned the blocks
+/// we request, they refuse those requests with their pruned height, so
+/// we stop requesting those blocks from them.
+async fn retrieve_pruned_heights(peers: &mut [SyncPeer]) {
+    // Send all requests first, so peers serve them in parallel
+    let mut requested = vec![];
+    for (i, peer) in peers.iter_mut().enumerate() {
+        match peer.request_pruned_height().await {
+            Ok(request_id) => requested.push((i, request_id)),
+            Err(e) => {
+                warn!(
+                    target: "darkfid::task::sync_task",
+                    "Failed to request pruned height from peer {}: {}", peer.channel.address(), e,
+                );
+                peer.dropped = true;
+            }
+        }
+    }
+
+    for (i, request_id) in requested {
+        match peers[i].receive_pruned_height(request_id).await {
+            Ok(height) => peers[i].pruned_height = height,
+            Err(e) => {
+                warn!(
+                    target: "darkfid::task::sync_task",
+                    "Peer {} didn't respond with its pruned height, considering it an archive node: {}",
+                    peers[i].channel.address(), e,
+                );
+            }
+        }
+    }
+}
 
-        // TODO: add a timeout here to retry
-        // Node waits for response
-        let response = block_response_sub.receive().await?;
+/// async task used for block syncing.
+/// Sync is performed headers-first: we retrieve the header chains extending
+/// our blockchain from several peers, select the heaviest one, based on its
+/// cummulative difficulty, and then retrieve its blocks in parallel ranges
+/// from the peers following it.
+pub async fn sync_task(node: &Darkfid) -> Result<()> {
+    info!(target: "darkfid::task::sync_task", "Starting blockchain sync...");
+    let notif_sub = node.subscribers.get("blocks").unwrap();
 
-        // Verify and store retrieved blocks
-        debug!(target: "darkfid::task::sync_task", "Processing received blocks");
-        node.validator.add_blocks(&response.blocks).await?;
+    loop {
+        // Block until at least node is connected to at least one peer
+        let mut channels = loop {
+            let channels = node.sync_p2p.channels().await;
+            if !channels.is_empty() {
+                break channels
+            }
+            warn!(target: "darkfid::task::sync_task", "Node is not connected to other nodes, waiting to retry...");
+            sleep(10).await;
+        };
 
-        // Notify subscriber
-        for block in &response.blocks {
-            let encoded_block = JsonValue::String(base64::encode(&serialize_async(block).await));
-            notif_sub.notify(vec![encoded_block].into()).await;
+        // Communication setup with a random set of connected peers,
+        // preferring archive nodes over pruning ones
+        channels.shuffle(&mut OsRng);
+        let mut peers = vec![];
+        for channel in channels {
+            match SyncPeer::new(channel.clone()).await {
+                Ok(peer) => peers.push(peer),
+                Err(e) => warn!(
+                    target: "darkfid::task::sync_task",
+                    "Failed to setup communication with peer {}: {}", channel.address(), e,
+                ),
+            }
+        }
+        retrieve_pruned_heights(&mut peers).await;
+        peers.sort_by_key(|peer| (peer.dropped, peer.pruned_height.is_some()));
+        for peer in peers.split_off(std::cmp::min(SYNC_PEERS, peers.len())) {
+            peer.close().await;
         }
 
-        let last_received = node.validator.blockchain.last()?;
-        info!(target: "darkfid::task::sync_task", "Last received block: {:?} - {:?}", last_received.0, last_received.1);
+        let synced = sync_round(node, &mut peers, notif_sub).await;
+        for peer in &peers {
+            peer.close().await;
+        }
 
-        if last == last_received {
+        if synced? {
             break
         }
 
-        last = last_received;
+        warn!(target: "darkfid::task::sync_task", "Sync round failed, retrying...");
+        sleep(10).await;
     }
 
     *node.validator.synced.write().await = true;
     info!(target: "darkfid::task::sync_task", "Blockchain synced!");
     Ok(())
 }
+
+/// Sync our blockchain from provided peers, until none of them extends it.
+/// Returns `false` if peers didn't respond or ran out before that happened.
+async fn sync_round(
+    node: &Darkfid,
+    peers: &mut [SyncPeer],
+    notif_sub: &JsonSubscriber,
+) -> Result<bool> {
+    loop {
+        let last = node.validator.blockchain.last()?;
+        info!(target: "darkfid::task::sync_task", "Last known block: {:?} - {:?}", last.0, last.1);
+
+        // Retrieve the heaviest header chain extending our blockchain
+        let Some(headers) = retrieve_best_chain(node, peers, last).await? else { return Ok(false) };
+
+        if headers.is_empty() {
+            return Ok(true)
+        }
+
+        // Retrieve and apply its blocks
+        if !retrieve_blocks(node, peers, &headers, notif_sub).await? {
+            return Ok(false)
+        }
+    }
+}
+
+/// Retrieve the header chains extending our last block from provided peers,
+/// validate them and return the heaviest one, based on its cummulative
+/// difficulty. Returns `None` if no peer responded.
+async fn retrieve_best_chain(
+    node: &Darkfid,
+    peers: &mut [SyncPeer],
+    last: (u64, blake3::Hash),
+) -> Result<Option<Vec<Header>>> {
+    let mut chains: Vec<Vec<Header>> = vec![vec![]; peers.len()];
+    let mut responded = vec![false; peers.len()];
+    let mut pending: Vec<usize> = (0..peers.len()).filter(|i| !peers[*i].dropped).collect();
+
+    while !pending.is_empty() {
+        // Send all requests first, so peers serve them in parallel
+        let mut requested = vec![];
+        for i in pending {
+            let height = chains[i].last().map_or(last.0, |h| h.height);
+            let request_id = match peers[i].request_headers(height).await {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!(
+                        target: "darkfid::task::sync_task",
+                        "Failed to request headers from peer {}: {}", peers[i].channel.address(), e,
+                    );
+                    peers[i].failed();
+                    continue
+                }
+            };
+            requested.push((i, request_id));
+        }
+
+        pending = vec![];
+        for (i, request_id) in requested {
+            let headers = match peers[i].receive_headers(request_id).await {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!(
+                        target: "darkfid::task::sync_task",
+                        "Peer {} didn't respond with headers: {}", peers[i].channel.address(), e,
+                    );
+                    peers[i].failed();
+                    continue
+                }
+            };
+            responded[i] = true;
+
+            // A full batch means the peer has more headers to give us
+            let full = headers.len() as u64 == HEADERS_BATCH;
+            chains[i].extend(headers);
+            if full && chains[i].len() < MAX_SYNC_HEADERS {
+                pending.push(i);
+            }
+        }
+    }
+
+    if !responded.contains(&true) {
+        return Ok(None)
+    }
+
+    // Validate each chain and keep track of the heaviest one
+    let mut best: Option<(BigUint, usize)> = None;
+    for (i, chain) in chains.iter().enumerate() {
+        peers[i].hashes = vec![];
+        if chain.is_empty() {
+            continue
+        }
+
+        // Peers following a different fork can't be synced from
+        if chain[0].previous != last.1 {
+            debug!(
+                target: "darkfid::task::sync_task",
+                "Peer {} doesn't extend our blockchain, skipping", peers[i].channel.address(),
+            );
+            continue
+        }
+
+        let difficulty = match node.validator.validate_headers(chain).await {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(target: "darkfid::task::sync_task", "Erroneous headers received: {}", e);
+                peers[i].penalise(node).await;
+                continue
+            }
+        };
+
+        peers[i].hashes = chain.iter().map(|h| h.hash()).collect::<Result<_>>()?;
+        if best.as_ref().map_or(true, |(d, _)| &difficulty > d) {
+            best = Some((difficulty, i));
+        }
+    }
+
+    let Some((difficulty, i)) = best else { return Ok(Some(vec![])) };
+    info!(
+        target: "darkfid::task::sync_task",
+        "Selected chain of {} headers from peer {}, with cummulative difficulty: {}",
+        chains[i].len(), peers[i].channel.address(), difficulty,
+    );
+
+    Ok(Some(std::mem::take(&mut chains[i])))
+}
+
+/// Retrieve the blocks of provided header chain in ranges of `BATCH` blocks,
+/// requested in parallel from the peers following it, and apply them in order.
+/// Returns `false` if the blocks couldn't be retrieved from the peers.
+async fn retrieve_blocks(
+    node: &Darkfid,
+    peers: &mut [SyncPeer],
+    headers: &[Header],
+    notif_sub: &JsonSubscriber,
+) -> Result<bool> {
+    let hashes: Vec<blake3::Hash> = headers.iter().map(|h| h.hash()).collect::<Result<_>>()?;
+
+    // Ranges are identified by their start index in the header chain,
+    // along with the number of attempts made to retrieve them.
+    let mut ranges: VecDeque<(usize, usize)> =
+        (0..headers.len()).step_by(BATCH as usize).map(|start| (start, 0)).collect();
+    // Retrieved ranges, along with the peer that served them
+    let mut received: BTreeMap<usize, (usize, Vec<BlockInfo>)> = BTreeMap::new();
+    // Index of the next header to apply
+    let mut next = 0;
+
+    while next < headers.len() {
+        // Assign each pending range to a different peer following the chain
+        let mut busy = vec![false; peers.len()];
+        let mut requested = vec![];
+        while let Some((start, attempts)) = ranges.pop_front() {
+            let end = std::cmp::min(start + BATCH as usize, headers.len());
+            let Some(i) = (0..peers.len())
+                .find(|i| !busy[*i] && peers[*i].serves(&hashes, headers[start].height, end))
+            else {
+                ranges.push_front((start, attempts));
+                break
+            };
+            busy[i] = true;
+
+            // Request the blocks after the one preceding the range
+            let request =
+                peers[i].request_blocks(headers[start].height - 1, headers[start].previous).await;
+            let request_id = match request {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!(
+                        target: "darkfid::task::sync_task",
+                        "Failed to request blocks from peer {}: {}", peers[i].channel.address(), e,
+                    );
+                    peers[i].failed();
+                    ranges.push_back((start, attempts + 1));
+                    continue
+                }
+            };
+            requested.push((i, request_id, start, end, attempts));
+        }
+
+        // No peer can serve the pending ranges
+        if requested.is_empty() {
+            warn!(target: "darkfid::task::sync_task", "No peers left to retrieve blocks from");
+            return Ok(false)
+        }
+
+        for (i, request_id, start, end, attempts) in requested {
+            let response = match peers[i].receive_blocks(request_id).await {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!(
+                        target: "darkfid::task::sync_task",
+                        "Peer {} didn't respond with blocks: {}", peers[i].channel.address(), e,
+                    );
+                    peers[i].failed();
+                    ranges.push_back((start, attempts + 1));
+                    continue
+                }
+            };
+
+            // The peer pruned the range since it told us its pruned height,
+            // or didn't tell us at all, so we retrieve it from another one
+            if let Some(pruned) = response.pruned {
+                debug!(
+                    target: "darkfid::task::sync_task",
+                    "Peer {} refused blocks request, having pruned blocks below {}",
+                    peers[i].channel.address(), pruned,
+                );
+                peers[i].pruned_height = Some(pruned);
+                ranges.push_back((start, attempts));
+                continue
+            }
+            let mut blocks = response.blocks.clone();
+
+            // Verify the peer sent us the blocks of the requested range
+            let range_hashes = &hashes[start..end];
+            blocks.truncate(range_hashes.len());
+            if blocks.len() != range_hashes.len() ||
+                blocks.iter().zip(range_hashes).any(|(b, h)| b.hash().ok().as_ref() != Some(h))
+            {
+                warn!(
+                    target: "darkfid::task::sync_task",
+                    "Peer {} sent blocks not matching the headers chain", peers[i].channel.address(),
+                );
+                peers[i].penalise(node).await;
+                ranges.push_back((start, attempts + 1));
+                continue
+            }
+
+            received.insert(start, (i, blocks));
+        }
+
+        if ranges.iter().any(|(_, attempts)| *attempts >= MAX_RETRIES) {
+            warn!(target: "darkfid::task::sync_task", "Failed to retrieve blocks range after {} attempts", MAX_RETRIES);
+            return Ok(false)
+        }
+
+        // Verify and store retrieved blocks that extend our blockchain
+        while let Some((i, blocks)) = received.remove(&next) {
+            debug!(target: "darkfid::task::sync_task", "Processing received blocks");
+            if let Err(e) = node.validator.add_blocks(&blocks).await {
+                warn!(target: "darkfid::task::sync_task", "Erroneous blocks received: {}", e);
+                peers[i].penalise(node).await;
+                return Ok(false)
+            }
+
+            // Notify subscriber
+            for block in &blocks {
+                let encoded_block =
+                    JsonValue::String(base64::encode(&serialize_async(block).await));
+                notif_sub.notify(vec![encoded_block].into()).await;
+            }
+
+            next += blocks.len();
+            let last_received = node.validator.blockchain.last()?;
+            info!(target: "darkfid::task::sync_task", "Last received block: {:?} - {:?}", last_received.0, last_received.1);
+        }
+    }
+
+    Ok(true)
+}
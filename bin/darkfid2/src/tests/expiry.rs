@@ -0,0 +1,87 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi::{
+    blockchain::BlockInfo,
+    tx::Transaction,
+    util::time::{TimeKeeper, Timestamp},
+    validator::{Validator, ValidatorConfig},
+    Result,
+};
+use darkfi_contract_test_harness::vks;
+
+#[test]
+fn purge_expired_txs() -> Result<()> {
+    smol::block_on(async {
+        // Start the chain 20 slots ago, so the node verifies slot 20
+        let mut genesis_block = BlockInfo::default();
+        genesis_block.header.timestamp = Timestamp(Timestamp::current_time().0 - 20 * 90);
+        let time_keeper = TimeKeeper::new(genesis_block.header.timestamp, 10, 90, 0);
+        let config = ValidatorConfig::new(
+            time_keeper,
+            3,
+            90,
+            None,
+            genesis_block,
+            0,
+            vec![],
+            false,
+            false,
+            vec![],
+        );
+
+        let sled_db = sled::Config::new().temporary(true).open()?;
+        let (_, vks) = vks::read_or_gen_vks_and_pks()?;
+        vks::inject(&sled_db, &vks)?;
+        let validator = Validator::new(&sled_db, config).await?;
+        let height = validator.consensus.time_keeper.current_slot();
+        assert!(height >= 20);
+
+        // Track a transaction valid until the current slot, and one whose
+        // window ended right before it
+        let live = Transaction { valid_until: Some(height), ..Default::default() };
+        let expired = Transaction { valid_until: Some(height - 1), ..Default::default() };
+        validator.blockchain.add_pending_txs(&[live.clone(), expired.clone()])?;
+        {
+            let mut mempool = validator.consensus.mempool.write().await;
+            let entries = [mempool.entry(&live, 1), mempool.entry(&expired, 1)];
+            for entry in entries {
+                mempool.insert(entry);
+            }
+        }
+
+        // Finalizing a block at the current slot purges only the expired
+        // transaction, without verifying the pending ones again
+        validator.purge_finalized_txs(&[], height).await?;
+        assert_eq!(validator.blockchain.get_pending_txs()?, vec![live.clone()]);
+        assert_eq!(validator.consensus.mempool.read().await.stats().count, 1);
+
+        // Once the window of the other transaction ends, it gets purged as well
+        validator.purge_finalized_txs(&[], height + 1).await?;
+        assert!(validator.blockchain.get_pending_txs()?.is_empty());
+        assert_eq!(validator.consensus.mempool.read().await.stats().count, 0);
+
+        // Expired transactions are purged from the pending store without
+        // being verified
+        validator.blockchain.add_pending_txs(&[expired])?;
+        validator.purge_pending_txs().await?;
+        assert!(validator.blockchain.get_pending_txs()?.is_empty());
+
+        Ok(())
+    })
+}
@@ -17,10 +17,15 @@
  */
 
 use darkfi::{
-    blockchain::Blockchain,
-    validator::{consensus::Fork, pow::PoWModule},
+    blockchain::{BlockInfo, Blockchain},
+    util::time::TimeKeeper,
+    validator::{
+        consensus::{Consensus, Fork, Reorg},
+        pow::PoWModule,
+    },
     Result,
 };
+use darkfi_sdk::pasta::pallas;
 
 #[test]
 fn forks() -> Result<()> {
@@ -63,3 +68,72 @@ fn forks() -> Result<()> {
         Ok(())
     })
 }
+
+/// Auxiliary function to generate a fork containing given blocks as proposals
+async fn fork_with(blockchain: &Blockchain, blocks: &[BlockInfo], rank: u64) -> Result<Fork> {
+    let module = PoWModule::new(blockchain.clone(), 90, None)?;
+    let mut fork = Fork::new(blockchain, module).await?;
+    for block in blocks {
+        let hash = fork.overlay.lock().unwrap().add_block(block)?;
+        fork.proposals.push(hash);
+    }
+    fork.rank = rank;
+
+    Ok(fork)
+}
+
+#[test]
+fn best_chain_reorg() -> Result<()> {
+    smol::block_on(async {
+        // Create a temporary blockchain and its consensus state
+        let blockchain = Blockchain::new(&sled::Config::new().temporary(true).open()?)?;
+        let genesis = BlockInfo::default();
+        blockchain.add_block(&genesis)?;
+        let time_keeper = TimeKeeper::new(genesis.header.timestamp, 10, 90, 0);
+        let consensus =
+            Consensus::new(blockchain.clone(), time_keeper, 3, 90, None, false, vec![])?;
+
+        // Generate dummy blocks for two competing forks
+        let block = |height: u64, nonce: u64| {
+            let mut block = BlockInfo::default();
+            block.header.height = height;
+            block.header.nonce = pallas::Base::from(nonce);
+            block
+        };
+        let (a1, a2) = (block(1, 1), block(2, 1));
+        let (b1, b2, b3) = (block(1, 2), block(2, 2), block(3, 2));
+
+        // Extending the best fork only announces the new blocks
+        *consensus.forks.write().await = vec![fork_with(&blockchain, &[a1.clone()], 1).await?];
+        let (reorg, blocks) = consensus.best_chain_update().await?;
+        assert_eq!(reorg, None);
+        assert_eq!(blocks.len(), 1);
+
+        *consensus.forks.write().await =
+            vec![fork_with(&blockchain, &[a1.clone(), a2.clone()], 2).await?];
+        let (reorg, blocks) = consensus.best_chain_update().await?;
+        assert_eq!(reorg, None);
+        assert_eq!(blocks[0].hash()?, a2.hash()?);
+
+        // A heavier competing fork rolls back the announced blocks
+        let fork_b = fork_with(&blockchain, &[b1.clone(), b2.clone(), b3.clone()], 3).await?;
+        consensus.forks.write().await.push(fork_b);
+        let (reorg, blocks) = consensus.best_chain_update().await?;
+        let expected = Reorg {
+            old_tip: a2.hash()?,
+            new_tip: b3.hash()?,
+            rolled_back: vec![a1.hash()?, a2.hash()?],
+        };
+        assert_eq!(reorg, Some(expected));
+        assert_eq!(blocks.len(), 3);
+
+        // Finalized proposals are not rolled back
+        blockchain.add_block(&b1)?;
+        *consensus.forks.write().await = vec![fork_with(&blockchain, &[b2, b3], 3).await?];
+        let (reorg, blocks) = consensus.best_chain_update().await?;
+        assert_eq!(reorg, None);
+        assert!(blocks.is_empty());
+
+        Ok(())
+    })
+}
@@ -19,15 +19,19 @@
 use std::{collections::HashMap, sync::Arc};
 
 use darkfi::{
-    blockchain::{BlockInfo, Header},
+    blockchain::{BlockInfo, BlockchainOverlay, Header},
     net::Settings,
     rpc::jsonrpc::JsonSubscriber,
     tx::Transaction,
     util::time::TimeKeeper,
-    validator::{pid::slot_pid_output, utils::genesis_txs_total, Validator, ValidatorConfig},
+    validator::{
+        pid::slot_pid_output, utils::genesis_txs_total, verification::apply_block_transactions,
+        Validator, ValidatorConfig,
+    },
     Result,
 };
 use darkfi_contract_test_harness::{vks, Holder, TestHarness};
+use darkfi_money_contract::client::call_decoder as money_call_decoder;
 use darkfi_sdk::{
     blockchain::{expected_reward, PidOutput, PreviousSlot, Slot, POS_START},
     pasta::{group::ff::Field, pallas},
@@ -93,6 +97,7 @@ impl Harness {
             vec![],
             config.pos_testing_mode,
             verify_fees,
+            vec![money_call_decoder()],
         );
 
         // Generate validators using pregenerated vks
@@ -228,8 +233,20 @@ impl Harness {
         // Generate the block
         let mut block = BlockInfo::new_empty(header, slots);
 
-        // Add transactions to the block
-        block.append_txs(vec![Transaction::default()])?;
+        // Add transactions to the block, along with the contracts
+        // state root they result in over Alice's canonical state
+        let validator = &self.alice.validator;
+        let overlay = BlockchainOverlay::new(&validator.blockchain)?;
+        let mut time_keeper = validator.consensus.time_keeper.clone();
+        time_keeper.verifying_slot = height;
+        apply_block_transactions(
+            &overlay,
+            &time_keeper,
+            &mut block,
+            vec![Transaction::default()],
+            self.config.pos_testing_mode,
+        )
+        .await?;
 
         // Attach signature
         block.signature = previous.signature;
@@ -254,6 +271,7 @@ pub async fn generate_node(
 
     let mut subscribers = HashMap::new();
     subscribers.insert("blocks", JsonSubscriber::new("blockchain.subscribe_blocks"));
+    subscribers.insert("best_chain", JsonSubscriber::new("blockchain.subscribe_best_chain"));
     subscribers.insert("txs", JsonSubscriber::new("blockchain.subscribe_txs"));
     if consensus_settings.is_some() {
         subscribers.insert("proposals", JsonSubscriber::new("blockchain.subscribe_proposals"));
@@ -26,7 +26,11 @@ use url::Url;
 mod harness;
 use harness::{generate_node, Harness, HarnessConfig};
 
+mod expiry;
 mod forks;
+mod prune;
+mod stratum;
+mod sync;
 
 async fn sync_pos_blocks_real(ex: Arc<Executor<'static>>) -> Result<()> {
     init_logger();
@@ -0,0 +1,89 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi::{
+    blockchain::{BlockInfo, Blockchain},
+    tx::Transaction,
+    Result,
+};
+
+#[test]
+fn prune() -> Result<()> {
+    // Create a temporary blockchain containing blocks with a transaction
+    // and a slot each
+    let blockchain = Blockchain::new(&sled::Config::new().temporary(true).open()?)?;
+    let mut blocks = vec![];
+    let mut hashes = vec![];
+    for height in 0..5 {
+        let mut block = BlockInfo::default();
+        block.header.height = height;
+        block.slots[0].id = height;
+        let tx = Transaction { valid_from: Some(height), ..Default::default() };
+        hashes.push(tx.hash()?);
+        block.txs = vec![tx];
+        blockchain.add_block(&block)?;
+        blocks.push(block);
+    }
+    assert_eq!(blockchain.pruned_height()?, None);
+
+    // Prune everything below height 3, except the genesis block
+    assert_eq!(blockchain.prune(3)?, 2);
+    assert_eq!(blockchain.pruned_height()?, Some(3));
+    let txs = blockchain.transactions.get(&hashes, false)?;
+    assert!(txs[0].is_some());
+    assert!(txs[1].is_none() && txs[2].is_none());
+    assert!(txs[3].is_some() && txs[4].is_some());
+
+    // Pruned transactions hashes are kept
+    assert!(!blockchain.pruned_txs.contains(&hashes[0])?);
+    assert!(blockchain.pruned_txs.contains(&hashes[1])?);
+    assert!(blockchain.pruned_txs.contains(&hashes[2])?);
+    assert!(!blockchain.pruned_txs.contains(&hashes[3])?);
+
+    // Pruned blocks slots are deleted
+    let block_hashes: Vec<blake3::Hash> = blocks.iter().map(|b| b.hash()).collect::<Result<_>>()?;
+    let blocks_slots = blockchain.blocks_slots.get(&block_hashes, false)?;
+    assert!(blocks_slots[0].is_some());
+    assert!(blocks_slots[1].is_none() && blocks_slots[2].is_none());
+    assert!(blocks_slots[3].is_some() && blocks_slots[4].is_some());
+    let slots = blockchain.slots.get(&[0, 1, 2, 3, 4], false)?;
+    assert!(slots[0].is_some());
+    assert!(slots[1].is_none() && slots[2].is_none());
+    assert!(slots[3].is_some() && slots[4].is_some());
+
+    // Pruned blocks are still recognised
+    for block in &blocks {
+        assert!(blockchain.has_block(block)?);
+    }
+    let mut unknown = blocks[1].clone();
+    unknown.txs = vec![Transaction { valid_from: Some(10), ..Default::default() }];
+    assert!(!blockchain.has_block(&unknown)?);
+
+    // Headers and blocks order are kept
+    assert_eq!(blockchain.len(), 5);
+    assert_eq!(blockchain.last()?.0, 4);
+
+    // Pruning continues from the last pruned height
+    assert_eq!(blockchain.prune(3)?, 0);
+    assert_eq!(blockchain.prune(4)?, 1);
+    assert_eq!(blockchain.pruned_height()?, Some(4));
+    assert!(blockchain.pruned_txs.contains(&hashes[3])?);
+    assert_eq!(blockchain.pruned_txs.len(), 3);
+
+    Ok(())
+}
@@ -0,0 +1,205 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use darkfi::{
+    blockchain::{BlockInfo, Header},
+    system::{msleep, timeout::timeout},
+    util::encoding::base64,
+    validator::pow::RandomXHasher,
+    Result,
+};
+use darkfi_sdk::{crypto::SecretKey, pasta::pallas};
+use darkfi_serial::deserialize;
+use num_bigint::BigUint;
+use rand::rngs::OsRng;
+use smol::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    Executor,
+};
+use tinyjson::JsonValue;
+use url::Url;
+
+use crate::stratum::StratumServer;
+
+/// Minimal Stratum client stub, talking to the server like a miner would
+struct StratumClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    id: f64,
+}
+
+impl StratumClient {
+    async fn connect(addr: &str) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(Self { reader: BufReader::new(stream.clone()), writer: stream, id: 0.0 })
+    }
+
+    /// Read next message the server sent us
+    async fn receive(&mut self) -> Result<HashMap<String, JsonValue>> {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).await?;
+        let message: JsonValue = line.trim().parse()?;
+        Ok(message.get::<HashMap<String, JsonValue>>().unwrap().clone())
+    }
+
+    /// Execute a request and return its (result, error) reply
+    async fn request(
+        &mut self,
+        method: &str,
+        params: Vec<JsonValue>,
+    ) -> Result<(JsonValue, JsonValue)> {
+        self.id += 1.0;
+        let request = JsonValue::Object(HashMap::from([
+            ("id".to_string(), JsonValue::Number(self.id)),
+            ("method".to_string(), JsonValue::String(method.to_string())),
+            ("params".to_string(), JsonValue::Array(params)),
+        ]));
+        let mut line = request.stringify()?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).await?;
+
+        let reply = self.receive().await?;
+        assert_eq!(reply["id"], JsonValue::Number(self.id));
+        Ok((reply["result"].clone(), reply["error"].clone()))
+    }
+
+    /// Submit a share and return the error code, if it got rejected
+    async fn submit(&mut self, job_id: &str, nonce: u64) -> Result<Option<f64>> {
+        let params = vec![
+            JsonValue::String("worker".to_string()),
+            JsonValue::String(job_id.to_string()),
+            JsonValue::String(format!("{:016x}", nonce)),
+        ];
+        let (result, error) = self.request("mining.submit", params).await?;
+        match error {
+            JsonValue::Null => {
+                assert_eq!(result, JsonValue::Boolean(true));
+                Ok(None)
+            }
+            error => Ok(Some(*error[0].get::<f64>().unwrap())),
+        }
+    }
+
+    /// Read a job notifications, returning its difficulty and ID
+    async fn job(&mut self, header: &Header) -> Result<(f64, String)> {
+        let difficulty = self.receive().await?;
+        assert_eq!(difficulty["method"], JsonValue::String("mining.set_difficulty".to_string()));
+        let difficulty = *difficulty["params"][0].get::<f64>().unwrap();
+
+        let notify = self.receive().await?;
+        assert_eq!(notify["method"], JsonValue::String("mining.notify".to_string()));
+        let params = notify["params"].get::<Vec<JsonValue>>().unwrap();
+        let seed = params[1].get::<String>().unwrap();
+        assert_eq!(seed, &header.previous.to_hex().to_string());
+        let blob = base64::decode(params[2].get::<String>().unwrap()).unwrap();
+        assert_eq!(&deserialize::<Header>(&blob)?, header);
+        assert_eq!(params[3], JsonValue::Boolean(true));
+
+        Ok((difficulty, params[0].get::<String>().unwrap().clone()))
+    }
+}
+
+async fn stratum_real(ex: Arc<Executor<'static>>) -> Result<()> {
+    // Start a Stratum server, with shares difficulty of 2
+    let server = StratumServer::new(2);
+    // Grab a free port, by binding port 0
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+    let endpoint = Url::parse(&format!("tcp://{}", addr))?;
+    ex.spawn(server.clone().listen(endpoint, ex.clone())).detach();
+    msleep(500).await;
+
+    // Connect a miner and subscribe to the server jobs
+    let mut client = StratumClient::connect(&addr.to_string()).await?;
+    let (result, _) = client.request("mining.subscribe", vec![]).await?;
+    let extranonce = u32::from_str_radix(result[1].get::<String>().unwrap(), 16).unwrap() as u64;
+    assert_eq!(result[2], JsonValue::Number(4.0));
+
+    // Shares are only accepted from authorized workers
+    assert_eq!(client.submit("0", extranonce << 32).await?, Some(24.0));
+    let params = vec![JsonValue::String("worker".to_string()), JsonValue::String("x".to_string())];
+    assert_eq!(client.request("mining.authorize", params).await?.0, JsonValue::Boolean(true));
+
+    // Find a nonce not meeting the shares difficulty and one meeting it
+    let max = BigUint::from_bytes_be(&[0xFF; 32]);
+    let block = BlockInfo::default();
+    let hasher = RandomXHasher::new(block.header.previous)?;
+    let (mut low, mut share) = (None, None);
+    let mut nonce = extranonce << 32;
+    while low.is_none() || share.is_none() {
+        let mut header = block.header.clone();
+        header.nonce = pallas::Base::from(nonce);
+        let out_hash = hasher.header_hash(&header)?;
+        if out_hash > &max / 2_u8 {
+            low.get_or_insert(nonce);
+        } else if share.is_none() {
+            share = Some((nonce, out_hash));
+        }
+        nonce += 1;
+    }
+    let (low, (share, share_hash)) = (low.unwrap(), share.unwrap());
+
+    // Hand out a job the share isn't a solution for
+    let secret = SecretKey::random(&mut OsRng);
+    server.new_job(block.clone(), secret, &share_hash - 1_u8, true).await?;
+    let (difficulty, job_id) = client.job(&block.header).await?;
+    assert_eq!(difficulty, 2.0);
+
+    // Verify invalid shares get rejected
+    assert_eq!(client.submit(&job_id, low).await?, Some(23.0));
+    assert_eq!(client.submit("ffff", share).await?, Some(21.0));
+    assert_eq!(client.submit(&job_id, share + (1 << 32)).await?, Some(20.0));
+    assert_eq!(client.submit(&job_id, share).await?, None);
+    assert_eq!(client.submit(&job_id, share).await?, Some(22.0));
+    assert!(timeout(Duration::from_millis(500), server.solution()).await.is_err());
+
+    // Hand out a job every nonce is a solution for, making the previous one stale
+    server.new_job(block.clone(), secret, max, true).await?;
+    let (difficulty, next_job_id) = client.job(&block.header).await?;
+    assert_eq!(difficulty, 1.0);
+    assert_eq!(client.submit(&job_id, low).await?, Some(21.0));
+    assert_eq!(client.submit(&next_job_id, low).await?, None);
+
+    // The solution is transmitted along with its signing key
+    let (solution, solution_secret) = server.solution().await?;
+    assert_eq!(solution.header.nonce, pallas::Base::from(low));
+    assert_eq!(solution.header.previous, block.header.previous);
+    assert_eq!(solution_secret, secret);
+
+    server.stop_connections().await;
+    Ok(())
+}
+
+#[test]
+fn stratum() -> Result<()> {
+    let ex = Arc::new(Executor::new());
+    let (signal, shutdown) = smol::channel::unbounded::<()>();
+
+    easy_parallel::Parallel::new().each(0..4, |_| smol::block_on(ex.run(shutdown.recv()))).finish(
+        || {
+            smol::block_on(async {
+                stratum_real(ex.clone()).await.unwrap();
+                drop(signal);
+            })
+        },
+    );
+
+    Ok(())
+}
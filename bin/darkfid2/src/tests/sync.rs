@@ -0,0 +1,56 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::Duration;
+
+use darkfi::{blockchain::Header, net::message_subscriber::MessageSubsystem, Error, Result};
+use darkfi_serial::serialize_async;
+
+use crate::{proto::HeaderSyncResponse, task::sync::receive_reply};
+
+#[test]
+fn drop_stale_replies() -> Result<()> {
+    smol::block_on(async {
+        let subsystem = MessageSubsystem::new();
+        subsystem.add_dispatch::<HeaderSyncResponse>().await;
+        let sub = subsystem.subscribe::<HeaderSyncResponse>().await?;
+        let wait = Duration::from_millis(500);
+
+        // A response to a previous request that arrived after it timed out,
+        // followed by the response to the current request.
+        let mut header = Header::default();
+        let stale = HeaderSyncResponse { id: 1, headers: vec![header.clone()] };
+        header.height = 1;
+        let current = HeaderSyncResponse { id: 2, headers: vec![header] };
+        for response in [&stale, &current] {
+            subsystem.notify("headersyncresponse", &serialize_async(response).await).await?;
+        }
+
+        // The stale response is dropped and the current one is returned
+        let reply = receive_reply(&sub, 2, wait).await?;
+        assert_eq!(reply.id, 2);
+        assert_eq!(reply.headers[0].height, 1);
+
+        // Only stale responses means the request timed out
+        subsystem.notify("headersyncresponse", &serialize_async(&stale).await).await?;
+        assert!(matches!(receive_reply(&sub, 3, wait).await, Err(Error::ChannelTimeout)));
+
+        sub.unsubscribe().await;
+        Ok(())
+    })
+}
@@ -24,14 +24,16 @@ use structopt_toml::StructOptToml;
 
 use darkfi::{
     net::{P2p, P2pPtr, Settings, SESSION_ALL},
-    rpc::jsonrpc::JsonSubscriber,
-    util::path::get_config_path,
+    rpc::{jsonrpc::JsonSubscriber, util::JsonValue},
+    util::{encoding::base64, path::get_config_path},
     validator::ValidatorPtr,
     Error, Result,
 };
+use darkfi_serial::serialize_async;
 
 use crate::{
     proto::{ProtocolBlock, ProtocolProposal, ProtocolSync, ProtocolTx},
+    rpc_blockchain::reorg_json,
     BlockchainNetwork, CONFIG_FILE,
 };
 
@@ -90,17 +92,45 @@ pub async fn spawn_consensus_p2p(
 
     let _validator = validator.clone();
     let _subscriber = subscribers.get("proposals").unwrap().clone();
+    let _best_chain_subscriber = subscribers.get("best_chain").unwrap().clone();
     registry
         .register(SESSION_ALL, move |channel, p2p| {
             let validator = _validator.clone();
             let subscriber = _subscriber.clone();
-            async move { ProtocolProposal::init(channel, validator, p2p, subscriber).await.unwrap() }
+            let best_chain_subscriber = _best_chain_subscriber.clone();
+            async move {
+                ProtocolProposal::init(channel, validator, p2p, subscriber, best_chain_subscriber)
+                    .await
+                    .unwrap()
+            }
         })
         .await;
 
     p2p
 }
 
+/// Auxiliary function to notify best chain subscribers of the blocks that entered
+/// the best fork since last call, preceded by a reorg event if previously
+/// notified blocks got dropped.
+pub async fn notify_best_chain(validator: &ValidatorPtr, subscriber: &JsonSubscriber) {
+    let (reorg, blocks) = match validator.consensus.best_chain_update().await {
+        Ok(v) => v,
+        Err(e) => {
+            error!(target: "darkfid::utils::notify_best_chain", "Failed updating best chain: {}", e);
+            return
+        }
+    };
+
+    if let Some(reorg) = reorg {
+        subscriber.notify(vec![reorg_json(&reorg)].into()).await;
+    }
+
+    for block in blocks {
+        let encoded_block = JsonValue::String(base64::encode(&serialize_async(&block).await));
+        subscriber.notify(vec![encoded_block].into()).await;
+    }
+}
+
 /// Auxiliary function to parse darkfid configuration file and extract requested
 /// blockchain network config.
 pub async fn parse_blockchain_config(
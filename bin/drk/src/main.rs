@@ -67,6 +67,9 @@ mod rpc_token;
 /// Blockchain methods
 mod rpc_blockchain;
 
+/// Light client methods
+mod rpc_light;
+
 /// CLI utility functions
 mod cli_util;
 use cli_util::{kaching, parse_token_pair, parse_value_pair};
@@ -187,6 +190,14 @@ enum Subcmd {
 
         /// DAO bulla, if the tokens are being sent to a DAO
         dao_bulla: Option<String>,
+
+        /// Block height from which the transaction is valid
+        #[clap(long)]
+        valid_from: Option<u64>,
+
+        /// Block height after which the transaction expires
+        #[clap(long)]
+        valid_until: Option<u64>,
     },
 
     /// OTC atomic swap
@@ -220,6 +231,26 @@ enum Subcmd {
         #[arg(short, long)]
         /// Reset Merkle tree to checkpoint index and start scanning
         checkpoint: Option<u64>,
+
+        #[arg(long)]
+        /// Verify headers and state proofs instead of trusting darkfid
+        light: bool,
+
+        #[arg(long, default_value = "~/.local/darkfi/drk/headers_db")]
+        /// Path to the light client headers database
+        headers_db: String,
+
+        #[arg(long, default_value = "testnet")]
+        /// Scanned network, whose hardcoded genesis block the headers must start from
+        network: String,
+
+        #[arg(long, default_value = "90")]
+        /// PoW block production target, in seconds, of the scanned network
+        pow_target: usize,
+
+        #[arg(long)]
+        /// Optional fixed PoW difficulty of the scanned network, used in testing
+        pow_fixed_difficulty: Option<u64>,
     },
 
     /// Explorer related subcommands
@@ -249,7 +280,15 @@ enum OtcSubcmd {
     },
 
     /// Build entire swap tx given the first half from stdin
-    Join,
+    Join {
+        /// Block height from which the transaction is valid
+        #[clap(long)]
+        valid_from: Option<u64>,
+
+        /// Block height after which the transaction expires
+        #[clap(long)]
+        valid_until: Option<u64>,
+    },
 
     /// Inspect a swap half or the full swap tx from stdin
     Inspect,
@@ -351,6 +390,14 @@ enum DaoSubcmd {
 
         /// Numeric identifier for the proposal
         proposal_id: u64,
+
+        /// Block height from which the transaction is valid
+        #[clap(long)]
+        valid_from: Option<u64>,
+
+        /// Block height after which the transaction expires
+        #[clap(long)]
+        valid_until: Option<u64>,
     },
 }
 
@@ -373,6 +420,9 @@ enum ExplorerSubcmd {
     /// Read a transaction from stdin and simulate it
     SimulateTx,
 
+    /// Read a transaction from stdin and print its contract execution trace
+    TraceTx,
+
     /// Fetch broadcasted transactions history
     TxsHistory {
         /// Fetch specific history record (optional)
@@ -744,14 +794,14 @@ async fn main() -> Result<()> {
             Ok(())
         }
 
-        Subcmd::Transfer { amount, token, recipient, dao, dao_bulla } => {
+        Subcmd::Transfer { amount, token, recipient, dao, dao_bulla, valid_from, valid_until } => {
             let _ = f64::from_str(&amount).with_context(|| "Invalid amount")?;
             let rcpt = PublicKey::from_str(&recipient).with_context(|| "Invalid recipient")?;
             let drk = Drk::new(args.endpoint).await?;
             let token_id = drk.get_token(token).await.with_context(|| "Invalid token alias")?;
 
             let tx = drk
-                .transfer(&amount, token_id, rcpt, dao, dao_bulla)
+                .transfer(&amount, token_id, rcpt, dao, dao_bulla, valid_from, valid_until)
                 .await
                 .with_context(|| "Failed to create payment transaction")?;
 
@@ -777,14 +827,14 @@ async fn main() -> Result<()> {
                     Ok(())
                 }
 
-                OtcSubcmd::Join => {
+                OtcSubcmd::Join { valid_from, valid_until } => {
                     let mut buf = String::new();
                     stdin().read_to_string(&mut buf)?;
                     let bytes = bs58::decode(&buf.trim()).into_vec()?;
                     let partial: PartialSwapData = deserialize(&bytes)?;
 
                     let tx = drk
-                        .join_swap(partial)
+                        .join_swap(partial, valid_from, valid_until)
                         .await
                         .with_context(|| "Failed to create a join swap transaction")?;
 
@@ -865,9 +915,34 @@ async fn main() -> Result<()> {
             }
         },
 
-        Subcmd::Scan { reset, list, checkpoint } => {
+        Subcmd::Scan {
+            reset,
+            list,
+            checkpoint,
+            light,
+            headers_db,
+            network,
+            pow_target,
+            pow_fixed_difficulty,
+        } => {
             let drk = Drk::new(args.endpoint).await?;
 
+            if light {
+                eprintln!("Light client scan requested.");
+                drk.scan_blocks_light(
+                    &headers_db,
+                    &network,
+                    pow_target,
+                    pow_fixed_difficulty,
+                    reset,
+                )
+                .await
+                .with_context(|| "Failed during light scanning")?;
+                eprintln!("Finished scanning blockchain");
+
+                return Ok(())
+            }
+
             if reset {
                 eprintln!("Reset requested.");
                 drk.scan_blocks(true).await.with_context(|| "Failed during scanning")?;
@@ -1093,7 +1168,7 @@ async fn main() -> Result<()> {
                 Ok(())
             }
 
-            DaoSubcmd::Exec { dao_alias, proposal_id } => {
+            DaoSubcmd::Exec { dao_alias, proposal_id, valid_from, valid_until } => {
                 let drk = Drk::new(args.endpoint).await?;
                 let dao_id = drk.get_dao_id(&dao_alias).await?;
                 let dao = drk.get_dao_by_id(dao_id).await?;
@@ -1101,7 +1176,7 @@ async fn main() -> Result<()> {
                 assert!(proposal.dao_bulla == dao.bulla());
 
                 let tx = drk
-                    .dao_exec(dao, proposal)
+                    .dao_exec(dao, proposal, valid_from, valid_until)
                     .await
                     .with_context(|| "Failed to execute DAO proposal")?;
 
@@ -1160,6 +1235,22 @@ async fn main() -> Result<()> {
                 Ok(())
             }
 
+            ExplorerSubcmd::TraceTx => {
+                eprintln!("Reading transaction from stdin...");
+                let mut buf = String::new();
+                stdin().read_to_string(&mut buf)?;
+                let bytes = bs58::decode(&buf.trim()).into_vec()?;
+                let tx = deserialize(&bytes)?;
+
+                let drk = Drk::new(args.endpoint).await?;
+
+                let trace = drk.trace_tx(&tx).await.with_context(|| "Failed to trace tx")?;
+
+                println!("{}", serde_json::to_string_pretty(&trace)?);
+
+                Ok(())
+            }
+
             ExplorerSubcmd::TxsHistory { tx_hash, encode } => {
                 let drk = Drk::new(args.endpoint).await?;
 
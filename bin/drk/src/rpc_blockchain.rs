@@ -19,17 +19,18 @@
 use anyhow::{anyhow, Result};
 use async_std::{stream::StreamExt, task};
 use darkfi::{
-    consensus::BlockInfo,
+    blockchain::BlockInfo,
     rpc::{
         client::RpcClient,
         jsonrpc::{JsonRequest, JsonResult},
     },
     system::Subscriber,
     tx::Transaction,
+    util::encoding::base64,
     wallet::walletdb::QueryType,
 };
 use darkfi_money_contract::client::{MONEY_INFO_COL_LAST_SCANNED_SLOT, MONEY_INFO_TABLE};
-use darkfi_sdk::crypto::ContractId;
+use darkfi_sdk::crypto::{ContractId, MerkleTree};
 use darkfi_serial::{deserialize, serialize};
 use serde_json::json;
 use signal_hook::consts::{SIGINT, SIGQUIT, SIGTERM};
@@ -38,12 +39,30 @@ use url::Url;
 
 use super::Drk;
 
+/// Maximum number of unfinalized subscription scanned blocks kept for rolling back
+const MAX_ROLLBACK_BLOCKS: usize = 100;
+
+/// An unfinalized block scanned from the best chain subscription, along with
+/// the wallet Merkle trees before scanning it, so it can be rolled back.
+struct ScannedBlock {
+    /// Block hash, as notified by darkfid
+    hash: String,
+    /// The scanned block
+    block: BlockInfo,
+    /// Money Merkle tree before scanning the block
+    money_tree: MerkleTree,
+    /// DAO daos and proposals Merkle trees before scanning the block
+    dao_trees: (MerkleTree, MerkleTree),
+}
+
 impl Drk {
-    /// Subscribes to darkfid's JSON-RPC notification endpoint that serves
-    /// new finalized blocks. Upon receiving them, all the transactions are
-    /// scanned and we check if any of them call the money contract, and if
-    /// the payments are intended for us. If so, we decrypt them and append
-    /// the metadata to our wallet.
+    /// Subscribes to darkfid's JSON-RPC notification endpoints that serve
+    /// new finalized blocks and best fork changes. Upon receiving blocks, all
+    /// the transactions are scanned and we check if any of them call the money
+    /// contract, and if the payments are intended for us. If so, we decrypt
+    /// them and append the metadata to our wallet. Blocks from the best fork
+    /// are scanned before being finalized, so when darkfid notifies us of a
+    /// reorg, the data recorded from the rolled back blocks is reverted.
     pub async fn subscribe_blocks(&self, endpoint: Url) -> Result<()> {
         let req = JsonRequest::new("blockchain.last_known_slot", json!([]));
         let rep = self.rpc_client.request(req).await?;
@@ -60,20 +79,26 @@ impl Drk {
         let subscriber = Subscriber::new();
         let subscription = subscriber.clone().subscribe().await;
 
-        let rpc_client = RpcClient::new(endpoint, None).await?;
-
-        let req = JsonRequest::new("blockchain.subscribe_blocks", json!([]));
-        task::spawn(async move { rpc_client.subscribe(req, subscriber).await.unwrap() });
-        eprintln!("Detached subscription to background");
+        for method in ["blockchain.subscribe_blocks", "blockchain.subscribe_best_chain"] {
+            let rpc_client = RpcClient::new(endpoint.clone(), None).await?;
+            let subscriber = subscriber.clone();
+            let req = JsonRequest::new(method, json!([]));
+            task::spawn(async move { rpc_client.subscribe(req, subscriber).await.unwrap() });
+        }
+        eprintln!("Detached subscriptions to background");
         eprintln!("All is good. Waiting for block notifications...");
 
+        let mut finalized_height = last_known;
+        let mut scanned: Vec<ScannedBlock> = vec![];
         let e = loop {
             match subscription.receive().await {
                 JsonResult::Notification(n) => {
                     eprintln!("Got Block notification from darkfid subscription");
-                    if n.method != "blockchain.subscribe_blocks" {
-                        break anyhow!("Got foreign notification from darkfid: {}", n.method)
-                    }
+                    let finalized = match n.method.as_str() {
+                        "blockchain.subscribe_blocks" => true,
+                        "blockchain.subscribe_best_chain" => false,
+                        _ => break anyhow!("Got foreign notification from darkfid: {}", n.method),
+                    };
 
                     let Some(params) = n.params.as_array() else {
                         break anyhow!("Received notification params are not an array")
@@ -83,18 +108,78 @@ impl Drk {
                         break anyhow!("Notification parameters are not len 1")
                     }
 
-                    let params = n.params.as_array().unwrap()[0].as_str().unwrap();
-                    let bytes = bs58::decode(params).into_vec()?;
+                    if let Some(reorg) = params[0].as_object() {
+                        eprintln!("Got reorg notification: {:?}", reorg);
+                        let Some(rolled_back) = reorg.get("rolled_back") else {
+                            break anyhow!("Reorg notification is missing rolled back blocks")
+                        };
+                        let rolled_back: Vec<String> =
+                            match serde_json::from_value(rolled_back.clone()) {
+                                Ok(v) => v,
+                                Err(e) => break anyhow!("Malformed reorg notification: {}", e),
+                            };
+                        if let Err(e) = self.rollback_blocks(&mut scanned, &rolled_back).await {
+                            break e
+                        }
+                        continue
+                    }
+
+                    let Some(params) = params[0].as_str() else {
+                        break anyhow!("Block notification is not a string")
+                    };
+                    let Some(bytes) = base64::decode(params) else {
+                        break anyhow!("Failed decoding block notification")
+                    };
 
                     let block_data: BlockInfo = deserialize(&bytes)?;
                     eprintln!("=======================================");
                     eprintln!("Block header:\n{:#?}", block_data.header);
                     eprintln!("=======================================");
+                    let block_hash = block_data.hash()?.to_hex().to_string();
+
+                    if finalized {
+                        finalized_height = block_data.header.height;
+
+                        // If we already scanned it from the best fork, it and
+                        // its predecessors can no longer be rolled back
+                        if let Some(index) = scanned.iter().position(|x| x.hash == block_hash) {
+                            eprintln!("Block was already scanned, marking it as finalized");
+                            for scanned_block in scanned.drain(..=index) {
+                                self.update_tx_history_records_status(
+                                    &scanned_block.block.txs,
+                                    "Finalized",
+                                )
+                                .await?;
+                            }
+                            continue
+                        }
+                    } else if block_data.header.height <= finalized_height {
+                        eprintln!("Block was already scanned as finalized, skipping");
+                        continue
+                    }
 
                     eprintln!("Deserialized successfully. Scanning block...");
+                    let money_tree = self.get_money_tree().await?;
+                    let dao_trees = self.get_dao_trees().await?;
                     self.scan_block_money(&block_data).await?;
                     self.scan_block_dao(&block_data).await?;
-                    self.update_tx_history_records_status(&block_data.txs, "Finalized").await?;
+
+                    if finalized {
+                        self.update_tx_history_records_status(&block_data.txs, "Finalized").await?;
+                        continue
+                    }
+
+                    // Keep the unfinalized block around, in case it gets rolled back
+                    self.update_tx_history_records_status(&block_data.txs, "Confirmed").await?;
+                    scanned.push(ScannedBlock {
+                        hash: block_hash,
+                        block: block_data,
+                        money_tree,
+                        dao_trees,
+                    });
+                    if scanned.len() > MAX_ROLLBACK_BLOCKS {
+                        scanned.remove(0);
+                    }
                 }
 
                 JsonResult::Error(e) => {
@@ -112,6 +197,50 @@ impl Drk {
         Err(e)
     }
 
+    /// Revert the wallet data recorded from the given rolled back blocks, which
+    /// must be the last unfinalized ones scanned from the subscription. Coins
+    /// created by their transactions are removed, coins they spent are marked
+    /// as unspent, DAO data they recorded is removed, their history records go
+    /// back to broadcasted, and the wallet Merkle trees are restored to their
+    /// state before the first of them.
+    async fn rollback_blocks(
+        &self,
+        scanned: &mut Vec<ScannedBlock>,
+        rolled_back: &[String],
+    ) -> Result<()> {
+        let Some(first) = rolled_back.first() else { return Ok(()) };
+        let Some(index) = scanned.iter().position(|x| &x.hash == first) else {
+            return Err(anyhow!(
+                "Rolled back block {} is outside the rollback window, please rescan",
+                first
+            ))
+        };
+
+        for scanned_block in scanned[index..].iter().rev() {
+            eprintln!("Rolling back block {}", scanned_block.hash);
+            for tx in scanned_block.block.txs.iter().rev() {
+                self.revert_tx_dao_data(tx).await?;
+                self.revert_tx_money_data(tx).await?;
+            }
+            self.update_tx_history_records_status(&scanned_block.block.txs, "Broadcasted").await?;
+        }
+
+        let first = &scanned[index];
+        self.put_money_tree(&first.money_tree).await?;
+        self.put_dao_trees(&first.dao_trees.0, &first.dao_trees.1).await?;
+
+        // Write the height before the rolled back blocks into `last_scanned_slot`
+        let query =
+            format!("UPDATE {} SET {} = ?1;", MONEY_INFO_TABLE, MONEY_INFO_COL_LAST_SCANNED_SLOT);
+        let params = json!([query, QueryType::Integer as u8, first.block.header.height - 1]);
+        let req = JsonRequest::new("wallet.exec_sql", params);
+        let _ = self.rpc_client.request(req).await?;
+
+        scanned.truncate(index);
+
+        Ok(())
+    }
+
     /// `scan_block_dao` will go over transactions in a block and fetch the ones dealing
     /// with the dao contract. Then over all of them, try to see if any are related
     /// to us. If any are found, the metadata is extracted and placed into the wallet
@@ -136,10 +265,10 @@ impl Drk {
             self.apply_tx_money_data(tx, true).await?;
         }
 
-        // Write this slot into `last_scanned_slot`
+        // Write this height into `last_scanned_slot`
         let query =
             format!("UPDATE {} SET {} = ?1;", MONEY_INFO_TABLE, MONEY_INFO_COL_LAST_SCANNED_SLOT);
-        let params = json!([query, QueryType::Integer as u8, block.header.slot]);
+        let params = json!([query, QueryType::Integer as u8, block.header.height]);
         let req = JsonRequest::new("wallet.exec_sql", params);
         let _ = self.rpc_client.request(req).await?;
 
@@ -186,15 +315,28 @@ impl Drk {
         Ok(is_valid)
     }
 
+    /// Execute the transaction with the state machine with tracing enabled,
+    /// and return the JSON execution traces of its contract calls
+    pub async fn trace_tx(&self, tx: &Transaction) -> Result<serde_json::Value> {
+        let params = json!([bs58::encode(&serialize(tx)).into_string()]);
+        let req = JsonRequest::new("tx.trace", params);
+        let rep = self.rpc_client.request(req).await?;
+
+        Ok(rep)
+    }
+
     /// Queries darkfid for a block with given slot
     async fn get_block_by_slot(&self, slot: u64) -> Result<Option<BlockInfo>> {
-        let req = JsonRequest::new("blockchain.get_slot", json!([slot]));
+        let req = JsonRequest::new("blockchain.get_slot", json!([slot.to_string()]));
 
         // This API is weird, we need some way of telling it's an empty slot and
         // not an error
         match self.rpc_client.request(req).await {
             Ok(v) => {
-                let block_bytes: Vec<u8> = serde_json::from_value(v)?;
+                let encoded: String = serde_json::from_value(v)?;
+                let Some(block_bytes) = base64::decode(&encoded) else {
+                    return Err(anyhow!("Failed decoding block"))
+                };
                 let block = deserialize(&block_bytes)?;
                 Ok(Some(block))
             }
@@ -219,19 +361,27 @@ impl Drk {
         }
     }
 
+    /// Reset the wallet state built by scanning blocks, so they can be
+    /// scanned again from the beginning.
+    pub async fn reset_scanned_state(&self) -> Result<()> {
+        self.reset_money_tree().await?;
+        self.reset_money_coins().await?;
+        self.reset_dao_trees().await?;
+        self.reset_daos().await?;
+        self.reset_dao_proposals().await?;
+        self.reset_dao_votes().await?;
+        self.update_all_tx_history_records_status("Rejected").await?;
+
+        Ok(())
+    }
+
     /// Scans the blockchain starting from the last scanned slot, for relevant
     /// money transfer transactions. If reset flag is provided, Merkle tree state
     /// and coins are reset, and start scanning from beginning. Alternatively,
     /// it looks for a checkpoint in the wallet to reset and start scanning from.
     pub async fn scan_blocks(&self, reset: bool) -> Result<()> {
         let mut sl = if reset {
-            self.reset_money_tree().await?;
-            self.reset_money_coins().await?;
-            self.reset_dao_trees().await?;
-            self.reset_daos().await?;
-            self.reset_dao_proposals().await?;
-            self.reset_dao_votes().await?;
-            self.update_all_tx_history_records_status("Rejected").await?;
+            self.reset_scanned_state().await?;
             0
         } else {
             self.last_scanned_slot().await?
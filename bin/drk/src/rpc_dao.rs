@@ -85,7 +85,8 @@ impl Drk {
         params.encode(&mut data)?;
         let calls = vec![ContractCall { contract_id: *DAO_CONTRACT_ID, data }];
         let proofs = vec![proofs];
-        let mut tx = Transaction { calls, proofs, signatures: vec![] };
+        let mut tx =
+            Transaction { calls, valid_from: None, valid_until: None, proofs, signatures: vec![] };
         let sigs = tx.create_sigs(&mut OsRng, &[dao.secret_key])?;
         tx.signatures = vec![sigs];
 
@@ -236,7 +237,8 @@ impl Drk {
         params.encode(&mut data)?;
         let calls = vec![ContractCall { contract_id: *DAO_CONTRACT_ID, data }];
         let proofs = vec![proofs];
-        let mut tx = Transaction { calls, proofs, signatures: vec![] };
+        let mut tx =
+            Transaction { calls, valid_from: None, valid_until: None, proofs, signatures: vec![] };
         let sigs = tx.create_sigs(&mut OsRng, &[signature_secret])?;
         tx.signatures = vec![sigs];
 
@@ -367,7 +369,8 @@ impl Drk {
         params.encode(&mut data)?;
         let calls = vec![ContractCall { contract_id: *DAO_CONTRACT_ID, data }];
         let proofs = vec![proofs];
-        let mut tx = Transaction { calls, proofs, signatures: vec![] };
+        let mut tx =
+            Transaction { calls, valid_from: None, valid_until: None, proofs, signatures: vec![] };
         let sigs = tx.create_sigs(&mut OsRng, &input_secrets)?;
         tx.signatures = vec![sigs];
 
@@ -376,7 +379,13 @@ impl Drk {
 
     /// Import given DAO votes into the wallet
     /// This function is really bad but I'm also really tired and annoyed.
-    pub async fn dao_exec(&self, dao: Dao, proposal: DaoProposal) -> Result<Transaction> {
+    pub async fn dao_exec(
+        &self,
+        dao: Dao,
+        proposal: DaoProposal,
+        valid_from: Option<u64>,
+        valid_until: Option<u64>,
+    ) -> Result<Transaction> {
         let dao_bulla = dao.bulla();
         eprintln!("Fetching proposal's votes");
         let votes = self.get_dao_proposal_votes(proposal.id).await?;
@@ -540,6 +549,8 @@ impl Drk {
 
         let mut tx = Transaction {
             calls: vec![xfer_call, exec_call],
+            valid_from,
+            valid_until,
             proofs: vec![xfer_debris.proofs, exec_proofs],
             signatures: vec![],
         };
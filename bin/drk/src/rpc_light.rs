@@ -0,0 +1,296 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use anyhow::{anyhow, Result};
+use darkfi::{
+    blockchain::{BlockInfo, Header, LightClient, StateProof},
+    rpc::jsonrpc::JsonRequest,
+    util::{encoding::base64, path::expand_path},
+    wallet::walletdb::QueryType,
+};
+use darkfi_money_contract::{
+    client::{MONEY_INFO_COL_LAST_SCANNED_SLOT, MONEY_INFO_TABLE},
+    MONEY_CONTRACT_COINS_TREE, MONEY_CONTRACT_NULLIFIERS_TREE,
+};
+use darkfi_sdk::crypto::{ContractId, MONEY_CONTRACT_ID};
+use darkfi_serial::{deserialize, serialize};
+use serde_json::json;
+
+use super::Drk;
+
+/// Number of headers requested from darkfid at once
+const HEADERS_BATCH: u64 = 1000;
+
+/// Number of times a state proof is requested again, after syncing the
+/// headers, in case darkfid moved past our last header in the meantime
+const STATE_PROOF_RETRIES: usize = 3;
+
+/// Hardcoded genesis blocks of each network, as darkfid uses them
+const GENESIS_BLOCK_LOCALNET: &str = include_str!("../../darkfid2/genesis_block_localnet");
+const GENESIS_BLOCK_TESTNET: &str = include_str!("../../darkfid2/genesis_block_testnet");
+const GENESIS_BLOCK_MAINNET: &str = include_str!("../../darkfid2/genesis_block_mainnet");
+
+/// Compute the hardcoded genesis block hash of given network.
+fn network_genesis(network: &str) -> Result<blake3::Hash> {
+    let genesis_block = match network {
+        "localnet" => GENESIS_BLOCK_LOCALNET,
+        "testnet" => GENESIS_BLOCK_TESTNET,
+        "mainnet" => GENESIS_BLOCK_MAINNET,
+        _ => return Err(anyhow!("Unsupported network `{}`", network)),
+    };
+
+    let bytes = bs58::decode(genesis_block.trim()).into_vec()?;
+    let genesis_block: BlockInfo = deserialize(&bytes)?;
+    Ok(genesis_block.hash()?)
+}
+
+impl Drk {
+    /// Queries darkfid for a sequence of headers, starting from given height
+    async fn get_headers(&self, start: u64, count: u64) -> Result<Vec<Header>> {
+        let req = JsonRequest::new(
+            "blockchain.get_headers",
+            json!([start.to_string(), count.to_string()]),
+        );
+        let rep = self.rpc_client.request(req).await?;
+
+        let encoded: Vec<String> = serde_json::from_value(rep)?;
+        let mut headers = Vec::with_capacity(encoded.len());
+        for header in encoded {
+            let Some(bytes) = base64::decode(&header) else {
+                return Err(anyhow!("Failed decoding header"))
+            };
+            headers.push(deserialize(&bytes)?);
+        }
+
+        Ok(headers)
+    }
+
+    /// Queries darkfid for a state proof of given key in a contract state tree,
+    /// against the header at given height
+    async fn get_state_proof(
+        &self,
+        contract_id: &ContractId,
+        tree_name: &str,
+        key: &[u8],
+        height: u64,
+    ) -> Result<StateProof> {
+        let params =
+            json!([contract_id.to_string(), tree_name, base64::encode(key), height.to_string()]);
+        let req = JsonRequest::new("blockchain.get_state_proof", params);
+        let rep = self.rpc_client.request(req).await?;
+
+        let encoded: String = serde_json::from_value(rep)?;
+        let Some(bytes) = base64::decode(&encoded) else {
+            return Err(anyhow!("Failed decoding state proof"))
+        };
+
+        Ok(deserialize(&bytes)?)
+    }
+
+    /// Queries darkfid for a block with given height, in its current format
+    async fn get_block_info(&self, height: u64) -> Result<BlockInfo> {
+        let req = JsonRequest::new("blockchain.get_slot", json!([height.to_string()]));
+        let rep = self.rpc_client.request(req).await?;
+
+        let encoded: String = serde_json::from_value(rep)?;
+        let Some(bytes) = base64::decode(&encoded) else {
+            return Err(anyhow!("Failed decoding block"))
+        };
+
+        Ok(deserialize(&bytes)?)
+    }
+
+    /// Sync and verify all headers darkfid knows after our last one. If darkfid
+    /// follows a fork of our chain, its whole fork gets retrieved, and replaces
+    /// our headers only if it is heavier. Since the wallet can't revert scanned
+    /// blocks, a reorg past given scanned height requires rescanning.
+    async fn sync_headers(&self, client: &mut LightClient, scanned: u64) -> Result<()> {
+        loop {
+            // Request headers starting from our last one, to detect forks
+            let last = client.last_header()?;
+            let headers = self.get_headers(last.height, HEADERS_BATCH).await?;
+            if headers.first() == Some(&last) {
+                if headers.len() == 1 {
+                    return Ok(())
+                }
+                client.add_headers(&headers[1..])?;
+                eprintln!("Verified headers up to height {}", client.last_header()?.height);
+                continue
+            }
+
+            // Find the last header we share with darkfid
+            let mut fork_height = last.height;
+            loop {
+                if fork_height == 0 {
+                    return Err(anyhow!("darkfid doesn't share our genesis header"))
+                }
+                fork_height -= 1;
+                let header = self.get_headers(fork_height, 1).await?;
+                if header.first() == Some(&client.header(fork_height)?) {
+                    break
+                }
+            }
+
+            // Retrieve its whole fork and apply it
+            let mut fork = vec![];
+            loop {
+                let start = fork_height + fork.len() as u64 + 1;
+                let headers = self.get_headers(start, HEADERS_BATCH).await?;
+                if headers.is_empty() {
+                    break
+                }
+                fork.extend(headers);
+            }
+
+            // darkfid is behind us in our chain
+            if fork.is_empty() {
+                return Ok(())
+            }
+
+            client.add_headers(&fork)?;
+            eprintln!(
+                "Reorged to heavier headers chain forking at height {}, up to height {}",
+                fork_height,
+                client.last_header()?.height
+            );
+
+            if fork_height < scanned {
+                return Err(anyhow!(
+                    "Headers chain reorged at height {}, below our last scanned block {}, please rescan using `scan --light --reset`",
+                    fork_height,
+                    scanned
+                ))
+            }
+        }
+    }
+
+    /// Retrieve the value of given key in a money contract state tree, or
+    /// `None` if it doesn't exist, verifying darkfid's proof against our
+    /// last header. Since darkfid only proves against its last block, we
+    /// sync the headers and retry if it moved past ours.
+    async fn verified_money_state(
+        &self,
+        client: &mut LightClient,
+        tree_name: &str,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>> {
+        let mut retries = 0;
+        loop {
+            let height = client.last_header()?.height;
+            match self.get_state_proof(&MONEY_CONTRACT_ID, tree_name, key, height).await {
+                Ok(proof) => {
+                    return Ok(client.verify_state(&MONEY_CONTRACT_ID, tree_name, key, &proof)?)
+                }
+                Err(e) if retries >= STATE_PROOF_RETRIES => return Err(e),
+                Err(_) => {
+                    retries += 1;
+                    self.sync_headers(client, height).await?;
+                }
+            }
+        }
+    }
+
+    /// Scans the blockchain in light client mode. Instead of trusting darkfid,
+    /// we sync and verify the headers chain proof of work, verify each scanned
+    /// block transactions against its header, and verify our unspent coins
+    /// against the contracts state the last header commits to. The genesis
+    /// header darkfid provides must match the network's hardcoded one. If reset
+    /// flag is provided, the scanned wallet state is reset, and we start
+    /// scanning from beginning.
+    pub async fn scan_blocks_light(
+        &self,
+        headers_db: &str,
+        network: &str,
+        pow_target: usize,
+        pow_fixed_difficulty: Option<u64>,
+        reset: bool,
+    ) -> Result<()> {
+        let network_genesis = network_genesis(network)?;
+        let db = sled::open(expand_path(headers_db)?)?;
+
+        let Some(genesis) = self.get_headers(0, 1).await?.pop() else {
+            return Err(anyhow!("darkfid didn't provide a genesis header"))
+        };
+        let mut client = LightClient::new(
+            &db,
+            &genesis,
+            &network_genesis,
+            pow_target,
+            pow_fixed_difficulty.map(|x| x.into()),
+        )?;
+
+        let mut height = if reset {
+            self.reset_scanned_state().await?;
+            0
+        } else {
+            self.last_scanned_slot().await?
+        };
+        self.sync_headers(&mut client, height).await?;
+        let last = client.last_header()?.height;
+
+        // Scan blocks after our last scanned one, verifying their transactions
+        while height < last {
+            height += 1;
+            eprint!("Requesting block {}... ", height);
+            let block = self.get_block_info(height).await?;
+            client.verify_block_txs(height, &block.txs)?;
+            eprintln!("Verified");
+
+            for tx in block.txs.iter() {
+                self.apply_tx_money_data(tx, true).await?;
+                self.apply_tx_dao_data(tx, true).await?;
+            }
+            self.update_tx_history_records_status(&block.txs, "Finalized").await?;
+
+            let query = format!(
+                "UPDATE {} SET {} = ?1;",
+                MONEY_INFO_TABLE, MONEY_INFO_COL_LAST_SCANNED_SLOT
+            );
+            let params = json!([query, QueryType::Integer as u8, height]);
+            let req = JsonRequest::new("wallet.exec_sql", params);
+            let _ = self.rpc_client.request(req).await?;
+        }
+
+        // Verify our unspent coins exist and are not spent, against the
+        // contracts state our last header commits to.
+        let coins = self.get_coins(false).await?;
+        eprintln!("Verifying {} unspent coins against the contracts state", coins.len());
+        for (coin, _) in coins {
+            let key = serialize(&coin.coin);
+            if self
+                .verified_money_state(&mut client, MONEY_CONTRACT_COINS_TREE, &key)
+                .await?
+                .is_none()
+            {
+                return Err(anyhow!("Coin {:?} doesn't exist in the contracts state", coin.coin))
+            }
+
+            let key = serialize(&coin.nullifier);
+            if self
+                .verified_money_state(&mut client, MONEY_CONTRACT_NULLIFIERS_TREE, &key)
+                .await?
+                .is_some()
+            {
+                eprintln!("Coin {:?} has been spent, marking it", coin.coin);
+                self.mark_spent_coin(&coin.coin).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
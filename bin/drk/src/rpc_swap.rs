@@ -170,7 +170,14 @@ impl Drk {
 
     /// Create a full transaction by inspecting and verifying given partial swap data,
     /// making the other half, and joining all this into a `Transaction` object.
-    pub async fn join_swap(&self, partial: PartialSwapData) -> Result<Transaction> {
+    /// The transaction can only be included at block heights between
+    /// `valid_from` and `valid_until`, if given.
+    pub async fn join_swap(
+        &self,
+        partial: PartialSwapData,
+        valid_from: Option<u64>,
+        valid_until: Option<u64>,
+    ) -> Result<Transaction> {
         // Our side of the tx in the pairs is the second half, so we try to find
         // an unspent coin like that in our wallet.
         let mut owncoins = self.get_coins(false).await?;
@@ -263,6 +270,8 @@ impl Drk {
         full_params.encode(&mut data)?;
         let mut tx = Transaction {
             calls: vec![ContractCall { contract_id, data }],
+            valid_from,
+            valid_until,
             proofs: vec![full_proofs],
             signatures: vec![],
         };
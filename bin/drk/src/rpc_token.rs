@@ -98,7 +98,8 @@ impl Drk {
         debris.params.encode(&mut data)?;
         let calls = vec![ContractCall { contract_id: *MONEY_CONTRACT_ID, data }];
         let proofs = vec![debris.proofs];
-        let mut tx = Transaction { calls, proofs, signatures: vec![] };
+        let mut tx =
+            Transaction { calls, valid_from: None, valid_until: None, proofs, signatures: vec![] };
         let sigs = tx.create_sigs(&mut OsRng, &[mint_authority.secret])?;
         tx.signatures = vec![sigs];
 
@@ -144,7 +145,8 @@ impl Drk {
         debris.params.encode(&mut data)?;
         let calls = vec![ContractCall { contract_id: *MONEY_CONTRACT_ID, data }];
         let proofs = vec![debris.proofs];
-        let mut tx = Transaction { calls, proofs, signatures: vec![] };
+        let mut tx =
+            Transaction { calls, valid_from: None, valid_until: None, proofs, signatures: vec![] };
         let sigs = tx.create_sigs(&mut OsRng, &[mint_authority.secret])?;
         tx.signatures = vec![sigs];
 
@@ -45,6 +45,9 @@ use super::Drk;
 
 impl Drk {
     /// Create a payment transaction. Returns the transaction object on success.
+    /// The transaction can only be included at block heights between
+    /// `valid_from` and `valid_until`, if given.
+    #[allow(clippy::too_many_arguments)]
     pub async fn transfer(
         &self,
         amount: &str,
@@ -52,6 +55,8 @@ impl Drk {
         recipient: PublicKey,
         dao: bool,
         dao_bulla: Option<String>,
+        valid_from: Option<u64>,
+        valid_until: Option<u64>,
     ) -> Result<Transaction> {
         let dao_bulla: Option<DaoBulla> = if dao {
             let Some(dao_bulla) = dao_bulla else {
@@ -157,7 +162,7 @@ impl Drk {
         debris.params.encode(&mut data)?;
         let calls = vec![ContractCall { contract_id, data }];
         let proofs = vec![debris.proofs];
-        let mut tx = Transaction { calls, proofs, signatures: vec![] };
+        let mut tx = Transaction { calls, valid_from, valid_until, proofs, signatures: vec![] };
         let sigs = tx.create_sigs(&mut OsRng, &debris.signature_secrets)?;
         tx.signatures = vec![sigs];
 
@@ -1163,6 +1163,31 @@ impl Drk {
         Ok(())
     }
 
+    /// Revert data related to DAO contract transactions from the wallet database,
+    /// for a transaction whose block got rolled back. DAOs it minted are unconfirmed,
+    /// and the proposals and votes it created are removed. The DAO Merkle trees must
+    /// be restored separately.
+    pub async fn revert_tx_dao_data(&self, tx: &Transaction) -> Result<()> {
+        let tx_hash = blake3::hash(&serialize(tx));
+
+        let daos: Vec<Dao> =
+            self.get_daos().await?.into_iter().filter(|x| x.tx_hash == Some(tx_hash)).collect();
+        self.unconfirm_daos(&daos).await?;
+
+        // Votes go first, since they might reference a removed proposal
+        for (table, column) in [
+            (DAO_VOTES_TABLE, DAO_VOTES_COL_TX_HASH),
+            (DAO_PROPOSALS_TABLE, DAO_PROPOSALS_COL_TX_HASH),
+        ] {
+            let query = format!("DELETE FROM {} WHERE {} = ?1;", table, column);
+            let params = json!([query, QueryType::Blob as u8, serialize(&tx_hash)]);
+            let req = JsonRequest::new("wallet.exec_sql", params);
+            let _ = self.rpc_client.request(req).await?;
+        }
+
+        Ok(())
+    }
+
     /// Confirm already imported DAO metadata into the wallet.
     /// Here we just write the leaf position, tx hash, and call index.
     /// Panics if the fields are None.
@@ -407,6 +407,34 @@ impl Drk {
         Ok(())
     }
 
+    /// Mark all coins in the wallet as unspent, if their nullifier is in the given set
+    pub async fn unspend_coins(&self, nullifiers: &[Nullifier]) -> Result<()> {
+        if nullifiers.is_empty() {
+            return Ok(())
+        }
+
+        for (coin, is_spent) in self.get_coins(true).await? {
+            if is_spent && nullifiers.contains(&coin.nullifier) {
+                self.unspend_coin(&coin.coin).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove a given coin from the wallet
+    pub async fn remove_coin(&self, coin: &Coin) -> Result<()> {
+        let query =
+            format!("DELETE FROM {} WHERE {} = ?1;", MONEY_COINS_TABLE, MONEY_COINS_COL_COIN);
+
+        let params = json!([query, QueryType::Blob as u8, serialize(&coin.inner())]);
+
+        let req = JsonRequest::new("wallet.exec_sql", params);
+        let _ = self.rpc_client.request(req).await?;
+
+        Ok(())
+    }
+
     /// Replace the Money Merkle tree in the wallet.
     pub async fn put_money_tree(&self, tree: &MerkleTree) -> Result<()> {
         let query = format!(
@@ -481,57 +509,7 @@ impl Drk {
 
     /// Append data related to Money contract transactions into the wallet database.
     pub async fn apply_tx_money_data(&self, tx: &Transaction, _confirm: bool) -> Result<()> {
-        let cid = *MONEY_CONTRACT_ID;
-
-        let mut nullifiers: Vec<Nullifier> = vec![];
-        let mut outputs: Vec<Output> = vec![];
-        let mut freezes: Vec<TokenId> = vec![];
-
-        for (i, call) in tx.calls.iter().enumerate() {
-            if call.contract_id == cid && call.data[0] == MoneyFunction::TransferV1 as u8 {
-                eprintln!("Found Money::TransferV1 in call {}", i);
-                let params: MoneyTransferParamsV1 = deserialize(&call.data[1..])?;
-
-                for input in params.inputs {
-                    nullifiers.push(input.nullifier);
-                }
-
-                for output in params.outputs {
-                    outputs.push(output);
-                }
-
-                continue
-            }
-
-            if call.contract_id == cid && call.data[0] == MoneyFunction::OtcSwapV1 as u8 {
-                eprintln!("Found Money::OtcSwapV1 in call {}", i);
-                let params: MoneyTransferParamsV1 = deserialize(&call.data[1..])?;
-
-                for input in params.inputs {
-                    nullifiers.push(input.nullifier);
-                }
-
-                for output in params.outputs {
-                    outputs.push(output);
-                }
-
-                continue
-            }
-
-            if call.contract_id == cid && call.data[0] == MoneyFunction::TokenMintV1 as u8 {
-                eprintln!("Found Money::MintV1 in call {}", i);
-                let params: MoneyTokenMintParamsV1 = deserialize(&call.data[1..])?;
-                outputs.push(params.output);
-                continue
-            }
-
-            if call.contract_id == cid && call.data[0] == MoneyFunction::TokenFreezeV1 as u8 {
-                eprintln!("Found Money::FreezeV1 in call {}", i);
-                let params: MoneyTokenFreezeParamsV1 = deserialize(&call.data[1..])?;
-                let token_id = TokenId::derive_public(params.signature_public);
-                freezes.push(token_id);
-            }
-        }
+        let (nullifiers, outputs, freezes) = parse_money_tx_data(tx)?;
 
         let secrets = self.get_money_secrets().await?;
         let dao_secrets = self.get_dao_secrets().await?;
@@ -646,6 +624,21 @@ impl Drk {
         Ok(())
     }
 
+    /// Revert data related to Money contract transactions from the wallet database,
+    /// for a transaction whose block got rolled back. Coins it created are removed
+    /// and coins it spent are marked as unspent. The Money Merkle tree must be
+    /// restored separately, and token freezes are not reverted.
+    pub async fn revert_tx_money_data(&self, tx: &Transaction) -> Result<()> {
+        let (nullifiers, outputs, _) = parse_money_tx_data(tx)?;
+
+        for output in outputs {
+            self.remove_coin(&output.coin).await?;
+        }
+        self.unspend_coins(&nullifiers).await?;
+
+        Ok(())
+    }
+
     /// Get the last scanned slot from the wallet
     pub async fn last_scanned_slot(&self) -> Result<u64> {
         let query =
@@ -786,3 +779,61 @@ impl Drk {
         Ok(())
     }
 }
+
+/// Auxiliary function to extract the nullifiers, outputs and frozen tokens
+/// of Money contract calls in a transaction.
+fn parse_money_tx_data(tx: &Transaction) -> Result<(Vec<Nullifier>, Vec<Output>, Vec<TokenId>)> {
+    let cid = *MONEY_CONTRACT_ID;
+
+    let mut nullifiers: Vec<Nullifier> = vec![];
+    let mut outputs: Vec<Output> = vec![];
+    let mut freezes: Vec<TokenId> = vec![];
+
+    for (i, call) in tx.calls.iter().enumerate() {
+        if call.contract_id == cid && call.data[0] == MoneyFunction::TransferV1 as u8 {
+            eprintln!("Found Money::TransferV1 in call {}", i);
+            let params: MoneyTransferParamsV1 = deserialize(&call.data[1..])?;
+
+            for input in params.inputs {
+                nullifiers.push(input.nullifier);
+            }
+
+            for output in params.outputs {
+                outputs.push(output);
+            }
+
+            continue
+        }
+
+        if call.contract_id == cid && call.data[0] == MoneyFunction::OtcSwapV1 as u8 {
+            eprintln!("Found Money::OtcSwapV1 in call {}", i);
+            let params: MoneyTransferParamsV1 = deserialize(&call.data[1..])?;
+
+            for input in params.inputs {
+                nullifiers.push(input.nullifier);
+            }
+
+            for output in params.outputs {
+                outputs.push(output);
+            }
+
+            continue
+        }
+
+        if call.contract_id == cid && call.data[0] == MoneyFunction::TokenMintV1 as u8 {
+            eprintln!("Found Money::MintV1 in call {}", i);
+            let params: MoneyTokenMintParamsV1 = deserialize(&call.data[1..])?;
+            outputs.push(params.output);
+            continue
+        }
+
+        if call.contract_id == cid && call.data[0] == MoneyFunction::TokenFreezeV1 as u8 {
+            eprintln!("Found Money::FreezeV1 in call {}", i);
+            let params: MoneyTokenFreezeParamsV1 = deserialize(&call.data[1..])?;
+            let token_id = TokenId::derive_public(params.signature_public);
+            freezes.push(token_id);
+        }
+    }
+
+    Ok((nullifiers, outputs, freezes))
+}
@@ -580,7 +580,8 @@ impl Faucetd {
         params.encode(&mut data).unwrap();
         let calls = vec![ContractCall { contract_id: cid, data }];
         let proofs = vec![secrets.proofs];
-        let mut tx = Transaction { calls, proofs, signatures: vec![] };
+        let mut tx =
+            Transaction { calls, valid_from: None, valid_until: None, proofs, signatures: vec![] };
         let sigs = tx.create_sigs(&mut OsRng, &secrets.signature_secrets).unwrap();
         tx.signatures = vec![sigs];
 
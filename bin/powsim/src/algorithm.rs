@@ -0,0 +1,183 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::VecDeque;
+
+use darkfi::{blockchain::Blockchain, validator::pow::PoWModule, Result};
+use num_bigint::BigUint;
+
+/// Number of most recent blocks LWMA uses for next difficulty calculation
+const LWMA_WINDOW: usize = 90;
+/// Number of blocks ASERT difficulty takes to double or halve, when
+/// blocks are ahead or behind schedule by that many blocks
+const ASERT_HALF_LIFE: u64 = 288;
+/// Fixed point precision used for ASERT fractional exponents
+const ASERT_RADIX_BITS: u32 = 32;
+
+/// A difficulty adjustment algorithm, fed with each mined block so it
+/// can compute the difficulty of the next one.
+pub trait DifficultyAlgorithm {
+    /// Algorithm name, used in the CSV output
+    fn name(&self) -> &'static str;
+
+    /// Compute the difficulty of the next block
+    fn next_difficulty(&self) -> Result<BigUint>;
+
+    /// Append a mined block timestamp and difficulty
+    fn append(&mut self, timestamp: u64, difficulty: &BigUint);
+}
+
+/// The windowed algorithm `PoWModule` currently uses, cutting outliers
+/// from the sorted timestamps window.
+pub struct Current(PoWModule);
+
+impl Current {
+    pub fn new(target: usize) -> Result<Self> {
+        let sled_db = sled::Config::new().temporary(true).open()?;
+        Ok(Self(PoWModule::new(Blockchain::new(&sled_db)?, target, None)?))
+    }
+}
+
+impl DifficultyAlgorithm for Current {
+    fn name(&self) -> &'static str {
+        "current"
+    }
+
+    fn next_difficulty(&self) -> Result<BigUint> {
+        self.0.next_difficulty()
+    }
+
+    fn append(&mut self, timestamp: u64, difficulty: &BigUint) {
+        self.0.append(timestamp, difficulty)
+    }
+}
+
+/// Linearly Weighted Moving Average: the average difficulty of the window,
+/// scaled by the ratio of the target to the window solve times average,
+/// with the most recent solve times weighted the most.
+pub struct Lwma {
+    target: u64,
+    initial: BigUint,
+    /// Most recent blocks (timestamp, difficulty), oldest first
+    blocks: VecDeque<(u64, BigUint)>,
+}
+
+impl Lwma {
+    pub fn new(target: u64, initial: BigUint) -> Self {
+        Self { target, initial, blocks: VecDeque::with_capacity(LWMA_WINDOW + 1) }
+    }
+}
+
+impl DifficultyAlgorithm for Lwma {
+    fn name(&self) -> &'static str {
+        "lwma"
+    }
+
+    fn next_difficulty(&self) -> Result<BigUint> {
+        if self.blocks.len() < 2 {
+            return Ok(self.initial.clone())
+        }
+
+        // Weighted sum of the solve times, clamped so a single bogus
+        // timestamp can't skew the result too much
+        let n = (self.blocks.len() - 1) as u64;
+        let mut weighted_times = 0_u64;
+        let mut total_difficulty = BigUint::from(0_u8);
+        for (i, window) in self.blocks.iter().collect::<Vec<_>>().windows(2).enumerate() {
+            let solve_time = window[1].0.saturating_sub(window[0].0).min(6 * self.target);
+            weighted_times += (i as u64 + 1) * solve_time;
+            total_difficulty += &window[1].1;
+        }
+        let weighted_times = weighted_times.max(n * n * self.target / 20).max(1);
+
+        // next = average difficulty * target / weighted average solve time
+        let next = total_difficulty * self.target * (n + 1) / (2 * weighted_times);
+        Ok(next.max(BigUint::from(1_u8)))
+    }
+
+    fn append(&mut self, timestamp: u64, difficulty: &BigUint) {
+        if self.blocks.len() > LWMA_WINDOW {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back((timestamp, difficulty.clone()));
+    }
+}
+
+/// Absolutely Scheduled Exponentially Rising Targets: the difficulty
+/// exponentially follows how far ahead or behind the ideal schedule
+/// the chain is, relative to an anchor block.
+pub struct Asert {
+    target: u64,
+    /// Anchor block difficulty
+    initial: BigUint,
+    /// Anchor block timestamp
+    anchor: Option<u64>,
+    /// Number of blocks appended after the anchor
+    height: u64,
+    /// Last block timestamp
+    last: u64,
+}
+
+impl Asert {
+    pub fn new(target: u64, initial: BigUint) -> Self {
+        Self { target, initial, anchor: None, height: 0, last: 0 }
+    }
+}
+
+impl DifficultyAlgorithm for Asert {
+    fn name(&self) -> &'static str {
+        "asert"
+    }
+
+    fn next_difficulty(&self) -> Result<BigUint> {
+        let Some(anchor) = self.anchor else { return Ok(self.initial.clone()) };
+
+        // Exponent is the schedule deviation, denominated in half lifes,
+        // as a fixed point number
+        let ideal = (self.target * (self.height + 1)) as i128;
+        let elapsed = self.last as i128 - anchor as i128;
+        let half_life = (self.target * ASERT_HALF_LIFE) as i128;
+        let exponent = ((ideal - elapsed) << ASERT_RADIX_BITS) / half_life;
+
+        // Split it into its integer part, applied as a shift, and its
+        // fractional part, applied as a fixed point factor
+        let shifts = exponent >> ASERT_RADIX_BITS;
+        let fraction =
+            (exponent - (shifts << ASERT_RADIX_BITS)) as f64 / (1_u64 << ASERT_RADIX_BITS) as f64;
+        let factor = (2_f64.powf(fraction) * (1_u64 << ASERT_RADIX_BITS) as f64) as u64;
+
+        // Difficulties can't exceed 256 bits anyway, so we bound the shifts
+        let mut next = &self.initial * factor;
+        let shifts = shifts - ASERT_RADIX_BITS as i128;
+        if shifts >= 0 {
+            next <<= shifts.min(256) as usize;
+        } else {
+            next >>= (-shifts).min(512) as usize;
+        }
+
+        Ok(next.max(BigUint::from(1_u8)))
+    }
+
+    fn append(&mut self, timestamp: u64, _difficulty: &BigUint) {
+        match self.anchor {
+            Some(_) => self.height += 1,
+            None => self.anchor = Some(timestamp),
+        }
+        self.last = timestamp;
+    }
+}
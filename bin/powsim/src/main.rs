@@ -0,0 +1,334 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fs::File,
+    io::{stdout, BufWriter, Write},
+    process::ExitCode,
+};
+
+use arg::Args;
+use darkfi::{blockchain::Blockchain, util::path::expand_path, Result, ANSI_LOGO};
+use num_bigint::BigUint;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+mod algorithm;
+use algorithm::{Asert, Current, DifficultyAlgorithm, Lwma};
+
+#[cfg(test)]
+mod tests;
+
+const ABOUT: &str =
+    concat!("powsim ", env!("CARGO_PKG_VERSION"), '\n', env!("CARGO_PKG_DESCRIPTION"));
+
+const USAGE: &str = r#"
+Usage: powsim [OPTIONS]
+
+Options:
+  -a <ALGOS>     Comma separated algorithms to run (default: current,lwma,asert)
+  -t <SECONDS>   Target block time (default: 90)
+  -s <SCHEDULE>  Hashrate schedule, as comma separated <BLOCKS>:<HASHRATE>
+                 segments (default: 2000:1000,2000:10000,2000:1000)
+  -d <DIFF>      Initial difficulty of the algorithms that need one
+                 (default: target times first hashrate, or first replayed)
+  -n <SEED>      Block times RNG seed (default: 0)
+  -r <DB>        Replay the difficulty store of the blockchain database at <DB>
+  -o <FILE>      Write the CSV output to <FILE> instead of stdout
+  -h             Print this help
+"#;
+
+/// CSV output header
+const CSV_HEADER: &str = "algorithm,height,timestamp,block_time,hashrate,difficulty";
+
+/// A mined block, as written in the CSV output
+struct Row {
+    height: u64,
+    timestamp: u64,
+    block_time: u64,
+    /// Network hashrate while the block was mined, if simulated
+    hashrate: Option<f64>,
+    difficulty: BigUint,
+}
+
+fn usage() {
+    print!("{}{}\n{}", ANSI_LOGO, ABOUT, USAGE);
+}
+
+/// Parse a hashrate schedule into its (blocks, hashrate) segments
+fn parse_schedule(schedule: &str) -> Option<Vec<(u64, f64)>> {
+    let mut segments = vec![];
+    for segment in schedule.split(',') {
+        let (blocks, hashrate) = segment.split_once(':')?;
+        let hashrate: f64 = hashrate.parse().ok()?;
+        if hashrate <= 0.0 {
+            return None
+        }
+        segments.push((blocks.parse().ok()?, hashrate));
+    }
+    Some(segments)
+}
+
+/// Initialize the requested algorithms
+fn algorithms(
+    names: &str,
+    target: u64,
+    initial: &BigUint,
+) -> Result<Option<Vec<Box<dyn DifficultyAlgorithm>>>> {
+    let mut algorithms: Vec<Box<dyn DifficultyAlgorithm>> = vec![];
+    for name in names.split(',') {
+        match name {
+            "current" => algorithms.push(Box::new(Current::new(target as usize)?)),
+            "lwma" => algorithms.push(Box::new(Lwma::new(target, initial.clone()))),
+            "asert" => algorithms.push(Box::new(Asert::new(target, initial.clone()))),
+            _ => {
+                eprintln!("Error: Unknown algorithm \"{}\"", name);
+                return Ok(None)
+            }
+        }
+    }
+    Ok(Some(algorithms))
+}
+
+/// Read the difficulty store records of the blockchain database at
+/// provided path, as rows ordered by height.
+fn load_difficulties(path: &str) -> Result<Vec<Row>> {
+    let sled_db = sled::open(expand_path(path)?)?;
+    let blockchain = Blockchain::new(&sled_db)?;
+    let mut records = blockchain.difficulties.get_all()?;
+    records.sort_by_key(|(height, _)| *height);
+
+    let mut last = records.first().map_or(0, |(_, d)| d.timestamp);
+    let mut rows = Vec::with_capacity(records.len());
+    for (height, d) in records {
+        let block_time = d.timestamp.saturating_sub(last);
+        last = d.timestamp;
+        rows.push(Row {
+            height,
+            timestamp: d.timestamp,
+            block_time,
+            hashrate: None,
+            difficulty: d.difficulty,
+        });
+    }
+
+    Ok(rows)
+}
+
+/// Mine blocks with provided algorithm, following the hashrate schedule.
+/// Block solve times are exponentially distributed, with mean the
+/// expected number of hashes, which is the difficulty, over the hashrate.
+fn simulate(
+    algorithm: &mut dyn DifficultyAlgorithm,
+    schedule: &[(u64, f64)],
+    seed: u64,
+) -> Result<Vec<Row>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut rows = vec![];
+    let mut time = 0.0;
+    let mut last = 0;
+    let mut height = 0;
+
+    for (blocks, hashrate) in schedule {
+        for _ in 0..*blocks {
+            let difficulty = algorithm.next_difficulty()?;
+
+            // Lossy conversion is fine, as we only need the magnitude
+            let expected_hashes: f64 = difficulty.to_string().parse().unwrap();
+            time += -(1.0 - rng.gen::<f64>()).ln() * expected_hashes / hashrate;
+
+            let timestamp = time as u64;
+            algorithm.append(timestamp, &difficulty);
+            rows.push(Row {
+                height,
+                timestamp,
+                block_time: timestamp - last,
+                hashrate: Some(*hashrate),
+                difficulty,
+            });
+            last = timestamp;
+            height += 1;
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Replay the recorded blocks with provided algorithm, returning the
+/// difficulty it would have computed for each one.
+fn replay(algorithm: &mut dyn DifficultyAlgorithm, recorded: &[Row]) -> Result<Vec<Row>> {
+    let mut rows = Vec::with_capacity(recorded.len());
+    for row in recorded {
+        let difficulty = algorithm.next_difficulty()?;
+        algorithm.append(row.timestamp, &row.difficulty);
+        rows.push(Row { difficulty, ..*row });
+    }
+
+    Ok(rows)
+}
+
+/// Print the block times summary of provided rows to stderr
+fn summary(name: &str, rows: &[Row]) {
+    if rows.is_empty() {
+        return
+    }
+
+    let times: Vec<f64> = rows.iter().map(|row| row.block_time as f64).collect();
+    let mean = times.iter().sum::<f64>() / times.len() as f64;
+    let variance = times.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / times.len() as f64;
+    let max = times.iter().copied().fold(0.0, f64::max);
+    eprintln!(
+        "{:<8} mean block time: {:.2}s, std deviation: {:.2}s, max: {}s",
+        name,
+        mean,
+        variance.sqrt(),
+        max
+    );
+}
+
+/// Write provided rows as CSV
+fn write_rows(output: &mut dyn Write, name: &str, rows: &[Row]) -> std::io::Result<()> {
+    for row in rows {
+        let hashrate = row.hashrate.map_or(String::new(), |h| h.to_string());
+        writeln!(
+            output,
+            "{},{},{},{},{},{}",
+            name, row.height, row.timestamp, row.block_time, hashrate, row.difficulty
+        )?;
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut hflag = false;
+    let mut algos = String::from("current,lwma,asert");
+    let mut target = String::from("90");
+    let mut schedule = String::from("2000:1000,2000:10000,2000:1000");
+    let mut initial = String::new();
+    let mut seed = String::from("0");
+    let mut db = String::new();
+    let mut output_file = String::new();
+
+    {
+        let mut args = Args::new().with_cb(|args, flag| match flag {
+            'a' => algos = args.eargf().to_string(),
+            't' => target = args.eargf().to_string(),
+            's' => schedule = args.eargf().to_string(),
+            'd' => initial = args.eargf().to_string(),
+            'n' => seed = args.eargf().to_string(),
+            'r' => db = args.eargf().to_string(),
+            'o' => output_file = args.eargf().to_string(),
+            _ => hflag = true,
+        });
+
+        args.parse();
+    }
+
+    let (Ok(target), Ok(seed)) = (target.parse::<u64>(), seed.parse::<u64>()) else {
+        usage();
+        return ExitCode::FAILURE
+    };
+    let Some(schedule) = parse_schedule(&schedule) else {
+        eprintln!("Error: Invalid hashrate schedule");
+        return ExitCode::FAILURE
+    };
+    if hflag || target == 0 || schedule.is_empty() {
+        usage();
+        return ExitCode::FAILURE
+    }
+
+    // Grab the recorded blocks, when replaying
+    let recorded = if db.is_empty() {
+        None
+    } else {
+        match load_difficulties(&db) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                eprintln!("Error: Failed reading difficulties from \"{}\". {}", db, e);
+                return ExitCode::FAILURE
+            }
+        }
+    };
+
+    let initial = if !initial.is_empty() {
+        let Ok(initial) = initial.parse::<BigUint>() else {
+            usage();
+            return ExitCode::FAILURE
+        };
+        initial
+    } else if let Some(recorded) = &recorded {
+        // Genesis difficulty is meaningless, so we use the first mined one
+        recorded.get(1).or(recorded.first()).map_or(BigUint::from(1_u8), |r| r.difficulty.clone())
+    } else {
+        BigUint::from((target as f64 * schedule[0].1).max(1.0) as u64)
+    };
+
+    let mut algorithms = match algorithms(&algos, target, &initial) {
+        Ok(Some(v)) => v,
+        Ok(None) => return ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("Error: Failed initializing algorithms. {}", e);
+            return ExitCode::FAILURE
+        }
+    };
+
+    let mut output: Box<dyn Write> = if output_file.is_empty() {
+        Box::new(BufWriter::new(stdout()))
+    } else {
+        match File::create(&output_file) {
+            Ok(v) => Box::new(BufWriter::new(v)),
+            Err(e) => {
+                eprintln!("Error: Failed creating \"{}\". {}", output_file, e);
+                return ExitCode::FAILURE
+            }
+        }
+    };
+
+    let mut res = writeln!(output, "{}", CSV_HEADER);
+    if let Some(recorded) = &recorded {
+        summary("recorded", recorded);
+        res = res.and_then(|_| write_rows(&mut output, "recorded", recorded));
+    }
+
+    for algorithm in algorithms.iter_mut() {
+        let rows = match &recorded {
+            Some(recorded) => replay(algorithm.as_mut(), recorded),
+            None => simulate(algorithm.as_mut(), &schedule, seed),
+        };
+        let rows = match rows {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Error: Algorithm \"{}\" failed. {}", algorithm.name(), e);
+                return ExitCode::FAILURE
+            }
+        };
+
+        // Replayed block times don't depend on the algorithm
+        if recorded.is_none() {
+            summary(algorithm.name(), &rows);
+        }
+        res = res.and_then(|_| write_rows(&mut output, algorithm.name(), &rows));
+    }
+
+    if let Err(e) = res.and_then(|_| output.flush()) {
+        eprintln!("Error: Failed writing CSV output. {}", e);
+        return ExitCode::FAILURE
+    }
+
+    ExitCode::SUCCESS
+}
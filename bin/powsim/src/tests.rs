@@ -0,0 +1,115 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use num_bigint::BigUint;
+
+use super::{parse_schedule, replay, simulate, Row};
+use crate::algorithm::{Asert, Current, DifficultyAlgorithm, Lwma};
+
+/// Target block time used in tests
+const TARGET: u64 = 90;
+
+#[test]
+fn schedules() {
+    assert_eq!(parse_schedule("10:1.5,5:2"), Some(vec![(10, 1.5), (5, 2.0)]));
+    assert_eq!(parse_schedule("10"), None);
+    assert_eq!(parse_schedule("10:0"), None);
+    assert_eq!(parse_schedule("x:1"), None);
+}
+
+#[test]
+fn algorithms_converge() -> darkfi::Result<()> {
+    // The current algorithm starts from difficulty 1, so we only
+    // verify it can be driven for the whole schedule
+    let hashrate = 1000.0;
+    let rows = simulate(&mut Current::new(TARGET as usize)?, &[(3000, hashrate)], 0)?;
+    assert_eq!(rows.len(), 3000);
+    assert!(rows.iter().all(|r| r.difficulty > BigUint::from(0_u8)));
+
+    // Under a constant hashrate, block times should average the target
+    let initial = BigUint::from(TARGET * hashrate as u64);
+    let mut algorithms: Vec<Box<dyn DifficultyAlgorithm>> =
+        vec![Box::new(Lwma::new(TARGET, initial.clone())), Box::new(Asert::new(TARGET, initial))];
+
+    for algorithm in algorithms.iter_mut() {
+        let rows = simulate(algorithm.as_mut(), &[(3000, hashrate)], 0)?;
+        assert_eq!(rows.len(), 3000);
+
+        let last = &rows[2000..];
+        let mean = last.iter().map(|r| r.block_time).sum::<u64>() as f64 / last.len() as f64;
+        assert!(
+            (mean - TARGET as f64).abs() < TARGET as f64 * 0.15,
+            "{} mean block time: {}",
+            algorithm.name(),
+            mean
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn asert_follows_schedule() -> darkfi::Result<()> {
+    let initial = BigUint::from(1000_u64);
+    let mut asert = Asert::new(TARGET, initial.clone());
+    assert_eq!(asert.next_difficulty()?, initial);
+
+    // Blocks on schedule keep the anchor difficulty
+    for height in 0..10 {
+        asert.append(height * TARGET, &initial);
+    }
+    assert_eq!(asert.next_difficulty()?, initial);
+
+    // Blocks a half life ahead of schedule double it
+    let mut asert = Asert::new(TARGET, initial.clone());
+    for _ in 0..289 {
+        asert.append(0, &initial);
+    }
+    assert_eq!(asert.next_difficulty()?, BigUint::from(2000_u64));
+
+    Ok(())
+}
+
+#[test]
+fn replays() -> darkfi::Result<()> {
+    // Steady chain of blocks mined on target
+    let difficulty = BigUint::from(5000_u64);
+    let recorded: Vec<Row> = (0..200)
+        .map(|height| Row {
+            height,
+            timestamp: height * TARGET,
+            block_time: if height == 0 { 0 } else { TARGET },
+            hashrate: None,
+            difficulty: difficulty.clone(),
+        })
+        .collect();
+
+    let rows = replay(&mut Lwma::new(TARGET, BigUint::from(1_u8)), &recorded)?;
+    assert_eq!(rows.len(), recorded.len());
+    for (row, record) in rows.iter().zip(&recorded) {
+        assert_eq!(
+            (row.height, row.timestamp, row.block_time),
+            (record.height, record.timestamp, record.block_time)
+        );
+    }
+
+    // Replayed difficulty matches the steady one
+    assert_eq!(rows.last().unwrap().difficulty, difficulty);
+
+    Ok(())
+}
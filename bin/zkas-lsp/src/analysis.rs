@@ -0,0 +1,296 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{io, panic};
+
+use darkfi::zkas::{Analyzer, Lexer, Opcode, Parser, VarType};
+use lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
+
+/// Prefix the parser uses for the heap variables it creates when
+/// flattening nested function calls. These are not user-visible.
+const INNER_VAR_PREFIX: &str = "_op_inner_";
+
+/// Kind of a symbol declared in a zkas source file
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Constant,
+    Witness,
+    Variable,
+}
+
+impl SymbolKind {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Constant => "constant",
+            Self::Witness => "witness",
+            Self::Variable => "variable",
+        }
+    }
+}
+
+/// A named value that can be referenced inside the circuit section
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub typ: VarType,
+    /// Position of the declaration in LSP coordinates
+    pub range: Range,
+}
+
+/// Result of running the zkas frontend over a document
+#[derive(Default)]
+pub struct Analysis {
+    pub diagnostics: Vec<Diagnostic>,
+    pub symbols: Vec<Symbol>,
+}
+
+impl Analysis {
+    /// Find the symbol declared with the given name
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+}
+
+/// Run the lexer, parser and type analyzer over the given source and
+/// gather diagnostics and declared symbols. On failure, the symbols
+/// collected up to the failing stage are still returned.
+///
+/// The zkas frontend is written for complete source files and can panic
+/// on some partial inputs that show up while typing. Such panics are
+/// caught here and reported as a diagnostic so the server stays alive.
+pub fn analyze(filename: &str, text: &str) -> Analysis {
+    match panic::catch_unwind(|| run_frontend(filename, text)) {
+        Ok(analysis) => analysis,
+        Err(payload) => {
+            let reason = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown error".to_string());
+
+            let diagnostic = Diagnostic {
+                range: Range::default(),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("zkas".to_string()),
+                message: format!("Internal zkas error on incomplete source: {}", reason),
+                ..Default::default()
+            };
+
+            Analysis { diagnostics: vec![diagnostic], symbols: vec![] }
+        }
+    }
+}
+
+fn run_frontend(filename: &str, text: &str) -> Analysis {
+    let mut analysis = Analysis::default();
+
+    // zkas works on the source with tabs expanded, and all reported
+    // columns refer to that form. We keep the original text around to
+    // map the columns back into editor coordinates.
+    let source = text.replace('\t', "    ").replace("\r\n", "\n");
+
+    let lexer = Lexer::new(filename, source.chars());
+    let tokens = match lexer.lex() {
+        Ok(v) => v,
+        Err(e) => {
+            analysis.diagnostics.push(to_diagnostic(text, &e));
+            return analysis
+        }
+    };
+
+    let parser = Parser::new(filename, source.chars(), tokens);
+    let (_namespace, _k, constants, witnesses, statements) = match parser.parse() {
+        Ok(v) => v,
+        Err(e) => {
+            analysis.diagnostics.push(to_diagnostic(text, &e));
+            return analysis
+        }
+    };
+
+    let mut analyzer = Analyzer::new(filename, source.chars(), constants, witnesses, statements);
+    if let Err(e) = analyzer.analyze_types() {
+        analysis.diagnostics.push(to_diagnostic(text, &e));
+    }
+
+    for c in &analyzer.constants {
+        analysis.symbols.push(Symbol {
+            name: c.name.clone(),
+            kind: SymbolKind::Constant,
+            typ: c.typ,
+            range: word_range(text, c.line, c.column),
+        });
+    }
+
+    for w in &analyzer.witnesses {
+        analysis.symbols.push(Symbol {
+            name: w.name.clone(),
+            kind: SymbolKind::Witness,
+            typ: w.typ,
+            range: word_range(text, w.line, w.column),
+        });
+    }
+
+    for v in &analyzer.heap {
+        if v.name.starts_with(INNER_VAR_PREFIX) {
+            continue
+        }
+
+        analysis.symbols.push(Symbol {
+            name: v.name.clone(),
+            kind: SymbolKind::Variable,
+            typ: v.typ,
+            range: word_range(text, v.line, v.column),
+        });
+    }
+
+    analysis
+}
+
+/// Return a human-readable signature of an opcode, e.g.
+/// `ec_add(EcPoint, EcPoint) -> EcPoint`
+pub fn opcode_signature(opcode: &Opcode) -> String {
+    let (return_types, arg_types) = opcode.arg_types();
+
+    let args: Vec<String> = arg_types
+        .iter()
+        .map(|t| match t {
+            VarType::BaseArray => "Base, ...".to_string(),
+            VarType::ScalarArray => "Scalar, ...".to_string(),
+            _ => t.name().to_string(),
+        })
+        .collect();
+
+    let mut sig = format!("{}({})", opcode.name(), args.join(", "));
+    if let Some(ret) = return_types.first() {
+        sig.push_str(&format!(" -> {}", ret.name()));
+    }
+
+    sig
+}
+
+/// Return all opcodes usable in zkas source code
+pub fn opcodes() -> Vec<Opcode> {
+    (0..=u8::MAX).filter_map(Opcode::from_repr).collect()
+}
+
+/// Find the identifier under the given editor position, returning it
+/// along with its range.
+pub fn word_at(text: &str, pos: Position) -> Option<(String, Range)> {
+    let line = text.lines().nth(pos.line as usize)?;
+    let chars: Vec<char> = line.chars().collect();
+
+    // Convert the UTF-16 offset into a char index.
+    let mut idx = 0;
+    let mut utf16 = 0;
+    while idx < chars.len() && utf16 < pos.character as usize {
+        utf16 += chars[idx].len_utf16();
+        idx += 1;
+    }
+
+    let is_ident = |c: &char| c.is_ascii_alphanumeric() || *c == '_';
+
+    let mut start = idx;
+    while start > 0 && is_ident(&chars[start - 1]) {
+        start -= 1;
+    }
+
+    let mut end = idx;
+    while end < chars.len() && is_ident(&chars[end]) {
+        end += 1;
+    }
+
+    if start == end {
+        return None
+    }
+
+    let word: String = chars[start..end].iter().collect();
+    let range = Range::new(
+        Position::new(pos.line, utf16_len(&chars[..start])),
+        Position::new(pos.line, utf16_len(&chars[..end])),
+    );
+
+    Some((word, range))
+}
+
+/// Convert a zkas error into an LSP diagnostic.
+/// The zkas error emitter formats the first line of its messages as
+/// `<msg> (line <ln>, column <col>)`, or just `<msg>` when the error
+/// can't be attributed to a location.
+fn to_diagnostic(text: &str, err: &io::Error) -> Diagnostic {
+    let err = err.to_string();
+    let first = err.lines().next().unwrap_or_default();
+
+    let (msg, range) = match parse_location(first) {
+        Some((msg, ln, col)) => (msg, word_range(text, ln, col)),
+        None => (first, Range::default()),
+    };
+
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("zkas".to_string()),
+        message: msg.to_string(),
+        ..Default::default()
+    }
+}
+
+fn parse_location(line: &str) -> Option<(&str, usize, usize)> {
+    let idx = line.rfind(" (line ")?;
+    let loc = line[idx + 7..].strip_suffix(')')?;
+    let (ln, col) = loc.split_once(", column ")?;
+    Some((&line[..idx], ln.parse().ok()?, col.parse().ok()?))
+}
+
+/// Map a 1-indexed zkas line/column (with tabs expanded to four spaces)
+/// onto the range of the word starting there in the original text.
+/// Falls back to a single character when there is no word.
+fn word_range(text: &str, line: usize, column: usize) -> Range {
+    if line == 0 {
+        return Range::default()
+    }
+
+    let Some(src_line) = text.lines().nth(line - 1) else { return Range::default() };
+    let chars: Vec<char> = src_line.chars().collect();
+
+    // Walk the original line until we reach the expanded column.
+    let mut idx = 0;
+    let mut expanded = 1;
+    while idx < chars.len() && expanded < column {
+        expanded += if chars[idx] == '\t' { 4 } else { 1 };
+        idx += 1;
+    }
+
+    let mut end = idx;
+    while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '_') {
+        end += 1;
+    }
+    if end == idx && end < chars.len() {
+        end += 1;
+    }
+
+    let ln = (line - 1) as u32;
+    Range::new(
+        Position::new(ln, utf16_len(&chars[..idx])),
+        Position::new(ln, utf16_len(&chars[..end])),
+    )
+}
+
+fn utf16_len(chars: &[char]) -> u32 {
+    chars.iter().map(|c| c.len_utf16() as u32).sum()
+}
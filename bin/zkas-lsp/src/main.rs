@@ -0,0 +1,251 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{collections::HashMap, error::Error, process::ExitCode};
+
+use darkfi::zkas::Opcode;
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as LspNotification, PublishDiagnostics,
+    },
+    request::{Completion, GotoDefinition, HoverRequest, Request as LspRequest},
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf, PublishDiagnosticsParams,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+
+/// zkas frontend wrapper producing diagnostics and symbols
+mod analysis;
+use analysis::{analyze, opcode_signature, opcodes, word_at, Analysis};
+
+#[cfg(test)]
+mod tests;
+
+type ServerResult<T> = Result<T, Box<dyn Error + Sync + Send>>;
+
+/// Open documents and their latest analysis
+#[derive(Default)]
+struct State {
+    documents: HashMap<Url, (String, Analysis)>,
+}
+
+impl State {
+    /// Store the given document text, analyze it, and publish diagnostics.
+    fn update(&mut self, conn: &Connection, uri: Url, text: String) -> ServerResult<()> {
+        let analysis = analyze(uri.path(), &text);
+
+        let params = PublishDiagnosticsParams::new(uri.clone(), analysis.diagnostics.clone(), None);
+        conn.sender.send(Message::Notification(Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        )))?;
+
+        self.documents.insert(uri, (text, analysis));
+        Ok(())
+    }
+
+    fn close(&mut self, conn: &Connection, uri: Url) -> ServerResult<()> {
+        self.documents.remove(&uri);
+
+        let params = PublishDiagnosticsParams::new(uri, vec![], None);
+        conn.sender.send(Message::Notification(Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        )))?;
+
+        Ok(())
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let pos = params.text_document_position_params;
+        let (text, analysis) = self.documents.get(&pos.text_document.uri)?;
+        let (word, range) = word_at(text, pos.position)?;
+
+        let value = if let Some(symbol) = analysis.lookup(&word) {
+            format!("```zkas\n{} {}: {}\n```", symbol.kind.name(), symbol.name, symbol.typ.name())
+        } else if let Some(opcode) = Opcode::from_name(&word) {
+            format!("```zkas\n{}\n```", opcode_signature(&opcode))
+        } else {
+            return None
+        };
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value }),
+            range: Some(range),
+        })
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let pos = params.text_document_position_params;
+        let (text, analysis) = self.documents.get(&pos.text_document.uri)?;
+        let (word, _) = word_at(text, pos.position)?;
+        let symbol = analysis.lookup(&word)?;
+
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            pos.text_document.uri.clone(),
+            symbol.range,
+        )))
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let uri = &params.text_document_position.text_document.uri;
+
+        let mut items: Vec<CompletionItem> = opcodes()
+            .iter()
+            .map(|op| CompletionItem {
+                label: op.name().to_string(),
+                kind: Some(CompletionItemKind::FUNCTION),
+                detail: Some(opcode_signature(op)),
+                ..Default::default()
+            })
+            .collect();
+
+        if let Some((_, analysis)) = self.documents.get(uri) {
+            for symbol in &analysis.symbols {
+                items.push(CompletionItem {
+                    label: symbol.name.clone(),
+                    kind: Some(CompletionItemKind::VARIABLE),
+                    detail: Some(format!("{} {}", symbol.kind.name(), symbol.typ.name())),
+                    ..Default::default()
+                });
+            }
+        }
+
+        Some(CompletionResponse::Array(items))
+    }
+}
+
+fn handle_request(state: &State, conn: &Connection, req: Request) -> ServerResult<()> {
+    let method = req.method.clone();
+    let resp = match method.as_str() {
+        HoverRequest::METHOD => {
+            let (id, params) = req.extract::<HoverParams>(HoverRequest::METHOD)?;
+            Response::new_ok(id, state.hover(params))
+        }
+
+        GotoDefinition::METHOD => {
+            let (id, params) = req.extract::<GotoDefinitionParams>(GotoDefinition::METHOD)?;
+            Response::new_ok(id, state.definition(params))
+        }
+
+        Completion::METHOD => {
+            let (id, params) = req.extract::<CompletionParams>(Completion::METHOD)?;
+            Response::new_ok(id, state.completion(params))
+        }
+
+        _ => method_not_found(req.id, &method),
+    };
+
+    conn.sender.send(Message::Response(resp))?;
+    Ok(())
+}
+
+fn handle_notification(
+    state: &mut State,
+    conn: &Connection,
+    not: Notification,
+) -> ServerResult<()> {
+    let method = not.method.clone();
+    match method.as_str() {
+        DidOpenTextDocument::METHOD => {
+            let params: DidOpenTextDocumentParams = not.extract(DidOpenTextDocument::METHOD)?;
+            state.update(conn, params.text_document.uri, params.text_document.text)?;
+        }
+
+        DidChangeTextDocument::METHOD => {
+            let params: DidChangeTextDocumentParams = not.extract(DidChangeTextDocument::METHOD)?;
+            // We advertise full document sync, so the last change holds the
+            // whole new text.
+            if let Some(change) = params.content_changes.into_iter().last() {
+                state.update(conn, params.text_document.uri, change.text)?;
+            }
+        }
+
+        DidCloseTextDocument::METHOD => {
+            let params: DidCloseTextDocumentParams = not.extract(DidCloseTextDocument::METHOD)?;
+            state.close(conn, params.text_document.uri)?;
+        }
+
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn method_not_found(id: RequestId, method: &str) -> Response {
+    Response::new_err(
+        id,
+        lsp_server::ErrorCode::MethodNotFound as i32,
+        format!("Unhandled method: {}", method),
+    )
+}
+
+fn run(conn: &Connection) -> ServerResult<()> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        ..Default::default()
+    };
+
+    conn.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut state = State::default();
+
+    for msg in &conn.receiver {
+        match msg {
+            Message::Request(req) => {
+                if conn.handle_shutdown(&req)? {
+                    return Ok(())
+                }
+                handle_request(&state, conn, req)?;
+            }
+            Message::Notification(not) => handle_notification(&mut state, conn, not)?,
+            Message::Response(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    // The zkas error emitter writes colored reports to stderr. We turn
+    // them into diagnostics instead, so keep it quiet.
+    std::env::set_var("ZKAS_SILENT", "1");
+
+    // The server talks LSP over stdin/stdout.
+    let (conn, io_threads) = Connection::stdio();
+
+    if let Err(e) = run(&conn) {
+        eprintln!("Error: {}", e);
+        return ExitCode::FAILURE
+    }
+
+    drop(conn);
+    if let Err(e) = io_threads.join() {
+        eprintln!("Error: {}", e);
+        return ExitCode::FAILURE
+    }
+
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,58 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::analysis::{analyze, SymbolKind};
+
+const SOURCE: &str = include_str!("../../../proof/arithmetic.zk");
+
+#[test]
+fn complete_source() {
+    let analysis = analyze("arithmetic.zk", SOURCE);
+    assert!(analysis.diagnostics.is_empty());
+
+    assert_eq!(analysis.lookup("a").unwrap().kind, SymbolKind::Witness);
+    assert_eq!(analysis.lookup("sum").unwrap().kind, SymbolKind::Variable);
+    assert!(analysis.lookup("product").is_some());
+}
+
+#[test]
+fn partial_source() {
+    std::env::set_var("ZKAS_SILENT", "1");
+
+    // Simulate typing the file out one character at a time. No
+    // intermediate state may take the server down, and every
+    // incomplete one has to be reported.
+    let chars: Vec<char> = SOURCE.chars().collect();
+    for i in 0..chars.len() {
+        let text: String = chars[..i].iter().collect();
+        let analysis = analyze("arithmetic.zk", &text);
+        if text.trim_end() != SOURCE.trim_end() {
+            assert!(!analysis.diagnostics.is_empty(), "no diagnostics for {:?}", text);
+        }
+    }
+}
+
+#[test]
+fn unopened_section() {
+    std::env::set_var("ZKAS_SILENT", "1");
+
+    let text = "k = 13;\nfield = \"pallas\";\n\nconstant \"Arith\"";
+    let analysis = analyze("arithmetic.zk", text);
+    assert_eq!(analysis.diagnostics.len(), 1);
+    assert!(analysis.symbols.is_empty());
+}
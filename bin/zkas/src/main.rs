@@ -25,7 +25,11 @@ use std::{
 use arg::Args;
 
 use darkfi::{
-    zkas::{Analyzer, Compiler, Lexer, Parser, ZkBinary},
+    zk::cost::CircuitCost,
+    zkas::{
+        ast::{Statement, Var},
+        Analyzer, Compiler, Lexer, Parser, ZkBinary,
+    },
     ANSI_LOGO,
 };
 
@@ -44,6 +48,7 @@ Options:
   -p         Preprocess only; do not compile
   -i         Interactive semantic analysis
   -e         Examine decoded bytecode
+  -r         Report circuit cost and optimization hints; do not write output
   -h         Print this help
 "#;
 
@@ -51,12 +56,79 @@ fn usage() {
     print!("{}{}\n{}", ANSI_LOGO, ABOUT, USAGE);
 }
 
+/// Print the estimated row usage, required `k`, and gas cost of the given
+/// circuit, along with any unused variables and duplicated computations.
+fn report(zkbin: &ZkBinary, dead_vars: &[Var], duplicates: &[(Statement, Statement)]) {
+    let cost = CircuitCost::measure(zkbin);
+
+    // Aggregate rows by opcode, in order of first appearance
+    let mut per_opcode: Vec<(&str, usize, usize)> = vec![];
+    for (opcode, rows) in &cost.opcode_rows {
+        match per_opcode.iter_mut().find(|(name, _, _)| *name == opcode.name()) {
+            Some(entry) => {
+                entry.1 += 1;
+                entry.2 += rows;
+            }
+            None => per_opcode.push((opcode.name(), 1, *rows)),
+        }
+    }
+
+    println!("Circuit \"{}\"\n", zkbin.namespace);
+    println!("{:<24}{:>8}{:>10}", "Opcode", "Count", "Rows");
+    for (name, count, rows) in &per_opcode {
+        println!("{:<24}{:>8}{:>10}", name, count, rows);
+    }
+    println!("{:<24}{:>8}{:>10}", "witnesses", zkbin.witnesses.len(), cost.witness_rows);
+    println!();
+    println!("Estimated rows: {}", cost.total_rows);
+    println!("Estimated minimum k: {} (declared: {})", cost.min_k, zkbin.k);
+    println!("Gas: {}", cost.gas);
+
+    if zkbin.k < cost.min_k {
+        println!(
+            "\nwarning: Declared k = {} may be too low for the estimated {} rows.",
+            zkbin.k, cost.total_rows
+        );
+    }
+
+    for var in dead_vars {
+        let (kind, name, line) = match var {
+            Var::Constant(c) => ("Constant", &c.name, c.line),
+            Var::Witness(w) => ("Witness", &w.name, w.line),
+            Var::Variable(v) => ("Heap variable", &v.name, v.line),
+        };
+        println!("warning: {} `{}` (line {}) is never used.", kind, name, line);
+    }
+
+    for (original, duplicate) in duplicates {
+        // Nested calls get their names generated by the parser, so we
+        // refer to them by opcode instead.
+        let describe = |stmt: &Statement| {
+            let name = &stmt.lhs.as_ref().unwrap().name;
+            if name.starts_with("_op_inner_") {
+                format!("`{}(...)`", stmt.opcode.name())
+            } else {
+                format!("`{}`", name)
+            }
+        };
+
+        println!(
+            "warning: {} (line {}) recomputes {} (line {}).",
+            describe(duplicate),
+            duplicate.line,
+            describe(original),
+            original.line,
+        );
+    }
+}
+
 fn main() -> ExitCode {
     let argv;
     let mut pflag = false;
     let mut iflag = false;
     let mut eflag = false;
     let mut sflag = false;
+    let mut rflag = false;
     let mut hflag = false;
     let mut output = String::new();
 
@@ -66,6 +138,7 @@ fn main() -> ExitCode {
             'i' => iflag = true,
             'e' => eflag = true,
             's' => sflag = true,
+            'r' => rflag = true,
             'o' => output = args.eargf().to_string(),
             _ => hflag = true,
         });
@@ -128,6 +201,14 @@ fn main() -> ExitCode {
         return ExitCode::SUCCESS
     }
 
+    // The compiler takes ownership of the analyzed AST, so we gather
+    // the optimization hints for the report beforehand.
+    let (dead_vars, duplicates) = if rflag {
+        (analyzer.find_dead_vars(), analyzer.find_duplicates())
+    } else {
+        (vec![], vec![])
+    };
+
     let compiler = Compiler::new(
         filename,
         source.chars(),
@@ -146,6 +227,12 @@ fn main() -> ExitCode {
     };
     // ANCHOR_END: zkas
 
+    if rflag {
+        let zkbin = ZkBinary::decode(&bincode).unwrap();
+        report(&zkbin, &dead_vars, &duplicates);
+        return ExitCode::SUCCESS
+    }
+
     let output = if output.is_empty() { format!("{}.bin", filename) } else { output };
 
     let mut file = match File::create(&output) {
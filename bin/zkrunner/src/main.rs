@@ -0,0 +1,279 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fs::{read, read_to_string},
+    io::{stdin, Read},
+    process::ExitCode,
+    time::Instant,
+};
+
+use arg::Args;
+use darkfi::{
+    zk::{
+        empty_witnesses, halo2::dev::MockProver, import_witness_json, witness_json_template,
+        zkas_type_checks, DebugOpValue, Proof, ProvingKey, VerifyingKey, ZkCircuit,
+    },
+    zkas::{compiler::MAGIC_BYTES, Analyzer, Compiler, Lexer, Parser, ZkBinary},
+    ANSI_LOGO,
+};
+use rand::rngs::OsRng;
+
+const ABOUT: &str =
+    concat!("zkrunner ", env!("CARGO_PKG_VERSION"), '\n', env!("CARGO_PKG_DESCRIPTION"));
+
+const USAGE: &str = r#"
+Usage: zkrunner [OPTIONS] <INPUT>
+
+Arguments:
+  <INPUT>    ZK script source or compiled zkas bincode
+
+Options:
+  -w <FILE>  Read witnesses and public inputs from JSON <FILE> ("-" for stdin)
+  -t         Print a witness JSON template for the circuit and exit
+  -m         Run the MockProver instead of creating a real proof
+  -d         Print the debug trace of the circuit's opcodes
+  -h         Print this help
+"#;
+
+fn usage() {
+    print!("{}{}\n{}", ANSI_LOGO, ABOUT, USAGE);
+}
+
+/// Read the given file and return the decoded zkas binary. Files starting
+/// with the zkas magic bytes are decoded as bincode, anything else is
+/// compiled as zkas source code.
+fn load_zkbin(filename: &str) -> Option<ZkBinary> {
+    let bytes = match read(filename) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error: Failed reading from \"{}\". {}", filename, e);
+            return None
+        }
+    };
+
+    if bytes.starts_with(&MAGIC_BYTES) {
+        return match ZkBinary::decode(&bytes) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                eprintln!("Error: Failed decoding zkas bincode. {}", e);
+                None
+            }
+        }
+    }
+
+    let source = match String::from_utf8(bytes) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error: \"{}\" is neither zkas bincode nor source. {}", filename, e);
+            return None
+        }
+    };
+
+    // Clean up tabs, and convert CRLF to LF.
+    let source = source.replace('\t', "    ").replace("\r\n", "\n");
+
+    // The zkas frontend reports its errors on stderr by itself.
+    let tokens = Lexer::new(filename, source.chars()).lex().ok()?;
+    let (namespace, k, constants, witnesses, statements) =
+        Parser::new(filename, source.chars(), tokens).parse().ok()?;
+
+    let mut analyzer = Analyzer::new(filename, source.chars(), constants, witnesses, statements);
+    analyzer.analyze_types().ok()?;
+
+    let compiler = Compiler::new(
+        filename,
+        source.chars(),
+        namespace,
+        k,
+        analyzer.constants,
+        analyzer.witnesses,
+        analyzer.statements,
+        analyzer.literals,
+        true,
+    );
+
+    let bincode = compiler.compile().ok()?;
+
+    match ZkBinary::decode(&bincode) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            eprintln!("Error: Failed decoding compiled zkas bincode. {}", e);
+            None
+        }
+    }
+}
+
+/// Print the opcodes of the circuit along with the values traced while
+/// synthesizing it.
+fn show_trace(zkbin: &ZkBinary, opvalues: &[DebugOpValue]) {
+    println!("{:<4} {:<22} {:<10} Values", "Line", "Opcode", "Type");
+    for (i, ((opcode, _), value)) in zkbin.opcodes.iter().zip(opvalues).enumerate() {
+        let (typ, values) = match value {
+            DebugOpValue::EcPoint(x, y) => ("EcPoint", format!("[{:?}, {:?}]", x, y)),
+            DebugOpValue::Base(v) => ("Base", format!("[{:?}]", v)),
+            DebugOpValue::Void => ("Void", String::new()),
+        };
+        println!("{:<4} {:<22} {:<10} {}", i, opcode.name(), typ, values);
+    }
+}
+
+fn main() -> ExitCode {
+    let argv;
+    let mut tflag = false;
+    let mut mflag = false;
+    let mut dflag = false;
+    let mut hflag = false;
+    let mut witness_file = String::new();
+
+    {
+        let mut args = Args::new().with_cb(|args, flag| match flag {
+            't' => tflag = true,
+            'm' => mflag = true,
+            'd' => dflag = true,
+            'w' => witness_file = args.eargf().to_string(),
+            _ => hflag = true,
+        });
+
+        argv = args.parse();
+    }
+
+    if hflag || argv.is_empty() || (!tflag && witness_file.is_empty()) {
+        usage();
+        return ExitCode::FAILURE
+    }
+
+    let Some(zkbin) = load_zkbin(argv[0].as_str()) else { return ExitCode::FAILURE };
+
+    if tflag {
+        match witness_json_template(&zkbin) {
+            Ok(v) => println!("{}", v),
+            Err(e) => {
+                eprintln!("Error: Failed creating witness template. {}", e);
+                return ExitCode::FAILURE
+            }
+        }
+        return ExitCode::SUCCESS
+    }
+
+    let mut json = String::new();
+    let res = if witness_file == "-" {
+        stdin().read_to_string(&mut json).map(|_| ())
+    } else {
+        read_to_string(&witness_file).map(|v| json = v)
+    };
+
+    if let Err(e) = res {
+        eprintln!("Error: Failed reading witnesses from \"{}\". {}", witness_file, e);
+        return ExitCode::FAILURE
+    }
+
+    println!("Decoding witnesses...");
+    let (witnesses, instances) = match import_witness_json(&json) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error: Failed decoding witnesses. {}", e);
+            return ExitCode::FAILURE
+        }
+    };
+
+    let mut circuit = ZkCircuit::new(witnesses, &zkbin);
+
+    println!("Checking witness and public input types...");
+    if let Err(e) = zkas_type_checks(&circuit, &zkbin, &instances) {
+        eprintln!("Error: Witnesses don't match the circuit. {}", e);
+        return ExitCode::FAILURE
+    }
+
+    if dflag {
+        circuit.enable_trace();
+    }
+
+    if mflag {
+        println!("Running MockProver...");
+        let now = Instant::now();
+        let prover = match MockProver::run(zkbin.k, &circuit, vec![instances]) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Error: Failed running MockProver. {}", e);
+                return ExitCode::FAILURE
+            }
+        };
+
+        if dflag {
+            show_trace(&zkbin, circuit.tracer.opvalues.borrow().as_ref().unwrap());
+        }
+
+        if let Err(failures) = prover.verify() {
+            for failure in failures {
+                eprintln!("Error: {}", failure);
+            }
+            return ExitCode::FAILURE
+        }
+
+        println!("MockProver verified successfully! [{:?}]", now.elapsed());
+        return ExitCode::SUCCESS
+    }
+
+    // The keys are built from a circuit without witness values, as the
+    // verifier would do.
+    let verifier_witnesses = match empty_witnesses(&zkbin) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error: Failed creating empty witnesses. {}", e);
+            return ExitCode::FAILURE
+        }
+    };
+    let verifier_circuit = ZkCircuit::new(verifier_witnesses, &zkbin);
+
+    println!("Building proving key...");
+    let now = Instant::now();
+    let proving_key = ProvingKey::build(zkbin.k, &verifier_circuit);
+    println!("Built proving key [{:?}]", now.elapsed());
+
+    println!("Building verifying key...");
+    let now = Instant::now();
+    let verifying_key = VerifyingKey::build(zkbin.k, &verifier_circuit);
+    println!("Built verifying key [{:?}]", now.elapsed());
+
+    println!("Proving knowledge of witnesses...");
+    let now = Instant::now();
+    let circuits = [circuit];
+    let proof = match Proof::create(&proving_key, &circuits, &instances, &mut OsRng) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error: Failed creating proof. {}", e);
+            return ExitCode::FAILURE
+        }
+    };
+    println!("Created proof of {} bytes [{:?}]", proof.as_ref().len(), now.elapsed());
+
+    if dflag {
+        show_trace(&zkbin, circuits[0].tracer.opvalues.borrow().as_ref().unwrap());
+    }
+
+    println!("Verifying ZK proof...");
+    let now = Instant::now();
+    if let Err(e) = proof.verify(&verifying_key, &instances) {
+        eprintln!("Error: Failed verifying proof. {}", e);
+        return ExitCode::FAILURE
+    }
+    println!("Proof verified successfully! [{:?}]", now.elapsed());
+
+    ExitCode::SUCCESS
+}
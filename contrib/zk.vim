@@ -27,7 +27,7 @@ syn keyword zkasInstruction
     \ range_check less_than_strict less_than_loose bool_check
     \ cond_select zero_cond witness_base
     \ constrain_equal_base constrain_equal_point
-    \ constrain_instance debug
+    \ constrain_instance constrain_encrypted_instance debug
 
 syn region zkasString start='"' end='"' contained
 
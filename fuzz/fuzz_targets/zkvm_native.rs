@@ -0,0 +1,298 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// Differential fuzzing of the zkVM against the native zkas evaluator.
+//
+// The input is used to generate a random well-typed circuit along with
+// witnesses for it. The circuit is then evaluated natively and run in the
+// MockProver with tracing enabled. We check that both compute the same
+// value for each opcode, and agree on whether the constraints hold.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use darkfi::{
+    zk::{
+        cost::CircuitCost,
+        halo2::{dev::MockProver, Value},
+        native::evaluate,
+        DebugOpValue, Witness, ZkCircuit,
+    },
+    zkas::{
+        types::{HeapType, LitType},
+        Opcode, VarType, ZkBinary,
+    },
+};
+use darkfi_sdk::{
+    crypto::{constants::MERKLE_DEPTH_ORCHARD, MerkleNode},
+    pasta::{
+        group::{ff::Field, Group},
+        pallas,
+    },
+};
+
+/// Maximum number of opcodes in a generated circuit, to keep the
+/// MockProver runs short.
+const MAX_OPCODES: usize = 16;
+
+/// Opcodes we generate. `DebugPrint` is left out as it writes to stdout.
+const OPCODES: [Opcode; 22] = [
+    Opcode::EcAdd,
+    Opcode::EcMul,
+    Opcode::EcMulBase,
+    Opcode::EcMulShort,
+    Opcode::EcMulVarBase,
+    Opcode::EcGetX,
+    Opcode::EcGetY,
+    Opcode::PoseidonHash,
+    Opcode::MerkleRoot,
+    Opcode::BaseAdd,
+    Opcode::BaseMul,
+    Opcode::BaseSub,
+    Opcode::WitnessBase,
+    Opcode::RangeCheck,
+    Opcode::LessThanStrict,
+    Opcode::LessThanLoose,
+    Opcode::BoolCheck,
+    Opcode::CondSelect,
+    Opcode::ZeroCondSelect,
+    Opcode::ConstrainEqualBase,
+    Opcode::ConstrainEqualPoint,
+    Opcode::ConstrainInstance,
+];
+
+/// Where a heap variable comes from
+#[derive(Clone, Copy)]
+enum Origin {
+    Constant,
+    Witness(usize),
+    Opcode(usize),
+}
+
+/// Reader over the fuzzer input
+struct Input<'a>(&'a [u8]);
+
+impl Input<'_> {
+    fn byte(&mut self) -> Option<u8> {
+        let (first, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(*first)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let mut buf = [0u8; 8];
+        for b in buf.iter_mut() {
+            *b = self.byte()?;
+        }
+        Some(u64::from_le_bytes(buf))
+    }
+
+    /// Base field element, biased towards edge cases of the range checks
+    fn base(&mut self) -> Option<pallas::Base> {
+        let two = pallas::Base::from(2);
+        let value = match self.byte()? % 8 {
+            0 => pallas::Base::ZERO,
+            1 => pallas::Base::ONE,
+            2 => -pallas::Base::ONE,
+            3 => two.pow([64, 0, 0, 0]),
+            4 => two.pow([253, 0, 0, 0]),
+            5 => two.pow([253, 0, 0, 0]) - pallas::Base::ONE,
+            _ => pallas::Base::from(self.u64()?),
+        };
+        Some(value)
+    }
+}
+
+/// Generate a random witness of one of the supported types
+fn gen_witness(input: &mut Input) -> Option<(VarType, Witness)> {
+    let witness = match input.byte()? % 7 {
+        0 | 1 => (VarType::Base, Witness::Base(Value::known(input.base()?))),
+        2 => (VarType::Scalar, Witness::Scalar(Value::known(pallas::Scalar::from(input.u64()?)))),
+        3 => {
+            let point = pallas::Point::generator() * pallas::Scalar::from(input.u64()?);
+            (VarType::EcPoint, Witness::EcPoint(Value::known(point)))
+        }
+        4 => {
+            let scalar = pallas::Scalar::from(input.u64()?) + pallas::Scalar::ONE;
+            let point = pallas::Point::generator() * scalar;
+            (VarType::EcNiPoint, Witness::EcNiPoint(Value::known(point)))
+        }
+        5 => (VarType::Uint32, Witness::Uint32(Value::known(input.u64()? as u32))),
+        _ => {
+            let seed = pallas::Base::from(input.u64()?);
+            let path: [MerkleNode; MERKLE_DEPTH_ORCHARD] =
+                core::array::from_fn(|i| MerkleNode::from(seed + pallas::Base::from(i as u64)));
+            (VarType::MerklePath, Witness::MerklePath(Value::known(path)))
+        }
+    };
+
+    Some(witness)
+}
+
+/// Pick a random heap variable of the given type
+fn pick(input: &mut Input, heap_types: &[VarType], typ: VarType) -> Option<usize> {
+    let candidates: Vec<usize> = (0..heap_types.len()).filter(|i| heap_types[*i] == typ).collect();
+    Some(candidates[input.byte()? as usize % candidates.len()])
+}
+
+/// Generate the arguments of an opcode, pushing any literals it uses.
+/// All argument types must be available on the heap.
+fn gen_args(
+    input: &mut Input,
+    opcode: Opcode,
+    heap_types: &[VarType],
+    literals: &mut Vec<(LitType, String)>,
+) -> Option<Vec<(HeapType, usize)>> {
+    let mut args = vec![];
+    for arg_type in opcode.arg_types().1 {
+        match arg_type {
+            VarType::Uint64 => {
+                let lit = match opcode {
+                    Opcode::RangeCheck if input.byte()? % 2 == 0 => 64,
+                    Opcode::RangeCheck => 253,
+                    _ => input.u64()?,
+                };
+                args.push((HeapType::Lit, literals.len()));
+                literals.push((LitType::Uint64, lit.to_string()));
+            }
+
+            VarType::BaseArray => {
+                for _ in 0..(input.byte()? % 4 + 1) {
+                    args.push((HeapType::Var, pick(input, heap_types, VarType::Base)?));
+                }
+            }
+
+            typ => args.push((HeapType::Var, pick(input, heap_types, typ)?)),
+        }
+    }
+
+    Some(args)
+}
+
+/// Generate a random well-typed circuit with witnesses, and return it along
+/// with the origin of each heap variable.
+fn gen_circuit(input: &mut Input) -> Option<(ZkBinary, Vec<Witness>, Vec<Origin>)> {
+    let constants = vec![
+        (VarType::EcFixedPointShort, "VALUE_COMMIT_VALUE".to_string()),
+        (VarType::EcFixedPoint, "VALUE_COMMIT_RANDOM".to_string()),
+        (VarType::EcFixedPointBase, "NULLIFIER_K".to_string()),
+    ];
+
+    let mut heap_types: Vec<VarType> = constants.iter().map(|(t, _)| *t).collect();
+    let mut origins = vec![Origin::Constant; constants.len()];
+
+    let mut witness_types = vec![];
+    let mut witnesses = vec![];
+    for i in 0..(input.byte()? % 8 + 1) as usize {
+        let (typ, witness) = gen_witness(input)?;
+        heap_types.push(typ);
+        origins.push(Origin::Witness(i));
+        witness_types.push(typ);
+        witnesses.push(witness);
+    }
+
+    let mut literals = vec![];
+    let mut opcodes = vec![];
+    while opcodes.len() < MAX_OPCODES {
+        let Some(byte) = input.byte() else { break };
+        let opcode = OPCODES[byte as usize % OPCODES.len()];
+        let (return_types, arg_types) = opcode.arg_types();
+
+        // Skip opcodes we have no arguments for
+        let available = arg_types.iter().all(|t| match t {
+            VarType::Uint64 => true,
+            VarType::BaseArray => heap_types.contains(&VarType::Base),
+            t => heap_types.contains(t),
+        });
+        if !available {
+            continue
+        }
+
+        let Some(args) = gen_args(input, opcode, &heap_types, &mut literals) else { break };
+
+        if let Some(typ) = return_types.first() {
+            heap_types.push(*typ);
+            origins.push(Origin::Opcode(opcodes.len()));
+        }
+        opcodes.push((opcode, args));
+    }
+
+    let zkbin = ZkBinary {
+        namespace: "fuzz".to_string(),
+        k: 0,
+        constants,
+        literals,
+        witnesses: witness_types,
+        opcodes,
+    };
+
+    Some((zkbin, witnesses, origins))
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut input = Input(data);
+    let Some((mut zkbin, witnesses, origins)) = gen_circuit(&mut input) else { return };
+
+    // Set the public inputs to the values the circuit constrains them to,
+    // then flip some of them to also exercise failing instances.
+    let instances_count =
+        zkbin.opcodes.iter().filter(|(op, _)| *op == Opcode::ConstrainInstance).count();
+    let dry_run = evaluate(&zkbin, &witnesses, &vec![pallas::Base::ZERO; instances_count])
+        .expect("native evaluation of a well-typed circuit failed");
+
+    let mut instances = vec![];
+    for (_, args) in zkbin.opcodes.iter().filter(|(op, _)| *op == Opcode::ConstrainInstance) {
+        let mut value = match origins[args[0].1] {
+            Origin::Opcode(i) => match dry_run.opvalues[i] {
+                DebugOpValue::Base(v) => v,
+                _ => unreachable!(),
+            },
+            Origin::Witness(i) => match witnesses[i] {
+                Witness::Base(v) => {
+                    let mut ret = pallas::Base::ZERO;
+                    v.map(|v| ret = v);
+                    ret
+                }
+                _ => unreachable!(),
+            },
+            Origin::Constant => unreachable!(),
+        };
+
+        if input.byte().unwrap_or(0) % 8 == 0 {
+            value += pallas::Base::ONE;
+        }
+        instances.push(value);
+    }
+
+    let native = evaluate(&zkbin, &witnesses, &instances).unwrap();
+
+    zkbin.k = CircuitCost::measure(&zkbin).min_k;
+    let mut circuit = ZkCircuit::new(witnesses, &zkbin);
+    circuit.enable_trace();
+
+    let prover = MockProver::run(zkbin.k, &circuit, vec![instances]).unwrap();
+    let traced = circuit.tracer.opvalues.borrow().clone().unwrap();
+    assert_eq!(native.opvalues.len(), traced.len());
+
+    // Values after a failing constraint are unconstrained in the circuit,
+    // so we only compare the ones computed before.
+    let valid = native.failures.first().copied().unwrap_or(traced.len());
+    assert_eq!(native.opvalues[..valid], traced[..valid]);
+
+    assert_eq!(native.is_satisfied(), prover.verify().is_ok());
+});
@@ -20,7 +20,7 @@ use darkfi_sdk::{
     blockchain::Slot,
     crypto::{
         schnorr::{SchnorrSecret, Signature},
-        SecretKey,
+        MerkleNode, SecretKey,
     },
     pasta::{group::ff::FromUniformBytes, pallas},
 };
@@ -127,13 +127,16 @@ impl BlockInfo {
         Ok(hasher.finalize())
     }
 
-    /// Append a transaction to the block. Also adds it to the Merkle tree.
-    pub fn append_tx(&mut self, tx: Transaction) -> Result<()> {
+    /// Compute the Merkle tree leaf of a transaction.
+    pub fn tx_leaf(tx: &Transaction) -> Result<MerkleNode> {
         let mut buf = [0u8; 64];
         buf[..blake3::OUT_LEN].copy_from_slice(tx.hash()?.as_bytes());
-        let leaf = pallas::Base::from_uniform_bytes(&buf);
+        Ok(pallas::Base::from_uniform_bytes(&buf).into())
+    }
 
-        self.header.tree.append(leaf.into());
+    /// Append a transaction to the block. Also adds it to the Merkle tree.
+    pub fn append_tx(&mut self, tx: Transaction) -> Result<()> {
+        self.header.tree.append(Self::tx_leaf(&tx)?);
         self.txs.push(tx);
 
         Ok(())
@@ -463,6 +466,17 @@ impl BlockOrderStoreOverlay {
         Ok(())
     }
 
+    /// Remove a slice of `u64` order numbers from the overlay.
+    pub fn remove(&self, order: &[u64]) -> Result<()> {
+        let mut lock = self.0.lock().unwrap();
+
+        for number in order {
+            lock.remove(SLED_BLOCK_ORDER_TREE, &number.to_be_bytes())?;
+        }
+
+        Ok(())
+    }
+
     /// Fetch given order numbers from the overlay.
     /// The resulting vector contains `Option`, which is `Some` if the number
     /// was found in the overlay, and otherwise it is `None`, if it has not.
@@ -668,4 +682,15 @@ impl BlockDifficultyStoreOverlay {
 
         Ok(())
     }
+
+    /// Remove the [`BlockDifficulty`] of given block heights from the overlay.
+    pub fn remove(&self, heights: &[u64]) -> Result<()> {
+        let mut lock = self.0.lock().unwrap();
+
+        for height in heights {
+            lock.remove(SLED_BLOCK_DIFFICULTY_TREE, &height.to_be_bytes())?;
+        }
+
+        Ok(())
+    }
 }
@@ -18,9 +18,13 @@ r* This program is distributed in the hope that it will be useful,
 
 use std::io::Cursor;
 
-use darkfi_sdk::crypto::ContractId;
-use darkfi_serial::{deserialize, serialize};
+use darkfi_sdk::{crypto::ContractId, pasta::pallas};
+#[cfg(feature = "async-serial")]
+use darkfi_serial::async_trait;
+use darkfi_serial::{deserialize, serialize, SerialDecodable, SerialEncodable};
 use log::{debug, error};
+use sled::IVec;
+use sled_overlay::SledDbOverlay;
 
 use crate::{
     zk::{empty_witnesses, VerifyingKey, ZkCircuit},
@@ -28,10 +32,24 @@ use crate::{
     Error, Result,
 };
 
-use super::SledDbOverlayPtr;
+use super::{
+    state_tree::{
+        record_hash, records_path, records_root, state_path, state_root, update_record,
+        update_state_leaf, RecordProof, RecordsNode, StateLeaf, StateProof, StateStorage,
+    },
+    SledDbOverlayPtr,
+};
 
 const SLED_CONTRACTS_TREE: &[u8] = b"_contracts";
 const SLED_BINCODE_TREE: &[u8] = b"_wasm_bincode";
+const SLED_MIGRATIONS_TREE: &[u8] = b"_contract_migrations";
+const SLED_STATE_SIZES_TREE: &[u8] = b"_contract_state_sizes";
+const SLED_STATE_LEAVES_TREE: &[u8] = b"_contract_state_leaves";
+const SLED_STATE_RECORDS_TREE: &[u8] = b"_contract_state_records";
+const SLED_STATE_SMT_TREE: &[u8] = b"_contract_state_smt";
+
+/// Number of records fetched at once when iterating over a state tree
+const STATE_RECORDS_BATCH: usize = 1000;
 
 /// The hardcoded db name for the zkas circuits database tree
 pub const SMART_CONTRACT_ZKAS_DB_NAME: &str = "_zkas";
@@ -98,6 +116,141 @@ impl WasmStoreOverlay {
     }
 }
 
+/// A state migration a contract performed when it was redeployed
+#[derive(Clone, Debug, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct ContractMigration {
+    /// blake3 hash of the previously deployed wasm bincode
+    pub previous_bincode: [u8; 32],
+    /// blake3 hash of the newly deployed wasm bincode
+    pub bincode: [u8; 32],
+    /// Block height at which the migration happened
+    pub height: u64,
+}
+
+/// The `MigrationStore` is a `sled` tree that stores the state migrations
+/// deployed contracts have performed.
+#[derive(Clone)]
+pub struct MigrationStore(sled::Tree);
+
+impl MigrationStore {
+    /// Opens or creates a `MigrationStore`.
+    /// The layout looks like this:
+    /// ```plaintext
+    ///  tree: "_contract_migrations"
+    ///   key: ContractId
+    /// value: Vec<ContractMigration>
+    /// ```
+    pub fn new(db: &sled::Db) -> Result<Self> {
+        let tree = db.open_tree(SLED_MIGRATIONS_TREE)?;
+        Ok(Self(tree))
+    }
+
+    /// Fetches the migrations performed by a given ContractId, in order.
+    pub fn get(&self, contract_id: &ContractId) -> Result<Vec<ContractMigration>> {
+        match self.0.get(serialize(contract_id))? {
+            Some(migrations) => Ok(deserialize(&migrations)?),
+            None => Ok(vec![]),
+        }
+    }
+}
+
+/// Overlay structure over a [`MigrationStore`] instance.
+pub struct MigrationStoreOverlay(SledDbOverlayPtr);
+
+impl MigrationStoreOverlay {
+    pub fn new(overlay: &SledDbOverlayPtr) -> Result<Self> {
+        overlay.lock().unwrap().open_tree(SLED_MIGRATIONS_TREE)?;
+        Ok(Self(overlay.clone()))
+    }
+
+    /// Fetches the migrations performed by a given ContractId, in order.
+    pub fn get(&self, contract_id: &ContractId) -> Result<Vec<ContractMigration>> {
+        match self.0.lock().unwrap().get(SLED_MIGRATIONS_TREE, &serialize(contract_id))? {
+            Some(migrations) => Ok(deserialize(&migrations)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Appends a migration to the ones performed by a given ContractId
+    pub fn append(&self, contract_id: &ContractId, migration: ContractMigration) -> Result<()> {
+        let mut migrations = self.get(contract_id)?;
+        migrations.push(migration);
+
+        let key = serialize(contract_id);
+        if let Err(e) =
+            self.0.lock().unwrap().insert(SLED_MIGRATIONS_TREE, &key, &serialize(&migrations))
+        {
+            error!(target: "blockchain::contractstoreoverlay", "Failed to insert migration to MigrationStore: {}", e);
+            return Err(e.into())
+        }
+
+        Ok(())
+    }
+}
+
+/// The `StateSizeStore` is a `sled` tree that stores the amount of bytes
+/// deployed contracts hold in their state trees.
+#[derive(Clone)]
+pub struct StateSizeStore(sled::Tree);
+
+impl StateSizeStore {
+    /// Opens or creates a `StateSizeStore`.
+    /// The layout looks like this:
+    /// ```plaintext
+    ///  tree: "_contract_state_sizes"
+    ///   key: ContractId
+    /// value: u64
+    /// ```
+    pub fn new(db: &sled::Db) -> Result<Self> {
+        let tree = db.open_tree(SLED_STATE_SIZES_TREE)?;
+        Ok(Self(tree))
+    }
+
+    /// Fetches the size in bytes of the keys and values a given ContractId
+    /// holds in its state trees.
+    pub fn get(&self, contract_id: &ContractId) -> Result<u64> {
+        match self.0.get(serialize(contract_id))? {
+            Some(size) => Ok(deserialize(&size)?),
+            None => Ok(0),
+        }
+    }
+}
+
+/// Overlay structure over a [`StateSizeStore`] instance.
+pub struct StateSizeStoreOverlay(SledDbOverlayPtr);
+
+impl StateSizeStoreOverlay {
+    pub fn new(overlay: &SledDbOverlayPtr) -> Result<Self> {
+        overlay.lock().unwrap().open_tree(SLED_STATE_SIZES_TREE)?;
+        Ok(Self(overlay.clone()))
+    }
+
+    /// Fetches the size in bytes of the keys and values a given ContractId
+    /// holds in its state trees.
+    pub fn get(&self, contract_id: &ContractId) -> Result<u64> {
+        match self.0.lock().unwrap().get(SLED_STATE_SIZES_TREE, &serialize(contract_id))? {
+            Some(size) => Ok(deserialize(&size)?),
+            None => Ok(0),
+        }
+    }
+
+    /// Adds the given (possibly negative) amount of bytes to the state size
+    /// of a given ContractId, returning the new size.
+    pub fn update(&self, contract_id: &ContractId, delta: i64) -> Result<u64> {
+        let size = self.get(contract_id)?.saturating_add_signed(delta);
+
+        let key = serialize(contract_id);
+        if let Err(e) =
+            self.0.lock().unwrap().insert(SLED_STATE_SIZES_TREE, &key, &serialize(&size))
+        {
+            error!(target: "blockchain::contractstoreoverlay", "Failed to insert state size to StateSizeStore: {}", e);
+            return Err(e.into())
+        }
+
+        Ok(size)
+    }
+}
+
 /// The `ContractStateStore` is a `sled` tree that stores pointers to contracts'
 /// databases. See the rustdoc for the impl functions for more info.
 #[derive(Clone)]
@@ -229,6 +382,9 @@ pub struct ContractStateStoreOverlay(SledDbOverlayPtr);
 impl ContractStateStoreOverlay {
     pub fn new(overlay: &SledDbOverlayPtr) -> Result<Self> {
         overlay.lock().unwrap().open_tree(SLED_CONTRACTS_TREE)?;
+        overlay.lock().unwrap().open_tree(SLED_STATE_LEAVES_TREE)?;
+        overlay.lock().unwrap().open_tree(SLED_STATE_RECORDS_TREE)?;
+        overlay.lock().unwrap().open_tree(SLED_STATE_SMT_TREE)?;
         Ok(Self(overlay.clone()))
     }
 
@@ -333,4 +489,292 @@ impl ContractStateStoreOverlay {
 
         Ok((zkbin, vk))
     }
+
+    /// Fetch the key-value pairs of a contract state tree in key order,
+    /// starting from `start` (inclusive) and stopping before `end` (exclusive)
+    /// if given, returning at most `limit` records. The state must have been
+    /// opened previously, using `init()` or `lookup()`.
+    ///
+    /// Records written or removed in the overlay take precedence over the
+    /// ones found in the underlying sled tree.
+    pub fn range(
+        &self,
+        tree: &[u8],
+        start: &[u8],
+        end: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let lock = self.0.lock().unwrap();
+        let Some(tree_overlay) = lock.state.caches.get(tree) else {
+            return Err(Error::ContractStateNotFound)
+        };
+
+        let mut disk = tree_overlay.tree.range(start..).peekable();
+        let mut cached = tree_overlay.cache.range(IVec::from(start)..).peekable();
+
+        let mut ret = vec![];
+        while ret.len() < limit {
+            let disk_key = match disk.peek() {
+                Some(Ok((k, _))) => Some(k.clone()),
+                Some(Err(_)) => return Err(disk.next().unwrap().unwrap_err().into()),
+                None => None,
+            };
+            let cached_key = cached.peek().map(|(k, _)| (*k).clone());
+
+            let (key, value) = match (disk_key, cached_key) {
+                (None, None) => break,
+
+                // Cached records shadow the ones on disk
+                (Some(d), Some(c)) if c <= d => {
+                    if c == d {
+                        disk.next();
+                    }
+                    let (k, v) = cached.next().unwrap();
+                    (k.clone(), v.clone())
+                }
+
+                (None, Some(_)) => {
+                    let (k, v) = cached.next().unwrap();
+                    (k.clone(), v.clone())
+                }
+
+                (Some(_), _) => {
+                    let (k, v) = disk.next().unwrap()?;
+                    if tree_overlay.removed.contains(&k) {
+                        continue
+                    }
+                    (k, v)
+                }
+            };
+
+            if let Some(end) = end {
+                if &key[..] >= end {
+                    break
+                }
+            }
+
+            ret.push((key.to_vec(), value.to_vec()));
+        }
+
+        Ok(ret)
+    }
+
+    /// Feed all the records of an opened tree to provided function, in key order,
+    /// fetching them in batches so the whole tree is never held in memory.
+    fn for_each_record(
+        &self,
+        tree: &[u8],
+        mut f: impl FnMut(&[u8], &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let mut start = vec![];
+        loop {
+            let records = self.range(tree, &start, None, STATE_RECORDS_BATCH)?;
+            for (key, value) in &records {
+                f(key, value)?;
+            }
+
+            if records.len() < STATE_RECORDS_BATCH {
+                return Ok(())
+            }
+
+            // Next batch starts right after the last key
+            start = records.last().unwrap().0.clone();
+            start.push(0);
+        }
+    }
+
+    /// Retrieve the pointers of all initialized contract state trees, sorted.
+    fn state_pointers(&self) -> Result<Vec<[u8; 32]>> {
+        let mut pointers = vec![];
+        self.for_each_record(SLED_CONTRACTS_TREE, |_, value| {
+            let state_pointers: Vec<[u8; 32]> = deserialize(value)?;
+            pointers.extend(state_pointers);
+            Ok(())
+        })?;
+        pointers.sort();
+
+        Ok(pointers)
+    }
+
+    /// Retrieve the stored leaf of provided contract state tree, along with
+    /// its index in the contracts state sparse Merkle tree, as last computed
+    /// by `update_state()`.
+    fn stored_leaf(&self, tree: &[u8; 32]) -> Result<Option<StoredStateLeaf>> {
+        match self.0.lock().unwrap().get(SLED_STATE_LEAVES_TREE, tree)? {
+            Some(bytes) => Ok(Some(deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Update the records of every contract state tree written or removed in
+    /// the overlay, along with the [`StateLeaf`] of the trees they belong to,
+    /// so only the paths of the modified records and leaves get rehashed.
+    /// Trees missing a leaf get the next free index in the contracts state
+    /// sparse Merkle tree, in pointer order. Returns the resulting contracts
+    /// state root, along with the pointers of the trees whose leaf changed,
+    /// sorted.
+    /// The layout of the leaves tree looks like this:
+    /// ```plaintext
+    ///  tree: "_contract_state_leaves"
+    ///   key: blake3(ContractId || tree_name)
+    /// value: (u64, blake3::Hash)
+    /// ```
+    pub fn update_state(&self) -> Result<(pallas::Base, Vec<[u8; 32]>)> {
+        let pointers = self.state_pointers()?;
+        let mut stored = Vec::with_capacity(pointers.len());
+        for tree in &pointers {
+            stored.push(self.stored_leaf(tree)?);
+        }
+        let mut next_index = stored.iter().flatten().count() as u64;
+
+        let mut lock = self.0.lock().unwrap();
+        let mut changed = vec![];
+        for (tree, stored) in pointers.into_iter().zip(stored) {
+            // Grab the keys written or removed in the overlay
+            lock.open_tree(&tree)?;
+            let Some(tree_overlay) = lock.state.caches.get(&tree[..]) else {
+                return Err(Error::ContractStateNotFound)
+            };
+            let keys: Vec<IVec> =
+                tree_overlay.cache.keys().chain(tree_overlay.removed.iter()).cloned().collect();
+            if keys.is_empty() && stored.is_some() {
+                continue
+            }
+
+            let mut storage = OverlayStateStorage(&mut *lock);
+            for key in keys {
+                let value = storage.0.get(&tree, &key)?;
+                let record = value.map(|value| record_hash(&key, &value));
+                update_record(&mut storage, &tree, &key, record)?;
+            }
+            let leaf = StateLeaf { tree, digest: records_root(&storage, &tree)? };
+
+            let index = match stored {
+                Some(stored) if stored.digest == leaf.digest => continue,
+                Some(stored) => stored.index,
+                None => {
+                    next_index += 1;
+                    next_index - 1
+                }
+            };
+            update_state_leaf(&mut storage, index, &leaf)?;
+            let stored = StoredStateLeaf { index, digest: leaf.digest };
+            lock.insert(SLED_STATE_LEAVES_TREE, &tree, &serialize(&stored))?;
+            changed.push(tree);
+        }
+
+        Ok((state_root(&OverlayStateStorage(&mut *lock))?, changed))
+    }
+
+    /// Generate a [`StateProof`] of given key membership, or absence, in an
+    /// existing contract state tree, against the block header at given
+    /// height, which must commit to the current overlay state leaves.
+    /// Only the nodes along the key and state tree leaf paths are read.
+    pub fn state_proof(
+        &self,
+        contract_id: &ContractId,
+        tree_name: &str,
+        key: &[u8],
+        height: u64,
+    ) -> Result<StateProof> {
+        let ptr = self.lookup(contract_id, tree_name)?;
+
+        // Trees initialized after the last `update_state()` are not committed to yet
+        let Some(stored) = self.stored_leaf(&ptr)? else {
+            return Err(Error::ContractStateNotFound)
+        };
+        let leaf = StateLeaf { tree: ptr, digest: stored.digest };
+
+        let mut lock = self.0.lock().unwrap();
+        let storage = OverlayStateStorage(&mut *lock);
+        let leaf_path = state_path(&storage, stored.index)?;
+        let (path, record_key) = records_path(&storage, &ptr, key)?;
+
+        let record = match record_key {
+            Some(key) => {
+                let Some(value) = storage.0.get(&ptr, &key)? else {
+                    return Err(Error::ContractStateNotFound)
+                };
+                Some(RecordProof { key, value: value.to_vec() })
+            }
+            None => None,
+        };
+
+        Ok(StateProof { height, leaf, leaf_index: stored.index, leaf_path, path, record })
+    }
+}
+
+/// Leaf of a contract state tree, as stored in the leaves tree.
+#[derive(SerialEncodable, SerialDecodable)]
+struct StoredStateLeaf {
+    /// Leaf index in the contracts state sparse Merkle tree
+    index: u64,
+    /// Root of the state tree records sparse Merkle tree
+    digest: blake3::Hash,
+}
+
+/// [`StateStorage`] over an overlay, keeping the contracts state trees nodes
+/// in their own sled trees, so they follow the overlay changes.
+/// The layout of their trees looks like this:
+/// ```plaintext
+///  tree: "_contract_state_records"
+///   key: blake3(ContractId || tree_name) || u16(depth) || prefix
+/// value: RecordsNode
+///
+///  tree: "_contract_state_smt"
+///   key: u8(level) || u64(index)
+/// value: pallas::Base
+/// ```
+struct OverlayStateStorage<'a>(&'a mut SledDbOverlay);
+
+impl OverlayStateStorage<'_> {
+    fn records_key(tree: &[u8; 32], depth: usize, prefix: &[u8; 32]) -> Vec<u8> {
+        [&tree[..], &(depth as u16).to_be_bytes(), &prefix[..]].concat()
+    }
+
+    fn state_key(level: usize, index: u64) -> Vec<u8> {
+        [&[level as u8][..], &index.to_be_bytes()].concat()
+    }
+}
+
+impl StateStorage for OverlayStateStorage<'_> {
+    fn records_node(
+        &self,
+        tree: &[u8; 32],
+        depth: usize,
+        prefix: &[u8; 32],
+    ) -> Result<Option<RecordsNode>> {
+        let key = Self::records_key(tree, depth, prefix);
+        match self.0.get(SLED_STATE_RECORDS_TREE, &key)? {
+            Some(bytes) => Ok(Some(deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_records_node(
+        &mut self,
+        tree: &[u8; 32],
+        depth: usize,
+        prefix: &[u8; 32],
+        node: Option<&RecordsNode>,
+    ) -> Result<()> {
+        let key = Self::records_key(tree, depth, prefix);
+        match node {
+            Some(node) => self.0.insert(SLED_STATE_RECORDS_TREE, &key, &serialize(node))?,
+            None => self.0.remove(SLED_STATE_RECORDS_TREE, &key)?,
+        };
+        Ok(())
+    }
+
+    fn state_node(&self, level: usize, index: u64) -> Result<Option<pallas::Base>> {
+        match self.0.get(SLED_STATE_SMT_TREE, &Self::state_key(level, index))? {
+            Some(bytes) => Ok(Some(deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_state_node(&mut self, level: usize, index: u64, node: pallas::Base) -> Result<()> {
+        self.0.insert(SLED_STATE_SMT_TREE, &Self::state_key(level, index), &serialize(&node))?;
+        Ok(())
+    }
 }
@@ -0,0 +1,244 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_sdk::crypto::ContractId;
+#[cfg(feature = "async-serial")]
+use darkfi_serial::async_trait;
+use darkfi_serial::{deserialize, serialize, SerialDecodable, SerialEncodable};
+
+use crate::{Error, Result};
+
+use super::SledDbOverlayPtr;
+
+const SLED_EVENTS_TREE: &[u8] = b"_contract_events";
+const SLED_EVENTS_INDEX_TREE: &[u8] = b"_contract_events_index";
+const SLED_EVENTS_CONTRACT_INDEX_TREE: &[u8] = b"_contract_events_contract_index";
+
+/// A structured event emitted by a contract while applying its state update.
+#[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct ContractEvent {
+    /// Contract that emitted the event
+    pub contract_id: ContractId,
+    /// Contract defined topic of the event
+    pub topic: [u8; 32],
+    /// Contract defined event data
+    pub data: Vec<u8>,
+}
+
+/// Build the index key of an event, in the form of
+/// `contract_id || topic || height || tx_hash`.
+fn index_key(
+    contract_id: &ContractId,
+    topic: &[u8; 32],
+    height: u64,
+    tx_hash: &blake3::Hash,
+) -> Vec<u8> {
+    let mut key = Vec::with_capacity(32 + 32 + 8 + 32);
+    key.extend_from_slice(&contract_id.to_bytes());
+    key.extend_from_slice(topic);
+    key.extend_from_slice(&height.to_be_bytes());
+    key.extend_from_slice(tx_hash.as_bytes());
+    key
+}
+
+/// Build the contract index key of an event, in the form of
+/// `contract_id || height || tx_hash`.
+fn contract_index_key(contract_id: &ContractId, height: u64, tx_hash: &blake3::Hash) -> Vec<u8> {
+    let mut key = Vec::with_capacity(32 + 8 + 32);
+    key.extend_from_slice(&contract_id.to_bytes());
+    key.extend_from_slice(&height.to_be_bytes());
+    key.extend_from_slice(tx_hash.as_bytes());
+    key
+}
+
+/// Parse the block height and transaction hash at the end of an index key
+fn parse_index_key(key: &[u8]) -> (u64, blake3::Hash) {
+    let offset = key.len() - 40;
+    let height = u64::from_be_bytes(key[offset..offset + 8].try_into().unwrap());
+    let tx_hash: [u8; 32] = key[offset + 8..].try_into().unwrap();
+    (height, blake3::Hash::from(tx_hash))
+}
+
+/// The `EventStore` is a set of `sled` trees storing all the events
+/// emitted by contracts. The main tree key is the transaction hash, and
+/// the value is the serialized vector of [`ContractEvent`] it emitted.
+/// The index tree contains an empty record for every emitted event, keyed
+/// by contract ID, topic, block height and transaction hash, so events can
+/// be looked up without parsing transactions. The contract index tree
+/// does the same without the topic, so all the events of a contract can
+/// be walked in block height order.
+#[derive(Clone)]
+pub struct EventStore {
+    pub main: sled::Tree,
+    pub index: sled::Tree,
+    pub contract_index: sled::Tree,
+}
+
+impl EventStore {
+    /// Opens a new or existing `EventStore` on the given sled database.
+    pub fn new(db: &sled::Db) -> Result<Self> {
+        let main = db.open_tree(SLED_EVENTS_TREE)?;
+        let index = db.open_tree(SLED_EVENTS_INDEX_TREE)?;
+        let contract_index = db.open_tree(SLED_EVENTS_CONTRACT_INDEX_TREE)?;
+        Ok(Self { main, index, contract_index })
+    }
+
+    /// Fetch the events of given tx hashes from the store.
+    /// The resulting vector contains `Option`, which is `Some` if events
+    /// for the tx were found in the store, and otherwise it is `None`.
+    /// The second parameter is a boolean which tells the function to fail in
+    /// case the events of at least one tx were not found.
+    pub fn get(
+        &self,
+        tx_hashes: &[blake3::Hash],
+        strict: bool,
+    ) -> Result<Vec<Option<Vec<ContractEvent>>>> {
+        let mut ret = Vec::with_capacity(tx_hashes.len());
+
+        for tx_hash in tx_hashes {
+            if let Some(found) = self.main.get(tx_hash.as_bytes())? {
+                let events = deserialize(&found)?;
+                ret.push(Some(events));
+            } else {
+                if strict {
+                    let s = tx_hash.to_hex().as_str().to_string();
+                    return Err(Error::EventsNotFound(s))
+                }
+                ret.push(None);
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Retrieve the block heights and hashes of the transactions in which
+    /// the given contract emitted events, optionally only the ones with the
+    /// given topic, in the block height range `[from, to]`.
+    /// At most `limit` records are returned, ordered by block height.
+    pub fn get_by_topic(
+        &self,
+        contract_id: &ContractId,
+        topic: Option<&[u8; 32]>,
+        from: u64,
+        to: u64,
+        limit: usize,
+    ) -> Result<Vec<(u64, blake3::Hash)>> {
+        let (tree, prefix) = match topic {
+            Some(topic) => (&self.index, [contract_id.to_bytes(), *topic].concat()),
+            None => (&self.contract_index, contract_id.to_bytes().to_vec()),
+        };
+
+        let mut start = prefix.clone();
+        start.extend_from_slice(&from.to_be_bytes());
+
+        let mut ret = vec![];
+        for record in tree.range(start..) {
+            if ret.len() == limit {
+                break
+            }
+
+            let (key, _) = record?;
+            if !key.starts_with(&prefix) {
+                break
+            }
+
+            let (height, tx_hash) = parse_index_key(&key);
+            if height > to {
+                break
+            }
+            ret.push((height, tx_hash));
+        }
+
+        Ok(ret)
+    }
+
+    /// Retrieve records count
+    pub fn len(&self) -> usize {
+        self.main.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.main.is_empty()
+    }
+}
+
+/// Overlay structure over a [`EventStore`] instance.
+pub struct EventStoreOverlay(SledDbOverlayPtr);
+
+impl EventStoreOverlay {
+    pub fn new(overlay: &SledDbOverlayPtr) -> Result<Self> {
+        overlay.lock().unwrap().open_tree(SLED_EVENTS_TREE)?;
+        overlay.lock().unwrap().open_tree(SLED_EVENTS_INDEX_TREE)?;
+        overlay.lock().unwrap().open_tree(SLED_EVENTS_CONTRACT_INDEX_TREE)?;
+        Ok(Self(overlay.clone()))
+    }
+
+    /// Insert the events emitted by a transaction included in the block
+    /// with the given height into the overlay, and index them.
+    pub fn insert(
+        &self,
+        height: u64,
+        tx_hash: &blake3::Hash,
+        events: &[ContractEvent],
+    ) -> Result<()> {
+        if events.is_empty() {
+            return Ok(())
+        }
+
+        let mut lock = self.0.lock().unwrap();
+        lock.insert(SLED_EVENTS_TREE, tx_hash.as_bytes(), &serialize(&events.to_vec()))?;
+
+        for event in events {
+            let key = index_key(&event.contract_id, &event.topic, height, tx_hash);
+            lock.insert(SLED_EVENTS_INDEX_TREE, &key, &[])?;
+            let key = contract_index_key(&event.contract_id, height, tx_hash);
+            lock.insert(SLED_EVENTS_CONTRACT_INDEX_TREE, &key, &[])?;
+        }
+
+        Ok(())
+    }
+
+    /// Fetch the events of given tx hashes from the overlay.
+    /// The resulting vector contains `Option`, which is `Some` if events
+    /// for the tx were found in the overlay, and otherwise it is `None`.
+    /// The second parameter is a boolean which tells the function to fail in
+    /// case the events of at least one tx were not found.
+    pub fn get(
+        &self,
+        tx_hashes: &[blake3::Hash],
+        strict: bool,
+    ) -> Result<Vec<Option<Vec<ContractEvent>>>> {
+        let mut ret = Vec::with_capacity(tx_hashes.len());
+        let lock = self.0.lock().unwrap();
+
+        for tx_hash in tx_hashes {
+            if let Some(found) = lock.get(SLED_EVENTS_TREE, tx_hash.as_bytes())? {
+                let events = deserialize(&found)?;
+                ret.push(Some(events));
+            } else {
+                if strict {
+                    let s = tx_hash.to_hex().as_str().to_string();
+                    return Err(Error::EventsNotFound(s))
+                }
+                ret.push(None);
+            }
+        }
+
+        Ok(ret)
+    }
+}
@@ -30,7 +30,7 @@ use crate::{util::time::Timestamp, Error, Result};
 
 use super::{parse_record, SledDbOverlayPtr};
 
-/// This struct represents a tuple of the form (version, previous, epoch, height, timestamp, nonce, merkle_root).
+/// This struct represents a tuple of the form (version, previous, epoch, height, timestamp, nonce, merkle_root, state_root).
 #[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
 pub struct Header {
     /// Block version
@@ -49,6 +49,8 @@ pub struct Header {
     pub nonce: pallas::Base,
     /// Merkle tree of the transactions contained in this block
     pub tree: MerkleTree,
+    /// Root of the contracts state sparse Merkle tree, after applying this block
+    pub state_root: pallas::Base,
 }
 
 impl Header {
@@ -61,7 +63,8 @@ impl Header {
     ) -> Self {
         let version = block_version(height);
         let tree = MerkleTree::new(1);
-        Self { version, previous, epoch, height, timestamp, nonce, tree }
+        let state_root = pallas::Base::ZERO;
+        Self { version, previous, epoch, height, timestamp, nonce, tree, state_root }
     }
 
     /// Compute the header's hash
@@ -75,6 +78,7 @@ impl Header {
         self.timestamp.encode(&mut hasher)?;
         self.nonce.encode(&mut hasher)?;
         self.tree.root(0).unwrap().encode(&mut hasher)?;
+        self.state_root.encode(&mut hasher)?;
 
         Ok(hasher.finalize())
     }
@@ -0,0 +1,208 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Light client, syncing and verifying only the chain [`Header`]s.
+//!
+//! Headers proof of work is verified the same way a full node does before
+//! retrieving the block bodies. Headers forking from an earlier stored one
+//! replace our chain only if they are heavier, so the client ends up following
+//! the heaviest valid chain it has been served by untrusted nodes. Everything
+//! else it requests from them, like block transactions or contract state
+//! records, gets verified against the headers it has already accepted.
+
+use darkfi_sdk::crypto::{ContractId, MerkleTree};
+use log::{debug, info};
+use num_bigint::BigUint;
+
+use crate::{
+    tx::Transaction,
+    validator::{pow::PoWModule, validation::validate_pow_header},
+    Error, Result,
+};
+
+use super::{
+    state_tree::StateProof, BlockDifficulty, BlockInfo, Blockchain, BlockchainOverlay, Header,
+};
+
+/// Light client state, holding the verified headers chain.
+pub struct LightClient {
+    /// Headers database. Only the headers, order and difficulties trees are used
+    pub blockchain: Blockchain,
+    /// PoW module, tracking the headers chain difficulties
+    module: PoWModule,
+}
+
+impl LightClient {
+    /// Instantiate a light client over given database. The provided genesis
+    /// header must hash to the network's hardcoded genesis hash. On first run
+    /// it gets stored, otherwise we check it matches the stored one.
+    pub fn new(
+        db: &sled::Db,
+        genesis: &Header,
+        network_genesis: &blake3::Hash,
+        pow_target: usize,
+        pow_fixed_difficulty: Option<BigUint>,
+    ) -> Result<Self> {
+        let blockchain = Blockchain::new(db)?;
+
+        let genesis_hash = genesis.hash()?;
+        if genesis.height != 0 || genesis_hash != *network_genesis {
+            return Err(Error::GenesisHeaderMismatch)
+        }
+
+        match blockchain.genesis() {
+            Ok((_, hash)) => {
+                if hash != genesis_hash {
+                    return Err(Error::GenesisHeaderMismatch)
+                }
+            }
+            Err(_) => {
+                info!(target: "blockchain::light_client", "Storing genesis header {}", genesis_hash);
+                blockchain.headers.insert(&[genesis.clone()])?;
+                blockchain.order.insert(&[genesis.height], &[genesis_hash])?;
+            }
+        }
+
+        let module = PoWModule::new(blockchain.clone(), pow_target, pow_fixed_difficulty)?;
+
+        Ok(Self { blockchain, module })
+    }
+
+    /// Retrieve the verified header at given height.
+    pub fn header(&self, height: u64) -> Result<Header> {
+        let hash = self.blockchain.order.get(&[height], true)?[0].unwrap();
+        Ok(self.blockchain.headers.get(&[hash], true)?[0].clone().unwrap())
+    }
+
+    /// Retrieve the last verified header.
+    pub fn last_header(&self) -> Result<Header> {
+        let (height, _) = self.blockchain.last()?;
+        self.header(height)
+    }
+
+    /// Retrieve the headers chain cummulative difficulty.
+    pub fn cummulative_difficulty(&self) -> &BigUint {
+        &self.module.cummulative_difficulty
+    }
+
+    /// Validate a sequence of [`Header`]s extending one of our stored ones,
+    /// and store them if all are valid. Headers extending our last one get
+    /// appended to our chain, while a fork of it replaces our headers after
+    /// the fork point, only if it has higher cummulative difficulty than our
+    /// chain. In case any of them fails, nothing gets written.
+    pub fn add_headers(&mut self, headers: &[Header]) -> Result<()> {
+        let Some(first) = headers.first() else { return Ok(()) };
+
+        // Grab the stored header they extend
+        if first.height == 0 {
+            return Err(Error::HeadersNotExtending)
+        }
+        let fork_height = first.height - 1;
+        match self.blockchain.order.get(&[fork_height], false)?[0] {
+            Some(hash) if hash == first.previous => {}
+            _ => return Err(Error::HeadersNotExtending),
+        }
+        let mut previous = self.header(fork_height)?;
+        let (last_height, _) = self.blockchain.last()?;
+
+        debug!(target: "blockchain::light_client::add_headers", "Instantiating BlockchainOverlay");
+        let overlay = BlockchainOverlay::new(&self.blockchain)?;
+
+        // Work on a module copy, so we don't have to revert it on failure.
+        // For forks, it gets rebuilt at the fork point.
+        let mut module = if fork_height == last_height {
+            self.module.clone()
+        } else {
+            self.module.at_height(&self.blockchain, fork_height)?
+        };
+
+        for header in headers {
+            validate_pow_header(header, &previous, &module)?;
+
+            // Generate header difficulty and update PoW module
+            let difficulty = module.next_difficulty()?;
+            let cummulative_difficulty = module.cummulative_difficulty.clone() + &difficulty;
+            let header_difficulty = BlockDifficulty::new(
+                header.height,
+                header.timestamp.0,
+                difficulty,
+                cummulative_difficulty,
+            );
+            module.append_difficulty(&overlay, header_difficulty)?;
+
+            // Store header and its order
+            let lock = overlay.lock().unwrap();
+            let hashes = lock.headers.insert(&[header.clone()])?;
+            lock.order.insert(&[header.height], &hashes)?;
+            drop(lock);
+
+            previous = header.clone();
+        }
+
+        // A fork must be heavier than our chain, in which case our
+        // headers after its last one are dropped
+        if fork_height != last_height {
+            if module.cummulative_difficulty <= self.module.cummulative_difficulty {
+                return Err(Error::HeadersForkNotHeavier)
+            }
+
+            info!(target: "blockchain::light_client::add_headers", "Reorg to heavier headers chain, forking at height {}", fork_height);
+            let stale: Vec<u64> = (previous.height + 1..=last_height).collect();
+            let lock = overlay.lock().unwrap();
+            lock.order.remove(&stale)?;
+            lock.difficulties.remove(&stale)?;
+        }
+
+        // Write the changes to the actual headers db
+        overlay.lock().unwrap().overlay.lock().unwrap().apply()?;
+        self.module = module;
+
+        Ok(())
+    }
+
+    /// Verify given transactions are exactly the ones of the block at given
+    /// height, in order, by recomputing its header transactions tree root.
+    pub fn verify_block_txs(&self, height: u64, txs: &[Transaction]) -> Result<()> {
+        let header = self.header(height)?;
+
+        let mut tree = MerkleTree::new(1);
+        for tx in txs {
+            tree.append(BlockInfo::tx_leaf(tx)?);
+        }
+
+        if tree.root(0) != header.tree.root(0) {
+            return Err(Error::BlockTxsMismatch(header.hash()?.to_string()))
+        }
+
+        Ok(())
+    }
+
+    /// Verify a [`StateProof`] for given key, in the contract state tree of
+    /// given name, against the verified header of its height. Returns the
+    /// key value if it exists, or `None` if its absence was proven.
+    pub fn verify_state(
+        &self,
+        contract_id: &ContractId,
+        tree_name: &str,
+        key: &[u8],
+        proof: &StateProof,
+    ) -> Result<Option<Vec<u8>>> {
+        let header = self.header(proof.height)?;
+        proof.verify(&header, &contract_id.hash_state_id(tree_name), key)
+    }
+}
@@ -37,20 +37,48 @@ pub use block_store::{
 pub mod header_store;
 pub use header_store::{Header, HeaderStore, HeaderStoreOverlay};
 
+/// Contracts state commitment definitions
+pub mod state_tree;
+pub use state_tree::{RecordProof, RecordsNode, StateLeaf, StateProof, StateStorage};
+
 /// Slots storage implementation
 pub mod slot_store;
 pub use slot_store::{BlocksSlotsStore, BlocksSlotsStoreOverlay, SlotStore, SlotStoreOverlay};
 
 /// Transactions related storage implementations
 pub mod tx_store;
-pub use tx_store::{PendingTxOrderStore, PendingTxStore, TxStore, TxStoreOverlay};
+pub use tx_store::{
+    PendingTxOrderStore, PendingTxStore, PrunedTxStore, PrunedTxStoreOverlay, TxStore,
+    TxStoreOverlay,
+};
 
 /// Contracts and Wasm storage implementations
 pub mod contract_store;
 pub use contract_store::{
-    ContractStateStore, ContractStateStoreOverlay, WasmStore, WasmStoreOverlay,
+    ContractMigration, ContractStateStore, ContractStateStoreOverlay, MigrationStore,
+    MigrationStoreOverlay, StateSizeStore, StateSizeStoreOverlay, WasmStore, WasmStoreOverlay,
 };
 
+/// Contract events storage implementation
+pub mod event_store;
+pub use event_store::{ContractEvent, EventStore, EventStoreOverlay};
+
+/// Canonical state snapshots export and import
+pub mod snapshot;
+pub use snapshot::{export_snapshot, import_snapshot, verify_snapshot, SnapshotHeader};
+
+/// Light client syncing only headers and verifying state proofs
+#[cfg(feature = "validator")]
+pub mod light_client;
+#[cfg(feature = "validator")]
+pub use light_client::LightClient;
+
+/// Key of the pruned height record, in the default sled tree
+const SLED_PRUNED_HEIGHT_KEY: &[u8] = b"_pruned_height";
+
+/// Number of blocks processed at once while pruning
+const PRUNE_BATCH: u64 = 1000;
+
 /// Structure holding all sled trees that define the concept of Blockchain.
 #[derive(Clone)]
 pub struct Blockchain {
@@ -70,6 +98,8 @@ pub struct Blockchain {
     pub difficulties: BlockDifficultyStore,
     /// Transactions sled tree
     pub transactions: TxStore,
+    /// Pruned transactions hashes sled tree
+    pub pruned_txs: PrunedTxStore,
     /// Pending transactions sled tree
     pub pending_txs: PendingTxStore,
     /// Pending transactions order sled tree
@@ -78,6 +108,12 @@ pub struct Blockchain {
     pub contracts: ContractStateStore,
     /// Wasm bincodes
     pub wasm_bincode: WasmStore,
+    /// Contract state migrations
+    pub migrations: MigrationStore,
+    /// Contract state sizes
+    pub state_sizes: StateSizeStore,
+    /// Contract events
+    pub events: EventStore,
 }
 
 impl Blockchain {
@@ -90,10 +126,14 @@ impl Blockchain {
         let blocks_slots = BlocksSlotsStore::new(db)?;
         let difficulties = BlockDifficultyStore::new(db)?;
         let transactions = TxStore::new(db)?;
+        let pruned_txs = PrunedTxStore::new(db)?;
         let pending_txs = PendingTxStore::new(db)?;
         let pending_txs_order = PendingTxOrderStore::new(db)?;
         let contracts = ContractStateStore::new(db)?;
         let wasm_bincode = WasmStore::new(db)?;
+        let migrations = MigrationStore::new(db)?;
+        let state_sizes = StateSizeStore::new(db)?;
+        let events = EventStore::new(db)?;
 
         Ok(Self {
             sled_db: db.clone(),
@@ -104,10 +144,14 @@ impl Blockchain {
             blocks_slots,
             difficulties,
             transactions,
+            pruned_txs,
             pending_txs,
             pending_txs_order,
             contracts,
             wasm_bincode,
+            migrations,
+            state_sizes,
+            events,
         })
     }
 
@@ -168,26 +212,37 @@ impl Blockchain {
             Err(_) => return Ok(false),
         };
 
-        // Check if we have all transactions
+        // Check if we have all transactions, or just their hashes if the block got pruned
         let txs: Vec<blake3::Hash> =
             block.txs.iter().map(|x| blake3::hash(&serialize(x))).collect();
-        if self.transactions.get(&txs, true).is_err() {
-            return Ok(false)
-        }
-
-        // Check if we have block slots uids vector
-        let slots = match self.blocks_slots.get(&[blockhash], true) {
-            Ok(v) => v[0].clone().unwrap(),
-            Err(_) => return Ok(false),
+        let pruned = match self.transactions.get(&txs, true) {
+            Ok(_) => false,
+            Err(_) => {
+                for tx in &txs {
+                    if !self.pruned_txs.contains(tx)? {
+                        return Ok(false)
+                    }
+                }
+                true
+            }
         };
-        let provided_block_slots: Vec<u64> = block.slots.iter().map(|x| x.id).collect();
-        if slots != provided_block_slots {
-            return Ok(false)
-        }
 
-        // Check if we have all slots
-        if self.slots.get(&slots, true).is_err() {
-            return Ok(false)
+        // Pruned blocks don't keep their slots
+        if !pruned {
+            // Check if we have block slots uids vector
+            let slots = match self.blocks_slots.get(&[blockhash], true) {
+                Ok(v) => v[0].clone().unwrap(),
+                Err(_) => return Ok(false),
+            };
+            let provided_block_slots: Vec<u64> = block.slots.iter().map(|x| x.id).collect();
+            if slots != provided_block_slots {
+                return Ok(false)
+            }
+
+            // Check if we have all slots
+            if self.slots.get(&slots, true).is_err() {
+                return Ok(false)
+            }
         }
 
         // Check provided info produces the same hash
@@ -247,6 +302,76 @@ impl Blockchain {
         self.get_blocks_by_hash(&hashes)
     }
 
+    /// Retrieve n headers after given start slot.
+    pub fn get_headers_after(&self, slot: u64, n: u64) -> Result<Vec<Header>> {
+        debug!(target: "blockchain", "get_headers_after(): {} -> {}", slot, n);
+        let hashes = self.order.get_after(slot, n)?;
+        let headers = self.headers.get(&hashes, true)?;
+        Ok(headers.into_iter().map(|h| h.unwrap()).collect())
+    }
+
+    /// Retrieve the height below which blocks transactions have been pruned, if any.
+    pub fn pruned_height(&self) -> Result<Option<u64>> {
+        match self.sled_db.get(SLED_PRUNED_HEIGHT_KEY)? {
+            Some(found) => Ok(Some(deserialize(&found)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Delete the transactions and slots of all blocks with height lower than
+    /// the given one, keeping their headers, difficulties and the contracts
+    /// state they resulted in. Pruned transactions hashes are kept, so they
+    /// are still recognised as already seen. The genesis block is never pruned.
+    /// Pruned blocks can no longer be retrieved, so they can't be served to
+    /// syncing nodes. Returns the number of deleted transactions.
+    pub fn prune(&self, height: u64) -> Result<usize> {
+        let mut start = self.pruned_height()?.unwrap_or(1);
+        let mut pruned = 0;
+
+        while start < height {
+            let end = std::cmp::min(start + PRUNE_BATCH, height);
+            debug!(target: "blockchain::prune", "Pruning blocks {} to {}", start, end - 1);
+
+            let heights: Vec<u64> = (start..end).collect();
+            let hashes: Vec<blake3::Hash> =
+                self.order.get(&heights, false)?.into_iter().flatten().collect();
+            let mut txs = vec![];
+            for block in self.blocks.get(&hashes, true)?.into_iter().flatten() {
+                txs.extend(block.txs);
+            }
+            let mut slots = vec![];
+            for block_slots in self.blocks_slots.get(&hashes, false)?.into_iter().flatten() {
+                slots.extend(block_slots);
+            }
+
+            // Move the transactions into the pruned index, along with dropping
+            // the slots, and mark the range as pruned, so next run continues
+            // after it.
+            let mut progress = sled::Batch::default();
+            progress.insert(SLED_PRUNED_HEIGHT_KEY, serialize(&end));
+            let trees = [
+                self.transactions.0.clone(),
+                self.pruned_txs.0.clone(),
+                self.blocks_slots.0.clone(),
+                self.slots.0.clone(),
+                (*self.sled_db).clone(),
+            ];
+            let batches = [
+                self.transactions.remove_batch(&txs),
+                self.pruned_txs.insert_batch(&txs),
+                self.blocks_slots.remove_batch(&hashes),
+                self.slots.remove_batch(&slots),
+                progress,
+            ];
+            self.atomic_write(&trees, &batches)?;
+
+            pruned += txs.len();
+            start = end;
+        }
+
+        Ok(pruned)
+    }
+
     /// Retrieve stored blocks count
     pub fn len(&self) -> usize {
         self.order.len()
@@ -418,10 +543,18 @@ pub struct BlockchainOverlay {
     pub difficulties: BlockDifficultyStoreOverlay,
     /// Transactions overlay
     pub transactions: TxStoreOverlay,
+    /// Pruned transactions hashes overlay
+    pub pruned_txs: PrunedTxStoreOverlay,
     /// Contract states overlay
     pub contracts: ContractStateStoreOverlay,
     /// Wasm bincodes overlay
     pub wasm_bincode: WasmStoreOverlay,
+    /// Contract state migrations overlay
+    pub migrations: MigrationStoreOverlay,
+    /// Contract state sizes overlay
+    pub state_sizes: StateSizeStoreOverlay,
+    /// Contract events overlay
+    pub events: EventStoreOverlay,
 }
 
 impl BlockchainOverlay {
@@ -435,8 +568,12 @@ impl BlockchainOverlay {
         let blocks_slots = BlocksSlotsStoreOverlay::new(&overlay)?;
         let difficulties = BlockDifficultyStoreOverlay::new(&overlay)?;
         let transactions = TxStoreOverlay::new(&overlay)?;
+        let pruned_txs = PrunedTxStoreOverlay::new(&overlay)?;
         let contracts = ContractStateStoreOverlay::new(&overlay)?;
         let wasm_bincode = WasmStoreOverlay::new(&overlay)?;
+        let migrations = MigrationStoreOverlay::new(&overlay)?;
+        let state_sizes = StateSizeStoreOverlay::new(&overlay)?;
+        let events = EventStoreOverlay::new(&overlay)?;
 
         Ok(Arc::new(Mutex::new(Self {
             overlay,
@@ -447,8 +584,12 @@ impl BlockchainOverlay {
             blocks_slots,
             difficulties,
             transactions,
+            pruned_txs,
             contracts,
             wasm_bincode,
+            migrations,
+            state_sizes,
+            events,
         })))
     }
 
@@ -507,26 +648,37 @@ impl BlockchainOverlay {
             Err(_) => return Ok(false),
         };
 
-        // Check if we have all transactions
+        // Check if we have all transactions, or just their hashes if the block got pruned
         let txs: Vec<blake3::Hash> =
             block.txs.iter().map(|x| blake3::hash(&serialize(x))).collect();
-        if self.transactions.get(&txs, true).is_err() {
-            return Ok(false)
-        }
-
-        // Check if we have block slots uids vector
-        let slots = match self.blocks_slots.get(&[blockhash], true) {
-            Ok(v) => v[0].clone().unwrap(),
-            Err(_) => return Ok(false),
+        let pruned = match self.transactions.get(&txs, true) {
+            Ok(_) => false,
+            Err(_) => {
+                for tx in &txs {
+                    if !self.pruned_txs.contains(tx)? {
+                        return Ok(false)
+                    }
+                }
+                true
+            }
         };
-        let provided_block_slots: Vec<u64> = block.slots.iter().map(|x| x.id).collect();
-        if slots != provided_block_slots {
-            return Ok(false)
-        }
 
-        // Check if we have all slots
-        if self.slots.get(&slots, true).is_err() {
-            return Ok(false)
+        // Pruned blocks don't keep their slots
+        if !pruned {
+            // Check if we have block slots uids vector
+            let slots = match self.blocks_slots.get(&[blockhash], true) {
+                Ok(v) => v[0].clone().unwrap(),
+                Err(_) => return Ok(false),
+            };
+            let provided_block_slots: Vec<u64> = block.slots.iter().map(|x| x.id).collect();
+            if slots != provided_block_slots {
+                return Ok(false)
+            }
+
+            // Check if we have all slots
+            if self.slots.get(&slots, true).is_err() {
+                return Ok(false)
+            }
         }
 
         // Check provided info produces the same hash
@@ -589,8 +741,12 @@ impl BlockchainOverlay {
         let blocks_slots = BlocksSlotsStoreOverlay::new(&overlay)?;
         let difficulties = BlockDifficultyStoreOverlay::new(&overlay)?;
         let transactions = TxStoreOverlay::new(&overlay)?;
+        let pruned_txs = PrunedTxStoreOverlay::new(&overlay)?;
         let contracts = ContractStateStoreOverlay::new(&overlay)?;
         let wasm_bincode = WasmStoreOverlay::new(&overlay)?;
+        let migrations = MigrationStoreOverlay::new(&overlay)?;
+        let state_sizes = StateSizeStoreOverlay::new(&overlay)?;
+        let events = EventStoreOverlay::new(&overlay)?;
 
         Ok(Arc::new(Mutex::new(Self {
             overlay,
@@ -601,8 +757,12 @@ impl BlockchainOverlay {
             blocks_slots,
             difficulties,
             transactions,
+            pruned_txs,
             contracts,
             wasm_bincode,
+            migrations,
+            state_sizes,
+            events,
         })))
     }
 }
@@ -64,6 +64,18 @@ impl SlotStore {
         Ok(self.0.contains_key(id.to_be_bytes())?)
     }
 
+    /// Generate the sled batch corresponding to a remove, so caller
+    /// can handle the write operation.
+    pub fn remove_batch(&self, ids: &[u64]) -> sled::Batch {
+        let mut batch = sled::Batch::default();
+
+        for id in ids {
+            batch.remove(&id.to_be_bytes());
+        }
+
+        batch
+    }
+
     /// Fetch given slots from the slot store.
     /// The resulting vector contains `Option`, which is `Some` if the slot
     /// was found in the slot store, and otherwise it is `None`, if it has not.
@@ -253,6 +265,18 @@ impl BlocksSlotsStore {
         Ok(self.0.contains_key(blockhash.as_bytes())?)
     }
 
+    /// Generate the sled batch corresponding to a remove, so caller
+    /// can handle the write operation.
+    pub fn remove_batch(&self, hashes: &[blake3::Hash]) -> sled::Batch {
+        let mut batch = sled::Batch::default();
+
+        for hash in hashes {
+            batch.remove(hash.as_bytes());
+        }
+
+        batch
+    }
+
     /// Fetch given blocks slots from the blocks slots store.
     /// The resulting vector contains `Option`, which is `Some` if the block slots
     /// were found in the blocks slots store, and otherwise it is `None`, if they have not.
@@ -0,0 +1,287 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use darkfi_serial::{Decodable, Encodable, SerialDecodable, SerialEncodable, VarInt};
+use log::info;
+
+use super::Blockchain;
+use crate::{Error, Result};
+
+/// Current snapshot format version
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+/// Number of tree entries to write in a single sled batch when importing
+const IMPORT_BATCH_SIZE: u64 = 10_000;
+
+/// Marker preceding each tree entry in a snapshot
+const ENTRY_MARKER: u8 = 1;
+
+/// Marker terminating a tree's entries in a snapshot
+const END_MARKER: u8 = 0;
+
+/// Snapshot file header, describing the canonical state it contains.
+/// The state hash commits to every tree entry that follows it, so a
+/// snapshot can be checked against a hash published by a trusted party
+/// before importing it.
+#[derive(Clone, Debug, Eq, PartialEq, SerialEncodable, SerialDecodable)]
+pub struct SnapshotHeader {
+    /// Snapshot format version
+    pub version: u8,
+    /// Height of the last block included in the snapshot
+    pub height: u64,
+    /// Hash of the last block included in the snapshot
+    pub block: blake3::Hash,
+    /// Hash of the serialized snapshot trees
+    pub state_hash: blake3::Hash,
+}
+
+/// Writer wrapper hashing everything written through it
+struct HashWriter<W: Write> {
+    inner: W,
+    hasher: blake3::Hasher,
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader wrapper hashing everything read through it
+struct HashReader<R: Read> {
+    inner: R,
+    hasher: blake3::Hasher,
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Retrieve the names of the sled trees holding canonical state, sorted.
+/// Pending transactions are node local, so they are excluded.
+fn state_tree_names(blockchain: &Blockchain) -> Vec<sled::IVec> {
+    let excluded = [blockchain.pending_txs.0.name(), blockchain.pending_txs_order.0.name()];
+    let mut names: Vec<sled::IVec> =
+        blockchain.sled_db.tree_names().into_iter().filter(|x| !excluded.contains(x)).collect();
+    names.sort();
+    names
+}
+
+/// Export the full canonical state of provided [`Blockchain`] into a snapshot
+/// file at given path. Since the canonical blockchain only contains finalized
+/// blocks, the snapshot is taken at its last block height. The node must not be
+/// processing blocks while exporting, so the trees are consistent with each other.
+/// Each tree's entries are written as a stream, every entry preceded by a marker
+/// byte and the stream terminated by an empty one, followed by the number of
+/// entries counted while iterating, so readers can verify it.
+pub fn export_snapshot(blockchain: &Blockchain, path: &Path) -> Result<SnapshotHeader> {
+    let (height, block) = blockchain.last()?;
+    info!(target: "blockchain::snapshot", "Exporting snapshot at height {} ({})", height, block);
+
+    let mut header = SnapshotHeader {
+        version: SNAPSHOT_VERSION,
+        height,
+        block,
+        state_hash: blake3::Hash::from([0u8; 32]),
+    };
+
+    // Reserve space for the header, which is written once the state hash is known
+    let mut file = File::create(path)?;
+    header.encode(&mut file)?;
+
+    let mut writer = HashWriter { inner: BufWriter::new(file), hasher: blake3::Hasher::new() };
+    let names = state_tree_names(blockchain);
+    VarInt(names.len() as u64).encode(&mut writer)?;
+    for name in names {
+        let tree = blockchain.sled_db.open_tree(&name)?;
+        name.to_vec().encode(&mut writer)?;
+        let mut entries = 0;
+        for record in tree.iter() {
+            let (key, value) = record?;
+            ENTRY_MARKER.encode(&mut writer)?;
+            key.to_vec().encode(&mut writer)?;
+            value.to_vec().encode(&mut writer)?;
+            entries += 1;
+        }
+        END_MARKER.encode(&mut writer)?;
+        VarInt(entries).encode(&mut writer)?;
+    }
+
+    // Write the actual header
+    header.state_hash = writer.hasher.finalize();
+    let mut file = writer.inner.into_inner().map_err(|e| Error::from(e.into_error()))?;
+    file.seek(SeekFrom::Start(0))?;
+    header.encode(&mut file)?;
+    file.sync_all()?;
+
+    info!(target: "blockchain::snapshot", "Snapshot exported with state hash: {}", header.state_hash);
+    Ok(header)
+}
+
+/// Read the snapshot file at given path, passing each tree entry to provided
+/// visitor, along with its tree name, and verify the snapshot trees match its
+/// header state hash.
+fn read_snapshot(
+    path: &Path,
+    mut visit: impl FnMut(&[u8], Vec<u8>, Vec<u8>) -> Result<()>,
+) -> Result<SnapshotHeader> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let header = SnapshotHeader::decode(&mut reader)?;
+    if header.version != SNAPSHOT_VERSION {
+        return Err(Error::SnapshotInvalid(format!("unsupported version {}", header.version)))
+    }
+
+    let mut reader = HashReader { inner: reader, hasher: blake3::Hasher::new() };
+    let trees: VarInt = Decodable::decode(&mut reader)?;
+    for _ in 0..trees.0 {
+        let name: Vec<u8> = Decodable::decode(&mut reader)?;
+        let mut entries = 0;
+        loop {
+            let marker: u8 = Decodable::decode(&mut reader)?;
+            match marker {
+                ENTRY_MARKER => {}
+                END_MARKER => break,
+                _ => return Err(Error::SnapshotInvalid(format!("invalid entry marker {}", marker))),
+            }
+
+            let key: Vec<u8> = Decodable::decode(&mut reader)?;
+            let value: Vec<u8> = Decodable::decode(&mut reader)?;
+            visit(&name, key, value)?;
+            entries += 1;
+        }
+
+        let expected: VarInt = Decodable::decode(&mut reader)?;
+        if entries != expected.0 {
+            return Err(Error::SnapshotInvalid("tree entries count mismatch".to_string()))
+        }
+    }
+
+    if !reader.inner.fill_buf()?.is_empty() {
+        return Err(Error::SnapshotInvalid("trailing data".to_string()))
+    }
+
+    if reader.hasher.finalize() != header.state_hash {
+        return Err(Error::SnapshotInvalid("state hash mismatch".to_string()))
+    }
+
+    Ok(header)
+}
+
+/// Read the header of the snapshot file at given path, and verify the
+/// snapshot trees match its state hash.
+pub fn verify_snapshot(path: &Path) -> Result<SnapshotHeader> {
+    read_snapshot(path, |_, _, _| Ok(()))
+}
+
+/// Import the snapshot file at given path into provided [`Blockchain`], which must
+/// be empty, so a node can bootstrap from it and only sync subsequent blocks.
+/// Before anything is written, the snapshot is fully verified against its state
+/// hash, which must match the provided trusted one, and its genesis block must
+/// match the provided network one. The imported chain tip must match the one
+/// the header describes.
+pub fn import_snapshot(
+    blockchain: &Blockchain,
+    path: &Path,
+    state_hash: &blake3::Hash,
+    genesis: &blake3::Hash,
+) -> Result<SnapshotHeader> {
+    for name in blockchain.sled_db.tree_names() {
+        if !blockchain.sled_db.open_tree(name)?.is_empty() {
+            return Err(Error::SnapshotDatabaseNotEmpty)
+        }
+    }
+
+    // Verify the snapshot and retrieve its genesis block hash
+    let order_tree = blockchain.order.0.name();
+    let genesis_key = 0_u64.to_be_bytes();
+    let mut snapshot_genesis = None;
+    let header = read_snapshot(path, |name, key, value| {
+        if name == order_tree.as_ref() && key == genesis_key {
+            snapshot_genesis = Some(value);
+        }
+        Ok(())
+    })?;
+
+    if header.state_hash != *state_hash {
+        return Err(Error::SnapshotInvalid(format!(
+            "state hash {} doesn't match expected {}",
+            header.state_hash, state_hash
+        )))
+    }
+
+    if snapshot_genesis.as_deref() != Some(genesis.as_bytes().as_slice()) {
+        return Err(Error::SnapshotInvalid("genesis block mismatch".to_string()))
+    }
+    info!(target: "blockchain::snapshot", "Importing snapshot at height {} ({})", header.height, header.block);
+
+    // Write the snapshot trees, in batches
+    let mut batch = sled::Batch::default();
+    let mut batch_tree: Option<sled::Tree> = None;
+    let mut batch_size = 0;
+    let imported = read_snapshot(path, |name, key, value| {
+        if batch_tree.as_ref().map_or(true, |tree| tree.name().as_ref() != name) {
+            if let Some(tree) = &batch_tree {
+                tree.apply_batch(std::mem::take(&mut batch))?;
+            }
+            batch_tree = Some(blockchain.sled_db.open_tree(name)?);
+            batch_size = 0;
+        }
+
+        batch.insert(key, value);
+        batch_size += 1;
+        if batch_size % IMPORT_BATCH_SIZE == 0 {
+            batch_tree.as_ref().unwrap().apply_batch(std::mem::take(&mut batch))?;
+        }
+        Ok(())
+    })?;
+    if let Some(tree) = &batch_tree {
+        tree.apply_batch(batch)?;
+    }
+    blockchain.sled_db.flush()?;
+
+    // Verify the snapshot didn't change while importing it
+    if imported != header {
+        return Err(Error::SnapshotInvalid("snapshot changed while importing".to_string()))
+    }
+
+    // Verify the imported chain tip is the one the header describes
+    if blockchain.last()? != (header.height, header.block) ||
+        blockchain.last_block()?.hash()? != header.block
+    {
+        return Err(Error::SnapshotInvalid("last block mismatch".to_string()))
+    }
+
+    info!(target: "blockchain::snapshot", "Snapshot imported successfully");
+    Ok(header)
+}
@@ -0,0 +1,651 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Commitment to the contracts state, included in each block [`Header`].
+//!
+//! Each contract state tree is committed to by a [`StateLeaf`], holding the
+//! root of a sparse Merkle tree over its records, positioned by their key
+//! blake3 hash. Subtrees holding a single record are replaced by it, so a
+//! record sits at the shortest prefix of its key hash no other record shares.
+//! The leaves of all state trees are then inserted into a Poseidon sparse
+//! Merkle tree, at the index they got when first committed to, whose root
+//! is the block state root.
+//!
+//! Both trees nodes are persisted through a [`StateStorage`], so only the
+//! paths of modified records and state trees get rehashed. Headers only
+//! carry the state root. A [`StateProof`] proves a key membership, or its
+//! absence through the empty subtree or the other record its path ends at,
+//! along with its state tree leaf sparse Merkle tree path, against a block
+//! [`Header`].
+
+use darkfi_sdk::{
+    crypto::smt::{gen_empty_hashes, FieldHasher, Poseidon},
+    pasta::{group::ff::FromUniformBytes, pallas},
+};
+#[cfg(feature = "async-serial")]
+use darkfi_serial::async_trait;
+use darkfi_serial::{SerialDecodable, SerialEncodable};
+
+use super::Header;
+use crate::{Error, Result};
+
+/// Height of the contracts state sparse Merkle tree
+pub const STATE_TREE_HEIGHT: usize = 32;
+
+/// Value used for empty leaves of the contracts state sparse Merkle tree
+pub const EMPTY_STATE_LEAF: [u8; 64] = [0u8; 64];
+
+/// Poseidon hasher used by the contracts state sparse Merkle tree
+pub type StateHasher = Poseidon<pallas::Base, 2>;
+
+/// Height of a contract state tree records sparse Merkle tree,
+/// as records are positioned by their key blake3 hash
+pub const RECORDS_TREE_HEIGHT: usize = 256;
+
+/// Root of an empty records sparse Merkle tree
+pub const EMPTY_RECORDS_ROOT: [u8; 32] = [0u8; 32];
+
+/// Commitment to a single contract state tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct StateLeaf {
+    /// Contract state tree pointer, as found in the contracts store
+    pub tree: [u8; 32],
+    /// Root of the sparse Merkle tree over the state tree records
+    pub digest: blake3::Hash,
+}
+
+impl StateLeaf {
+    /// Convert the leaf into a field element, to insert into the sparse Merkle tree
+    pub fn to_field(&self) -> pallas::Base {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&self.tree);
+        hasher.update(self.digest.as_bytes());
+
+        let mut buf = [0u8; 64];
+        buf[..blake3::OUT_LEN].copy_from_slice(hasher.finalize().as_bytes());
+        pallas::Base::from_uniform_bytes(&buf)
+    }
+}
+
+/// Node of a contract state tree records sparse Merkle tree.
+/// Empty subtrees have no node.
+#[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub enum RecordsNode {
+    /// Subtree holding a single record, by its key and record hash
+    Leaf(Vec<u8>, blake3::Hash),
+    /// Subtree holding multiple records, by its root
+    Internal(blake3::Hash),
+}
+
+impl RecordsNode {
+    /// Retrieve the subtree root.
+    pub fn hash(&self) -> blake3::Hash {
+        match self {
+            Self::Leaf(_, hash) => *hash,
+            Self::Internal(hash) => *hash,
+        }
+    }
+}
+
+/// Storage of the contracts state trees nodes. Records tree nodes are keyed
+/// by their depth and the key hash prefix leading to them, while contracts
+/// state sparse Merkle tree nodes are keyed by their level, starting from the
+/// leaves, and their index in it.
+pub trait StateStorage {
+    /// Retrieve a records tree node, if its subtree is not empty.
+    fn records_node(
+        &self,
+        tree: &[u8; 32],
+        depth: usize,
+        prefix: &[u8; 32],
+    ) -> Result<Option<RecordsNode>>;
+
+    /// Write a records tree node, or remove it if its subtree became empty.
+    fn set_records_node(
+        &mut self,
+        tree: &[u8; 32],
+        depth: usize,
+        prefix: &[u8; 32],
+        node: Option<&RecordsNode>,
+    ) -> Result<()>;
+
+    /// Retrieve a contracts state sparse Merkle tree node, if it was ever set.
+    fn state_node(&self, level: usize, index: u64) -> Result<Option<pallas::Base>>;
+
+    /// Write a contracts state sparse Merkle tree node.
+    fn set_state_node(&mut self, level: usize, index: u64, node: pallas::Base) -> Result<()>;
+}
+
+/// Compute the hash of a contract state record, used as a leaf
+/// of its state tree records sparse Merkle tree.
+pub fn record_hash(key: &[u8], value: &[u8]) -> blake3::Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[0x00]);
+    hasher.update(&(key.len() as u64).to_le_bytes());
+    hasher.update(key);
+    hasher.update(value);
+    hasher.finalize()
+}
+
+/// Compute the hash of a records sparse Merkle tree node.
+pub fn record_node_hash(left: &blake3::Hash, right: &blake3::Hash) -> blake3::Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[0x01]);
+    hasher.update(left.as_bytes());
+    hasher.update(right.as_bytes());
+    hasher.finalize()
+}
+
+/// Retrieve the bit of provided key hash at given depth, which
+/// is set when its path goes to the right child.
+fn records_bit(key_hash: &[u8; 32], depth: usize) -> bool {
+    (key_hash[depth / 8] >> (7 - depth % 8)) & 1 == 1
+}
+
+/// Retrieve the prefix of provided key hash up to given depth,
+/// with the remaining bits zeroed, identifying a records tree node.
+pub fn records_prefix(key_hash: &[u8; 32], depth: usize) -> [u8; 32] {
+    let mut prefix = [0u8; 32];
+    prefix[..depth / 8].copy_from_slice(&key_hash[..depth / 8]);
+    if depth % 8 != 0 {
+        prefix[depth / 8] = key_hash[depth / 8] & (0xFF << (8 - depth % 8));
+    }
+    prefix
+}
+
+/// Retrieve the root of a records sparse Merkle tree.
+pub fn records_root<S: StateStorage>(storage: &S, tree: &[u8; 32]) -> Result<blake3::Hash> {
+    Ok(match storage.records_node(tree, 0, &[0u8; 32])? {
+        Some(node) => node.hash(),
+        None => blake3::Hash::from(EMPTY_RECORDS_ROOT),
+    })
+}
+
+/// Set the record hash of provided key in a records sparse Merkle tree,
+/// or remove it if `None`, rewriting only the nodes along its path.
+pub fn update_record<S: StateStorage>(
+    storage: &mut S,
+    tree: &[u8; 32],
+    key: &[u8],
+    record: Option<blake3::Hash>,
+) -> Result<()> {
+    let key_hash = blake3::hash(key);
+    update_records_node(storage, tree, 0, key, key_hash.as_bytes(), record)?;
+    Ok(())
+}
+
+/// Update the records tree node at given depth on the key path, returning it.
+fn update_records_node<S: StateStorage>(
+    storage: &mut S,
+    tree: &[u8; 32],
+    depth: usize,
+    key: &[u8],
+    key_hash: &[u8; 32],
+    record: Option<blake3::Hash>,
+) -> Result<Option<RecordsNode>> {
+    let prefix = records_prefix(key_hash, depth);
+    let current = storage.records_node(tree, depth, &prefix)?;
+    let node = match &current {
+        None => record.map(|hash| RecordsNode::Leaf(key.to_vec(), hash)),
+        Some(RecordsNode::Leaf(other, _)) if other == key => {
+            record.map(|hash| RecordsNode::Leaf(key.to_vec(), hash))
+        }
+        // Removing a missing key leaves the record found on its path untouched
+        Some(RecordsNode::Leaf(..)) if record.is_none() => return Ok(current.clone()),
+        // Nodes at the bottom of the tree can only be the key record
+        Some(_) if depth == RECORDS_TREE_HEIGHT => {
+            return Err(Error::StateProofInvalid("records tree is malformed".to_string()))
+        }
+        Some(RecordsNode::Leaf(other, hash)) => {
+            // Push the other record one level down, so ours can be inserted next to it
+            let other_prefix = records_prefix(blake3::hash(other).as_bytes(), depth + 1);
+            let other = RecordsNode::Leaf(other.clone(), *hash);
+            storage.set_records_node(tree, depth + 1, &other_prefix, Some(&other))?;
+            update_records_children(storage, tree, depth, key, key_hash, record)?
+        }
+        Some(RecordsNode::Internal(_)) => {
+            update_records_children(storage, tree, depth, key, key_hash, record)?
+        }
+    };
+
+    if node != current {
+        storage.set_records_node(tree, depth, &prefix, node.as_ref())?;
+    }
+
+    Ok(node)
+}
+
+/// Update the child of the records tree node at given depth on the key path,
+/// and combine it with its sibling into the node.
+fn update_records_children<S: StateStorage>(
+    storage: &mut S,
+    tree: &[u8; 32],
+    depth: usize,
+    key: &[u8],
+    key_hash: &[u8; 32],
+    record: Option<blake3::Hash>,
+) -> Result<Option<RecordsNode>> {
+    let child = update_records_node(storage, tree, depth + 1, key, key_hash, record)?;
+
+    let mut sibling_prefix = records_prefix(key_hash, depth + 1);
+    sibling_prefix[depth / 8] ^= 0x80 >> (depth % 8);
+    let sibling = storage.records_node(tree, depth + 1, &sibling_prefix)?;
+
+    let (left, right) =
+        if records_bit(key_hash, depth) { (sibling, child) } else { (child, sibling) };
+    match (left, right) {
+        (None, None) => Ok(None),
+
+        // A single record replaces its subtree
+        (Some(RecordsNode::Leaf(leaf_key, hash)), None) |
+        (None, Some(RecordsNode::Leaf(leaf_key, hash))) => {
+            let leaf_prefix = records_prefix(blake3::hash(&leaf_key).as_bytes(), depth + 1);
+            storage.set_records_node(tree, depth + 1, &leaf_prefix, None)?;
+            Ok(Some(RecordsNode::Leaf(leaf_key, hash)))
+        }
+
+        (left, right) => {
+            let empty = blake3::Hash::from(EMPTY_RECORDS_ROOT);
+            let left = left.map(|x| x.hash()).unwrap_or(empty);
+            let right = right.map(|x| x.hash()).unwrap_or(empty);
+            Ok(Some(RecordsNode::Internal(record_node_hash(&left, &right))))
+        }
+    }
+}
+
+/// Walk a records sparse Merkle tree along provided key path, returning the
+/// siblings of each node on it, from the root down, along with the record
+/// the path ends at, if its subtree is not empty.
+pub fn records_path<S: StateStorage>(
+    storage: &S,
+    tree: &[u8; 32],
+    key: &[u8],
+) -> Result<(Vec<blake3::Hash>, Option<Vec<u8>>)> {
+    let key_hash = blake3::hash(key);
+    let key_hash = key_hash.as_bytes();
+
+    let mut path = vec![];
+    for depth in 0..=RECORDS_TREE_HEIGHT {
+        match storage.records_node(tree, depth, &records_prefix(key_hash, depth))? {
+            None => return Ok((path, None)),
+            Some(RecordsNode::Leaf(leaf_key, _)) => return Ok((path, Some(leaf_key))),
+            Some(RecordsNode::Internal(_)) if depth == RECORDS_TREE_HEIGHT => break,
+            Some(RecordsNode::Internal(_)) => {
+                let mut sibling_prefix = records_prefix(key_hash, depth + 1);
+                sibling_prefix[depth / 8] ^= 0x80 >> (depth % 8);
+                let sibling = match storage.records_node(tree, depth + 1, &sibling_prefix)? {
+                    Some(node) => node.hash(),
+                    None => blake3::Hash::from(EMPTY_RECORDS_ROOT),
+                };
+                path.push(sibling);
+            }
+        }
+    }
+
+    Err(Error::StateProofInvalid("records tree is malformed".to_string()))
+}
+
+/// Generate the hashes of empty contracts state sparse Merkle tree nodes,
+/// for each level from the leaves up to the root.
+fn empty_state_nodes() -> Result<[pallas::Base; STATE_TREE_HEIGHT + 1]> {
+    Ok(gen_empty_hashes::<_, _, { STATE_TREE_HEIGHT + 1 }>(&StateHasher::new(), &EMPTY_STATE_LEAF)?)
+}
+
+/// Retrieve the contracts state root.
+pub fn state_root<S: StateStorage>(storage: &S) -> Result<pallas::Base> {
+    match storage.state_node(STATE_TREE_HEIGHT, 0)? {
+        Some(root) => Ok(root),
+        None => Ok(empty_state_nodes()?[STATE_TREE_HEIGHT]),
+    }
+}
+
+/// Set the contracts state sparse Merkle tree leaf at given index,
+/// rewriting only the nodes along its path.
+pub fn update_state_leaf<S: StateStorage>(
+    storage: &mut S,
+    mut index: u64,
+    leaf: &StateLeaf,
+) -> Result<()> {
+    if index >> STATE_TREE_HEIGHT != 0 {
+        return Err(Error::StateProofInvalid("state tree is full".to_string()))
+    }
+
+    let empty = empty_state_nodes()?;
+    let hasher = StateHasher::new();
+    let mut node = leaf.to_field();
+    storage.set_state_node(0, index, node)?;
+    for (level, empty) in empty.iter().enumerate().take(STATE_TREE_HEIGHT) {
+        let sibling = storage.state_node(level, index ^ 1)?.unwrap_or(*empty);
+        node = match index & 1 {
+            0 => hasher.hash([node, sibling])?,
+            _ => hasher.hash([sibling, node])?,
+        };
+        index >>= 1;
+        storage.set_state_node(level + 1, index, node)?;
+    }
+
+    Ok(())
+}
+
+/// Generate the contracts state sparse Merkle tree path of the leaf at
+/// given index, as the sibling of each node from the leaf up to the root.
+pub fn state_path<S: StateStorage>(storage: &S, mut index: u64) -> Result<Vec<pallas::Base>> {
+    let empty = empty_state_nodes()?;
+    let mut path = Vec::with_capacity(STATE_TREE_HEIGHT);
+    for empty in empty.iter().take(STATE_TREE_HEIGHT) {
+        path.push(storage.state_node(path.len(), index ^ 1)?.unwrap_or(*empty));
+        index >>= 1;
+    }
+
+    Ok(path)
+}
+
+/// Compute the contracts state root from a leaf at given index and its
+/// sparse Merkle tree path, as generated by [`state_path`].
+pub fn state_path_root(
+    leaf: &StateLeaf,
+    index: u64,
+    path: &[pallas::Base],
+) -> Result<pallas::Base> {
+    if path.len() != STATE_TREE_HEIGHT || index >> STATE_TREE_HEIGHT != 0 {
+        return Err(Error::StateProofInvalid("state tree leaf path is malformed".to_string()))
+    }
+
+    let hasher = StateHasher::new();
+    let mut node = leaf.to_field();
+    for (level, sibling) in path.iter().enumerate() {
+        node = match (index >> level) & 1 {
+            0 => hasher.hash([node, *sibling])?,
+            _ => hasher.hash([*sibling, node])?,
+        };
+    }
+
+    Ok(node)
+}
+
+/// A contract state tree record.
+#[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct RecordProof {
+    /// Record key
+    pub key: Vec<u8>,
+    /// Record value
+    pub value: Vec<u8>,
+}
+
+/// Proof of a key membership, or absence, in a contract state tree, against
+/// the contracts state committed to by the block [`Header`] at given height.
+/// The state tree leaf is proven against the header state root, then the key
+/// path is proven against the leaf. Membership is proven by the path ending at
+/// the key record, while absence is proven by it ending at an empty subtree,
+/// or at another record, which then holds the whole subtree.
+#[derive(Debug, Clone, PartialEq, Eq, SerialEncodable, SerialDecodable)]
+pub struct StateProof {
+    /// Height of the block header the proof is against
+    pub height: u64,
+    /// Contract state tree leaf, committing to its records
+    pub leaf: StateLeaf,
+    /// Leaf position in the contracts state sparse Merkle tree
+    pub leaf_index: u64,
+    /// Leaf sparse Merkle tree path, from the leaf up to the state root
+    pub leaf_path: Vec<pallas::Base>,
+    /// Siblings of the nodes on the key path, from the records tree root down
+    pub path: Vec<blake3::Hash>,
+    /// The record the key path ends at, if its subtree is not empty
+    pub record: Option<RecordProof>,
+}
+
+impl StateProof {
+    /// Verify the proof for given key, in the state tree of given pointer,
+    /// against provided header, which must already be trusted. Returns the
+    /// key value if it exists, or `None` if its absence was proven.
+    pub fn verify(&self, header: &Header, tree: &[u8; 32], key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let invalid = |reason: &str| Err(Error::StateProofInvalid(reason.to_string()));
+
+        if header.height != self.height || self.leaf.tree != *tree {
+            return invalid("proof is for another header or tree")
+        }
+
+        // Header state root must commit to the state tree leaf
+        let leaf = &self.leaf;
+        if state_path_root(leaf, self.leaf_index, &self.leaf_path)? != header.state_root {
+            return invalid("state tree leaf isn't committed to by the header")
+        }
+
+        if self.path.len() > RECORDS_TREE_HEIGHT {
+            return invalid("records path is malformed")
+        }
+
+        // The path must end at the key record, another record on the key path, or nothing
+        let key_hash = blake3::hash(key);
+        let depth = self.path.len();
+        let (mut node, value) = match &self.record {
+            Some(record) => {
+                let record_key_hash = blake3::hash(&record.key);
+                if records_prefix(record_key_hash.as_bytes(), depth) !=
+                    records_prefix(key_hash.as_bytes(), depth)
+                {
+                    return invalid("record is not on the key path")
+                }
+                let value = if record.key == key { Some(record.value.clone()) } else { None };
+                (record_hash(&record.key, &record.value), value)
+            }
+            None => (blake3::Hash::from(EMPTY_RECORDS_ROOT), None),
+        };
+
+        for (depth, sibling) in self.path.iter().enumerate().rev() {
+            node = match records_bit(key_hash.as_bytes(), depth) {
+                false => record_node_hash(&node, sibling),
+                true => record_node_hash(sibling, &node),
+            };
+        }
+
+        if node != leaf.digest {
+            return invalid("records path doesn't match the state tree leaf")
+        }
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use darkfi_sdk::crypto::smt::SparseMerkleTree;
+
+    use super::*;
+
+    /// In memory state storage
+    #[derive(Default)]
+    struct MemoryStorage {
+        records: HashMap<([u8; 32], usize, [u8; 32]), RecordsNode>,
+        state: HashMap<(usize, u64), pallas::Base>,
+    }
+
+    impl StateStorage for MemoryStorage {
+        fn records_node(
+            &self,
+            tree: &[u8; 32],
+            depth: usize,
+            prefix: &[u8; 32],
+        ) -> Result<Option<RecordsNode>> {
+            Ok(self.records.get(&(*tree, depth, *prefix)).cloned())
+        }
+
+        fn set_records_node(
+            &mut self,
+            tree: &[u8; 32],
+            depth: usize,
+            prefix: &[u8; 32],
+            node: Option<&RecordsNode>,
+        ) -> Result<()> {
+            match node {
+                Some(node) => self.records.insert((*tree, depth, *prefix), node.clone()),
+                None => self.records.remove(&(*tree, depth, *prefix)),
+            };
+            Ok(())
+        }
+
+        fn state_node(&self, level: usize, index: u64) -> Result<Option<pallas::Base>> {
+            Ok(self.state.get(&(level, index)).cloned())
+        }
+
+        fn set_state_node(&mut self, level: usize, index: u64, node: pallas::Base) -> Result<()> {
+            self.state.insert((level, index), node);
+            Ok(())
+        }
+    }
+
+    fn leaf(tree: u8, digest: &[u8]) -> StateLeaf {
+        StateLeaf { tree: [tree; 32], digest: blake3::hash(digest) }
+    }
+
+    #[test]
+    fn records_updates() -> Result<()> {
+        let tree = [7u8; 32];
+        let mut storage = MemoryStorage::default();
+        assert_eq!(records_root(&storage, &tree)?, blake3::Hash::from(EMPTY_RECORDS_ROOT));
+
+        // A single record is the tree root
+        let hash = record_hash(&[0], &[0]);
+        update_record(&mut storage, &tree, &[0], Some(hash))?;
+        assert_eq!(records_root(&storage, &tree)?, hash);
+        assert_eq!(storage.records.len(), 1);
+
+        // The root only depends on the records set, not the order they were written in
+        let mut roots = vec![];
+        for keys in [[1u8, 2, 3], [3, 1, 2]] {
+            let mut storage = MemoryStorage::default();
+            for key in keys {
+                update_record(&mut storage, &tree, &[key], Some(record_hash(&[key], &[key])))?;
+            }
+            roots.push(records_root(&storage, &tree)?);
+        }
+        assert_eq!(roots[0], roots[1]);
+
+        // Removing records collapses the tree back, dropping its nodes
+        for key in 1u8..4 {
+            update_record(&mut storage, &tree, &[key], Some(record_hash(&[key], &[key])))?;
+        }
+        update_record(&mut storage, &tree, &[9], None)?;
+        for key in 1u8..4 {
+            update_record(&mut storage, &tree, &[key], None)?;
+        }
+        assert_eq!(records_root(&storage, &tree)?, hash);
+        assert_eq!(storage.records.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn state_proofs() -> Result<()> {
+        let tree = [7u8; 32];
+        let mut storage = MemoryStorage::default();
+        for key in [1u8, 3] {
+            update_record(&mut storage, &tree, &[key], Some(record_hash(&[key], &[key])))?;
+        }
+        let leaves = [leaf(0, b"a"), StateLeaf { tree, digest: records_root(&storage, &tree)? }];
+        for (index, leaf) in leaves.iter().enumerate() {
+            update_state_leaf(&mut storage, index as u64, leaf)?;
+        }
+
+        let mut header = Header::default();
+        header.state_root = state_root(&storage)?;
+
+        let proof = |key: u8| -> Result<StateProof> {
+            let (path, record) = records_path(&storage, &tree, &[key])?;
+            let record = record.map(|key| RecordProof { value: key.clone(), key });
+            Ok(StateProof {
+                height: header.height,
+                leaf: leaves[1],
+                leaf_index: 1,
+                leaf_path: state_path(&storage, 1)?,
+                path,
+                record,
+            })
+        };
+
+        // Membership
+        assert_eq!(proof(3)?.verify(&header, &tree, &[3])?, Some(vec![3]));
+
+        // Absence
+        for key in [0u8, 2, 4] {
+            assert_eq!(proof(key)?.verify(&header, &tree, &[key])?, None);
+        }
+
+        // A record not on the key path doesn't prove its absence
+        let mut moved = proof(3)?;
+        moved.record = Some(RecordProof { key: vec![5], value: vec![5] });
+        assert!(moved.verify(&header, &tree, &[3]).is_err());
+
+        // Tampered records are rejected
+        let mut tampered = proof(3)?;
+        tampered.record.as_mut().unwrap().value = vec![4];
+        assert!(tampered.verify(&header, &tree, &[3]).is_err());
+
+        // A member key can't be proven absent by hiding its record
+        let mut hidden = proof(3)?;
+        hidden.record = None;
+        assert!(hidden.verify(&header, &tree, &[3]).is_err());
+
+        // Leaves not committed to by the header are rejected
+        let mut moved = proof(3)?;
+        moved.leaf_index = 0;
+        assert!(moved.verify(&header, &tree, &[3]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn state_root_and_paths() -> Result<()> {
+        let ours = [leaf(0, b"a"), leaf(1, b"b"), leaf(2, b"c")];
+        let mut storage = MemoryStorage::default();
+        for (index, leaf) in ours.iter().enumerate() {
+            update_state_leaf(&mut storage, index as u64, leaf)?;
+        }
+
+        // Incremental updates match the sparse Merkle tree built from scratch
+        let leaves: BTreeMap<u32, pallas::Base> =
+            ours.iter().enumerate().map(|(i, x)| (i as u32, x.to_field())).collect();
+        let smt = SparseMerkleTree::<pallas::Base, StateHasher, STATE_TREE_HEIGHT>::new(
+            &leaves,
+            &StateHasher::new(),
+            &EMPTY_STATE_LEAF,
+        )?;
+        assert_eq!(state_root(&storage)?, smt.root());
+
+        // A changed record digest changes the root
+        let root = state_root(&storage)?;
+        update_state_leaf(&mut storage, 1, &leaf(1, b"x"))?;
+        assert_ne!(state_root(&storage)?, root);
+        update_state_leaf(&mut storage, 1, &ours[1])?;
+        assert_eq!(state_root(&storage)?, root);
+
+        // Leaves can be proven against the root, at their position only
+        for (index, leaf) in ours.iter().enumerate() {
+            let path = state_path(&storage, index as u64)?;
+            assert_eq!(state_path_root(leaf, index as u64, &path)?, root);
+            assert_ne!(state_path_root(leaf, index as u64 + 1, &path)?, root);
+        }
+
+        // Malformed paths are rejected
+        assert!(state_path_root(&ours[0], 0, &[]).is_err());
+
+        Ok(())
+    }
+}
@@ -27,6 +27,7 @@ use super::{parse_record, parse_u64_key_record, SledDbOverlayPtr};
 const SLED_TX_TREE: &[u8] = b"_transactions";
 const SLED_PENDING_TX_TREE: &[u8] = b"_pending_transactions";
 const SLED_PENDING_TX_ORDER_TREE: &[u8] = b"_pending_transactions_order";
+const SLED_PRUNED_TX_TREE: &[u8] = b"_pruned_transactions";
 
 /// The `TxStore` is a `sled` tree storing all the blockchain's
 /// transactions where the key is the transaction hash, and the value is
@@ -118,6 +119,25 @@ impl TxStore {
         Ok(txs)
     }
 
+    /// Remove a slice of [`blake3::Hash`] from the txstore.
+    pub fn remove(&self, txs_hashes: &[blake3::Hash]) -> Result<()> {
+        let batch = self.remove_batch(txs_hashes);
+        self.0.apply_batch(batch)?;
+        Ok(())
+    }
+
+    /// Generate the sled batch corresponding to a remove, so caller
+    /// can handle the write operation.
+    pub fn remove_batch(&self, txs_hashes: &[blake3::Hash]) -> sled::Batch {
+        let mut batch = sled::Batch::default();
+
+        for tx_hash in txs_hashes {
+            batch.remove(tx_hash.as_bytes());
+        }
+
+        batch
+    }
+
     /// Retrieve records count
     pub fn len(&self) -> usize {
         self.0.len()
@@ -186,6 +206,62 @@ impl TxStoreOverlay {
     }
 }
 
+/// The `PrunedTxStore` is a `sled` tree storing the hashes of all the
+/// transactions pruned from the [`TxStore`], so they are still known
+/// to the blockchain. The key is the transaction hash, and the value
+/// is empty.
+#[derive(Clone)]
+pub struct PrunedTxStore(pub sled::Tree);
+
+impl PrunedTxStore {
+    /// Opens a new or existing `PrunedTxStore` on the given sled database.
+    pub fn new(db: &sled::Db) -> Result<Self> {
+        let tree = db.open_tree(SLED_PRUNED_TX_TREE)?;
+        Ok(Self(tree))
+    }
+
+    /// Generate the sled batch corresponding to an insert, so caller
+    /// can handle the write operation.
+    pub fn insert_batch(&self, tx_hashes: &[blake3::Hash]) -> sled::Batch {
+        let mut batch = sled::Batch::default();
+
+        for tx_hash in tx_hashes {
+            batch.insert(tx_hash.as_bytes(), &[] as &[u8]);
+        }
+
+        batch
+    }
+
+    /// Check if the pruned tx store contains a given transaction hash.
+    pub fn contains(&self, tx_hash: &blake3::Hash) -> Result<bool> {
+        Ok(self.0.contains_key(tx_hash.as_bytes())?)
+    }
+
+    /// Retrieve records count
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Overlay structure over a [`PrunedTxStore`] instance.
+pub struct PrunedTxStoreOverlay(SledDbOverlayPtr);
+
+impl PrunedTxStoreOverlay {
+    pub fn new(overlay: &SledDbOverlayPtr) -> Result<Self> {
+        overlay.lock().unwrap().open_tree(SLED_PRUNED_TX_TREE)?;
+        Ok(Self(overlay.clone()))
+    }
+
+    /// Check if the overlay contains a given transaction hash.
+    pub fn contains(&self, tx_hash: &blake3::Hash) -> Result<bool> {
+        Ok(self.0.lock().unwrap().contains_key(SLED_PRUNED_TX_TREE, tx_hash.as_bytes())?)
+    }
+}
+
 /// The `PendingTxStore` is a `sled` tree storing all the node pending
 /// transactions where the key is the transaction hash, and the value is
 /// the serialized transaction.
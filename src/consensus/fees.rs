@@ -77,6 +77,7 @@ pub fn circuit_gas_use(zkbin: &ZkBinary) -> u64 {
             Opcode::ConstrainEqualBase => 10,
             Opcode::ConstrainEqualPoint => 20,
             Opcode::ConstrainInstance => 10,
+            Opcode::ConstrainEncryptedInstance => 20 + 20 * opcode.1.len() as u64,
             Opcode::DebugPrint => 100,
         };
 
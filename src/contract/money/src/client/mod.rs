@@ -26,14 +26,15 @@
 //! the necessary objects provided by the caller. This is intentional, so we
 //! are able to abstract away any wallet interfaces to client implementations.
 
+use darkfi::validator::mempool::CallDecoder;
 use darkfi_sdk::{
     bridgetree,
-    crypto::{pasta_prelude::*, Nullifier, SecretKey, TokenId, DARK_TOKEN_ID},
+    crypto::{pasta_prelude::*, Nullifier, SecretKey, TokenId, DARK_TOKEN_ID, MONEY_CONTRACT_ID},
     pasta::pallas,
 };
 use darkfi_serial::{async_trait, SerialDecodable, SerialEncodable};
 
-use crate::model::Coin;
+use crate::model::{decode_fee_paid, decode_nullifiers, Coin};
 
 /// `Money::FeeV1` API
 pub mod fee_v1;
@@ -62,6 +63,16 @@ pub mod unstake_v1;
 /// `Money::PoWRewardV1` API
 pub mod pow_reward_v1;
 
+/// Decoder of this contract's calls, used by nodes to track the
+/// fees paid and the nullifiers revealed by pending transactions.
+pub fn call_decoder() -> CallDecoder {
+    CallDecoder {
+        contract_id: *MONEY_CONTRACT_ID,
+        fee_paid: decode_fee_paid,
+        nullifiers: decode_nullifiers,
+    }
+}
+
 // Wallet SQL table constant names. These have to represent the `wallet.sql`
 // SQL schema.
 // TODO: They should also be prefixed with the contract ID to avoid collisions.
@@ -24,7 +24,9 @@ use darkfi_sdk::{
     error::ContractError,
     pasta::pallas,
 };
-use darkfi_serial::{SerialDecodable, SerialEncodable};
+use darkfi_serial::{deserialize, SerialDecodable, SerialEncodable};
+
+use crate::MoneyFunction;
 
 #[cfg(feature = "client")]
 use darkfi_serial::async_trait;
@@ -385,3 +387,42 @@ pub struct ConsensusUnstakeUpdateV1 {
     pub nullifier: Nullifier,
 }
 // ANCHOR_END: ConsensusUnstakeUpdate
+
+/// Retrieve the fee paid by provided `Money::FeeV1` call data, if it is one.
+/// Used by nodes to prioritise pending transactions.
+pub fn decode_fee_paid(data: &[u8]) -> Option<u64> {
+    if data.len() < 9 || data[0] != MoneyFunction::FeeV1 as u8 {
+        return None
+    }
+
+    // The first 8 bytes after the function identifier are the u64 fee
+    deserialize(&data[1..9]).ok()
+}
+
+/// Retrieve the nullifiers revealed by provided Money contract call data,
+/// or `None` if it can't be decoded. Used by nodes to detect pending
+/// transactions spending the same coins.
+pub fn decode_nullifiers(data: &[u8]) -> Option<Vec<Nullifier>> {
+    let (function, params) = data.split_first()?;
+    let nullifiers = match MoneyFunction::try_from(*function).ok()? {
+        MoneyFunction::FeeV1 => {
+            let params: MoneyFeeParamsV1 = deserialize(params.get(8..)?).ok()?;
+            vec![params.input.nullifier]
+        }
+        MoneyFunction::TransferV1 | MoneyFunction::OtcSwapV1 => {
+            let params: MoneyTransferParamsV1 = deserialize(params).ok()?;
+            params.inputs.iter().map(|input| input.nullifier).collect()
+        }
+        MoneyFunction::StakeV1 => {
+            let params: MoneyStakeParamsV1 = deserialize(params).ok()?;
+            vec![params.input.nullifier]
+        }
+        MoneyFunction::UnstakeV1 => {
+            let params: MoneyUnstakeParamsV1 = deserialize(params).ok()?;
+            vec![params.input.nullifier]
+        }
+        _ => vec![],
+    };
+
+    Some(nullifiers)
+}
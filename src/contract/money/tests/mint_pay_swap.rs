@@ -29,6 +29,7 @@
 
 use darkfi::Result;
 use darkfi_contract_test_harness::{init_logger, Holder, TestHarness};
+use darkfi_money_contract::model::{decode_fee_paid, decode_nullifiers};
 use log::info;
 
 #[test]
@@ -118,6 +119,11 @@ fn mint_pay_swap() -> Result<()> {
         alice_owncoins.retain(|x| x != &spent_coins[0]);
         assert!(alice_owncoins.is_empty());
 
+        // Nodes decode the revealed nullifiers from the call data
+        let nullifiers = decode_nullifiers(&transfer_tx.calls[0].data.data).unwrap();
+        assert_eq!(nullifiers, vec![transfer_params.inputs[0].nullifier]);
+        assert_eq!(decode_fee_paid(&transfer_tx.calls[0].data.data), None);
+
         for holder in &HOLDERS {
             info!(target: "money", "[{holder:?}] ==============================");
             info!(target: "money", "[{holder:?}] Executing Alice2Bob payment tx");
@@ -16,9 +16,9 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{env, str::FromStr};
+use std::{env, str::FromStr, time::Instant};
 
-use darkfi::Result;
+use darkfi::{runtime::module_cache::MODULE_CACHE, Result};
 use darkfi_contract_test_harness::{init_logger, Holder, TestHarness};
 use log::info;
 use rand::{prelude::IteratorRandom, Rng};
@@ -253,3 +253,79 @@ fn alice2alice_multiplecoins_random_amounts() -> Result<()> {
         Ok(())
     })
 }
+
+#[test]
+#[ignore]
+fn transfer_verification_module_cache() -> Result<()> {
+    smol::block_on(async {
+        init_logger();
+
+        // Holders this test will use
+        const HOLDERS: [Holder; 2] = [Holder::Faucet, Holder::Alice];
+
+        const ALICE_AIRDROP: u64 = 1000;
+
+        // Slot to verify against
+        let current_slot = 0;
+
+        // n transactions to verify
+        let mut n = 10;
+        for arg in env::args() {
+            match usize::from_str(&arg) {
+                Ok(v) => {
+                    n = v;
+                    break
+                }
+                Err(_) => continue,
+            };
+        }
+
+        // Initialize harness
+        let mut th = TestHarness::new(&["money".to_string()], false).await?;
+
+        let (airdrop_tx, airdrop_params) =
+            th.airdrop_native(ALICE_AIRDROP, &Holder::Alice, None, None)?;
+        for holder in &HOLDERS {
+            th.execute_airdrop_native_tx(holder, &airdrop_tx, &airdrop_params, current_slot)
+                .await?;
+        }
+
+        let owncoin = th.gather_owncoin(&Holder::Alice, &airdrop_params.outputs[0], None)?;
+        let token_id = owncoin.note.token_id;
+
+        // Build transactions spending the same coin. Each of them is valid
+        // on its own, and we never apply them.
+        let mut txs = vec![];
+        for _ in 0..n {
+            let amount = rand::thread_rng().gen_range(1..ALICE_AIRDROP);
+            let (tx, _, _) =
+                th.transfer(amount, &Holder::Alice, &Holder::Alice, &[owncoin.clone()], token_id)?;
+            txs.push(tx);
+        }
+
+        // Verify them with every contract being compiled on each call,
+        // and then with the compiled modules being cached.
+        for enabled in [false, true] {
+            MODULE_CACHE.clear();
+            MODULE_CACHE.set_enabled(enabled);
+
+            let timer = Instant::now();
+            for tx in &txs {
+                th.verify_transfer_tx(&Holder::Faucet, tx, current_slot).await?;
+            }
+            let elapsed = timer.elapsed();
+
+            info!(
+                target: "money",
+                "Verified {} transfers with module cache {}: {:?} ({:?}/tx)",
+                n,
+                if enabled { "enabled" } else { "disabled" },
+                elapsed,
+                elapsed / n as u32,
+            );
+        }
+
+        // Thanks for reading
+        Ok(())
+    })
+}
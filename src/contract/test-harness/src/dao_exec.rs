@@ -219,6 +219,8 @@ impl TestHarness {
                 DarkLeaf { data: xfer_call, parent_index: Some(2), children_indexes: vec![] },
                 DarkLeaf { data: exec_call, parent_index: None, children_indexes: vec![0, 1] },
             ],
+            valid_from: None,
+            valid_until: None,
             proofs: vec![auth_xfer_proofs, xfer_secrets.proofs, exec_proofs],
             signatures: vec![],
         };
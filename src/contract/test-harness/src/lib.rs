@@ -19,7 +19,8 @@
 use std::{collections::HashMap, io::Cursor, time::Instant};
 
 use darkfi::{
-    blockchain::BlockInfo,
+    blockchain::{BlockInfo, BlockchainOverlay, BlockchainOverlayPtr},
+    runtime::{native::NativeRuntime, trace::ExecutionTrace},
     tx::Transaction,
     util::{
         pcg::Pcg32,
@@ -33,7 +34,9 @@ use darkfi::{
 };
 use darkfi_dao_contract::model::{DaoBulla, DaoProposalBulla};
 use darkfi_money_contract::{
-    client::{ConsensusNote, ConsensusOwnCoin, MoneyNote, OwnCoin},
+    client::{
+        call_decoder as money_call_decoder, ConsensusNote, ConsensusOwnCoin, MoneyNote, OwnCoin,
+    },
     model::{ConsensusOutput, Output},
 };
 use darkfi_sdk::{
@@ -43,6 +46,7 @@ use darkfi_sdk::{
         pasta_prelude::Field, poseidon_hash, ContractId, Keypair, MerkleNode, MerkleTree,
         Nullifier, PublicKey, SecretKey, TokenId,
     },
+    entrypoint::NativeContract,
     pasta::pallas,
 };
 use log::{info, warn};
@@ -170,6 +174,7 @@ impl Wallet {
             faucet_pubkeys.to_vec(),
             false,
             verify_fees,
+            vec![money_call_decoder()],
         );
         let validator = Validator::new(&sled_db, config).await?;
 
@@ -312,6 +317,56 @@ impl TestHarness {
         Ok(())
     }
 
+    /// Execute given [`Transaction`] against the holder's state with tracing
+    /// enabled, without applying it, and dump the execution traces as JSON.
+    /// Useful when debugging a failing transaction.
+    pub async fn trace_tx(
+        &self,
+        holder: &Holder,
+        tx: &Transaction,
+        slot: u64,
+    ) -> Result<Vec<ExecutionTrace>> {
+        let wallet = self.holders.get(holder).unwrap();
+        let (traces, err) = wallet.validator.trace_transaction(tx, slot).await?;
+
+        for trace in &traces {
+            info!(target: "test_harness", "{}", trace.to_json().stringify().unwrap());
+        }
+
+        if let Some(e) = err {
+            warn!(target: "test_harness", "Transaction execution failed: {}", e);
+        }
+
+        Ok(traces)
+    }
+
+    /// Create a [`NativeRuntime`] running a natively linked contract over a
+    /// fresh overlay of the holder's blockchain, verifying the given slot.
+    /// The contract doesn't have to be compiled to WASM, and panics in it
+    /// are returned as [`darkfi::Error::ContractPanic`]. The overlay is
+    /// returned as well, so the resulting state can be inspected.
+    pub fn native_runtime(
+        &self,
+        holder: &Holder,
+        contract: NativeContract,
+        contract_id: ContractId,
+        slot: u64,
+    ) -> Result<(NativeRuntime, BlockchainOverlayPtr)> {
+        let wallet = self.holders.get(holder).unwrap();
+        let overlay = BlockchainOverlay::new(&wallet.validator.blockchain)?;
+
+        let current_time_keeper = &wallet.validator.consensus.time_keeper;
+        let time_keeper = TimeKeeper::new(
+            current_time_keeper.genesis_ts,
+            current_time_keeper.epoch_length,
+            current_time_keeper.slot_time,
+            slot,
+        );
+
+        let runtime = NativeRuntime::new(contract, overlay.clone(), contract_id, time_keeper)?;
+        Ok((runtime, overlay))
+    }
+
     pub fn gather_owncoin(
         &mut self,
         holder: &Holder,
@@ -345,6 +345,15 @@ pub enum Error {
     #[error("Miner task stopped")]
     MinerTaskStopped,
 
+    #[error("Events task stopped")]
+    EventsTaskStopped,
+
+    #[error("Prune task stopped")]
+    PruneTaskStopped,
+
+    #[error("Stratum server stopped")]
+    StratumServerStopped,
+
     #[error("Calculated total work is zero")]
     PoWTotalWorkIsZero,
 
@@ -357,6 +366,9 @@ pub enum Error {
     #[error("Provided output hash is greater than current target")]
     PoWInvalidOutHash,
 
+    #[error("RandomX VM setup failed: {0}")]
+    PoWRandomXSetupFailed(String),
+
     // ===============
     // Database errors
     // ===============
@@ -378,6 +390,9 @@ pub enum Error {
     #[error("Transaction already seen")]
     TransactionAlreadySeen,
 
+    #[error("Events of transaction {0} not found in database")]
+    EventsNotFound(String),
+
     #[error("Input vectors have different length")]
     InvalidInputLengths,
 
@@ -405,6 +420,24 @@ pub enum Error {
     #[error("Block {0} contains 0 transactions")]
     BlockContainsNoTransactions(String),
 
+    #[error("Block {0} state diverges, modified contract state trees: {1}")]
+    BlockStateDiverged(String, String),
+
+    #[error("Block {0} transactions don't match its header")]
+    BlockTxsMismatch(String),
+
+    #[error("State proof is invalid: {0}")]
+    StateProofInvalid(String),
+
+    #[error("Genesis header doesn't match the network or stored one")]
+    GenesisHeaderMismatch,
+
+    #[error("Headers don't extend any stored header")]
+    HeadersNotExtending,
+
+    #[error("Headers fork isn't heavier than the stored chain")]
+    HeadersForkNotHeavier,
+
     #[error("Verifying slot missmatch")]
     VerifyingSlotMissmatch(),
 
@@ -432,6 +465,12 @@ pub enum Error {
     #[error("zkas bincode not found in sled database")]
     ZkasBincodeNotFound,
 
+    #[error("Snapshot is invalid: {0}")]
+    SnapshotInvalid(String),
+
+    #[error("Snapshot can only be imported into an empty database")]
+    SnapshotDatabaseNotEmpty,
+
     // =============
     // Wallet errors
     // =============
@@ -475,6 +514,10 @@ pub enum Error {
     #[error("Contract execution failed: {0}")]
     ContractError(darkfi_sdk::error::ContractError),
 
+    #[cfg(feature = "native-runtime")]
+    #[error("Contract panicked: {0}")]
+    ContractPanic(String),
+
     #[cfg(feature = "darkfi-sdk")]
     #[error("Invalid DarkTree: {0}")]
     DarkTreeError(darkfi_sdk::error::DarkTreeError),
@@ -536,6 +579,9 @@ pub enum Error {
     #[error("Failed decoding bincode: {0}")]
     ZkasDecoderError(String),
 
+    #[error("Failed evaluating zkas circuit: {0}")]
+    ZkasEvalError(String),
+
     #[cfg(feature = "util")]
     #[error("System clock is not correct!")]
     InvalidClock,
@@ -638,6 +684,18 @@ pub enum TxVerifyFailed {
     #[error("Insufficient fee paid")]
     InsufficientFee,
 
+    #[error("Transaction is not valid at block height {0}")]
+    OutsideValidityWindow(u64),
+
+    #[error("Transaction fee rate is too low to replace pending transaction {0}")]
+    ReplacementFeeTooLow(String),
+
+    #[error("Mempool is full and transaction fee rate is too low")]
+    MempoolFull,
+
+    #[error("Nullifier {0} is revealed more than once in transaction")]
+    DuplicateNullifier(String),
+
     #[error("Erroneous transactions found")]
     ErroneousTxs(Vec<crate::tx::Transaction>),
 }
@@ -0,0 +1,294 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Versioned gas schedules of the WASM runtime.
+//!
+//! A [`GasSchedule`] describes how much gas every WASM operator and every
+//! host function costs, along with the gas limit of a single contract call.
+//! Schedules are activated at a given block height, so pricing can be
+//! upgraded by appending a new schedule to [`GAS_SCHEDULES`], while blocks
+//! before its activation keep being verified with the previous one.
+
+use wasmer::wasmparser::Operator;
+
+/// Cost of a host function, charged as `base + per_byte * bytes`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HostCost {
+    /// Fixed cost of a call
+    pub base: u64,
+    /// Cost of every byte (or item) processed by the call
+    pub per_byte: u64,
+}
+
+impl HostCost {
+    pub const fn new(base: u64, per_byte: u64) -> Self {
+        Self { base, per_byte }
+    }
+
+    /// Gas cost of a call processing the given amount of bytes
+    pub fn gas(&self, bytes: u64) -> u64 {
+        self.base.saturating_add(self.per_byte.saturating_mul(bytes))
+    }
+}
+
+/// Host functions with a configurable cost
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostFunction {
+    DrkLog,
+    SetReturnData,
+    PutObjectBytes,
+    GetObjectBytes,
+    GetObjectSize,
+    DbInit,
+    DbLookup,
+    DbSet,
+    DbDel,
+    DbGet,
+    DbContainsKey,
+    /// Charged for the arguments of `db_iter_prefix` and `db_range`
+    DbIter,
+    /// Charged for every record returned by `db_iter_prefix` and `db_range`
+    DbIterItem,
+    ZkasDbSet,
+    MerkleAdd,
+    GetSlot,
+    EmitEvent,
+    CallContractView,
+}
+
+/// Costs of the host functions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HostCosts {
+    pub drk_log: HostCost,
+    pub set_return_data: HostCost,
+    pub put_object_bytes: HostCost,
+    pub get_object_bytes: HostCost,
+    pub get_object_size: HostCost,
+    pub db_init: HostCost,
+    pub db_lookup: HostCost,
+    pub db_set: HostCost,
+    pub db_del: HostCost,
+    pub db_get: HostCost,
+    pub db_contains_key: HostCost,
+    pub db_iter: HostCost,
+    pub db_iter_item: HostCost,
+    pub zkas_db_set: HostCost,
+    pub merkle_add: HostCost,
+    pub get_slot: HostCost,
+    pub emit_event: HostCost,
+    pub call_contract_view: HostCost,
+}
+
+/// Costs of the WASM operator classes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OperatorCosts {
+    /// Any operator not in the classes below
+    pub base: u64,
+    /// Multiplication, division, remainder and floating point arithmetic
+    pub arithmetic: u64,
+    /// Linear memory loads, stores and bulk operations
+    pub memory: u64,
+    /// Direct and indirect function calls
+    pub call: u64,
+    /// `memory.grow`. The amount of pages is not known when the module is
+    /// compiled, so this is a flat cost on top of the host-side limits.
+    pub memory_grow: u64,
+}
+
+impl OperatorCosts {
+    /// Return the cost of the given operator
+    pub fn cost(&self, operator: &Operator) -> u64 {
+        use Operator::*;
+
+        match operator {
+            MemoryGrow { .. } => self.memory_grow,
+
+            Call { .. } | CallIndirect { .. } => self.call,
+
+            I32Load { .. } |
+            I64Load { .. } |
+            F32Load { .. } |
+            F64Load { .. } |
+            I32Load8S { .. } |
+            I32Load8U { .. } |
+            I32Load16S { .. } |
+            I32Load16U { .. } |
+            I64Load8S { .. } |
+            I64Load8U { .. } |
+            I64Load16S { .. } |
+            I64Load16U { .. } |
+            I64Load32S { .. } |
+            I64Load32U { .. } |
+            I32Store { .. } |
+            I64Store { .. } |
+            F32Store { .. } |
+            F64Store { .. } |
+            I32Store8 { .. } |
+            I32Store16 { .. } |
+            I64Store8 { .. } |
+            I64Store16 { .. } |
+            I64Store32 { .. } |
+            MemorySize { .. } |
+            MemoryCopy { .. } |
+            MemoryFill { .. } |
+            MemoryInit { .. } => self.memory,
+
+            I32Mul | I32DivS | I32DivU | I32RemS | I32RemU | I64Mul | I64DivS | I64DivU |
+            I64RemS | I64RemU | F32Add | F32Sub | F32Mul | F32Div | F32Sqrt | F64Add | F64Sub |
+            F64Mul | F64Div | F64Sqrt => self.arithmetic,
+
+            _ => self.base,
+        }
+    }
+}
+
+/// A versioned gas schedule of the WASM runtime
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GasSchedule {
+    /// Schedule version, part of the compiled modules cache key
+    pub version: u32,
+    /// Block height from which this schedule applies
+    pub activation_height: u64,
+    /// Gas limit for a single contract call (Single WASM instance)
+    pub gas_limit: u64,
+    /// Costs of the WASM operators
+    pub operators: OperatorCosts,
+    /// Costs of the host functions
+    pub host: HostCosts,
+    /// Cost of every WASM page the host grows the memory by to fit a payload
+    pub memory_grow_per_page: u64,
+    /// Cost of every opcode, witness and literal of a deployed zkas bincode
+    pub zkas_bincode_item: u64,
+    /// Storage fee of every byte a contract adds to its state with
+    /// `db_set`, `zkas_db_set` or `merkle_add`
+    pub storage_per_byte: u64,
+    /// Gas refunded for every byte a contract frees from its state with
+    /// `db_set` or `db_del`. Must not exceed `storage_per_byte`.
+    pub storage_refund_per_byte: u64,
+}
+
+impl GasSchedule {
+    /// Return the cost of the given host function
+    pub fn host_cost(&self, function: HostFunction) -> HostCost {
+        match function {
+            HostFunction::DrkLog => self.host.drk_log,
+            HostFunction::SetReturnData => self.host.set_return_data,
+            HostFunction::PutObjectBytes => self.host.put_object_bytes,
+            HostFunction::GetObjectBytes => self.host.get_object_bytes,
+            HostFunction::GetObjectSize => self.host.get_object_size,
+            HostFunction::DbInit => self.host.db_init,
+            HostFunction::DbLookup => self.host.db_lookup,
+            HostFunction::DbSet => self.host.db_set,
+            HostFunction::DbDel => self.host.db_del,
+            HostFunction::DbGet => self.host.db_get,
+            HostFunction::DbContainsKey => self.host.db_contains_key,
+            HostFunction::DbIter => self.host.db_iter,
+            HostFunction::DbIterItem => self.host.db_iter_item,
+            HostFunction::ZkasDbSet => self.host.zkas_db_set,
+            HostFunction::MerkleAdd => self.host.merkle_add,
+            HostFunction::GetSlot => self.host.get_slot,
+            HostFunction::EmitEvent => self.host.emit_event,
+            HostFunction::CallContractView => self.host.call_contract_view,
+        }
+    }
+}
+
+/// The initial gas schedule. Every operator costs 1 and host functions
+/// are charged per byte they read or write.
+pub const GAS_SCHEDULE_V1: GasSchedule = GasSchedule {
+    version: 1,
+    activation_height: 0,
+    gas_limit: 400_000_000,
+    operators: OperatorCosts { base: 1, arithmetic: 1, memory: 1, call: 1, memory_grow: 1 },
+    host: HostCosts {
+        drk_log: HostCost::new(0, 1),
+        set_return_data: HostCost::new(0, 1),
+        put_object_bytes: HostCost::new(0, 1),
+        get_object_bytes: HostCost::new(0, 1),
+        get_object_size: HostCost::new(0, 1),
+        db_init: HostCost::new(0, 1),
+        db_lookup: HostCost::new(0, 1),
+        db_set: HostCost::new(0, 1),
+        db_del: HostCost::new(0, 1),
+        db_get: HostCost::new(0, 1),
+        db_contains_key: HostCost::new(0, 1),
+        db_iter: HostCost::new(0, 1),
+        db_iter_item: HostCost::new(100, 1),
+        zkas_db_set: HostCost::new(0, 1),
+        merkle_add: HostCost::new(0, 1),
+        get_slot: HostCost::new(0, 1),
+        emit_event: HostCost::new(0, 10),
+        call_contract_view: HostCost::new(10_000, 1),
+    },
+    memory_grow_per_page: 0,
+    zkas_bincode_item: 100,
+    storage_per_byte: 0,
+    storage_refund_per_byte: 0,
+};
+
+/// Introduces storage fees: writes are charged for every byte they add
+/// to the contract state, and refunded half of that for every byte freed.
+/// Activated on the first block after genesis, so the hardcoded genesis
+/// block of every network keeps verifying under the schedule it was
+/// created with, while every block on top of it pays for its state.
+pub const GAS_SCHEDULE_V2: GasSchedule = GasSchedule {
+    version: 2,
+    activation_height: 1,
+    storage_per_byte: 100,
+    storage_refund_per_byte: 50,
+    ..GAS_SCHEDULE_V1
+};
+
+/// All gas schedules, ordered by their activation height
+pub static GAS_SCHEDULES: &[GasSchedule] = &[GAS_SCHEDULE_V1, GAS_SCHEDULE_V2];
+
+/// Return the gas schedule to use for the given block height
+pub fn gas_schedule(height: u64) -> &'static GasSchedule {
+    GAS_SCHEDULES.iter().rev().find(|x| x.activation_height <= height).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gas_schedules_ordering() {
+        assert_eq!(GAS_SCHEDULES[0].activation_height, 0);
+        for pair in GAS_SCHEDULES.windows(2) {
+            assert!(pair[0].activation_height < pair[1].activation_height);
+            assert!(pair[0].version < pair[1].version);
+        }
+
+        for schedule in GAS_SCHEDULES {
+            assert!(schedule.storage_refund_per_byte <= schedule.storage_per_byte);
+        }
+
+        assert_eq!(gas_schedule(0).version, 1);
+        assert_eq!(gas_schedule(GAS_SCHEDULE_V2.activation_height - 1).version, 1);
+        assert_eq!(gas_schedule(GAS_SCHEDULE_V2.activation_height).version, 2);
+        assert_eq!(gas_schedule(u64::MAX).version, GAS_SCHEDULES.last().unwrap().version);
+    }
+
+    #[test]
+    fn host_cost() {
+        let cost = HostCost::new(100, 2);
+        assert_eq!(cost.gas(0), 100);
+        assert_eq!(cost.gas(10), 120);
+        assert_eq!(cost.gas(u64::MAX), u64::MAX);
+    }
+}
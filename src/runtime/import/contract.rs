@@ -0,0 +1,209 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::io::Cursor;
+
+use darkfi_sdk::crypto::ContractId;
+use darkfi_serial::Decodable;
+use log::{debug, error};
+use wasmer::{FunctionEnvMut, WasmPtr};
+
+use super::acl::acl_allow;
+use crate::runtime::{
+    gas_schedule::HostFunction,
+    trace::TraceStep,
+    vm_runtime::{ContractSection, Env, Runtime, MAX_CALL_DEPTH},
+};
+
+/// Call the `__view` section of another deployed contract, and return its
+/// result data to the caller.
+///
+/// * `ptr` must contain the callee `ContractId` and the payload for the call.
+///
+/// The callee runs in a nested [`Runtime`] on the same blockchain overlay,
+/// so it sees any state changes the caller has made so far. Since it runs
+/// in the View [`ContractSection`], it is only able to read from databases.
+/// The gas used by the callee is subtracted from the caller's remaining gas,
+/// and calls can be nested up to [`MAX_CALL_DEPTH`] times.
+///
+/// On success, returns the index of the callee's return data in the
+/// `objects` Vector. Otherwise, returns an error code.
+pub(crate) fn call_contract_view(
+    mut ctx: FunctionEnvMut<Env>,
+    ptr: WasmPtr<u8>,
+    ptr_len: u32,
+) -> i64 {
+    let (env, mut store) = ctx.data_and_store_mut();
+    let cid = env.contract_id;
+
+    // Enforce function ACL
+    if let Err(e) = acl_allow(
+        env,
+        &[
+            ContractSection::Deploy,
+            ContractSection::Exec,
+            ContractSection::Metadata,
+            ContractSection::Migrate,
+            ContractSection::Update,
+            ContractSection::View,
+        ],
+    ) {
+        error!(
+            target: "runtime::contract::call_contract_view",
+            "[WASM] [{}] call_contract_view(): Called in unauthorized section: {}", cid, e,
+        );
+        return darkfi_sdk::error::CALLER_ACCESS_DENIED
+    }
+
+    // Subtract used gas. Here we count the length read from the memory slice,
+    // along with the base cost of the call.
+    if env.subtract_host_gas(&mut store, HostFunction::CallContractView, ptr_len as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
+
+    if env.call_depth >= MAX_CALL_DEPTH {
+        error!(
+            target: "runtime::contract::call_contract_view",
+            "[WASM] [{}] call_contract_view(): Maximum call depth exceeded", cid,
+        );
+        return darkfi_sdk::error::CALL_DEPTH_EXCEEDED
+    }
+
+    let memory_view = env.memory_view(&store);
+    let Ok(mem_slice) = ptr.slice(&memory_view, ptr_len) else {
+        error!(
+            target: "runtime::contract::call_contract_view",
+            "[WASM] [{}] call_contract_view(): Failed to make slice from ptr", cid,
+        );
+        return darkfi_sdk::error::CALL_CONTRACT_FAILED
+    };
+
+    let mut buf = vec![0_u8; ptr_len as usize];
+    if let Err(e) = mem_slice.read_slice(&mut buf) {
+        error!(
+            target: "runtime::contract::call_contract_view",
+            "[WASM] [{}] call_contract_view(): Failed to read from memory slice: {}", cid, e,
+        );
+        return darkfi_sdk::error::CALL_CONTRACT_FAILED
+    };
+
+    let mut buf_reader = Cursor::new(buf);
+
+    let callee: ContractId = match Decodable::decode(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(
+                target: "runtime::contract::call_contract_view",
+                "[WASM] [{}] call_contract_view(): Failed to decode ContractId: {}", cid, e,
+            );
+            return darkfi_sdk::error::CALL_CONTRACT_FAILED
+        }
+    };
+
+    let payload: Vec<u8> = match Decodable::decode(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(
+                target: "runtime::contract::call_contract_view",
+                "[WASM] [{}] call_contract_view(): Failed to decode payload: {}", cid, e,
+            );
+            return darkfi_sdk::error::CALL_CONTRACT_FAILED
+        }
+    };
+
+    // Make sure we've read the entire buffer
+    if buf_reader.position() != ptr_len as u64 {
+        error!(
+            target: "runtime::contract::call_contract_view",
+            "[WASM] [{}] call_contract_view(): Trailing bytes in argument stream", cid,
+        );
+        return darkfi_sdk::error::CALL_CONTRACT_FAILED
+    }
+
+    // Fetch the callee bincode. The lock must not be held while the nested
+    // runtime executes, as its host functions will acquire it.
+    let bincode = match env.blockchain.lock().unwrap().wasm_bincode.get(callee) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(
+                target: "runtime::contract::call_contract_view",
+                "[WASM] [{}] call_contract_view(): Failed to fetch bincode of {}: {}",
+                cid, callee, e,
+            );
+            return darkfi_sdk::error::CALL_CONTRACT_FAILED
+        }
+    };
+
+    let mut runtime =
+        match Runtime::new(&bincode, env.blockchain.clone(), callee, env.time_keeper.clone()) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    target: "runtime::contract::call_contract_view",
+                    "[WASM] [{}] call_contract_view(): Failed to instantiate {}: {}",
+                    cid, callee, e,
+                );
+                return darkfi_sdk::error::CALL_CONTRACT_FAILED
+            }
+        };
+
+    // Trace the callee execution as part of the caller's trace
+    if env.trace.borrow().is_some() {
+        runtime.enable_trace();
+    }
+
+    // The callee can use at most the gas the caller has remaining
+    let Ok(gas_limit) = env.remaining_gas(&mut store) else {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    };
+    debug!(
+        target: "runtime::contract::call_contract_view",
+        "[WASM] [{}] call_contract_view(): Calling {} with gas limit {}", cid, callee, gas_limit,
+    );
+    let ret = runtime.view(&payload, env.call_depth + 1, gas_limit);
+
+    // Subtract the gas used by the callee, even if it failed
+    let gas_used = gas_limit.saturating_sub(runtime.gas_remaining());
+    if env.subtract_gas(&mut store, gas_used).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
+
+    if let Some(trace) = runtime.take_trace() {
+        env.trace_step(TraceStep::View(trace));
+    }
+
+    let return_data = match ret {
+        Ok(v) => v,
+        Err(e) => {
+            error!(
+                target: "runtime::contract::call_contract_view",
+                "[WASM] [{}] call_contract_view(): Call to {} failed: {}", cid, callee, e,
+            );
+            return darkfi_sdk::error::CALL_CONTRACT_FAILED
+        }
+    };
+
+    // Copy the return data to the VM by pushing it to the objects Vector.
+    let mut objects = env.objects.borrow_mut();
+    if objects.len() == u32::MAX as usize {
+        return darkfi_sdk::error::DATA_TOO_LARGE
+    }
+
+    objects.push(return_data);
+    (objects.len() - 1) as i64
+}
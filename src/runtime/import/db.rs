@@ -18,7 +18,7 @@
 
 use std::io::Cursor;
 
-use darkfi_sdk::crypto::ContractId;
+use darkfi_sdk::{crypto::ContractId, db::DB_ITER_MAX_ITEMS};
 use darkfi_serial::{deserialize, serialize, Decodable};
 use log::{debug, error, info};
 use wasmer::{FunctionEnvMut, WasmPtr};
@@ -26,7 +26,11 @@ use wasmer::{FunctionEnvMut, WasmPtr};
 use super::acl::acl_allow;
 use crate::{
     blockchain::contract_store::SMART_CONTRACT_ZKAS_DB_NAME,
-    runtime::vm_runtime::{ContractSection, Env},
+    runtime::{
+        gas_schedule::HostFunction,
+        trace::DbAccessKind,
+        vm_runtime::{ContractSection, Env},
+    },
     zk::{empty_witnesses, VerifyingKey, ZkCircuit},
     zkas::ZkBinary,
 };
@@ -49,14 +53,15 @@ impl DbHandle {
 /// This function expects to receive a pointer from which a `ContractId`
 /// and the `db_name` will be read.
 ///
-/// This function should **only** be allowed in `ContractSection::Deploy`, as that
-/// is called when a contract is being (re)deployed and databases have to be created.
+/// This function should **only** be allowed in `ContractSection::Deploy` and
+/// `ContractSection::Migrate`, as those are called when a contract is being
+/// (re)deployed and databases have to be created.
 pub(crate) fn db_init(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u32) -> i64 {
     let (env, mut store) = ctx.data_and_store_mut();
     let cid = env.contract_id;
 
     // Enforce function ACL
-    if let Err(e) = acl_allow(env, &[ContractSection::Deploy]) {
+    if let Err(e) = acl_allow(env, &[ContractSection::Deploy, ContractSection::Migrate]) {
         error!(
             target: "runtime::db::db_init",
             "[WASM] [{}] db_init(): Called in unauthorized section: {}", cid, e,
@@ -66,7 +71,9 @@ pub(crate) fn db_init(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u
 
     // Subtract used gas. Here we count the length read from the memory slice.
     // TODO: There should probably be an additional fee to open a new sled tree.
-    env.subtract_gas(&mut store, ptr_len as u64);
+    if env.subtract_host_gas(&mut store, HostFunction::DbInit, ptr_len as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     // This takes lock of the blockchain overlay reference in the wasm env
     let contracts = &env.blockchain.lock().unwrap().contracts;
@@ -209,7 +216,9 @@ pub(crate) fn db_lookup(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len:
             ContractSection::Deploy,
             ContractSection::Exec,
             ContractSection::Metadata,
+            ContractSection::Migrate,
             ContractSection::Update,
+            ContractSection::View,
         ],
     ) {
         error!(
@@ -220,7 +229,9 @@ pub(crate) fn db_lookup(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len:
     }
 
     // Subtract used gas. Here we count the length read from the memory slice.
-    env.subtract_gas(&mut store, ptr_len as u64);
+    if env.subtract_host_gas(&mut store, HostFunction::DbLookup, ptr_len as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     // Read memory location that contains the ContractId and DB name
     let memory_view = env.memory_view(&store);
@@ -323,13 +334,19 @@ pub(crate) fn db_lookup(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len:
 /// * `ptr` must contain the DbHandle index and the key-value pair.
 /// * The DbHandle must match the ContractId.
 ///
-/// This function can be called only from the Deploy or Update [`ContractSection`].
+/// Besides the call cost, a storage fee is charged for every byte the write adds
+/// to the contract state, and part of it is refunded for every byte it frees.
+///
+/// This function can be called only from the Deploy, Migrate or Update [`ContractSection`].
 /// Returns `SUCCESS` on success, otherwise returns an error value.
 pub(crate) fn db_set(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u32) -> i64 {
     let (env, mut store) = ctx.data_and_store_mut();
     let cid = env.contract_id;
 
-    if let Err(e) = acl_allow(env, &[ContractSection::Deploy, ContractSection::Update]) {
+    if let Err(e) = acl_allow(
+        env,
+        &[ContractSection::Deploy, ContractSection::Migrate, ContractSection::Update],
+    ) {
         error!(
             target: "runtime::db::db_set",
             "[WASM] [{}] db_set(): Called in unauthorized section: {}", cid, e,
@@ -338,7 +355,9 @@ pub(crate) fn db_set(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u3
     }
 
     // Subtract used gas. Here we count the length hread from the memory slice.
-    env.subtract_gas(&mut store, ptr_len as u64);
+    if env.subtract_host_gas(&mut store, HostFunction::DbSet, ptr_len as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     // Ensure that it is possible to read from the memory that this function needs
     let memory_view = env.memory_view(&store);
@@ -430,36 +449,66 @@ pub(crate) fn db_set(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u3
         return darkfi_sdk::error::CALLER_ACCESS_DENIED
     }
 
-    // Insert key-value pair into the database corresponding to this contract
-    if env
-        .blockchain
-        .lock()
-        .unwrap()
-        .overlay
-        .lock()
-        .unwrap()
-        .insert(&db_handle.tree, &key, &value)
-        .is_err()
-    {
+    env.trace_db(DbAccessKind::Write, db_handle, &key, Some(&value));
+
+    // Insert key-value pair into the database corresponding to this contract,
+    // keeping track of the net amount of bytes it adds to the contract state.
+    let blockchain = env.blockchain.lock().unwrap();
+    let mut overlay = blockchain.overlay.lock().unwrap();
+
+    let previous_size = match overlay.get(&db_handle.tree, &key) {
+        Ok(v) => v.map_or(0, |v| key.len() + v.len()),
+        Err(e) => {
+            error!(
+                target: "runtime::db::db_set",
+                "[WASM] [{}] db_set(): Internal error getting from tree: {}", cid, e,
+            );
+            return darkfi_sdk::error::DB_SET_FAILED
+        }
+    };
+
+    if overlay.insert(&db_handle.tree, &key, &value).is_err() {
         error!(
             target: "runtime::db::db_set",
             "[WASM] [{}] db_set(): Couldn't insert to db_handle tree", cid,
         );
         return darkfi_sdk::error::DB_SET_FAILED
     }
+    drop(overlay);
+
+    let delta = (key.len() + value.len()) as i64 - previous_size as i64;
+    if let Err(e) = blockchain.state_sizes.update(&cid, delta) {
+        error!(
+            target: "runtime::db::db_set",
+            "[WASM] [{}] db_set(): Couldn't update contract state size: {}", cid, e,
+        );
+        return darkfi_sdk::error::DB_SET_FAILED
+    }
+    drop(blockchain);
+    drop(db_handles);
+
+    // Charge the storage fee for the added bytes, or refund the freed ones
+    if env.charge_storage(&mut store, delta).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     darkfi_sdk::entrypoint::SUCCESS
 }
 
 /// Remove a key from the database.
 ///
-/// This function can be called only from the Deploy or Update [`ContractSection`].
+/// Part of the storage fee is refunded for the bytes the removal frees.
+///
+/// This function can be called only from the Deploy, Migrate or Update [`ContractSection`].
 /// Returns `SUCCESS` on success, otherwise returns an error value.
 pub(crate) fn db_del(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u32) -> i64 {
     let (env, mut store) = ctx.data_and_store_mut();
     let cid = env.contract_id;
 
-    if let Err(e) = acl_allow(env, &[ContractSection::Deploy, ContractSection::Update]) {
+    if let Err(e) = acl_allow(
+        env,
+        &[ContractSection::Deploy, ContractSection::Migrate, ContractSection::Update],
+    ) {
         error!(
             target: "runtime::db::db_del",
             "[WASM] [{}] db_del(): Called in unauthorized section: {}", cid, e,
@@ -468,7 +517,9 @@ pub(crate) fn db_del(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u3
     }
 
     // Subtract used gas. Here we count the length of the looked-up key.
-    env.subtract_gas(&mut store, ptr_len as u64);
+    if env.subtract_host_gas(&mut store, HostFunction::DbDel, ptr_len as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     // Ensure that it is possible to read from the memory that this function needs
     let memory_view = env.memory_view(&store);
@@ -548,22 +599,55 @@ pub(crate) fn db_del(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u3
         return darkfi_sdk::error::CALLER_ACCESS_DENIED
     }
 
-    // Remove key-value pair from the database corresponding to this contract
-    if env.blockchain.lock().unwrap().overlay.lock().unwrap().remove(&db_handle.tree, &key).is_err()
-    {
+    env.trace_db(DbAccessKind::Delete, db_handle, &key, None);
+
+    // Remove key-value pair from the database corresponding to this contract,
+    // keeping track of the amount of bytes it frees from the contract state.
+    let blockchain = env.blockchain.lock().unwrap();
+    let mut overlay = blockchain.overlay.lock().unwrap();
+
+    let previous_size = match overlay.get(&db_handle.tree, &key) {
+        Ok(v) => v.map_or(0, |v| key.len() + v.len()),
+        Err(e) => {
+            error!(
+                target: "runtime::db::db_del",
+                "[WASM] [{}] db_del(): Internal error getting from tree: {}", cid, e,
+            );
+            return darkfi_sdk::error::DB_DEL_FAILED
+        }
+    };
+
+    if overlay.remove(&db_handle.tree, &key).is_err() {
         error!(
             target: "runtime::db::db_del",
             "[WASM] [{}] db_del(): Couldn't remove key from db_handle tree", cid,
         );
         return darkfi_sdk::error::DB_DEL_FAILED
     }
+    drop(overlay);
+
+    let delta = -(previous_size as i64);
+    if let Err(e) = blockchain.state_sizes.update(&cid, delta) {
+        error!(
+            target: "runtime::db::db_del",
+            "[WASM] [{}] db_del(): Couldn't update contract state size: {}", cid, e,
+        );
+        return darkfi_sdk::error::DB_DEL_FAILED
+    }
+    drop(blockchain);
+    drop(db_handles);
+
+    // Refund part of the storage fee for the freed bytes
+    if env.charge_storage(&mut store, delta).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     darkfi_sdk::entrypoint::SUCCESS
 }
 
 /// Reads a value by key from the key-value store.
 ///
-/// This function can be called from the Deploy, Exec, or Metadata [`ContractSection`].
+/// This function can be called from the Deploy, Exec, Metadata, Migrate, or View [`ContractSection`].
 ///
 /// On success, returns the length of the `objects` Vector in the environment.
 /// Otherwise, returns an error code.
@@ -571,9 +655,16 @@ pub(crate) fn db_get(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u3
     let (env, mut store) = ctx.data_and_store_mut();
     let cid = env.contract_id;
 
-    if let Err(e) =
-        acl_allow(env, &[ContractSection::Deploy, ContractSection::Exec, ContractSection::Metadata])
-    {
+    if let Err(e) = acl_allow(
+        env,
+        &[
+            ContractSection::Deploy,
+            ContractSection::Exec,
+            ContractSection::Metadata,
+            ContractSection::Migrate,
+            ContractSection::View,
+        ],
+    ) {
         error!(
             target: "runtime::db::db_get",
             "[WASM] [{}] db_get(): Called in unauthorized section: {}", cid, e,
@@ -582,7 +673,9 @@ pub(crate) fn db_get(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u3
     }
 
     // Subtract used gas. Here we count the length of the looked-up key.
-    env.subtract_gas(&mut store, ptr_len as u64);
+    if env.subtract_host_gas(&mut store, HostFunction::DbGet, ptr_len as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     // Ensure that it is possible to read memory
     let memory_view = env.memory_view(&store);
@@ -667,6 +760,7 @@ pub(crate) fn db_get(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u3
                 return darkfi_sdk::error::DB_GET_FAILED
             }
         };
+    env.trace_db(DbAccessKind::Read, db_handle, &key, ret.as_deref());
     drop(db_handles);
 
     // Return special error if the data is empty
@@ -683,7 +777,12 @@ pub(crate) fn db_get(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u3
     }
 
     // Subtract used gas. Here we count the length of the data read from db.
-    env.subtract_gas(&mut store, return_data.len() as u64);
+    if env
+        .subtract_host_gas_bytes(&mut store, HostFunction::DbGet, return_data.len() as u64)
+        .is_err()
+    {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     // Copy the data (Vec<u8>) to the VM by pushing it to the objects Vector.
     let mut objects = env.objects.borrow_mut();
@@ -706,9 +805,16 @@ pub(crate) fn db_contains_key(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, pt
     let (env, mut store) = ctx.data_and_store_mut();
     let cid = env.contract_id;
 
-    if let Err(e) =
-        acl_allow(env, &[ContractSection::Deploy, ContractSection::Exec, ContractSection::Metadata])
-    {
+    if let Err(e) = acl_allow(
+        env,
+        &[
+            ContractSection::Deploy,
+            ContractSection::Exec,
+            ContractSection::Metadata,
+            ContractSection::Migrate,
+            ContractSection::View,
+        ],
+    ) {
         error!(
             target: "runtime::db::db_contains_key",
             "[WASM] [{}] db_contains_key(): Called in unauthorized section: {}", cid, e,
@@ -717,7 +823,9 @@ pub(crate) fn db_contains_key(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, pt
     }
 
     // Subtract used gas. Here we count the length of the looked-up key.
-    env.subtract_gas(&mut store, ptr_len as u64);
+    if env.subtract_host_gas(&mut store, HostFunction::DbContainsKey, ptr_len as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     // Ensure memory is readable
     let memory_view = env.memory_view(&store);
@@ -790,6 +898,8 @@ pub(crate) fn db_contains_key(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, pt
     // Retrieve DbHandle using the index
     let db_handle = &db_handles[db_handle_index];
 
+    env.trace_db(DbAccessKind::Read, db_handle, &key, None);
+
     // Lookup key parameter in the database
     match env.blockchain.lock().unwrap().overlay.lock().unwrap().contains_key(&db_handle.tree, &key)
     {
@@ -804,6 +914,212 @@ pub(crate) fn db_contains_key(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, pt
     }
 }
 
+/// Return the smallest key larger than every key starting with `prefix`,
+/// or `None` if there is no such key.
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end)
+        }
+    }
+
+    None
+}
+
+/// Iterate over the records of a database with keys starting with a given
+/// prefix, in key order.
+///
+/// This function expects to receive a pointer from which the `DbHandle`
+/// index, the prefix, an optional cursor, and the maximum number of records
+/// to return will be read. The cursor is the last key returned by a previous
+/// call, and the iteration continues after it.
+///
+/// The records are serialized as a `Vec<(Vec<u8>, Vec<u8>)>` and pushed to
+/// the objects store. Returns the index of the object on success, otherwise
+/// returns an error code.
+pub(crate) fn db_iter_prefix(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u32) -> i64 {
+    db_iter(ctx, ptr, ptr_len, true)
+}
+
+/// Iterate over the records of a database with keys in `[start, end)`,
+/// in key order.
+///
+/// This function expects to receive a pointer from which the `DbHandle`
+/// index, the start and end keys, an optional cursor, and the maximum number
+/// of records to return will be read. The cursor is the last key returned by
+/// a previous call, and the iteration continues after it.
+///
+/// The records are serialized as a `Vec<(Vec<u8>, Vec<u8>)>` and pushed to
+/// the objects store. Returns the index of the object on success, otherwise
+/// returns an error code.
+pub(crate) fn db_range(ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u32) -> i64 {
+    db_iter(ctx, ptr, ptr_len, false)
+}
+
+/// Shared implementation of `db_iter_prefix` and `db_range`, selected by `prefix`
+fn db_iter(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_len: u32, prefix: bool) -> i64 {
+    let name = if prefix { "db_iter_prefix" } else { "db_range" };
+    let (env, mut store) = ctx.data_and_store_mut();
+    let cid = env.contract_id;
+
+    if let Err(e) = acl_allow(
+        env,
+        &[
+            ContractSection::Deploy,
+            ContractSection::Exec,
+            ContractSection::Metadata,
+            ContractSection::Migrate,
+            ContractSection::View,
+        ],
+    ) {
+        error!(
+            target: "runtime::db::db_iter",
+            "[WASM] [{}] {}(): Called in unauthorized section: {}", cid, name, e,
+        );
+        return darkfi_sdk::error::CALLER_ACCESS_DENIED
+    }
+
+    // Subtract used gas. Here we count the length of the arguments.
+    if env.subtract_host_gas(&mut store, HostFunction::DbIter, ptr_len as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
+
+    // Ensure memory is readable
+    let memory_view = env.memory_view(&store);
+    let Ok(mem_slice) = ptr.slice(&memory_view, ptr_len) else {
+        error!(
+            target: "runtime::db::db_iter",
+            "[WASM] [{}] {}(): Failed to make slice from ptr", cid, name,
+        );
+        return darkfi_sdk::error::DB_ITER_FAILED
+    };
+
+    let mut buf = vec![0_u8; ptr_len as usize];
+    if let Err(e) = mem_slice.read_slice(&mut buf) {
+        error!(
+            target: "runtime::db::db_iter",
+            "[WASM] [{}] {}(): Failed to read from memory slice: {}", cid, name, e,
+        );
+        return darkfi_sdk::error::DB_ITER_FAILED
+    };
+
+    let mut buf_reader = Cursor::new(buf);
+
+    // Decode the DbHandle index, the key bounds, the cursor, and the limit
+    let args: Result<(u32, Vec<u8>, Option<Vec<u8>>, Option<Vec<u8>>, u32), _> = (|| {
+        let db_handle_index: u32 = Decodable::decode(&mut buf_reader)?;
+        let (start, end) = if prefix {
+            let key_prefix: Vec<u8> = Decodable::decode(&mut buf_reader)?;
+            let end = prefix_end(&key_prefix);
+            (key_prefix, end)
+        } else {
+            let start: Vec<u8> = Decodable::decode(&mut buf_reader)?;
+            let end: Vec<u8> = Decodable::decode(&mut buf_reader)?;
+            (start, Some(end))
+        };
+        let cursor: Option<Vec<u8>> = Decodable::decode(&mut buf_reader)?;
+        let limit: u32 = Decodable::decode(&mut buf_reader)?;
+        Ok::<_, std::io::Error>((db_handle_index, start, end, cursor, limit))
+    })();
+
+    let (db_handle_index, start, end, cursor, limit) = match args {
+        Ok(v) => v,
+        Err(e) => {
+            error!(
+                target: "runtime::db::db_iter",
+                "[WASM] [{}] {}(): Failed to decode arguments: {}", cid, name, e,
+            );
+            return darkfi_sdk::error::DB_ITER_FAILED
+        }
+    };
+
+    // Make sure there are no trailing bytes in the buffer.
+    // This means we've used all data that was supplied.
+    if buf_reader.position() != ptr_len as u64 {
+        error!(
+            target: "runtime::db::db_iter",
+            "[WASM] [{}] {}(): Trailing bytes in argument stream", cid, name,
+        );
+        return darkfi_sdk::error::DB_ITER_FAILED
+    }
+
+    if limit == 0 || limit > DB_ITER_MAX_ITEMS {
+        error!(
+            target: "runtime::db::db_iter",
+            "[WASM] [{}] {}(): Limit must be between 1 and {}", cid, name, DB_ITER_MAX_ITEMS,
+        );
+        return darkfi_sdk::error::DB_ITER_FAILED
+    }
+
+    // Continue right after the cursor, if it's within the range. The
+    // smallest key larger than the cursor is the cursor followed by 0x00.
+    let start = match cursor {
+        Some(mut cursor) if cursor >= start => {
+            cursor.push(0x00);
+            cursor
+        }
+        _ => start,
+    };
+
+    let db_handles = env.db_handles.borrow();
+
+    // Ensure DbHandle index is within bounds
+    let Some(db_handle) = db_handles.get(db_handle_index as usize) else {
+        error!(
+            target: "runtime::db::db_iter",
+            "[WASM] [{}] {}(): Requested DbHandle that is out of bounds", cid, name,
+        );
+        return darkfi_sdk::error::DB_ITER_FAILED
+    };
+
+    let records = match env.blockchain.lock().unwrap().contracts.range(
+        &db_handle.tree,
+        &start,
+        end.as_deref(),
+        limit as usize,
+    ) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(
+                target: "runtime::db::db_iter",
+                "[WASM] [{}] {}(): Internal error iterating tree: {}", cid, name, e,
+            );
+            return darkfi_sdk::error::DB_ITER_FAILED
+        }
+    };
+    for (k, v) in &records {
+        env.trace_db(DbAccessKind::Read, db_handle, k, Some(v));
+    }
+    drop(db_handles);
+
+    // Subtract used gas. Here we charge for every record returned,
+    // counting the length of its key and value.
+    for (k, v) in &records {
+        if env
+            .subtract_host_gas(&mut store, HostFunction::DbIterItem, (k.len() + v.len()) as u64)
+            .is_err()
+        {
+            return darkfi_sdk::error::INTERNAL_ERROR
+        }
+    }
+
+    let return_data = serialize(&records);
+    if return_data.len() > u32::MAX as usize {
+        return darkfi_sdk::error::DATA_TOO_LARGE
+    }
+
+    // Copy the data to the VM by pushing it to the objects Vector.
+    let mut objects = env.objects.borrow_mut();
+    if objects.len() == u32::MAX as usize {
+        return darkfi_sdk::error::DATA_TOO_LARGE
+    }
+
+    objects.push(return_data);
+    (objects.len() - 1) as i64
+}
+
 /// Given a zkas circuit, create a VerifyingKey and insert them both into the db.
 ///
 /// This function can only be called from the Deploy [`ContractSection`].
@@ -821,7 +1137,9 @@ pub(crate) fn zkas_db_set(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_le
     }
 
     // Subtract used gas. Here we count the length read from the memory slice.
-    env.subtract_gas(&mut store, ptr_len as u64);
+    if env.subtract_host_gas(&mut store, HostFunction::ZkasDbSet, ptr_len as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     let memory_view = env.memory_view(&store);
 
@@ -867,12 +1185,14 @@ pub(crate) fn zkas_db_set(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_le
         }
     };
 
-    // Subtract used gas. We count 100 gas per opcode, witness, and literal.
-    // This is likely bad.
+    // Subtract used gas. We count a fixed cost per opcode, witness, and literal,
+    // as defined by the gas schedule.
     // TODO: This should be better-priced.
-    let gas_cost =
-        (zkbin.literals.len() + zkbin.witnesses.len() + zkbin.opcodes.len()) as u64 * 100;
-    env.subtract_gas(&mut store, gas_cost);
+    let items = (zkbin.literals.len() + zkbin.witnesses.len() + zkbin.opcodes.len()) as u64;
+    let gas_cost = items.saturating_mul(env.gas_schedule.zkas_bincode_item);
+    if env.subtract_gas(&mut store, gas_cost).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     // Because of `Runtime::Deploy`, we should be sure that the zkas db is index zero.
     let db_handles = env.db_handles.borrow();
@@ -889,20 +1209,21 @@ pub(crate) fn zkas_db_set(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_le
     // Check if there is existing bincode and compare it. Return DB_SUCCESS if
     // they're the same. The assumption should be that VerifyingKey was generated
     // already so we can skip things after this guard.
-    match env
+    let key = serialize(&zkbin.namespace);
+    let previous_size = match env
         .blockchain
         .lock()
         .unwrap()
         .overlay
         .lock()
         .unwrap()
-        .get(&db_handle.tree, &serialize(&zkbin.namespace))
+        .get(&db_handle.tree, &key)
     {
         Ok(v) => {
-            if let Some(bytes) = v {
+            if let Some(bytes) = &v {
                 // We allow a panic here because this db should never be corrupted in this way.
                 let (existing_zkbin, _): (Vec<u8>, Vec<u8>) =
-                    deserialize(&bytes).expect("deserialize tuple");
+                    deserialize(bytes).expect("deserialize tuple");
 
                 if existing_zkbin == zkbin_bytes {
                     debug!(
@@ -913,6 +1234,7 @@ pub(crate) fn zkas_db_set(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_le
                     return darkfi_sdk::entrypoint::SUCCESS
                 }
             }
+            v.map_or(0, |v| key.len() + v.len())
         }
         Err(e) => {
             error!(
@@ -953,29 +1275,45 @@ pub(crate) fn zkas_db_set(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, ptr_le
         return darkfi_sdk::error::DB_SET_FAILED
     }
 
-    // Insert the key-value pair into the database.
-    let key = serialize(&zkbin.namespace);
+    // Insert the key-value pair into the database, keeping track of the
+    // net amount of bytes it adds to the contract state.
     let value = serialize(&(zkbin_bytes, vk_buf));
-    if env
-        .blockchain
-        .lock()
-        .unwrap()
-        .overlay
-        .lock()
-        .unwrap()
-        .insert(&db_handle.tree, &key, &value)
-        .is_err()
-    {
+    env.trace_db(DbAccessKind::Write, db_handle, &key, Some(&value));
+    let blockchain = env.blockchain.lock().unwrap();
+    if blockchain.overlay.lock().unwrap().insert(&db_handle.tree, &key, &value).is_err() {
         error!(
             target: "runtime::db::zkas_db_set",
             "[WASM] [{}] zkas_db_set(): Couldn't insert to db_handle tree", cid,
         );
         return darkfi_sdk::error::DB_SET_FAILED
     }
+
+    let delta = (key.len() + value.len()) as i64 - previous_size as i64;
+    if let Err(e) = blockchain.state_sizes.update(&cid, delta) {
+        error!(
+            target: "runtime::db::zkas_db_set",
+            "[WASM] [{}] zkas_db_set(): Couldn't update contract state size: {}", cid, e,
+        );
+        return darkfi_sdk::error::DB_SET_FAILED
+    }
+    drop(blockchain);
     drop(db_handles);
 
-    // Subtract used gas. Here we count the bytes written into the db.
-    env.subtract_gas(&mut store, (key.len() + value.len()) as u64);
+    // Subtract used gas. Here we count the bytes written into the db,
+    // and the storage fee for the bytes added to the contract state.
+    if env
+        .subtract_host_gas_bytes(
+            &mut store,
+            HostFunction::ZkasDbSet,
+            (key.len() + value.len()) as u64,
+        )
+        .is_err()
+    {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
+    if env.charge_storage(&mut store, delta).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     darkfi_sdk::entrypoint::SUCCESS
 }
@@ -24,7 +24,11 @@ use log::{debug, error};
 use wasmer::{FunctionEnvMut, WasmPtr};
 
 use super::acl::acl_allow;
-use crate::runtime::vm_runtime::{ContractSection, Env};
+use crate::runtime::{
+    gas_schedule::HostFunction,
+    trace::DbAccessKind,
+    vm_runtime::{ContractSection, Env},
+};
 
 /// Adds data to merkle tree. The tree, database connection, and new data to add is
 /// read from `ptr` at offset specified by `len`.
@@ -45,7 +49,9 @@ pub(crate) fn merkle_add(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u3
     }
 
     // Subtract used gas. Here we count the length read from the memory slice.
-    env.subtract_gas(&mut store, len as u64);
+    if env.subtract_host_gas(&mut store, HostFunction::MerkleAdd, len as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     let memory_view = env.memory_view(&store);
     let Ok(mem_slice) = ptr.slice(&memory_view, len) else {
@@ -183,6 +189,7 @@ pub(crate) fn merkle_add(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u3
             return darkfi_sdk::error::INTERNAL_ERROR
         }
     };
+    env.trace_db(DbAccessKind::Read, db_info, &tree_key, ret.as_deref());
 
     let Some(return_data) = ret else {
         error!(
@@ -253,9 +260,12 @@ pub(crate) fn merkle_add(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u3
         return darkfi_sdk::error::INTERNAL_ERROR
     }
 
-    // Apply changes to overlay
+    // Apply changes to overlay, keeping track of the net amount of bytes
+    // they add to the contract state.
     let lock = env.blockchain.lock().unwrap();
     let mut overlay = lock.overlay.lock().unwrap();
+    let mut delta = tree_data.len() as i64 - return_data.len() as i64;
+    env.trace_db(DbAccessKind::Write, db_info, &tree_key, Some(&tree_data));
     if overlay.insert(&db_info.tree, &tree_key, &tree_data).is_err() {
         error!(
             target: "runtime::merkle::merkle_add",
@@ -283,6 +293,20 @@ pub(crate) fn merkle_add(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u3
             return darkfi_sdk::error::INTERNAL_ERROR
         }
 
+        // Only roots not already in the set grow the contract state
+        match overlay.contains_key(&db_roots.tree, &root_value) {
+            Ok(true) => {}
+            Ok(false) => delta += root_value.len() as i64,
+            Err(e) => {
+                error!(
+                    target: "runtime::merkle::merkle_add",
+                    "[WASM] [{}] merkle_add(): Internal error checking db_roots tree: {}", cid, e,
+                );
+                return darkfi_sdk::error::INTERNAL_ERROR
+            }
+        }
+
+        env.trace_db(DbAccessKind::Write, db_roots, &root_value, Some(&[][..]));
         if overlay.insert(&db_roots.tree, &root_value, &[]).is_err() {
             error!(
                 target: "runtime::merkle::merkle_add",
@@ -300,6 +324,21 @@ pub(crate) fn merkle_add(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u3
         );
 
         let latest_root = serialize(new_roots.last().unwrap());
+        match overlay.get(&db_info.tree, &root_key) {
+            Ok(v) => {
+                let previous_size = v.map_or(0, |v| root_key.len() + v.len());
+                delta += (root_key.len() + latest_root.len()) as i64 - previous_size as i64;
+            }
+            Err(e) => {
+                error!(
+                    target: "runtime::merkle::merkle_add",
+                    "[WASM] [{}] merkle_add(): Internal error getting from db_info tree: {}", cid, e,
+                );
+                return darkfi_sdk::error::INTERNAL_ERROR
+            }
+        }
+
+        env.trace_db(DbAccessKind::Write, db_info, &root_key, Some(&latest_root));
         if overlay.insert(&db_info.tree, &root_key, &latest_root).is_err() {
             error!(
                 target: "runtime::merkle::merkle_add",
@@ -309,16 +348,30 @@ pub(crate) fn merkle_add(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u3
         }
     }
 
+    drop(overlay);
+    if let Err(e) = lock.state_sizes.update(&cid, delta) {
+        error!(
+            target: "runtime::merkle::merkle_add",
+            "[WASM] [{}] merkle_add(): Couldn't update contract state size: {}", cid, e,
+        );
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
+
     // Subtract used gas.
     // Here we count:
     // * The size of the Merkle tree we deserialized from the db.
     // * The size of the Merkle tree we serialized into the db.
     // * The size of the new Merkle roots we wrote into the db.
-    drop(overlay);
+    // * The storage fee for the bytes added to the contract state.
     drop(lock);
     drop(db_handles);
     let spent_gas = return_data.len() + tree_data.len() + (new_roots.len() * 32);
-    env.subtract_gas(&mut store, spent_gas as u64);
+    if env.subtract_host_gas_bytes(&mut store, HostFunction::MerkleAdd, spent_gas as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
+    if env.charge_storage(&mut store, delta).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     darkfi_sdk::entrypoint::SUCCESS
 }
@@ -19,6 +19,9 @@
 /// Access control for host functions
 mod acl;
 
+/// Host functions for calling into other contracts
+pub(crate) mod contract;
+
 /// Host functions for interacting with db backend
 pub(crate) mod db;
 
@@ -16,18 +16,30 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::io::Cursor;
+
+use darkfi_sdk::event::{EVENT_MAX_DATA_SIZE, EVENT_MAX_PER_CALL};
+use darkfi_serial::Decodable;
 use log::error;
 use wasmer::{FunctionEnvMut, WasmPtr};
 
 use super::acl::acl_allow;
-use crate::runtime::vm_runtime::{ContractSection, Env};
+use crate::{
+    blockchain::ContractEvent,
+    runtime::{
+        gas_schedule::HostFunction,
+        vm_runtime::{ContractSection, Env},
+    },
+};
 
 /// Host function for logging strings.
 pub(crate) fn drk_log(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) {
     let (env, mut store) = ctx.data_and_store_mut();
 
     // Subtract used gas. Here we count the length of the string.
-    env.subtract_gas(&mut store, len as u64);
+    if env.subtract_host_gas(&mut store, HostFunction::DrkLog, len as u64).is_err() {
+        return
+    }
 
     let memory_view = env.memory_view(&store);
     match ptr.read_utf8_string(&memory_view, len) {
@@ -56,7 +68,9 @@ pub(crate) fn set_return_data(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, le
     let cid = &env.contract_id;
 
     // Enforce function ACL
-    if let Err(e) = acl_allow(env, &[ContractSection::Metadata, ContractSection::Exec]) {
+    if let Err(e) =
+        acl_allow(env, &[ContractSection::Metadata, ContractSection::Exec, ContractSection::View])
+    {
         error!(
             target: "runtime::util::set_return_data",
             "[WASM] [{}] set_return_data(): Called in unauthorized section: {}", cid, e,
@@ -65,7 +79,9 @@ pub(crate) fn set_return_data(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, le
     }
 
     // Subtract used gas. Here we count the length read from the memory slice.
-    env.subtract_gas(&mut store, len as u64);
+    if env.subtract_host_gas(&mut store, HostFunction::SetReturnData, len as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     let memory_view = env.memory_view(&store);
     let Ok(slice) = ptr.slice(&memory_view, len) else { return darkfi_sdk::error::INTERNAL_ERROR };
@@ -80,6 +96,88 @@ pub(crate) fn set_return_data(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, le
     darkfi_sdk::entrypoint::SUCCESS
 }
 
+/// Appends a structured event to the `events` field of [`Env`].
+/// The event topic and data will be read from `ptr` at a memory offset
+/// specified by `len`.
+///
+/// This function can only be called from the Update [`ContractSection`],
+/// so events are only produced for state changes that actually get applied.
+///
+/// Returns `SUCCESS` on success, otherwise returns an error code corresponding
+/// to a [`ContractError`].
+pub(crate) fn emit_event(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, len: u32) -> i64 {
+    let (env, mut store) = ctx.data_and_store_mut();
+    let cid = env.contract_id;
+
+    // Enforce function ACL
+    if let Err(e) = acl_allow(env, &[ContractSection::Update]) {
+        error!(
+            target: "runtime::util::emit_event",
+            "[WASM] [{}] emit_event(): Called in unauthorized section: {}", cid, e,
+        );
+        return darkfi_sdk::error::CALLER_ACCESS_DENIED
+    }
+
+    // Subtract used gas. Here we count the length read from the memory slice.
+    // Events are stored forever, so the schedule weighs this more than a plain read.
+    if env.subtract_host_gas(&mut store, HostFunction::EmitEvent, len as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
+
+    let memory_view = env.memory_view(&store);
+    let Ok(slice) = ptr.slice(&memory_view, len) else { return darkfi_sdk::error::INTERNAL_ERROR };
+    let Ok(buf) = slice.read_to_vec() else { return darkfi_sdk::error::INTERNAL_ERROR };
+
+    let mut buf_reader = Cursor::new(buf);
+
+    let topic: [u8; 32] = match Decodable::decode(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(
+                target: "runtime::util::emit_event",
+                "[WASM] [{}] emit_event(): Failed to decode topic: {}", cid, e,
+            );
+            return darkfi_sdk::error::INTERNAL_ERROR
+        }
+    };
+
+    let data: Vec<u8> = match Decodable::decode(&mut buf_reader) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(
+                target: "runtime::util::emit_event",
+                "[WASM] [{}] emit_event(): Failed to decode data: {}", cid, e,
+            );
+            return darkfi_sdk::error::INTERNAL_ERROR
+        }
+    };
+
+    // Make sure we've read the entire buffer
+    if buf_reader.position() != len as u64 {
+        error!(
+            target: "runtime::util::emit_event",
+            "[WASM] [{}] emit_event(): Trailing bytes in argument stream", cid,
+        );
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
+
+    if data.len() > EVENT_MAX_DATA_SIZE {
+        return darkfi_sdk::error::DATA_TOO_LARGE
+    }
+
+    let mut events = env.events.borrow_mut();
+    if events.len() >= EVENT_MAX_PER_CALL {
+        error!(
+            target: "runtime::util::emit_event",
+            "[WASM] [{}] emit_event(): Too many events emitted", cid,
+        );
+        return darkfi_sdk::error::DATA_TOO_LARGE
+    }
+    events.push(ContractEvent { contract_id: cid, topic, data });
+
+    darkfi_sdk::entrypoint::SUCCESS
+}
+
 /// Appends a new object to the [`Env`] objects store.
 /// The data for the object is read from `ptr`.
 ///
@@ -90,7 +188,9 @@ pub(crate) fn put_object_bytes(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, l
     let cid = env.contract_id;
 
     // Subtract used gas. Here we count the length read from the memory slice.
-    env.subtract_gas(&mut store, len as u64);
+    if env.subtract_host_gas(&mut store, HostFunction::PutObjectBytes, len as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     let memory_view = env.memory_view(&store);
     //debug!(target: "runtime::util", "diagnostic:");
@@ -159,7 +259,9 @@ pub(crate) fn get_object_bytes(mut ctx: FunctionEnvMut<Env>, ptr: WasmPtr<u8>, i
     }
 
     // Subtract used gas. Here we count the bytes written to the memory slice
-    env.subtract_gas(&mut store, obj.len() as u64);
+    if env.subtract_host_gas(&mut store, HostFunction::GetObjectBytes, obj.len() as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     // Read N bytes from the object and write onto the ptr.
     let memory_view = env.memory_view(&store);
@@ -210,7 +312,9 @@ pub(crate) fn get_object_size(mut ctx: FunctionEnvMut<Env>, idx: u32) -> i64 {
 
     // Subtract used gas. Here we count the size of the object.
     // TODO: This could probably be fixed-cost
-    env.subtract_gas(&mut store, obj_len as u64);
+    if env.subtract_host_gas(&mut store, HostFunction::GetObjectSize, obj_len as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     obj_len as i64
 }
@@ -270,9 +374,16 @@ pub(crate) fn get_slot(mut ctx: FunctionEnvMut<Env>, slot: u64) -> i64 {
     let cid = &env.contract_id;
 
     // Enforce function ACL
-    if let Err(e) =
-        acl_allow(env, &[ContractSection::Deploy, ContractSection::Metadata, ContractSection::Exec])
-    {
+    if let Err(e) = acl_allow(
+        env,
+        &[
+            ContractSection::Deploy,
+            ContractSection::Metadata,
+            ContractSection::Exec,
+            ContractSection::Migrate,
+            ContractSection::View,
+        ],
+    ) {
         error!(
             target: "runtime::db::db_get_slot",
             "[WASM] [{}] get_slot({}): Called in unauthorized section: {}", cid, slot, e,
@@ -292,7 +403,9 @@ pub(crate) fn get_slot(mut ctx: FunctionEnvMut<Env>, slot: u64) -> i64 {
     };
 
     // Subtract used gas. Here we count the size of the object.
-    env.subtract_gas(&mut store, ret.len() as u64);
+    if env.subtract_host_gas(&mut store, HostFunction::GetSlot, ret.len() as u64).is_err() {
+        return darkfi_sdk::error::INTERNAL_ERROR
+    }
 
     // Copy Vec<u8> to the VM
     let mut objects = env.objects.borrow_mut();
@@ -19,6 +19,19 @@
 /// Main WASM VM runtime implementation
 pub mod vm_runtime;
 
+/// Cache of compiled WASM modules
+pub mod module_cache;
+
+/// Versioned WASM gas schedules
+pub mod gas_schedule;
+
+/// Contract execution tracing
+pub mod trace;
+
+/// Native contract execution backend, for testing
+#[cfg(feature = "native-runtime")]
+pub mod native;
+
 /// VM memory access (read/write)
 pub(crate) mod memory;
 
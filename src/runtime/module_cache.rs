@@ -0,0 +1,400 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Cache of compiled WASM modules.
+//!
+//! Compiling a contract with Singlepass and the metering middleware is
+//! the most expensive part of instantiating a [`super::vm_runtime::Runtime`],
+//! and the same few contracts are executed over and over when verifying
+//! blocks. Compiled modules are kept in memory keyed by the hash of the
+//! contract bincode along with the metering configuration, and can also
+//! be persisted to a directory so they survive node restarts.
+//!
+//! Each cached module keeps the `Engine` it was compiled or deserialized
+//! with, as every new `Store` has to be created from it.
+//!
+//! Module files are prefixed with the blake3 hash of the serialized
+//! module, which is checked before deserializing it, and are written to
+//! a temporary file first, then renamed, so a crash mid-write never leaves
+//! a truncated module behind to be loaded as native code.
+//!
+//! Both the in-memory and the on-disk cache hold at most
+//! [`DEFAULT_CAPACITY`] modules (configurable with
+//! [`ModuleCache::set_capacity`]), evicting the least recently used ones.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+    time::SystemTime,
+};
+
+use darkfi_sdk::crypto::ContractId;
+use lazy_static::lazy_static;
+use log::{debug, error, warn};
+use wasmer::{Engine, Module, NativeEngineExt};
+
+use crate::Result;
+
+/// Default number of modules kept in memory and on disk
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// Extension of the module files persisted on disk
+const MODULE_EXTENSION: &str = "wasmu";
+/// Extension of the module files being written to disk
+const TEMP_EXTENSION: &str = "tmp";
+
+lazy_static! {
+    /// Global module cache used by [`super::vm_runtime::Runtime`]
+    pub static ref MODULE_CACHE: ModuleCache = ModuleCache::new();
+}
+
+/// Key of a compiled module in the cache
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ModuleCacheKey {
+    /// blake3 hash of the contract WASM bincode
+    pub bincode_hash: [u8; 32],
+    /// Version of the gas schedule the operator costs were compiled with
+    pub schedule_version: u32,
+    /// Gas limit the metering middleware was configured with
+    pub gas_limit: u64,
+}
+
+impl ModuleCacheKey {
+    pub fn new(wasm_bytes: &[u8], schedule_version: u32, gas_limit: u64) -> Self {
+        Self { bincode_hash: *blake3::hash(wasm_bytes).as_bytes(), schedule_version, gas_limit }
+    }
+
+    /// File name used when persisting the module to disk
+    fn filename(&self) -> String {
+        format!(
+            "{}-v{}-{}.{}",
+            blake3::Hash::from(self.bincode_hash).to_hex(),
+            self.schedule_version,
+            self.gas_limit,
+            MODULE_EXTENSION,
+        )
+    }
+}
+
+/// A compiled module along with its engine
+struct CachedModule {
+    engine: Engine,
+    module: Module,
+    /// Contracts that were executed using this module, by their byte representation
+    contract_ids: HashSet<[u8; 32]>,
+    /// Value of the cache clock when this module was last used
+    last_used: u64,
+}
+
+/// Modules held in memory, along with a logical clock used to find the
+/// least recently used entry
+#[derive(Default)]
+struct Modules {
+    entries: HashMap<ModuleCacheKey, CachedModule>,
+    clock: u64,
+}
+
+impl Modules {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Drop the least recently used modules until at most `capacity` remain
+    fn evict(&mut self, capacity: usize) {
+        while self.entries.len() > capacity {
+            let Some(key) = self.entries.iter().min_by_key(|(_, c)| c.last_used).map(|(k, _)| *k)
+            else {
+                break
+            };
+            debug!(target: "runtime::module_cache", "Evicting module {}", key.filename());
+            self.entries.remove(&key);
+        }
+    }
+}
+
+/// In-memory and optional on-disk cache of compiled WASM modules
+pub struct ModuleCache {
+    /// Compiled modules in memory
+    modules: Mutex<Modules>,
+    /// Optional directory to persist compiled modules to
+    disk_path: RwLock<Option<PathBuf>>,
+    /// Maximum number of modules kept in memory and on disk
+    capacity: RwLock<usize>,
+    /// Flag to bypass the cache, compiling on every call
+    enabled: RwLock<bool>,
+}
+
+impl ModuleCache {
+    pub fn new() -> Self {
+        Self {
+            modules: Mutex::new(Modules::default()),
+            disk_path: RwLock::new(None),
+            capacity: RwLock::new(DEFAULT_CAPACITY),
+            enabled: RwLock::new(true),
+        }
+    }
+
+    /// Persist compiled modules in the given directory, and load them from
+    /// there on cache misses. The directory must only be writable by the
+    /// node, as the modules in it are only checked against corruption.
+    /// Temporary files left over by interrupted writes get removed.
+    pub fn set_disk_path(&self, path: PathBuf) -> Result<()> {
+        fs::create_dir_all(&path)?;
+        for entry in fs::read_dir(&path)?.filter_map(|entry| entry.ok()) {
+            if entry.path().extension().is_some_and(|ext| ext == TEMP_EXTENSION) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+        *self.disk_path.write().unwrap() = Some(path);
+        Ok(())
+    }
+
+    /// Set the maximum number of modules kept in memory and on disk,
+    /// evicting the least recently used ones if there are more.
+    pub fn set_capacity(&self, capacity: usize) {
+        *self.capacity.write().unwrap() = capacity;
+        self.modules.lock().unwrap().evict(capacity);
+        if let Some(path) = self.disk_path.read().unwrap().as_ref() {
+            prune_disk(path, capacity);
+        }
+    }
+
+    /// Enable or disable the cache. When disabled, every call to
+    /// [`ModuleCache::get_or_compile`] compiles the module.
+    pub fn set_enabled(&self, enabled: bool) {
+        *self.enabled.write().unwrap() = enabled;
+    }
+
+    /// Drop all in-memory modules
+    pub fn clear(&self) {
+        self.modules.lock().unwrap().entries.clear();
+    }
+
+    /// Number of modules held in memory
+    pub fn len(&self) -> usize {
+        self.modules.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the engine and module for the given key, used by `contract_id`.
+    /// On a miss the module is loaded from disk if present, or otherwise
+    /// compiled with the engine returned by `compile`, and then cached.
+    /// The cache is not locked while loading or compiling, so concurrent
+    /// misses of the same key may both compile it, and the first one to
+    /// finish gets cached.
+    pub fn get_or_compile(
+        &self,
+        key: &ModuleCacheKey,
+        contract_id: ContractId,
+        wasm_bytes: &[u8],
+        compile: impl FnOnce() -> Engine,
+    ) -> Result<(Engine, Module)> {
+        if !*self.enabled.read().unwrap() {
+            let engine = compile();
+            let module = Module::new(&engine, wasm_bytes)?;
+            return Ok((engine, module))
+        }
+
+        if let Some(v) = self.lookup(key, &contract_id) {
+            debug!(target: "runtime::module_cache", "Found cached module for {}", contract_id);
+            return Ok(v)
+        }
+
+        let (engine, module) = match self.load_from_disk(key) {
+            Some(v) => v,
+            None => {
+                debug!(target: "runtime::module_cache", "Compiling module for {}", contract_id);
+                let engine = compile();
+                let module = Module::new(&engine, wasm_bytes)?;
+                self.store_to_disk(key, &module);
+                (engine, module)
+            }
+        };
+
+        // Another caller may have cached the module in the meantime
+        if let Some(v) = self.lookup(key, &contract_id) {
+            return Ok(v)
+        }
+
+        let capacity = *self.capacity.read().unwrap();
+        let mut modules = self.modules.lock().unwrap();
+        let last_used = modules.tick();
+        let cached = CachedModule {
+            engine: engine.clone(),
+            module: module.clone(),
+            contract_ids: HashSet::from([contract_id.to_bytes()]),
+            last_used,
+        };
+        modules.entries.insert(*key, cached);
+        modules.evict(capacity);
+
+        Ok((engine, module))
+    }
+
+    /// Return the cached module for the given key, marking it as used
+    /// by `contract_id`.
+    fn lookup(&self, key: &ModuleCacheKey, contract_id: &ContractId) -> Option<(Engine, Module)> {
+        let mut modules = self.modules.lock().unwrap();
+        let last_used = modules.tick();
+        let cached = modules.entries.get_mut(key)?;
+        cached.contract_ids.insert(contract_id.to_bytes());
+        cached.last_used = last_used;
+        Some((cached.engine.clone(), cached.module.clone()))
+    }
+
+    /// Drop the modules used by `contract_id` which were not compiled from
+    /// `wasm_bytes`. This is called when a contract gets redeployed.
+    pub fn invalidate(&self, contract_id: &ContractId, wasm_bytes: &[u8]) {
+        let bincode_hash = *blake3::hash(wasm_bytes).as_bytes();
+        let mut modules = self.modules.lock().unwrap();
+
+        let mut stale = vec![];
+        for (key, cached) in modules.entries.iter_mut() {
+            if key.bincode_hash == bincode_hash ||
+                !cached.contract_ids.remove(&contract_id.to_bytes())
+            {
+                continue
+            }
+
+            // Other contracts may still run the same bincode
+            if cached.contract_ids.is_empty() {
+                stale.push(*key);
+            }
+        }
+
+        for key in stale {
+            debug!(target: "runtime::module_cache", "Invalidating module for {}", contract_id);
+            modules.entries.remove(&key);
+            if let Some(path) = self.disk_path.read().unwrap().as_ref() {
+                let _ = fs::remove_file(path.join(key.filename()));
+            }
+        }
+    }
+
+    /// Try to deserialize a module previously stored on disk
+    fn load_from_disk(&self, key: &ModuleCacheKey) -> Option<(Engine, Module)> {
+        let path = self.disk_path.read().unwrap().as_ref()?.join(key.filename());
+        if !path.exists() {
+            return None
+        }
+
+        // Verify the module bytes against the hash they are prefixed with
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!(target: "runtime::module_cache", "Failed reading module from {:?}: {}", path, e);
+                return None
+            }
+        };
+        if bytes.len() < blake3::OUT_LEN ||
+            blake3::hash(&bytes[blake3::OUT_LEN..]).as_bytes() != &bytes[..blake3::OUT_LEN]
+        {
+            warn!(target: "runtime::module_cache", "Module file {:?} is corrupted, removing it", path);
+            let _ = fs::remove_file(&path);
+            return None
+        }
+
+        // A headless engine is enough to run precompiled modules.
+        let engine = Engine::headless();
+
+        // SAFETY: The cache directory is only written by us, see `set_disk_path()`,
+        // and the module bytes match the hash they were stored with.
+        // Artifacts from incompatible wasmer versions are rejected with an error.
+        match unsafe { Module::deserialize(&engine, &bytes[blake3::OUT_LEN..]) } {
+            Ok(module) => {
+                debug!(target: "runtime::module_cache", "Loaded module from {:?}", path);
+                // The modification time orders the files for eviction
+                if let Ok(file) = fs::File::options().write(true).open(&path) {
+                    let _ = file.set_modified(SystemTime::now());
+                }
+                Some((engine, module))
+            }
+            Err(e) => {
+                warn!(target: "runtime::module_cache", "Failed loading module from {:?}: {}", path, e);
+                let _ = fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    /// Persist a compiled module to disk, if configured, evicting the
+    /// least recently used files over capacity.
+    fn store_to_disk(&self, key: &ModuleCacheKey, module: &Module) {
+        let Some(dir) = self.disk_path.read().unwrap().clone() else { return };
+
+        let bytes = match module.serialize() {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error!(target: "runtime::module_cache", "Failed serializing module: {}", e);
+                return
+            }
+        };
+
+        // Write the module to a temporary file, then move it in place
+        let path = dir.join(key.filename());
+        let temp_path = path.with_extension(TEMP_EXTENSION);
+        let contents = [blake3::hash(&bytes).as_bytes(), &bytes[..]].concat();
+        if let Err(e) = fs::write(&temp_path, contents).and_then(|_| fs::rename(&temp_path, &path))
+        {
+            error!(target: "runtime::module_cache", "Failed storing module to {:?}: {}", path, e);
+            let _ = fs::remove_file(&temp_path);
+            return
+        }
+
+        prune_disk(&dir, *self.capacity.read().unwrap());
+    }
+}
+
+/// Remove the least recently used module files in `dir` until at most
+/// `capacity` remain.
+fn prune_disk(dir: &Path, capacity: usize) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+
+    let mut files: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == MODULE_EXTENSION))
+        .map(|path| {
+            let modified =
+                fs::metadata(&path).and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+            (modified, path)
+        })
+        .collect();
+
+    if files.len() <= capacity {
+        return
+    }
+
+    files.sort();
+    for (_, path) in &files[..files.len() - capacity] {
+        debug!(target: "runtime::module_cache", "Evicting module file {:?}", path);
+        let _ = fs::remove_file(path);
+    }
+}
+
+impl Default for ModuleCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,419 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Native execution backend for smart contracts.
+//!
+//! Instead of compiling a contract to WASM, its crate can be linked natively
+//! into a test binary and executed with a [`NativeRuntime`]. The contract's
+//! calls to the SDK host functions resolve to the symbols exported by this
+//! module, which forward them to the very same host implementations the WASM
+//! runtime uses, against the same blockchain overlay. The arguments are staged
+//! through a scratch linear memory owned by the runtime, so the host functions
+//! behave exactly like they do for WASM contracts.
+//!
+//! Native execution is not metered. A panic in the contract, or in a host
+//! function it calls, unwinds up to the section boundary, where it's caught
+//! and returned as [`Error::ContractPanic`] with the panic message, like a
+//! trapping WASM contract fails its call.
+
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    mem::size_of,
+    panic::{self, AssertUnwindSafe},
+    ptr::copy_nonoverlapping,
+};
+
+use darkfi_sdk::{
+    crypto::ContractId,
+    entrypoint::{self, ContractSectionFn, NativeContract},
+};
+use log::{debug, error, info};
+use wasmer::{
+    Engine, ExportError, FunctionEnv, FunctionEnvMut, Memory, MemoryType, Pages, Store, WasmPtr,
+    WASM_PAGE_SIZE,
+};
+use wasmer_compiler_singlepass::Singlepass;
+
+use super::{
+    gas_schedule::gas_schedule,
+    import,
+    import::db::DbHandle,
+    vm_runtime::{ContractSection, Env, Runtime},
+};
+use crate::{
+    blockchain::{
+        contract_store::SMART_CONTRACT_ZKAS_DB_NAME, BlockchainOverlayPtr, ContractEvent,
+    },
+    util::time::TimeKeeper,
+    Error, Result,
+};
+
+/// A native contract call in progress, whose host functions are being served
+struct NativeFrame {
+    store: *mut Store,
+    ctx: FunctionEnv<Env>,
+}
+
+thread_local! {
+    /// Native contract calls in progress on this thread, innermost last
+    static FRAMES: RefCell<Vec<NativeFrame>> = RefCell::new(vec![]);
+}
+
+/// Pops the innermost frame once a native contract call returns or unwinds
+struct FrameGuard;
+
+impl Drop for FrameGuard {
+    fn drop(&mut self) {
+        FRAMES.with(|frames| frames.borrow_mut().pop());
+    }
+}
+
+/// Runtime executing a natively linked contract.
+pub struct NativeRuntime {
+    /// Section functions of the contract
+    contract: NativeContract,
+    /// Store owning the scratch memory used to pass data to the host functions
+    store: Store,
+    /// Wrapper for [`Env`]
+    ctx: FunctionEnv<Env>,
+}
+
+impl NativeRuntime {
+    /// Create a new native runtime for the given contract.
+    pub fn new(
+        contract: NativeContract,
+        blockchain: BlockchainOverlayPtr,
+        contract_id: ContractId,
+        time_keeper: TimeKeeper,
+    ) -> Result<Self> {
+        info!(target: "runtime::native", "[NATIVE] Instantiating a new runtime");
+        let schedule = gas_schedule(time_keeper.verifying_slot);
+
+        let mut store = Store::new(Engine::from(Singlepass::new()));
+        let memory = Memory::new(&mut store, MemoryType::new(Pages(1), None, false))?;
+
+        let ctx = FunctionEnv::new(
+            &mut store,
+            Env {
+                blockchain,
+                db_handles: RefCell::new(vec![]),
+                contract_id,
+                contract_bincode: vec![],
+                contract_section: ContractSection::Null,
+                contract_return_data: Cell::new(None),
+                logs: RefCell::new(vec![]),
+                events: RefCell::new(vec![]),
+                memory: Some(memory),
+                objects: RefCell::new(vec![]),
+                time_keeper,
+                instance: None,
+                unmetered: true,
+                call_depth: 0,
+                gas_schedule: schedule,
+                trace: RefCell::new(None),
+            },
+        );
+
+        Ok(Self { contract, store, ctx })
+    }
+
+    /// Call a contract section using a supplied payload. Returns a `Vec<u8>`
+    /// corresponding to the result data of the call.
+    fn call(&mut self, section: ContractSection, payload: &[u8]) -> Result<Vec<u8>> {
+        debug!(target: "runtime::native", "Calling {} method", section.name());
+
+        let func: Option<ContractSectionFn> = match section {
+            ContractSection::Deploy => Some(self.contract.initialize),
+            ContractSection::Exec => Some(self.contract.entrypoint),
+            ContractSection::Update => Some(self.contract.update),
+            ContractSection::Metadata => Some(self.contract.metadata),
+            ContractSection::View => self.contract.view,
+            ContractSection::Migrate => self.contract.migrate,
+            ContractSection::Null => unreachable!(),
+        };
+
+        // Missing sections fail the same way they do for WASM contracts
+        let Some(func) = func else {
+            return Err(ExportError::Missing(section.name().to_string()).into())
+        };
+
+        let env_mut = self.ctx.as_mut(&mut self.store);
+        env_mut.contract_section = section;
+        assert!(env_mut.contract_return_data.take().is_none());
+
+        // The payload length is read by the contract as a u64, so we keep the
+        // buffer aligned for it.
+        let payload = Runtime::serialize_payload(&env_mut.contract_id, payload);
+        let mut input = vec![0_u64; payload.len() / size_of::<u64>() + 1];
+        // SAFETY: `input` is at least as long as `payload`
+        unsafe {
+            copy_nonoverlapping(payload.as_ptr(), input.as_mut_ptr() as *mut u8, payload.len())
+        };
+
+        FRAMES.with(|frames| {
+            frames.borrow_mut().push(NativeFrame { store: &mut self.store, ctx: self.ctx.clone() })
+        });
+        let guard = FrameGuard;
+        let input = input.as_mut_ptr() as *mut u8;
+        // SAFETY: the payload has the layout `entrypoint::deserialize` expects,
+        // and the store is only accessed through the frame until the call returns.
+        // Section functions use the `C-unwind` ABI, so a panic can be caught here.
+        let retval = panic::catch_unwind(AssertUnwindSafe(|| unsafe { func(input) }));
+        drop(guard);
+
+        let env_mut = self.ctx.as_mut(&mut self.store);
+        env_mut.contract_section = ContractSection::Null;
+        let retdata = env_mut.contract_return_data.take().unwrap_or_default();
+
+        let retval = match retval {
+            Ok(v) => v,
+            Err(payload) => {
+                let msg = panic_message(payload.as_ref());
+                error!(target: "runtime::native", "[NATIVE] Contract panicked: {}", msg);
+                return Err(Error::ContractPanic(msg))
+            }
+        };
+
+        match retval {
+            entrypoint::SUCCESS => Ok(retdata),
+            _ => {
+                let err = darkfi_sdk::error::ContractError::from(retval);
+                error!(target: "runtime::native", "[NATIVE] Contract returned: {:?}", err);
+                Err(Error::ContractError(err))
+            }
+        }
+    }
+
+    /// Run the contract's `__initialize` section, like [`Runtime::deploy`].
+    /// No bincode is recorded in the `WasmStore` for native contracts.
+    pub fn deploy(&mut self, payload: &[u8]) -> Result<()> {
+        let cid = self.ctx.as_ref(&self.store).contract_id;
+        info!(target: "runtime::native", "[NATIVE] Running deploy() for ContractID: {}", cid);
+
+        // Scoped for borrows
+        {
+            let env_mut = self.ctx.as_mut(&mut self.store);
+            // The zkas db is always index 0 in db handles when deploying
+            let contracts = &env_mut.blockchain.lock().unwrap().contracts;
+
+            let zkas_tree_handle =
+                match contracts.lookup(&env_mut.contract_id, SMART_CONTRACT_ZKAS_DB_NAME) {
+                    Ok(v) => v,
+                    Err(_) => contracts.init(&env_mut.contract_id, SMART_CONTRACT_ZKAS_DB_NAME)?,
+                };
+
+            let mut db_handles = env_mut.db_handles.borrow_mut();
+            db_handles.push(DbHandle::new(env_mut.contract_id, zkas_tree_handle));
+        }
+
+        let _ = self.call(ContractSection::Deploy, payload)?;
+
+        info!(target: "runtime::native", "[NATIVE] Successfully deployed ContractID: {}", cid);
+        Ok(())
+    }
+
+    /// Run the contract's `__metadata` section, like [`Runtime::metadata`].
+    pub fn metadata(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        self.call(ContractSection::Metadata, payload)
+    }
+
+    /// Run the contract's `__entrypoint` section, like [`Runtime::exec`].
+    pub fn exec(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        self.call(ContractSection::Exec, payload)
+    }
+
+    /// Run the contract's `__update` section, like [`Runtime::apply`].
+    pub fn apply(&mut self, update: &[u8]) -> Result<()> {
+        let _ = self.call(ContractSection::Update, update)?;
+        Ok(())
+    }
+
+    /// Run the contract's `__view` section, like [`Runtime::view`].
+    pub fn view(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        self.call(ContractSection::View, payload)
+    }
+
+    /// Take the structured events emitted by the contract so far.
+    pub fn take_events(&mut self) -> Vec<ContractEvent> {
+        self.ctx.as_ref(&self.store).events.take()
+    }
+}
+
+/// Extract the message of a caught panic
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        return msg.to_string()
+    }
+
+    match payload.downcast_ref::<String>() {
+        Some(msg) => msg.clone(),
+        None => "Unknown panic".to_string(),
+    }
+}
+
+/// Run `f` with the store and environment of the innermost native call.
+fn with_frame<T>(f: impl FnOnce(&mut Store, &FunctionEnv<Env>) -> T) -> T {
+    let (store, ctx) = FRAMES.with(|frames| {
+        let frames = frames.borrow();
+        let Some(frame) = frames.last() else {
+            panic!("Host function called outside of a native contract call")
+        };
+        (frame.store, frame.ctx.clone())
+    });
+
+    // SAFETY: the frame was pushed by `NativeRuntime::call`, which holds the
+    // store borrowed and doesn't touch it until the contract call returns.
+    f(unsafe { &mut *store }, &ctx)
+}
+
+/// Grow the scratch memory so it holds at least `len` bytes.
+fn reserve_memory(store: &mut Store, ctx: &FunctionEnv<Env>, len: usize) -> Result<()> {
+    let memory = ctx.as_ref(&*store).memory().clone();
+    let size = memory.view(&*store).data_size() as usize;
+    if len > size {
+        let pages = (len - size) / WASM_PAGE_SIZE + 1;
+        memory.grow(store, Pages(pages as u32))?;
+    }
+
+    Ok(())
+}
+
+/// Copy the contract's `(ptr, len)` argument into the scratch memory and
+/// call the host function `func` with it.
+///
+/// # Safety
+/// `ptr` must be valid for reads of `len` bytes.
+unsafe fn call_with_input(
+    ptr: *const u8,
+    len: u32,
+    func: fn(FunctionEnvMut<Env>, WasmPtr<u8>, u32) -> i64,
+) -> i64 {
+    let input = std::slice::from_raw_parts(ptr, len as usize);
+
+    with_frame(|store, ctx| {
+        if let Err(e) = reserve_memory(store, ctx, input.len()) {
+            error!(target: "runtime::native", "[NATIVE] Failed to grow scratch memory: {}", e);
+            return darkfi_sdk::error::INTERNAL_ERROR
+        }
+
+        let env = ctx.as_ref(&*store);
+        if let Err(e) = env.memory_view(&*store).write(0, input) {
+            error!(target: "runtime::native", "[NATIVE] Failed to write to scratch memory: {}", e);
+            return darkfi_sdk::error::INTERNAL_ERROR
+        }
+
+        func(ctx.clone().into_mut(store), WasmPtr::new(0), len)
+    })
+}
+
+/// Export host functions taking a `(ptr, len)` argument to native contracts
+macro_rules! native_imports {
+    ($($name:ident => $func:path),* $(,)?) => {
+        $(
+            /// # Safety
+            /// `ptr` must be valid for reads of `len` bytes.
+            #[no_mangle]
+            pub unsafe extern "C-unwind" fn $name(ptr: *const u8, len: u32) -> i64 {
+                call_with_input(ptr, len, $func)
+            }
+        )*
+    };
+}
+
+/// Export the time getter host functions to native contracts
+macro_rules! native_getters {
+    ($($name:ident => $func:path),* $(,)?) => {
+        $(
+            #[no_mangle]
+            pub extern "C-unwind" fn $name() -> u64 {
+                with_frame(|store, ctx| $func(ctx.clone().into_mut(store)))
+            }
+        )*
+    };
+}
+
+native_imports! {
+    set_return_data_ => import::util::set_return_data,
+    put_object_bytes_ => import::util::put_object_bytes,
+    emit_event_ => import::util::emit_event,
+    db_init_ => import::db::db_init,
+    db_lookup_ => import::db::db_lookup,
+    db_get_ => import::db::db_get,
+    db_contains_key_ => import::db::db_contains_key,
+    db_iter_prefix_ => import::db::db_iter_prefix,
+    db_range_ => import::db::db_range,
+    db_set_ => import::db::db_set,
+    db_del_ => import::db::db_del,
+    zkas_db_set_ => import::db::zkas_db_set,
+    merkle_add_ => import::merkle::merkle_add,
+    call_contract_view_ => import::contract::call_contract_view,
+}
+
+native_getters! {
+    get_current_epoch_ => import::util::get_current_epoch,
+    get_current_block_height_ => import::util::get_current_block_height,
+    get_current_slot_ => import::util::get_current_slot,
+    get_verifying_block_height_ => import::util::get_verifying_block_height,
+    get_verifying_slot_ => import::util::get_verifying_slot,
+    get_verifying_block_height_epoch_ => import::util::get_verifying_block_height_epoch,
+    get_verifying_slot_epoch_ => import::util::get_verifying_slot_epoch,
+    get_blockchain_time_ => import::util::get_blockchain_time,
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn get_object_size_(idx: u32) -> i64 {
+    with_frame(|store, ctx| import::util::get_object_size(ctx.clone().into_mut(store), idx))
+}
+
+/// # Safety
+/// `ptr` must be valid for writes of the size of the object at `idx`.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn get_object_bytes_(ptr: *const u8, idx: u32) -> i64 {
+    with_frame(|store, ctx| {
+        let len = match ctx.as_ref(&*store).objects.borrow().get(idx as usize) {
+            Some(obj) => obj.len(),
+            None => 0,
+        };
+
+        if let Err(e) = reserve_memory(store, ctx, len) {
+            error!(target: "runtime::native", "[NATIVE] Failed to grow scratch memory: {}", e);
+            return darkfi_sdk::error::INTERNAL_ERROR
+        }
+
+        let ret = import::util::get_object_bytes(ctx.clone().into_mut(store), WasmPtr::new(0), idx);
+        if ret != entrypoint::SUCCESS {
+            return ret
+        }
+
+        // Copy the object from the scratch memory into the contract's buffer
+        let output = std::slice::from_raw_parts_mut(ptr as *mut u8, len);
+        let env = ctx.as_ref(&*store);
+        if let Err(e) = env.memory_view(&*store).read(0, output) {
+            error!(target: "runtime::native", "[NATIVE] Failed to read from scratch memory: {}", e);
+            return darkfi_sdk::error::INTERNAL_ERROR
+        }
+
+        ret
+    })
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn get_slot_(slot: u64) -> i64 {
+    with_frame(|store, ctx| import::util::get_slot(ctx.clone().into_mut(store), slot))
+}
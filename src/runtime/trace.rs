@@ -0,0 +1,369 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Execution tracing of the WASM runtime.
+//!
+//! When tracing is enabled on a [`super::vm_runtime::Runtime`] with
+//! `Runtime::enable_trace`, every host function call is recorded along
+//! with its arguments, the gas remaining before and after it and its
+//! return value, together with the database accesses it performed.
+//! The resulting [`ExecutionTrace`] can be serialized to JSON, so a full
+//! trace of a failing transaction can be inspected.
+
+use std::collections::HashMap;
+
+use darkfi_sdk::crypto::ContractId;
+use tinyjson::JsonValue;
+use wasmer::{FunctionEnvMut, WasmPtr};
+
+use super::{import::db::DbHandle, vm_runtime::Env};
+
+/// Kind of a database access
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DbAccessKind {
+    Read,
+    Write,
+    Delete,
+}
+
+impl DbAccessKind {
+    pub const fn name(&self) -> &str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+/// A single step of a contract execution
+#[derive(Clone, Debug)]
+pub enum TraceStep {
+    /// A contract section was entered
+    SectionStart {
+        /// Name of the section
+        section: String,
+        /// Length of the serialized payload
+        payload_len: usize,
+        /// Gas remaining when entering the section
+        gas_remaining: u64,
+    },
+    /// A host function was called
+    HostCall {
+        /// Name of the host function
+        function: &'static str,
+        /// Raw arguments the function was called with
+        args: Vec<u64>,
+        /// Memory slice read by the function, if it takes one
+        input: Option<Vec<u8>>,
+        /// Gas remaining before the call
+        gas_before: u64,
+        /// Gas remaining after the call
+        gas_after: u64,
+        /// Value returned to the contract, if any
+        ret: Option<i64>,
+    },
+    /// A database was accessed by the preceding host function call
+    DbAccess {
+        /// Kind of the access
+        kind: DbAccessKind,
+        /// Contract owning the accessed database
+        contract_id: ContractId,
+        /// Accessed tree
+        tree: [u8; 32],
+        /// Hash of the accessed key
+        key: blake3::Hash,
+        /// Hash of the read or written value, if any
+        value: Option<blake3::Hash>,
+    },
+    /// A nested cross-contract view call
+    View(ExecutionTrace),
+    /// A contract section returned
+    SectionEnd {
+        /// Name of the section
+        section: String,
+        /// Return value of the section, or the runtime error message
+        result: std::result::Result<i64, String>,
+        /// Gas remaining when leaving the section
+        gas_remaining: u64,
+    },
+}
+
+/// Execution trace of a single contract runtime
+#[derive(Clone, Debug)]
+pub struct ExecutionTrace {
+    /// The contract ID being executed
+    pub contract_id: ContractId,
+    /// Recorded execution steps, in order
+    pub steps: Vec<TraceStep>,
+}
+
+fn json_obj<const N: usize>(vals: [(&str, JsonValue); N]) -> JsonValue {
+    JsonValue::Object(HashMap::from(vals.map(|(k, v)| (k.to_string(), v))))
+}
+
+fn json_num(n: f64) -> JsonValue {
+    JsonValue::Number(n)
+}
+
+fn json_str(s: impl ToString) -> JsonValue {
+    JsonValue::String(s.to_string())
+}
+
+fn json_opt(v: Option<JsonValue>) -> JsonValue {
+    v.unwrap_or(JsonValue::Null)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl TraceStep {
+    pub fn to_json(&self) -> JsonValue {
+        match self {
+            Self::SectionStart { section, payload_len, gas_remaining } => json_obj([
+                ("step", json_str("section_start")),
+                ("section", json_str(section)),
+                ("payload_len", json_num(*payload_len as f64)),
+                ("gas_remaining", json_num(*gas_remaining as f64)),
+            ]),
+            Self::HostCall { function, args, input, gas_before, gas_after, ret } => json_obj([
+                ("step", json_str("host_call")),
+                ("function", json_str(function)),
+                ("args", JsonValue::Array(args.iter().map(|x| json_num(*x as f64)).collect())),
+                ("input", json_opt(input.as_ref().map(|x| json_str(to_hex(x))))),
+                ("gas_before", json_num(*gas_before as f64)),
+                ("gas_after", json_num(*gas_after as f64)),
+                ("ret", json_opt(ret.map(|x| json_num(x as f64)))),
+            ]),
+            Self::DbAccess { kind, contract_id, tree, key, value } => json_obj([
+                ("step", json_str("db_access")),
+                ("kind", json_str(kind.name())),
+                ("contract_id", json_str(contract_id)),
+                ("tree", json_str(blake3::Hash::from(*tree).to_hex())),
+                ("key", json_str(key.to_hex())),
+                ("value", json_opt(value.map(|x| json_str(x.to_hex())))),
+            ]),
+            Self::View(trace) => json_obj([("step", json_str("view")), ("trace", trace.to_json())]),
+            Self::SectionEnd { section, result, gas_remaining } => {
+                let (ret, error) = match result {
+                    Ok(ret) => (json_num(*ret as f64), JsonValue::Null),
+                    Err(e) => (JsonValue::Null, json_str(e)),
+                };
+                json_obj([
+                    ("step", json_str("section_end")),
+                    ("section", json_str(section)),
+                    ("ret", ret),
+                    ("error", error),
+                    ("gas_remaining", json_num(*gas_remaining as f64)),
+                ])
+            }
+        }
+    }
+}
+
+impl ExecutionTrace {
+    pub fn to_json(&self) -> JsonValue {
+        json_obj([
+            ("contract_id", json_str(self.contract_id)),
+            ("steps", JsonValue::Array(self.steps.iter().map(|x| x.to_json()).collect())),
+        ])
+    }
+}
+
+/// Host function argument types that can be recorded in a trace
+pub(crate) trait TraceArg {
+    fn trace_arg(&self) -> u64;
+}
+
+impl TraceArg for u32 {
+    fn trace_arg(&self) -> u64 {
+        *self as u64
+    }
+}
+
+impl TraceArg for u64 {
+    fn trace_arg(&self) -> u64 {
+        *self
+    }
+}
+
+impl TraceArg for WasmPtr<u8> {
+    fn trace_arg(&self) -> u64 {
+        self.offset() as u64
+    }
+}
+
+/// Host function return types that can be recorded in a trace
+pub(crate) trait TraceRet {
+    fn trace_ret(&self) -> Option<i64>;
+}
+
+impl TraceRet for i64 {
+    fn trace_ret(&self) -> Option<i64> {
+        Some(*self)
+    }
+}
+
+impl TraceRet for u64 {
+    fn trace_ret(&self) -> Option<i64> {
+        Some(*self as i64)
+    }
+}
+
+impl TraceRet for () {
+    fn trace_ret(&self) -> Option<i64> {
+        None
+    }
+}
+
+/// Record the start of a host function call, if tracing is enabled.
+/// If `input` is given, the memory slice it points to is recorded too.
+/// Returns the index of the recorded step, to be passed to [`end_call`].
+pub(crate) fn begin_call(
+    ctx: &mut FunctionEnvMut<Env>,
+    function: &'static str,
+    args: Vec<u64>,
+    input: Option<(WasmPtr<u8>, u32)>,
+) -> Option<usize> {
+    let (env, mut store) = ctx.data_and_store_mut();
+    if env.trace.borrow().is_none() {
+        return None
+    }
+
+    let input = input.and_then(|(ptr, len)| {
+        let memory_view = env.memory_view(&store);
+        ptr.slice(&memory_view, len).ok()?.read_to_vec().ok()
+    });
+
+    let gas_before = env.remaining_gas(&mut store).unwrap_or_default();
+    let mut trace = env.trace.borrow_mut();
+    let steps = trace.as_mut().unwrap();
+    steps.push(TraceStep::HostCall {
+        function,
+        args,
+        input,
+        gas_before,
+        gas_after: gas_before,
+        ret: None,
+    });
+
+    Some(steps.len() - 1)
+}
+
+/// Record the gas remaining and the return value of a host function call
+/// started with [`begin_call`].
+pub(crate) fn end_call(ctx: &mut FunctionEnvMut<Env>, idx: Option<usize>, value: Option<i64>) {
+    let Some(idx) = idx else { return };
+
+    let (env, mut store) = ctx.data_and_store_mut();
+    let gas_remaining = env.remaining_gas(&mut store).unwrap_or_default();
+    let mut trace = env.trace.borrow_mut();
+    if let Some(TraceStep::HostCall { gas_after, ret, .. }) =
+        trace.as_mut().and_then(|steps| steps.get_mut(idx))
+    {
+        *gas_after = gas_remaining;
+        *ret = value;
+    }
+}
+
+impl Env {
+    /// Record a database access, if tracing is enabled
+    pub(crate) fn trace_db(
+        &self,
+        kind: DbAccessKind,
+        db_handle: &DbHandle,
+        key: &[u8],
+        value: Option<&[u8]>,
+    ) {
+        if let Some(steps) = self.trace.borrow_mut().as_mut() {
+            steps.push(TraceStep::DbAccess {
+                kind,
+                contract_id: db_handle.contract_id,
+                tree: db_handle.tree,
+                key: blake3::hash(key),
+                value: value.map(blake3::hash),
+            });
+        }
+    }
+
+    /// Record a step, if tracing is enabled
+    pub(crate) fn trace_step(&self, step: TraceStep) {
+        if let Some(steps) = self.trace.borrow_mut().as_mut() {
+            steps.push(step);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use darkfi_sdk::pasta::pallas;
+
+    use super::*;
+
+    #[test]
+    fn trace_to_json() {
+        let contract_id = ContractId::from(pallas::Base::from(42));
+        let trace = ExecutionTrace {
+            contract_id,
+            steps: vec![
+                TraceStep::HostCall {
+                    function: "db_get",
+                    args: vec![8, 4],
+                    input: Some(vec![0xde, 0xad, 0xbe, 0xef]),
+                    gas_before: 100,
+                    gas_after: 96,
+                    ret: Some(0),
+                },
+                TraceStep::DbAccess {
+                    kind: DbAccessKind::Read,
+                    contract_id,
+                    tree: [0; 32],
+                    key: blake3::hash(b"key"),
+                    value: None,
+                },
+                TraceStep::SectionEnd {
+                    section: "__entrypoint".to_string(),
+                    result: Err("out of gas".to_string()),
+                    gas_remaining: 0,
+                },
+            ],
+        };
+
+        let json: HashMap<String, JsonValue> = trace.to_json().try_into().unwrap();
+        assert_eq!(json["contract_id"], JsonValue::String(contract_id.to_string()));
+
+        let steps: &Vec<JsonValue> = json["steps"].get().unwrap();
+        assert_eq!(steps.len(), 3);
+
+        let call: &HashMap<String, JsonValue> = steps[0].get().unwrap();
+        assert_eq!(call["function"], JsonValue::String("db_get".to_string()));
+        assert_eq!(call["input"], JsonValue::String("deadbeef".to_string()));
+        assert_eq!(call["gas_after"], JsonValue::Number(96.0));
+
+        let access: &HashMap<String, JsonValue> = steps[1].get().unwrap();
+        assert_eq!(access["kind"], JsonValue::String("read".to_string()));
+        assert_eq!(access["value"], JsonValue::Null);
+
+        let end: &HashMap<String, JsonValue> = steps[2].get().unwrap();
+        assert_eq!(end["ret"], JsonValue::Null);
+        assert_eq!(end["error"], JsonValue::String("out of gas".to_string()));
+
+        assert!(trace.to_json().stringify().is_ok());
+    }
+}
@@ -25,8 +25,9 @@ use darkfi_sdk::{crypto::ContractId, entrypoint};
 use darkfi_serial::serialize;
 use log::{debug, error, info};
 use wasmer::{
-    imports, wasmparser::Operator, AsStoreMut, AsStoreRef, CompilerConfig, Function, FunctionEnv,
-    Instance, Memory, MemoryView, Module, Pages, Store, Value, WASM_PAGE_SIZE,
+    imports, wasmparser::Operator, AsStoreMut, AsStoreRef, CompilerConfig, Engine, Function,
+    FunctionEnv, FunctionEnvMut, Instance, Memory, MemoryView, Pages, Store, Value, WasmPtr,
+    WASM_PAGE_SIZE,
 };
 use wasmer_compiler_singlepass::Singlepass;
 use wasmer_middlewares::{
@@ -34,9 +35,19 @@ use wasmer_middlewares::{
     Metering,
 };
 
-use super::{import, import::db::DbHandle, memory::MemoryManipulation};
+use super::{
+    gas_schedule::{gas_schedule, GasSchedule, HostFunction},
+    import,
+    import::db::DbHandle,
+    memory::MemoryManipulation,
+    module_cache::{ModuleCacheKey, MODULE_CACHE},
+    trace::{self, ExecutionTrace, TraceArg, TraceRet, TraceStep},
+};
 use crate::{
-    blockchain::{contract_store::SMART_CONTRACT_ZKAS_DB_NAME, BlockchainOverlayPtr},
+    blockchain::{
+        contract_store::SMART_CONTRACT_ZKAS_DB_NAME, BlockchainOverlayPtr, ContractEvent,
+        ContractMigration,
+    },
     util::time::TimeKeeper,
     Error, Result,
 };
@@ -44,8 +55,37 @@ use crate::{
 /// Name of the wasm linear memory in our guest module
 const MEMORY: &str = "memory";
 
-/// Gas limit for a single contract call (Single WASM instance)
-const GAS_LIMIT: u64 = 400_000_000;
+/// Maximum nesting depth of cross-contract view calls
+pub const MAX_CALL_DEPTH: u8 = 4;
+
+/// Create a host function which records its calls in the execution trace
+/// when tracing is enabled. With `input`, the memory slice pointed to by
+/// the `(ptr, len)` arguments is recorded as well.
+macro_rules! traced {
+    (@inner $store:expr, $ctx:expr, $name:literal, $func:path, ($($arg:ident: $ty:ty),*), $input:expr) => {
+        Function::new_typed_with_env(
+            $store,
+            $ctx,
+            |mut ctx: FunctionEnvMut<Env>, $($arg: $ty),*| {
+                // The arguments are only collected when tracing is enabled,
+                // to keep untraced host calls free of allocations.
+                let idx = match ctx.data().trace.borrow().is_some() {
+                    true => trace::begin_call(&mut ctx, $name, vec![$($arg.trace_arg()),*], $input),
+                    false => None,
+                };
+                let ret = $func(ctx.as_mut(), $($arg),*);
+                trace::end_call(&mut ctx, idx, ret.trace_ret());
+                ret
+            },
+        )
+    };
+    ($store:expr, $ctx:expr, $name:literal, $func:path, (ptr: WasmPtr<u8>, len: u32), input) => {
+        traced!(@inner $store, $ctx, $name, $func, (ptr: WasmPtr<u8>, len: u32), Some((ptr, len)))
+    };
+    ($store:expr, $ctx:expr, $name:literal, $func:path, ($($arg:ident: $ty:ty),*)) => {
+        traced!(@inner $store, $ctx, $name, $func, ($($arg: $ty),*), None)
+    };
+}
 
 // ANCHOR: contract-section
 #[derive(Clone, Copy, PartialEq)]
@@ -58,6 +98,10 @@ pub enum ContractSection {
     Update,
     /// Metadata
     Metadata,
+    /// Read-only view function, called by other contracts
+    View,
+    /// State migration function, run when a contract is redeployed
+    Migrate,
     /// Placeholder state before any initialization
     Null,
 }
@@ -70,6 +114,8 @@ impl ContractSection {
             Self::Exec => "__entrypoint",
             Self::Update => "__update",
             Self::Metadata => "__metadata",
+            Self::View => "__view",
+            Self::Migrate => "__migrate",
             Self::Null => unreachable!(),
         }
     }
@@ -91,6 +137,8 @@ pub struct Env {
     pub contract_return_data: Cell<Option<Vec<u8>>>,
     /// Logs produced by the contract
     pub logs: RefCell<Vec<String>>,
+    /// Structured events emitted by the contract
+    pub events: RefCell<Vec<ContractEvent>>,
     /// Direct memory access to the VM
     pub memory: Option<Memory>,
     /// Object store for transferring memory from the host to VM
@@ -99,6 +147,15 @@ pub struct Env {
     pub time_keeper: TimeKeeper,
     /// Parent `Instance`
     pub instance: Option<Arc<Instance>>,
+    /// Whether gas metering is disabled. Only natively linked contracts,
+    /// which have no `Instance` to meter, run unmetered.
+    pub unmetered: bool,
+    /// Nesting depth of cross-contract view calls, 0 for the outermost call
+    pub call_depth: u8,
+    /// Gas schedule used to price operators and host functions
+    pub gas_schedule: &'static GasSchedule,
+    /// Recorded execution steps, if tracing is enabled
+    pub trace: RefCell<Option<Vec<TraceStep>>>,
 }
 
 impl Env {
@@ -118,20 +175,96 @@ impl Env {
         self.memory.as_ref().unwrap()
     }
 
+    /// Return the `Instance` gas is metered on, or `None` if the runtime
+    /// is explicitly unmetered. Errors if a metered runtime has no instance.
+    fn metered_instance(&self) -> Result<Option<&Arc<Instance>>> {
+        match (self.instance.as_ref(), self.unmetered) {
+            (_, true) => Ok(None),
+            (Some(instance), false) => Ok(Some(instance)),
+            (None, false) => {
+                error!(
+                    target: "runtime::vm_runtime",
+                    "[WASM] [{}] Gas metering attempted without an instance", self.contract_id,
+                );
+                Err(Error::WasmerRuntimeError("Gas metering without an instance".to_string()))
+            }
+        }
+    }
+
+    /// Return the remaining gas in the current runtime.
+    /// Unmetered runtimes always have the full gas limit available.
+    pub fn remaining_gas(&self, ctx: &mut impl AsStoreMut) -> Result<u64> {
+        let Some(instance) = self.metered_instance()? else {
+            return Ok(self.gas_schedule.gas_limit)
+        };
+
+        match get_remaining_points(ctx, instance) {
+            MeteringPoints::Remaining(rem) => Ok(rem),
+            MeteringPoints::Exhausted => Ok(0),
+        }
+    }
+
     /// Subtract given gas cost from remaining gas in the current runtime
-    pub fn subtract_gas(&mut self, ctx: &mut impl AsStoreMut, gas: u64) {
-        match get_remaining_points(ctx, self.instance.as_ref().unwrap()) {
+    pub fn subtract_gas(&mut self, ctx: &mut impl AsStoreMut, gas: u64) -> Result<()> {
+        let Some(instance) = self.metered_instance()? else { return Ok(()) };
+
+        match get_remaining_points(ctx, instance) {
             MeteringPoints::Remaining(rem) => {
                 if gas > rem {
-                    set_remaining_points(ctx, self.instance.as_ref().unwrap(), 0);
+                    set_remaining_points(ctx, instance, 0);
                 } else {
-                    set_remaining_points(ctx, self.instance.as_ref().unwrap(), rem - gas);
+                    set_remaining_points(ctx, instance, rem - gas);
                 }
             }
             MeteringPoints::Exhausted => {
-                set_remaining_points(ctx, self.instance.as_ref().unwrap(), 0);
+                set_remaining_points(ctx, instance, 0);
             }
         }
+
+        Ok(())
+    }
+
+    /// Subtract the cost of a host function call processing the given
+    /// amount of bytes, as priced by the gas schedule.
+    pub fn subtract_host_gas(
+        &mut self,
+        ctx: &mut impl AsStoreMut,
+        function: HostFunction,
+        bytes: u64,
+    ) -> Result<()> {
+        let gas = self.gas_schedule.host_cost(function).gas(bytes);
+        self.subtract_gas(ctx, gas)
+    }
+
+    /// Subtract only the per-byte cost of a host function, for additional
+    /// data processed by a call that has already been charged its base cost.
+    pub fn subtract_host_gas_bytes(
+        &mut self,
+        ctx: &mut impl AsStoreMut,
+        function: HostFunction,
+        bytes: u64,
+    ) -> Result<()> {
+        let gas = self.gas_schedule.host_cost(function).per_byte.saturating_mul(bytes);
+        self.subtract_gas(ctx, gas)
+    }
+
+    /// Charge the storage fee for the given net amount of bytes the contract
+    /// added to its state. If bytes were freed instead, part of the fee is
+    /// refunded, without the remaining gas ever exceeding the gas limit.
+    pub fn charge_storage(&mut self, ctx: &mut impl AsStoreMut, delta: i64) -> Result<()> {
+        if delta >= 0 {
+            let gas = self.gas_schedule.storage_per_byte.saturating_mul(delta as u64);
+            return self.subtract_gas(ctx, gas)
+        }
+
+        let Some(instance) = self.metered_instance()? else { return Ok(()) };
+        let refund = self.gas_schedule.storage_refund_per_byte.saturating_mul(delta.unsigned_abs());
+        if let MeteringPoints::Remaining(rem) = get_remaining_points(ctx, instance) {
+            let remaining = rem.saturating_add(refund).min(self.gas_schedule.gas_limit);
+            set_remaining_points(ctx, instance, remaining);
+        }
+
+        Ok(())
     }
 }
 
@@ -154,25 +287,36 @@ impl Runtime {
         time_keeper: TimeKeeper,
     ) -> Result<Self> {
         info!(target: "runtime::vm_runtime", "[WASM] Instantiating a new runtime");
+        // The gas schedule is selected by the height of the block being
+        // verified, so older blocks keep being priced the way they were.
+        let schedule = gas_schedule(time_keeper.verifying_slot);
+
         // This function will be called for each `Operator` encountered during
         // the wasm module execution. It should return the cost of the operator
-        // that it received as its first argument. For now, every wasm opcode
-        // has a cost of `1`.
+        // that it received as its first argument, as defined by the schedule.
         // https://docs.rs/wasmparser/latest/wasmparser/enum.Operator.html
-        let cost_function = |_operator: &Operator| -> u64 { 1 };
+        let cost_function = move |operator: &Operator| -> u64 { schedule.operators.cost(operator) };
 
         // `Metering` needs to be configured with a limit and a cost function.
         // For each `Operator`, the metering middleware will call the cost
         // function and subtract the cost from the remaining points.
-        let metering = Arc::new(Metering::new(GAS_LIMIT, cost_function));
 
-        // Define the compiler and middleware, engine, and store
-        let mut compiler_config = Singlepass::new();
-        compiler_config.push_middleware(metering);
-        let mut store = Store::new(compiler_config);
+        // Define the compiler and middleware, and engine. A `Metering` instance
+        // can only be used for a single module, so every compilation needs a
+        // fresh engine.
+        let compile = || {
+            let metering = Arc::new(Metering::new(schedule.gas_limit, cost_function));
+            let mut compiler_config = Singlepass::new();
+            compiler_config.push_middleware(metering);
+            Engine::from(compiler_config)
+        };
 
+        // Fetch the compiled module from the cache, compiling it if needed
         debug!(target: "runtime::vm_runtime", "Compiling module");
-        let module = Module::new(&store, wasm_bytes)?;
+        let key = ModuleCacheKey::new(wasm_bytes, schedule.version, schedule.gas_limit);
+        let (engine, module) =
+            MODULE_CACHE.get_or_compile(&key, contract_id, wasm_bytes, compile)?;
+        let mut store = Store::new(engine);
 
         // Initialize data
         let db_handles = RefCell::new(vec![]);
@@ -190,145 +334,226 @@ impl Runtime {
                 contract_section: ContractSection::Null,
                 contract_return_data: Cell::new(None),
                 logs,
+                events: RefCell::new(vec![]),
                 memory: None,
                 objects: RefCell::new(vec![]),
                 time_keeper,
                 instance: None,
+                unmetered: false,
+                call_depth: 0,
+                gas_schedule: schedule,
+                trace: RefCell::new(None),
             },
         );
 
         let imports = imports! {
             "env" => {
-                "drk_log_" => Function::new_typed_with_env(
+                "drk_log_" => traced!(
                     &mut store,
                     &ctx,
+                    "drk_log",
                     import::util::drk_log,
+                    (ptr: WasmPtr<u8>, len: u32), input
                 ),
 
-                "set_return_data_" => Function::new_typed_with_env(
+                "emit_event_" => traced!(
                     &mut store,
                     &ctx,
+                    "emit_event",
+                    import::util::emit_event,
+                    (ptr: WasmPtr<u8>, len: u32), input
+                ),
+
+                "set_return_data_" => traced!(
+                    &mut store,
+                    &ctx,
+                    "set_return_data",
                     import::util::set_return_data,
+                    (ptr: WasmPtr<u8>, len: u32), input
                 ),
 
-                "db_init_" => Function::new_typed_with_env(
+                "db_init_" => traced!(
                     &mut store,
                     &ctx,
+                    "db_init",
                     import::db::db_init,
+                    (ptr: WasmPtr<u8>, len: u32), input
                 ),
 
-                "db_lookup_" => Function::new_typed_with_env(
+                "db_lookup_" => traced!(
                     &mut store,
                     &ctx,
+                    "db_lookup",
                     import::db::db_lookup,
+                    (ptr: WasmPtr<u8>, len: u32), input
                 ),
 
-                "db_get_" => Function::new_typed_with_env(
+                "db_get_" => traced!(
                     &mut store,
                     &ctx,
+                    "db_get",
                     import::db::db_get,
+                    (ptr: WasmPtr<u8>, len: u32), input
                 ),
 
-                "db_contains_key_" => Function::new_typed_with_env(
+                "db_contains_key_" => traced!(
                     &mut store,
                     &ctx,
+                    "db_contains_key",
                     import::db::db_contains_key,
+                    (ptr: WasmPtr<u8>, len: u32), input
+                ),
+
+                "db_iter_prefix_" => traced!(
+                    &mut store,
+                    &ctx,
+                    "db_iter_prefix",
+                    import::db::db_iter_prefix,
+                    (ptr: WasmPtr<u8>, len: u32), input
                 ),
 
-                "db_set_" => Function::new_typed_with_env(
+                "db_range_" => traced!(
                     &mut store,
                     &ctx,
+                    "db_range",
+                    import::db::db_range,
+                    (ptr: WasmPtr<u8>, len: u32), input
+                ),
+
+                "db_set_" => traced!(
+                    &mut store,
+                    &ctx,
+                    "db_set",
                     import::db::db_set,
+                    (ptr: WasmPtr<u8>, len: u32), input
                 ),
 
-                "db_del_" => Function::new_typed_with_env(
+                "db_del_" => traced!(
                     &mut store,
                     &ctx,
+                    "db_del",
                     import::db::db_del,
+                    (ptr: WasmPtr<u8>, len: u32), input
                 ),
 
-                "zkas_db_set_" => Function::new_typed_with_env(
+                "zkas_db_set_" => traced!(
                     &mut store,
                     &ctx,
+                    "zkas_db_set",
                     import::db::zkas_db_set,
+                    (ptr: WasmPtr<u8>, len: u32), input
                 ),
 
-                "put_object_bytes_" => Function::new_typed_with_env(
+                "put_object_bytes_" => traced!(
                     &mut store,
                     &ctx,
+                    "put_object_bytes",
                     import::util::put_object_bytes,
+                    (ptr: WasmPtr<u8>, len: u32), input
                 ),
 
-                "get_object_bytes_" => Function::new_typed_with_env(
+                "get_object_bytes_" => traced!(
                     &mut store,
                     &ctx,
+                    "get_object_bytes",
                     import::util::get_object_bytes,
+                    (ptr: WasmPtr<u8>, idx: u32)
                 ),
 
-                "get_object_size_" => Function::new_typed_with_env(
+                "get_object_size_" => traced!(
                     &mut store,
                     &ctx,
+                    "get_object_size",
                     import::util::get_object_size,
+                    (idx: u32)
                 ),
 
-                "merkle_add_" => Function::new_typed_with_env(
+                "merkle_add_" => traced!(
                     &mut store,
                     &ctx,
+                    "merkle_add",
                     import::merkle::merkle_add,
+                    (ptr: WasmPtr<u8>, len: u32), input
                 ),
 
-                "get_current_epoch_" => Function::new_typed_with_env(
+                "get_current_epoch_" => traced!(
                     &mut store,
                     &ctx,
+                    "get_current_epoch",
                     import::util::get_current_epoch,
+                    ()
                 ),
 
-                "get_current_block_height_" => Function::new_typed_with_env(
+                "get_current_block_height_" => traced!(
                     &mut store,
                     &ctx,
+                    "get_current_block_height",
                     import::util::get_current_block_height,
+                    ()
                 ),
 
-                "get_current_slot_" => Function::new_typed_with_env(
+                "get_current_slot_" => traced!(
                     &mut store,
                     &ctx,
+                    "get_current_slot",
                     import::util::get_current_slot,
+                    ()
                 ),
 
-                "get_verifying_block_height_" => Function::new_typed_with_env(
+                "get_verifying_block_height_" => traced!(
                     &mut store,
                     &ctx,
+                    "get_verifying_block_height",
                     import::util::get_verifying_block_height,
+                    ()
                 ),
 
-                "get_verifying_slot_" => Function::new_typed_with_env(
+                "get_verifying_slot_" => traced!(
                     &mut store,
                     &ctx,
+                    "get_verifying_slot",
                     import::util::get_verifying_slot,
+                    ()
                 ),
 
-                "get_verifying_block_height_epoch_" => Function::new_typed_with_env(
+                "get_verifying_block_height_epoch_" => traced!(
                     &mut store,
                     &ctx,
+                    "get_verifying_block_height_epoch",
                     import::util::get_verifying_block_height_epoch,
+                    ()
                 ),
 
-                "get_verifying_slot_epoch_" => Function::new_typed_with_env(
+                "get_verifying_slot_epoch_" => traced!(
                     &mut store,
                     &ctx,
+                    "get_verifying_slot_epoch",
                     import::util::get_verifying_slot_epoch,
+                    ()
                 ),
 
-                "get_slot_" => Function::new_typed_with_env(
+                "get_slot_" => traced!(
                     &mut store,
                     &ctx,
+                    "get_slot",
                     import::util::get_slot,
+                    (slot: u64)
                 ),
 
-                "get_blockchain_time_" => Function::new_typed_with_env(
+                "get_blockchain_time_" => traced!(
                     &mut store,
                     &ctx,
+                    "get_blockchain_time",
                     import::util::get_blockchain_time,
+                    ()
+                ),
+
+                "call_contract_view_" => traced!(
+                    &mut store,
+                    &ctx,
+                    "call_contract_view",
+                    import::contract::call_contract_view,
+                    (ptr: WasmPtr<u8>, len: u32), input
                 ),
             }
         };
@@ -365,6 +590,13 @@ impl Runtime {
         self.set_memory_page_size(pages_required as u32)?;
         self.copy_to_memory(&payload)?;
 
+        let gas_remaining = self.gas_remaining();
+        self.ctx.as_ref(&self.store).trace_step(TraceStep::SectionStart {
+            section: section.name().to_string(),
+            payload_len: payload.len(),
+            gas_remaining,
+        });
+
         debug!(target: "runtime::vm_runtime", "Getting {} function", section.name());
         let entrypoint = self.instance.exports.get_function(section.name())?;
 
@@ -385,6 +617,7 @@ impl Runtime {
                 info!(target: "runtime::vm_runtime", "[WASM] {}", self.gas_info());
                 // WasmerRuntimeError panics are handled here. Return from run() immediately.
                 error!(target: "runtime::vm_runtime", "[WASM] Wasmer Runtime Error: {:#?}", e);
+                self.trace_section_end(section, Err(e.to_string()));
                 return Err(e.into())
             }
         };
@@ -421,6 +654,8 @@ impl Runtime {
             }
         };
 
+        self.trace_section_end(section, Ok(retval));
+
         // Check the integer return value of the call. A value of `entrypoint::SUCCESS` (i.e. zero)
         // corresponds to a successful contract call; in this case, we return the contract's
         // result data. Otherwise, map the integer return value to a [`ContractError`].
@@ -466,6 +701,20 @@ impl Runtime {
             db_handles.push(DbHandle::new(env_mut.contract_id, zkas_tree_handle));
         }
 
+        // If a different bincode was previously deployed, give the new one a
+        // chance to migrate the existing state, before initializing on top of it.
+        let env = self.ctx.as_ref(&self.store);
+        let previous = match env.blockchain.lock().unwrap().wasm_bincode.get(env.contract_id) {
+            Ok(v) => Some(v),
+            Err(Error::WasmBincodeNotFound) => None,
+            Err(e) => return Err(e),
+        };
+        if let Some(previous) = previous {
+            if previous != env.contract_bincode && self.has_section(ContractSection::Migrate) {
+                self.migrate(&previous)?;
+            }
+        }
+
         //debug!(target: "runtime::vm_runtime", "[WASM] payload: {:?}", payload);
         let _ = self.call(ContractSection::Deploy, payload)?;
 
@@ -478,10 +727,47 @@ impl Runtime {
             .wasm_bincode
             .insert(env_mut.contract_id, &env_mut.contract_bincode)?;
 
+        // Drop any compiled modules of the previously deployed bincode
+        MODULE_CACHE.invalidate(&env_mut.contract_id, &env_mut.contract_bincode);
+
         info!(target: "runtime::vm_runtime", "[WASM] Successfully deployed ContractID: {}", cid);
         Ok(())
     }
 
+    /// This function runs when a smart contract is redeployed with a different bincode,
+    /// right before its `__initialize` function, so the migration sees the state exactly
+    /// as the previous version left it.
+    ///
+    /// The runtime will look for a `__migrate` symbol in the wasm code, and execute it
+    /// with the blake3 hash of the previously deployed bincode as its payload, so the
+    /// contract can bring the existing state of its databases into the shape the new
+    /// version expects. Like `deploy`, this is only reachable through a deployment
+    /// signed by the contract's deployer. On success, the migration is recorded in
+    /// the `MigrationStore`.
+    fn migrate(&mut self, previous_bincode: &[u8]) -> Result<()> {
+        let cid = self.ctx.as_ref(&self.store).contract_id;
+        info!(target: "runtime::vm_runtime", "[WASM] Running migrate() for ContractID: {}", cid);
+
+        let previous_hash = blake3::hash(previous_bincode);
+        let _ = self.call(ContractSection::Migrate, previous_hash.as_bytes())?;
+
+        let env = self.ctx.as_ref(&self.store);
+        let migration = ContractMigration {
+            previous_bincode: *previous_hash.as_bytes(),
+            bincode: *blake3::hash(&env.contract_bincode).as_bytes(),
+            height: env.time_keeper.verifying_slot,
+        };
+        env.blockchain.lock().unwrap().migrations.append(&cid, migration)?;
+
+        info!(target: "runtime::vm_runtime", "[WASM] Successfully migrated ContractID: {}", cid);
+        Ok(())
+    }
+
+    /// Check if the wasm code exports the function of the given [`ContractSection`]
+    pub fn has_section(&self, section: ContractSection) -> bool {
+        self.instance.exports.get_function(section.name()).is_ok()
+    }
+
     /// This function runs first in the entire scheme of executing a smart contract.
     ///
     /// The runtime will look for a `__metadata` symbol in the wasm code and execute it.
@@ -535,6 +821,77 @@ impl Runtime {
         Ok(())
     }
 
+    /// This function runs when another contract calls into this one using the
+    /// `call_contract_view` host function.
+    ///
+    /// The runtime will look for a `__view` symbol in the wasm code, and execute
+    /// it with the given payload. The view section can only read from the overlay
+    /// databases, and its return data is handed back to the calling contract.
+    /// `call_depth` is the nesting depth of this call, and `gas_limit` the gas
+    /// the caller had remaining, which bounds the gas this call can use.
+    pub fn view(&mut self, payload: &[u8], call_depth: u8, gas_limit: u64) -> Result<Vec<u8>> {
+        let cid = self.ctx.as_ref(&self.store).contract_id;
+        info!(target: "runtime::vm_runtime", "[WASM] Running view() for ContractID: {}", cid);
+
+        self.ctx.as_mut(&mut self.store).call_depth = call_depth;
+        let max_gas = self.ctx.as_ref(&self.store).gas_schedule.gas_limit;
+        set_remaining_points(&mut self.store, &self.instance, gas_limit.min(max_gas));
+
+        debug!(target: "runtime::vm_runtime", "view payload: {:?}", payload);
+        let ret = self.call(ContractSection::View, payload)?;
+        debug!(target: "runtime::vm_runtime", "view returned: {:?}", ret);
+
+        info!(target: "runtime::vm_runtime", "[WASM] Successfully viewed ContractID: {}", cid);
+        Ok(ret)
+    }
+
+    /// Return the remaining gas using wasm's concept of metering points.
+    pub fn gas_remaining(&mut self) -> u64 {
+        match get_remaining_points(&mut self.store, &self.instance) {
+            MeteringPoints::Remaining(rem) => rem,
+            MeteringPoints::Exhausted => 0,
+        }
+    }
+
+    /// Enable execution tracing. Every host function call, database access
+    /// and nested view call made from now on is recorded, and can be
+    /// retrieved with [`Runtime::take_trace`].
+    pub fn enable_trace(&mut self) {
+        let env = self.ctx.as_ref(&self.store);
+        if env.trace.borrow().is_none() {
+            env.trace.replace(Some(vec![]));
+        }
+    }
+
+    /// Take the execution trace recorded so far, if tracing is enabled.
+    /// Tracing stays enabled, starting from an empty trace.
+    pub fn take_trace(&mut self) -> Option<ExecutionTrace> {
+        let env = self.ctx.as_ref(&self.store);
+        let steps = env.trace.borrow_mut().as_mut().map(std::mem::take)?;
+        Some(ExecutionTrace { contract_id: env.contract_id, steps })
+    }
+
+    /// Record the end of a contract section, if tracing is enabled.
+    fn trace_section_end(
+        &mut self,
+        section: ContractSection,
+        result: std::result::Result<i64, String>,
+    ) {
+        let gas_remaining = self.gas_remaining();
+        self.ctx.as_ref(&self.store).trace_step(TraceStep::SectionEnd {
+            section: section.name().to_string(),
+            result,
+            gas_remaining,
+        });
+    }
+
+    /// Take the structured events emitted by the contract so far.
+    /// Events can only be emitted by `apply`, so this should be called
+    /// after it succeeds.
+    pub fn take_events(&mut self) -> Vec<ContractEvent> {
+        self.ctx.as_ref(&self.store).events.take()
+    }
+
     /// Prints the wasm contract logs.
     fn print_logs(&self) {
         let logs = self.ctx.as_ref(&self.store).logs.borrow();
@@ -546,36 +903,39 @@ impl Runtime {
     /// Calculate the remaining gas using wasm's concept
     /// of metering points.
     pub fn gas_used(&mut self) -> u64 {
+        let gas_limit = self.ctx.as_ref(&self.store).gas_schedule.gas_limit;
         let remaining_points = get_remaining_points(&mut self.store, &self.instance);
 
         match remaining_points {
             MeteringPoints::Remaining(rem) => {
-                if rem > GAS_LIMIT {
+                if rem > gas_limit {
                     // This should never occur, but catch it explicitly to avoid
                     // potential underflow issues when calculating `remaining_points`.
-                    unreachable!("Remaining wasm points exceed GAS_LIMIT");
+                    unreachable!("Remaining wasm points exceed the gas limit");
                 }
-                GAS_LIMIT - rem
+                gas_limit - rem
             }
-            MeteringPoints::Exhausted => GAS_LIMIT + 1,
+            MeteringPoints::Exhausted => gas_limit + 1,
         }
     }
 
     // Return a message informing the user whether there is any
-    // gas remaining. Values equal to GAS_LIMIT are not considered
+    // gas remaining. Values equal to the gas limit are not considered
     // to be exhausted. e.g. Using 100/100 gas should not give a
     // 'gas exhausted' message.
     fn gas_info(&mut self) -> String {
+        let gas_limit = self.ctx.as_ref(&self.store).gas_schedule.gas_limit;
         let gas_used = self.gas_used();
 
-        if gas_used > GAS_LIMIT {
-            format!("Gas fully exhausted: {}/{}", gas_used, GAS_LIMIT)
+        if gas_used > gas_limit {
+            format!("Gas fully exhausted: {}/{}", gas_used, gas_limit)
         } else {
-            format!("Gas used: {}/{}", gas_used, GAS_LIMIT)
+            format!("Gas used: {}/{}", gas_used, gas_limit)
         }
     }
 
     /// Set the memory page size. Returns the previous memory size.
+    /// The pages are charged as priced by the gas schedule.
     fn set_memory_page_size(&mut self, pages: u32) -> Result<Pages> {
         // Grab memory by value
         let memory = self.take_memory();
@@ -583,6 +943,13 @@ impl Runtime {
         let ret = memory.grow(&mut self.store, Pages(pages))?;
         // Replace the memory back again
         self.ctx.as_mut(&mut self.store).memory = Some(memory);
+
+        let gas = self.ctx.as_ref(&self.store).gas_schedule.memory_grow_per_page * pages as u64;
+        if gas > 0 {
+            let remaining = self.gas_remaining();
+            set_remaining_points(&mut self.store, &self.instance, remaining.saturating_sub(gas));
+        }
+
         Ok(ret)
     }
 
@@ -606,7 +973,7 @@ impl Runtime {
     /// Serialize contract payload to the format accepted by the runtime functions.
     /// We keep the same payload as a slice of bytes, and prepend it with a [`ContractId`],
     /// and then a little-endian u64 to tell the payload's length.
-    fn serialize_payload(cid: &ContractId, payload: &[u8]) -> Vec<u8> {
+    pub(crate) fn serialize_payload(cid: &ContractId, payload: &[u8]) -> Vec<u8> {
         let ser_cid = serialize(cid);
         let payload_len = payload.len();
         let mut out = Vec::with_capacity(ser_cid.len() + 8 + payload_len);
@@ -51,6 +51,10 @@ pub mod note;
 pub mod nullifier;
 pub use nullifier::Nullifier;
 
+/// Poseidon duplex sponge encryption
+pub mod poseidon_encryption;
+pub use poseidon_encryption::{poseidon_decrypt, poseidon_encrypt};
+
 /// Pedersen commitment utilities
 pub mod pedersen;
 pub use pedersen::{pedersen_commitment_base, pedersen_commitment_u64};
@@ -0,0 +1,168 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Symmetric encryption of field elements using a Poseidon duplex sponge.
+//!
+//! The sponge state is initialized with `[key, nonce, len]` and permuted.
+//! Then for every `RATE` elements of the message, the elements are added
+//! to the rate part of the state, which becomes the ciphertext, and the
+//! state is permuted again before absorbing the next chunk.
+//!
+//! This matches the `constrain_encrypted_instance` zkas opcode, so a
+//! ciphertext created here can be used as the public inputs of a proof
+//! showing it encrypts the witnessed plaintext. A `(key, nonce)` pair must
+//! never be reused. The scheme provides no authentication by itself.
+
+use halo2_gadgets::poseidon::primitives::{P128Pow5T3, Spec};
+use pasta_curves::{group::ff::Field, pallas};
+
+/// Width of the Poseidon state
+const WIDTH: usize = 3;
+
+/// Number of state elements absorbed per permutation
+const RATE: usize = 2;
+
+/// Apply the `P128Pow5T3` permutation to the given state, following the
+/// round structure of the `halo2_gadgets` Poseidon primitives.
+fn permute(state: &mut [pallas::Base; WIDTH]) {
+    let (round_constants, mds, _) = <P128Pow5T3 as Spec<pallas::Base, WIDTH, RATE>>::constants();
+    let r_f = <P128Pow5T3 as Spec<pallas::Base, WIDTH, RATE>>::full_rounds() / 2;
+    let r_p = <P128Pow5T3 as Spec<pallas::Base, WIDTH, RATE>>::partial_rounds();
+    let sbox = <P128Pow5T3 as Spec<pallas::Base, WIDTH, RATE>>::sbox;
+
+    let apply_mds = |state: &mut [pallas::Base; WIDTH]| {
+        let mut new_state = [pallas::Base::ZERO; WIDTH];
+        for i in 0..WIDTH {
+            for j in 0..WIDTH {
+                new_state[i] += mds[i][j] * state[j];
+            }
+        }
+        *state = new_state;
+    };
+
+    for (round, rcs) in round_constants.iter().enumerate() {
+        if round < r_f || round >= r_f + r_p {
+            for (word, rc) in state.iter_mut().zip(rcs.iter()) {
+                *word = sbox(*word + rc);
+            }
+        } else {
+            for (word, rc) in state.iter_mut().zip(rcs.iter()) {
+                *word += rc;
+            }
+            state[0] = sbox(state[0]);
+        }
+
+        apply_mds(state);
+    }
+}
+
+/// Initialize the duplex sponge state for a message of `len` elements
+fn init_state(key: pallas::Base, nonce: pallas::Base, len: usize) -> [pallas::Base; WIDTH] {
+    let mut state = [key, nonce, pallas::Base::from(len as u64)];
+    permute(&mut state);
+    state
+}
+
+/// Encrypt the given field elements with `key` and `nonce`.
+/// The returned ciphertext has the same length as the plaintext.
+pub fn poseidon_encrypt(
+    key: pallas::Base,
+    nonce: pallas::Base,
+    plaintext: &[pallas::Base],
+) -> Vec<pallas::Base> {
+    let mut state = init_state(key, nonce, plaintext.len());
+    let mut ciphertext = Vec::with_capacity(plaintext.len());
+
+    for (i, chunk) in plaintext.chunks(RATE).enumerate() {
+        if i > 0 {
+            permute(&mut state);
+        }
+
+        for (j, m) in chunk.iter().enumerate() {
+            state[j] += m;
+            ciphertext.push(state[j]);
+        }
+    }
+
+    ciphertext
+}
+
+/// Decrypt the given ciphertext created by [`poseidon_encrypt`] with the
+/// same `key` and `nonce`.
+pub fn poseidon_decrypt(
+    key: pallas::Base,
+    nonce: pallas::Base,
+    ciphertext: &[pallas::Base],
+) -> Vec<pallas::Base> {
+    let mut state = init_state(key, nonce, ciphertext.len());
+    let mut plaintext = Vec::with_capacity(ciphertext.len());
+
+    for (i, chunk) in ciphertext.chunks(RATE).enumerate() {
+        if i > 0 {
+            permute(&mut state);
+        }
+
+        for (j, c) in chunk.iter().enumerate() {
+            plaintext.push(c - state[j]);
+            state[j] = *c;
+        }
+    }
+
+    plaintext
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::poseidon_hash;
+    use pasta_curves::group::ff::PrimeField;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_poseidon_encryption() {
+        let key = pallas::Base::random(&mut OsRng);
+        let nonce = pallas::Base::random(&mut OsRng);
+
+        for len in 0..6 {
+            let plaintext: Vec<pallas::Base> =
+                (0..len).map(|_| pallas::Base::random(&mut OsRng)).collect();
+
+            let ciphertext = poseidon_encrypt(key, nonce, &plaintext);
+            assert_eq!(ciphertext.len(), plaintext.len());
+            assert_eq!(poseidon_decrypt(key, nonce, &ciphertext), plaintext);
+
+            // A different nonce gives a different ciphertext
+            if len > 0 {
+                let other = poseidon_encrypt(key, nonce + pallas::Base::ONE, &plaintext);
+                assert_ne!(other, ciphertext);
+            }
+        }
+    }
+
+    #[test]
+    fn test_permutation() {
+        // A constant-length hash of two elements is a single permutation of
+        // the message with the length in the capacity element.
+        let a = pallas::Base::random(&mut OsRng);
+        let b = pallas::Base::random(&mut OsRng);
+
+        let mut state = [a, b, pallas::Base::from_u128(2 << 64)];
+        permute(&mut state);
+        assert_eq!(state[0], poseidon_hash([a, b]));
+    }
+}
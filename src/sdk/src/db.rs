@@ -16,7 +16,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use darkfi_serial::Encodable;
+use darkfi_serial::{deserialize, Encodable};
 
 use super::{
     crypto::ContractId,
@@ -107,6 +107,75 @@ pub fn db_contains_key(db_handle: DbHandle, key: &[u8]) -> GenericResult<bool> {
     }
 }
 
+/// Maximum number of records that can be requested from [`db_iter_prefix`]
+/// and [`db_range`] in a single call.
+pub const DB_ITER_MAX_ITEMS: u32 = 1000;
+
+/// Everyone can call this. Will read at most `limit` records with keys
+/// starting with `prefix` from the key-value store, in key order.
+///
+/// To fetch the next page, call it again passing the last returned key as
+/// the `cursor`. Fewer than `limit` records means the iteration is done.
+///
+/// ```
+/// let mut cursor = None;
+/// loop {
+///     let records = db_iter_prefix(db_handle, prefix, cursor.as_deref(), 100)?;
+///     // ...
+///     if records.len() < 100 {
+///         break
+///     }
+///     cursor = records.last().map(|(k, _)| k.clone());
+/// }
+/// ```
+pub fn db_iter_prefix(
+    db_handle: DbHandle,
+    prefix: &[u8],
+    cursor: Option<&[u8]>,
+    limit: u32,
+) -> GenericResult<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut len = 0;
+    let mut buf = vec![];
+    len += db_handle.encode(&mut buf)?;
+    len += prefix.to_vec().encode(&mut buf)?;
+    len += cursor.map(|c| c.to_vec()).encode(&mut buf)?;
+    len += limit.encode(&mut buf)?;
+
+    let ret = unsafe { db_iter_prefix_(buf.as_ptr(), len as u32) };
+    parse_records(ret)
+}
+
+/// Everyone can call this. Will read at most `limit` records with keys in
+/// `[start, end)` from the key-value store, in key order.
+///
+/// Paging works the same way as in [`db_iter_prefix`].
+pub fn db_range(
+    db_handle: DbHandle,
+    start: &[u8],
+    end: &[u8],
+    cursor: Option<&[u8]>,
+    limit: u32,
+) -> GenericResult<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut len = 0;
+    let mut buf = vec![];
+    len += db_handle.encode(&mut buf)?;
+    len += start.to_vec().encode(&mut buf)?;
+    len += end.to_vec().encode(&mut buf)?;
+    len += cursor.map(|c| c.to_vec()).encode(&mut buf)?;
+    len += limit.encode(&mut buf)?;
+
+    let ret = unsafe { db_range_(buf.as_ptr(), len as u32) };
+    parse_records(ret)
+}
+
+/// Auxiliary function to decode the records returned by the iteration functions
+fn parse_records(ret: i64) -> GenericResult<Vec<(Vec<u8>, Vec<u8>)>> {
+    match parse_ret(ret)? {
+        Some(bytes) => Ok(deserialize(&bytes)?),
+        None => Ok(vec![]),
+    }
+}
+
 /// Only update() can call this. Set a value within the transaction.
 ///
 /// ```
@@ -171,11 +240,13 @@ pub fn zkas_db_set(bincode: &[u8]) -> GenericResult<()> {
     }
 }
 
-extern "C" {
+extern "C-unwind" {
     fn db_init_(ptr: *const u8, len: u32) -> i64;
     fn db_lookup_(ptr: *const u8, len: u32) -> i64;
     fn db_get_(ptr: *const u8, len: u32) -> i64;
     fn db_contains_key_(ptr: *const u8, len: u32) -> i64;
+    fn db_iter_prefix_(ptr: *const u8, len: u32) -> i64;
+    fn db_range_(ptr: *const u8, len: u32) -> i64;
     fn db_set_(ptr: *const u8, len: u32) -> i64;
     fn db_del_(ptr: *const u8, len: u32) -> i64;
 
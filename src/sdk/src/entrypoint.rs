@@ -23,6 +23,25 @@ use crate::crypto::ContractId;
 /// Success exit code for a contract
 pub const SUCCESS: i64 = 0;
 
+/// Signature of the functions exported for every contract section.
+/// The `C-unwind` ABI lets a panic in a natively linked contract unwind
+/// back to the host, which catches it at the section boundary.
+pub type ContractSectionFn = unsafe extern "C-unwind" fn(input: *mut u8) -> i64;
+
+/// Section functions of a contract crate that is linked natively instead of
+/// being compiled to WASM. Contracts using [`define_contract!`] export theirs
+/// as `NATIVE_CONTRACT` on non-WASM targets, so the host can call into them
+/// directly.
+#[derive(Clone, Copy)]
+pub struct NativeContract {
+    pub initialize: ContractSectionFn,
+    pub entrypoint: ContractSectionFn,
+    pub update: ContractSectionFn,
+    pub metadata: ContractSectionFn,
+    pub view: Option<ContractSectionFn>,
+    pub migrate: Option<ContractSectionFn>,
+}
+
 #[macro_export]
 macro_rules! define_contract {
     (
@@ -31,9 +50,54 @@ macro_rules! define_contract {
         apply: $apply_func:ident,
         metadata: $metadata_func:ident
     ) => {
+        $crate::define_contract!(@sections $init_func, $exec_func, $apply_func, $metadata_func);
+        $crate::define_contract!(@native None, None);
+    };
+
+    (
+        init: $init_func:ident,
+        exec: $exec_func:ident,
+        apply: $apply_func:ident,
+        metadata: $metadata_func:ident,
+        view: $view_func:ident
+    ) => {
+        $crate::define_contract!(@sections $init_func, $exec_func, $apply_func, $metadata_func);
+        $crate::define_contract!(@view $view_func);
+        $crate::define_contract!(@native Some(__view), None);
+    };
+
+    (
+        init: $init_func:ident,
+        exec: $exec_func:ident,
+        apply: $apply_func:ident,
+        metadata: $metadata_func:ident,
+        migrate: $migrate_func:ident
+    ) => {
+        $crate::define_contract!(@sections $init_func, $exec_func, $apply_func, $metadata_func);
+        $crate::define_contract!(@migrate $migrate_func);
+        $crate::define_contract!(@native None, Some(__migrate));
+    };
+
+    (
+        init: $init_func:ident,
+        exec: $exec_func:ident,
+        apply: $apply_func:ident,
+        metadata: $metadata_func:ident,
+        view: $view_func:ident,
+        migrate: $migrate_func:ident
+    ) => {
+        $crate::define_contract!(@sections $init_func, $exec_func, $apply_func, $metadata_func);
+        $crate::define_contract!(@view $view_func);
+        $crate::define_contract!(@migrate $migrate_func);
+        $crate::define_contract!(@native Some(__view), Some(__migrate));
+    };
+
+    // The section symbols are only exported unmangled in WASM, so several
+    // contract crates can be linked natively into the same binary.
+    (@sections $init_func:ident, $exec_func:ident, $apply_func:ident, $metadata_func:ident) => {
         /// # Safety
-        #[no_mangle]
-        pub unsafe extern "C" fn __initialize(input: *mut u8) -> i64 {
+        #[cfg_attr(target_arch = "wasm32", no_mangle)]
+        pub unsafe extern "C-unwind" fn __initialize(input: *mut u8) -> i64 {
             let (contract_id, instruction_data) = $crate::entrypoint::deserialize(input);
 
             match $init_func(contract_id, &instruction_data) {
@@ -41,8 +105,8 @@ macro_rules! define_contract {
                 Err(e) => e.into(),
             }
         }
-        #[no_mangle]
-        pub unsafe extern "C" fn __entrypoint(input: *mut u8) -> i64 {
+        #[cfg_attr(target_arch = "wasm32", no_mangle)]
+        pub unsafe extern "C-unwind" fn __entrypoint(input: *mut u8) -> i64 {
             let (contract_id, instruction_data) = $crate::entrypoint::deserialize(input);
 
             match $exec_func(contract_id, &instruction_data) {
@@ -50,8 +114,8 @@ macro_rules! define_contract {
                 Err(e) => e.into(),
             }
         }
-        #[no_mangle]
-        pub unsafe extern "C" fn __update(input: *mut u8) -> i64 {
+        #[cfg_attr(target_arch = "wasm32", no_mangle)]
+        pub unsafe extern "C-unwind" fn __update(input: *mut u8) -> i64 {
             let (contract_id, update_data) = $crate::entrypoint::deserialize(input);
 
             match $apply_func(contract_id, &update_data) {
@@ -59,8 +123,8 @@ macro_rules! define_contract {
                 Err(e) => e.into(),
             }
         }
-        #[no_mangle]
-        pub unsafe extern "C" fn __metadata(input: *mut u8) -> i64 {
+        #[cfg_attr(target_arch = "wasm32", no_mangle)]
+        pub unsafe extern "C-unwind" fn __metadata(input: *mut u8) -> i64 {
             let (contract_id, instruction_data) = $crate::entrypoint::deserialize(input);
 
             match $metadata_func(contract_id, &instruction_data) {
@@ -69,6 +133,48 @@ macro_rules! define_contract {
             }
         }
     };
+
+    (@view $view_func:ident) => {
+        /// # Safety
+        #[cfg_attr(target_arch = "wasm32", no_mangle)]
+        pub unsafe extern "C-unwind" fn __view(input: *mut u8) -> i64 {
+            let (contract_id, instruction_data) = $crate::entrypoint::deserialize(input);
+
+            match $view_func(contract_id, &instruction_data) {
+                Ok(()) => $crate::entrypoint::SUCCESS,
+                Err(e) => e.into(),
+            }
+        }
+    };
+
+    // The migrate function receives the blake3 hash of the previously
+    // deployed bincode as its instruction data.
+    (@migrate $migrate_func:ident) => {
+        /// # Safety
+        #[cfg_attr(target_arch = "wasm32", no_mangle)]
+        pub unsafe extern "C-unwind" fn __migrate(input: *mut u8) -> i64 {
+            let (contract_id, previous_bincode_hash) = $crate::entrypoint::deserialize(input);
+
+            match $migrate_func(contract_id, &previous_bincode_hash) {
+                Ok(()) => $crate::entrypoint::SUCCESS,
+                Err(e) => e.into(),
+            }
+        }
+    };
+
+    (@native $view:expr, $migrate:expr) => {
+        /// Section functions of this contract, for linking it natively
+        #[cfg(not(target_arch = "wasm32"))]
+        pub const NATIVE_CONTRACT: $crate::entrypoint::NativeContract =
+            $crate::entrypoint::NativeContract {
+                initialize: __initialize,
+                entrypoint: __entrypoint,
+                update: __update,
+                metadata: __metadata,
+                view: $view,
+                migrate: $migrate,
+            };
+    };
 }
 
 /// Deserialize a given payload in `entrypoint`
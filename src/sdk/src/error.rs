@@ -75,6 +75,15 @@ pub enum ContractError {
     #[error("Db contains_key failed")]
     DbContainsKeyFailed,
 
+    #[error("Db iteration failed")]
+    DbIterFailed,
+
+    #[error("Contract call failed")]
+    CallContractFailed,
+
+    #[error("Contract call depth exceeded")]
+    CallDepthExceeded,
+
     #[error("Invalid function call")]
     InvalidFunction,
 
@@ -122,6 +131,9 @@ pub const SMT_INVALID_LEAF: i64 = to_builtin!(18);
 pub const SMT_INVALID_PATH_NODES: i64 = to_builtin!(19);
 pub const GET_SYSTEM_TIME_FAILED: i64 = to_builtin!(20);
 pub const DATA_TOO_LARGE: i64 = to_builtin!(21);
+pub const DB_ITER_FAILED: i64 = to_builtin!(22);
+pub const CALL_CONTRACT_FAILED: i64 = to_builtin!(23);
+pub const CALL_DEPTH_EXCEEDED: i64 = to_builtin!(24);
 
 impl From<ContractError> for i64 {
     fn from(err: ContractError) -> Self {
@@ -146,6 +158,9 @@ impl From<ContractError> for i64 {
             ContractError::SmtInvalidPathNodes => SMT_INVALID_PATH_NODES,
             ContractError::GetSystemTimeFailed => GET_SYSTEM_TIME_FAILED,
             ContractError::DataTooLarge => DATA_TOO_LARGE,
+            ContractError::DbIterFailed => DB_ITER_FAILED,
+            ContractError::CallContractFailed => CALL_CONTRACT_FAILED,
+            ContractError::CallDepthExceeded => CALL_DEPTH_EXCEEDED,
             ContractError::Custom(error) => {
                 if error == 0 {
                     CUSTOM_ZERO
@@ -181,6 +196,9 @@ impl From<i64> for ContractError {
             SMT_INVALID_PATH_NODES => Self::SmtInvalidPathNodes,
             GET_SYSTEM_TIME_FAILED => Self::GetSystemTimeFailed,
             DATA_TOO_LARGE => Self::DataTooLarge,
+            DB_ITER_FAILED => Self::DbIterFailed,
+            CALL_CONTRACT_FAILED => Self::CallContractFailed,
+            CALL_DEPTH_EXCEEDED => Self::CallDepthExceeded,
             _ => Self::Custom(error as u32),
         }
     }
@@ -0,0 +1,54 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_serial::Encodable;
+
+use super::error::{ContractError, ContractResult};
+
+/// Maximum size of the data of a single event
+pub const EVENT_MAX_DATA_SIZE: usize = 4096;
+
+/// Maximum number of events a single contract call can emit
+pub const EVENT_MAX_PER_CALL: usize = 64;
+
+/// Only `update` can call this. Emits a structured event with the given
+/// topic and data, which is stored on-chain along with the transaction
+/// and indexed by the contract ID and topic.
+///
+/// ```
+/// emit_event(&topic, &serialize(&coin))?;
+/// ```
+pub fn emit_event(topic: &[u8; 32], data: &[u8]) -> ContractResult {
+    if data.len() > EVENT_MAX_DATA_SIZE {
+        return Err(ContractError::DataTooLarge)
+    }
+
+    let mut len = 0;
+    let mut buf = vec![];
+    len += topic.encode(&mut buf)?;
+    len += data.to_vec().encode(&mut buf)?;
+
+    match unsafe { emit_event_(buf.as_ptr(), len as u32) } {
+        0 => Ok(()),
+        errcode => Err(ContractError::from(errcode)),
+    }
+}
+
+extern "C-unwind" {
+    fn emit_event_(ptr: *const u8, len: u32) -> i64;
+}
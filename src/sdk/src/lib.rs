@@ -36,6 +36,9 @@ pub mod entrypoint;
 /// Error handling
 pub mod error;
 
+/// Structured contract events
+pub mod event;
+
 /// Logging infrastructure
 pub mod log;
 
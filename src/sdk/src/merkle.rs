@@ -53,6 +53,6 @@ pub fn merkle_add(
     }
 }
 
-extern "C" {
+extern "C-unwind" {
     fn merkle_add_(ptr: *const u8, len: u32) -> i64;
 }
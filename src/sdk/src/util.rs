@@ -16,7 +16,12 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use super::error::{ContractError, GenericResult};
+use darkfi_serial::Encodable;
+
+use super::{
+    crypto::ContractId,
+    error::{ContractError, GenericResult},
+};
 
 /// Calls the `set_return_data` WASM function. Returns Ok(()) on success.
 /// Otherwise, convert the i64 error code into a [`ContractError`].
@@ -163,7 +168,24 @@ pub fn get_blockchain_time() -> u64 {
     unsafe { get_blockchain_time_() }
 }
 
-extern "C" {
+/// Call the `__view` section of another deployed contract with the given
+/// payload, and return the data it set with [`set_return_data`].
+/// The callee can only read state, and its gas usage is charged to the caller.
+///
+/// ```
+/// data = call_contract_view(contract_id, &payload)?;
+/// ```
+pub fn call_contract_view(contract_id: &ContractId, payload: &[u8]) -> GenericResult<Vec<u8>> {
+    let mut len = 0;
+    let mut buf = vec![];
+    len += contract_id.encode(&mut buf)?;
+    len += payload.to_vec().encode(&mut buf)?;
+
+    let ret = unsafe { call_contract_view_(buf.as_ptr(), len as u32) };
+    Ok(parse_ret(ret)?.unwrap_or_default())
+}
+
+extern "C-unwind" {
     fn set_return_data_(ptr: *const u8, len: u32) -> i64;
     fn put_object_bytes_(ptr: *const u8, len: u32) -> i64;
     fn get_object_bytes_(ptr: *const u8, len: u32) -> i64;
@@ -178,4 +200,6 @@ extern "C" {
     fn get_verifying_slot_epoch_() -> u64;
     fn get_slot_(slot: u64) -> i64;
     fn get_blockchain_time_() -> u64;
+
+    fn call_contract_view_(ptr: *const u8, len: u32) -> i64;
 }
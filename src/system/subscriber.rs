@@ -48,6 +48,11 @@ impl<T: Clone> Subscription<T> {
         }
     }
 
+    /// Grab a pending message, without waiting for one
+    pub fn try_receive(&self) -> Option<T> {
+        self.recv_queue.try_recv().ok()
+    }
+
     /// Must be called manually since async Drop is not possible in Rust
     pub async fn unsubscribe(&self) {
         self.parent.clone().unsubscribe(self.id).await
@@ -16,7 +16,10 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    io::{Error as IoError, ErrorKind, Read, Write},
+};
 
 use darkfi_sdk::{
     crypto::{
@@ -30,9 +33,9 @@ use darkfi_sdk::{
 };
 
 #[cfg(feature = "async-serial")]
-use darkfi_serial::async_trait;
+use darkfi_serial::{async_trait, AsyncDecodable, AsyncEncodable, AsyncRead, AsyncWrite};
 
-use darkfi_serial::{Encodable, SerialDecodable, SerialEncodable};
+use darkfi_serial::{Decodable, Encodable, VarInt};
 use log::{debug, error};
 use rand::{CryptoRng, RngCore};
 
@@ -49,14 +52,28 @@ macro_rules! zip {
     )
 }
 
+/// Version of transactions without a validity window. These are encoded
+/// as their calls, proofs and signatures, without an explicit version.
+pub const TX_VERSION_LEGACY: u8 = 1;
+
+/// Version of transactions with a validity window. These are encoded
+/// starting with an empty calls length, which legacy transactions can't
+/// have unless they're entirely empty like the genesis one, followed by
+/// the version, the calls, the validity window, the proofs and signatures.
+pub const TX_VERSION_VALIDITY_WINDOW: u8 = 2;
+
 // ANCHOR: transaction
 /// A Transaction contains an arbitrary number of `ContractCall` objects,
 /// along with corresponding ZK proofs and Schnorr signatures. `DarkLeaf`
 /// is used to map relations between contract calls in the transaciton.
-#[derive(Clone, Default, Eq, PartialEq, SerialEncodable, SerialDecodable)]
+#[derive(Clone, Default, Eq, PartialEq)]
 pub struct Transaction {
     /// Calls executed in this transaction
     pub calls: Vec<DarkLeaf<ContractCall>>,
+    /// First block height the transaction can be included at, if bounded
+    pub valid_from: Option<u64>,
+    /// Last block height the transaction can be included at, if bounded
+    pub valid_until: Option<u64>,
     /// Attached ZK proofs
     pub proofs: Vec<Vec<Proof>>,
     /// Attached Schnorr signatures
@@ -65,6 +82,39 @@ pub struct Transaction {
 // ANCHOR_END: transaction
 
 impl Transaction {
+    /// Return the encoding version of the transaction, which is
+    /// [`TX_VERSION_VALIDITY_WINDOW`] if it has a bounded validity window.
+    pub fn version(&self) -> u8 {
+        match self.valid_from.is_some() || self.valid_until.is_some() {
+            true => TX_VERSION_VALIDITY_WINDOW,
+            false => TX_VERSION_LEGACY,
+        }
+    }
+
+    /// Check if the transaction can be included in a block at the given height,
+    /// according to its validity window.
+    pub fn is_valid_at(&self, height: u64) -> bool {
+        if let Some(valid_from) = self.valid_from {
+            if height < valid_from {
+                return false
+            }
+        }
+
+        if let Some(valid_until) = self.valid_until {
+            if height > valid_until {
+                return false
+            }
+        }
+
+        true
+    }
+
+    /// Check if the transaction can no longer be included in any block after
+    /// the given height, because its validity window has passed.
+    pub fn is_expired(&self, height: u64) -> bool {
+        matches!(self.valid_until, Some(valid_until) if height > valid_until)
+    }
+
     /// Verify ZK proofs for the entire transaction.
     pub async fn verify_zkps(
         &self,
@@ -124,6 +174,11 @@ impl Transaction {
         // Hash the transaction without the signatures
         let mut hasher = blake3::Hasher::new();
         self.calls.encode(&mut hasher)?;
+        // Legacy transactions keep their signing hash
+        if self.version() == TX_VERSION_VALIDITY_WINDOW {
+            self.valid_from.encode(&mut hasher)?;
+            self.valid_until.encode(&mut hasher)?;
+        }
         self.proofs.encode(&mut hasher)?;
         let data_hash = hasher.finalize();
 
@@ -164,6 +219,11 @@ impl Transaction {
         // Hash the transaction without the signatures
         let mut hasher = blake3::Hasher::new();
         self.calls.encode(&mut hasher)?;
+        // Legacy transactions keep their signing hash
+        if self.version() == TX_VERSION_VALIDITY_WINDOW {
+            self.valid_from.encode(&mut hasher)?;
+            self.valid_until.encode(&mut hasher)?;
+        }
         self.proofs.encode(&mut hasher)?;
         let data_hash = hasher.finalize();
 
@@ -193,6 +253,125 @@ impl Transaction {
     }
 }
 
+impl Encodable for Transaction {
+    fn encode<S: Write>(&self, mut s: S) -> std::io::Result<usize> {
+        let mut len = 0;
+        if self.version() == TX_VERSION_LEGACY {
+            len += self.calls.encode(&mut s)?;
+        } else {
+            len += VarInt(0).encode(&mut s)?;
+            len += self.version().encode(&mut s)?;
+            len += self.calls.encode(&mut s)?;
+            len += self.valid_from.encode(&mut s)?;
+            len += self.valid_until.encode(&mut s)?;
+        }
+        len += self.proofs.encode(&mut s)?;
+        len += self.signatures.encode(&mut s)?;
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "async-serial")]
+#[async_trait]
+impl AsyncEncodable for Transaction {
+    async fn encode_async<S: AsyncWrite + Unpin + Send>(
+        &self,
+        s: &mut S,
+    ) -> std::io::Result<usize> {
+        let mut len = 0;
+        if self.version() == TX_VERSION_LEGACY {
+            len += self.calls.encode_async(s).await?;
+        } else {
+            len += VarInt(0).encode_async(s).await?;
+            len += self.version().encode_async(s).await?;
+            len += self.calls.encode_async(s).await?;
+            len += self.valid_from.encode_async(s).await?;
+            len += self.valid_until.encode_async(s).await?;
+        }
+        len += self.proofs.encode_async(s).await?;
+        len += self.signatures.encode_async(s).await?;
+        Ok(len)
+    }
+}
+
+/// Error for a versioned transaction without a validity window, as its
+/// legacy encoding is the canonical one.
+fn non_canonical_tx_error() -> IoError {
+    IoError::new(ErrorKind::Other, "Versioned transaction without a validity window")
+}
+
+impl Decodable for Transaction {
+    fn decode<D: Read>(mut d: D) -> std::io::Result<Self> {
+        let VarInt(calls_len) = Decodable::decode(&mut d)?;
+
+        let mut tx = Self::default();
+        if calls_len > 0 {
+            for _ in 0..calls_len {
+                tx.calls.push(Decodable::decode(&mut d)?);
+            }
+        } else {
+            let version: u8 = Decodable::decode(&mut d)?;
+            match version {
+                // An entirely empty legacy transaction, the byte being its proofs length
+                0 => {
+                    tx.signatures = Decodable::decode(&mut d)?;
+                    return Ok(tx)
+                }
+                TX_VERSION_VALIDITY_WINDOW => {
+                    tx.calls = Decodable::decode(&mut d)?;
+                    tx.valid_from = Decodable::decode(&mut d)?;
+                    tx.valid_until = Decodable::decode(&mut d)?;
+                    if tx.version() != version {
+                        return Err(non_canonical_tx_error())
+                    }
+                }
+                _ => return Err(IoError::new(ErrorKind::Other, "Unknown transaction version")),
+            }
+        }
+
+        tx.proofs = Decodable::decode(&mut d)?;
+        tx.signatures = Decodable::decode(&mut d)?;
+        Ok(tx)
+    }
+}
+
+#[cfg(feature = "async-serial")]
+#[async_trait]
+impl AsyncDecodable for Transaction {
+    async fn decode_async<D: AsyncRead + Unpin + Send>(d: &mut D) -> std::io::Result<Self> {
+        let VarInt(calls_len) = AsyncDecodable::decode_async(d).await?;
+
+        let mut tx = Self::default();
+        if calls_len > 0 {
+            for _ in 0..calls_len {
+                tx.calls.push(AsyncDecodable::decode_async(d).await?);
+            }
+        } else {
+            let version: u8 = AsyncDecodable::decode_async(d).await?;
+            match version {
+                // An entirely empty legacy transaction, the byte being its proofs length
+                0 => {
+                    tx.signatures = AsyncDecodable::decode_async(d).await?;
+                    return Ok(tx)
+                }
+                TX_VERSION_VALIDITY_WINDOW => {
+                    tx.calls = AsyncDecodable::decode_async(d).await?;
+                    tx.valid_from = AsyncDecodable::decode_async(d).await?;
+                    tx.valid_until = AsyncDecodable::decode_async(d).await?;
+                    if tx.version() != version {
+                        return Err(non_canonical_tx_error())
+                    }
+                }
+                _ => return Err(IoError::new(ErrorKind::Other, "Unknown transaction version")),
+            }
+        }
+
+        tx.proofs = AsyncDecodable::decode_async(d).await?;
+        tx.signatures = AsyncDecodable::decode_async(d).await?;
+        Ok(tx)
+    }
+}
+
 // Avoid showing the proofs and sigs in the debug output since often they are very long.
 impl std::fmt::Debug for Transaction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -208,6 +387,12 @@ impl std::fmt::Debug for Transaction {
             writeln!(f, "    children: {:?}", call.children_indexes)?;
             writeln!(f, "  }},")?;
         }
+        if let Some(valid_from) = self.valid_from {
+            writeln!(f, "  valid_from: {}", valid_from)?;
+        }
+        if let Some(valid_until) = self.valid_until {
+            writeln!(f, "  valid_until: {}", valid_until)?;
+        }
         writeln!(f, "}}")
     }
 }
@@ -239,6 +424,10 @@ pub struct ContractCallLeaf {
 pub struct TransactionBuilder {
     /// Contract calls trees forest
     pub calls: DarkForest<ContractCallLeaf>,
+    /// First block height the transaction can be included at, if bounded
+    pub valid_from: Option<u64>,
+    /// Last block height the transaction can be included at, if bounded
+    pub valid_until: Option<u64>,
 }
 
 // TODO: for now we build the trees manually, but we should
@@ -251,7 +440,7 @@ impl TransactionBuilder {
         children: Vec<DarkTree<ContractCallLeaf>>,
     ) -> DarkTreeResult<Self> {
         let calls = DarkForest::new(Some(MIN_TX_CALLS), Some(MAX_TX_CALLS));
-        let mut self_ = Self { calls };
+        let mut self_ = Self { calls, valid_from: None, valid_until: None };
         self_.append(data, children)?;
         Ok(self_)
     }
@@ -266,6 +455,13 @@ impl TransactionBuilder {
         self.calls.append(tree)
     }
 
+    /// Restrict the block heights the transaction can be included at.
+    /// Both bounds are inclusive, and `None` leaves a side unbounded.
+    pub fn set_validity(&mut self, valid_from: Option<u64>, valid_until: Option<u64>) {
+        self.valid_from = valid_from;
+        self.valid_until = valid_until;
+    }
+
     /// Builder builds the calls vector using the [`DarkForest`]
     /// and generates the corresponding [`Transaction`].
     pub fn build(&mut self) -> DarkTreeResult<Transaction> {
@@ -288,6 +484,12 @@ impl TransactionBuilder {
             proofs.push(leaf.data.proofs);
         }
 
-        Ok(Transaction { calls, proofs, signatures: vec![] })
+        Ok(Transaction {
+            calls,
+            valid_from: self.valid_from,
+            valid_until: self.valid_until,
+            proofs,
+            signatures: vec![],
+        })
     }
 }
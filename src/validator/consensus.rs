@@ -31,10 +31,12 @@ use crate::{
     tx::Transaction,
     util::time::{TimeKeeper, Timestamp},
     validator::{
+        mempool::{CallDecoder, Mempool, MEMPOOL_MAX_SIZE},
         pid::slot_pid_output,
         pow::PoWModule,
         utils::{best_forks_indexes, block_rank, find_extended_fork_index, previous_slot_info},
-        verify_block, verify_proposal, verify_transactions,
+        verification::apply_block_transactions,
+        verify_block, verify_proposal,
     },
     Error, Result,
 };
@@ -59,6 +61,10 @@ pub struct Consensus {
     pub forks: RwLock<Vec<Fork>>,
     /// Canonical blockchain PoW module state
     pub module: RwLock<PoWModule>,
+    /// Pending transactions fee priority index
+    pub mempool: RwLock<Mempool>,
+    /// Best fork proposal hashes, as last announced by `best_chain_update()`
+    pub best_chain: RwLock<Vec<blake3::Hash>>,
     /// Flag to enable PoS testing mode
     pub pos_testing_mode: bool,
 }
@@ -72,6 +78,7 @@ impl Consensus {
         pow_target: usize,
         pow_fixed_difficulty: Option<BigUint>,
         pos_testing_mode: bool,
+        call_decoders: Vec<CallDecoder>,
     ) -> Result<Self> {
         let module =
             RwLock::new(PoWModule::new(blockchain.clone(), pow_target, pow_fixed_difficulty)?);
@@ -83,6 +90,8 @@ impl Consensus {
             checked_finalization: RwLock::new(0),
             forks: RwLock::new(vec![]),
             module,
+            mempool: RwLock::new(Mempool::new(MEMPOOL_MAX_SIZE, call_decoders)),
+            best_chain: RwLock::new(vec![]),
             pos_testing_mode,
         })
     }
@@ -154,8 +163,10 @@ impl Consensus {
         };
 
         // Grab forks' unproposed transactions
-        let mut unproposed_txs = fork.unproposed_txs(&self.blockchain, &time_keeper).await?;
-        unproposed_txs.push(producer_tx);
+        let mempool = self.mempool.read().await;
+        let mut unproposed_txs = fork.unproposed_txs(&self.blockchain, &mempool)?;
+        drop(mempool);
+        unproposed_txs.insert(0, producer_tx);
 
         // Grab forks' last block proposal(previous)
         let previous = fork.last_proposal()?;
@@ -173,8 +184,17 @@ impl Consensus {
         // Generate the block
         let mut block = BlockInfo::new_empty(header, fork.slots.clone());
 
-        // Add transactions to the block
-        block.append_txs(unproposed_txs)?;
+        // Apply transactions over a clone of the forks' overlay, adding the
+        // valid ones to the block, along with the contracts state root
+        let overlay = fork.overlay.lock().unwrap().full_clone()?;
+        apply_block_transactions(
+            &overlay,
+            &time_keeper,
+            &mut block,
+            unproposed_txs,
+            self.pos_testing_mode,
+        )
+        .await?;
 
         Ok(block)
     }
@@ -360,6 +380,60 @@ impl Consensus {
         Ok((fork, None))
     }
 
+    /// Compare the current best fork against the one last announced, and
+    /// return the blocks that extend it. If the best fork no longer extends
+    /// the announced one, a [`Reorg`] is also returned, listing the announced
+    /// blocks that got dropped. Proposals that got finalized in the meantime
+    /// are not considered dropped, as they are now part of canonical.
+    pub async fn best_chain_update(&self) -> Result<(Option<Reorg>, Vec<BlockInfo>)> {
+        let forks = self.forks.read().await;
+        let mut best_chain = self.best_chain.write().await;
+
+        // Strip the announced proposals that got finalized
+        let mut finalized = 0;
+        for hash in best_chain.iter() {
+            if !self.blockchain.blocks.contains(hash)? {
+                break
+            }
+            finalized += 1;
+        }
+        best_chain.drain(..finalized);
+
+        // Grab the best fork. On ties, we keep following the announced one.
+        let common_prefix = |proposals: &[blake3::Hash]| {
+            proposals.iter().zip(best_chain.iter()).take_while(|(a, b)| a == b).count()
+        };
+        let fork = best_forks_indexes(&forks)?
+            .into_iter()
+            .map(|i| &forks[i])
+            .max_by_key(|fork| common_prefix(&fork.proposals))
+            .unwrap();
+        let common = common_prefix(&fork.proposals);
+
+        // Check if announced proposals got dropped
+        let reorg = if common < best_chain.len() {
+            let canonical_tip = self.blockchain.last()?.1;
+            let reorg = Reorg {
+                old_tip: *best_chain.last().unwrap(),
+                new_tip: *fork.proposals.last().unwrap_or(&canonical_tip),
+                rolled_back: best_chain[common..].to_vec(),
+            };
+            info!(
+                target: "validator::consensus::best_chain_update",
+                "Best fork reorganised from {} to {}, {} blocks rolled back",
+                reorg.old_tip, reorg.new_tip, reorg.rolled_back.len(),
+            );
+            Some(reorg)
+        } else {
+            None
+        };
+
+        let blocks = fork.overlay.lock().unwrap().get_blocks_by_hash(&fork.proposals[common..])?;
+        *best_chain = fork.proposals.clone();
+
+        Ok((reorg, blocks))
+    }
+
     /// Consensus finalization logic:
     /// - If the current best fork has reached greater length than the security threshold, and
     ///   no other fork exist with same rank, all proposals excluding the last one in that fork
@@ -401,6 +475,18 @@ impl Consensus {
     }
 }
 
+/// This struct represents a best fork reorganisation, where previously
+/// announced proposals got replaced by the ones of another fork.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reorg {
+    /// Previously announced best fork tip
+    pub old_tip: blake3::Hash,
+    /// New best fork tip
+    pub new_tip: blake3::Hash,
+    /// Dropped proposal hashes, in chain order
+    pub rolled_back: Vec<blake3::Hash>,
+}
+
 /// This struct represents a block proposal, used for consensus.
 #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
 pub struct Proposal {
@@ -495,19 +581,21 @@ impl Fork {
         }
     }
 
-    /// Auxiliary function to retrieve unproposed valid transactions.
-    pub async fn unproposed_txs(
+    /// Auxiliary function to retrieve unproposed transactions,
+    /// ordered by their fee rate, highest first. They get verified,
+    /// and capped, when applied to the block using them.
+    pub fn unproposed_txs(
         &self,
         blockchain: &Blockchain,
-        time_keeper: &TimeKeeper,
+        mempool: &Mempool,
     ) -> Result<Vec<Transaction>> {
+        // Order mempool transactions by their fee rate
+        let mut hashes = self.mempool.clone();
+        mempool.sort_by_priority(&mut hashes);
+
         // Retrieve all mempool transactions
-        let mut unproposed_txs: Vec<Transaction> = blockchain
-            .pending_txs
-            .get(&self.mempool, true)?
-            .iter()
-            .map(|x| x.clone().unwrap())
-            .collect();
+        let mut unproposed_txs: Vec<Transaction> =
+            blockchain.pending_txs.get(&hashes, true)?.iter().map(|x| x.clone().unwrap()).collect();
 
         // Iterate over fork proposals to find already proposed transactions
         // and remove them from the unproposed_txs vector.
@@ -518,21 +606,6 @@ impl Fork {
             }
         }
 
-        // Check if transactions exceed configured cap
-        if unproposed_txs.len() > TXS_CAP {
-            unproposed_txs = unproposed_txs[0..TXS_CAP].to_vec()
-        }
-
-        // Clone forks' overlay
-        let overlay = self.overlay.lock().unwrap().full_clone()?;
-
-        // Verify transactions
-        let erroneous_txs =
-            verify_transactions(&overlay, time_keeper, &unproposed_txs, false).await?;
-        if !erroneous_txs.is_empty() {
-            unproposed_txs.retain(|x| !erroneous_txs.contains(x));
-        }
-
         Ok(unproposed_txs)
     }
 
@@ -0,0 +1,443 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap, HashSet},
+};
+
+use darkfi_sdk::crypto::{ContractId, Nullifier};
+use darkfi_serial::serialize;
+
+use crate::{error::TxVerifyFailed, tx::Transaction, Result};
+
+/// Max total size of pending transactions, in bytes
+pub const MEMPOOL_MAX_SIZE: usize = 32 * 1024 * 1024;
+
+/// Fee paid per unit of gas used by a transaction.
+/// Rates are compared by cross multiplication, so no precision is lost.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FeeRate {
+    /// Fee paid by the transaction
+    pub fee: u64,
+    /// Gas used by the transaction
+    pub gas: u64,
+}
+
+impl FeeRate {
+    /// Fee rate as a floating point number, for display purposes
+    pub fn as_f64(&self) -> f64 {
+        self.fee as f64 / self.gas.max(1) as f64
+    }
+
+    /// Check if this rate is strictly higher than provided one.
+    /// Unlike [`Ord`], equal rates paying different fees don't exceed each other.
+    pub fn exceeds(&self, other: &Self) -> bool {
+        self.fee as u128 * other.gas.max(1) as u128 > other.fee as u128 * self.gas.max(1) as u128
+    }
+}
+
+impl Ord for FeeRate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let lhs = self.fee as u128 * other.gas.max(1) as u128;
+        let rhs = other.fee as u128 * self.gas.max(1) as u128;
+        // Equal rates are ordered by the fee paid, so ordering is consistent with equality.
+        // This tie-break only matters for sorting, use `exceeds` to compare rates.
+        lhs.cmp(&rhs).then(self.fee.cmp(&other.fee)).then(self.gas.cmp(&other.gas))
+    }
+}
+
+impl PartialOrd for FeeRate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Decoder of the fee paid and the nullifiers revealed by a contract's calls.
+/// Contracts expose these for the call parameters they define, so the mempool
+/// doesn't depend on their layouts.
+#[derive(Clone, Copy, Debug)]
+pub struct CallDecoder {
+    /// Contract whose calls are decoded
+    pub contract_id: ContractId,
+    /// Retrieve the fee paid by provided call data, if it is a fee call
+    pub fee_paid: fn(&[u8]) -> Option<u64>,
+    /// Retrieve the nullifiers revealed by provided call data,
+    /// or `None` if it can't be decoded
+    pub nullifiers: fn(&[u8]) -> Option<Vec<Nullifier>>,
+}
+
+/// Pending transaction information tracked by the [`Mempool`]
+#[derive(Clone, Debug)]
+pub struct MempoolEntry {
+    /// Transaction hash
+    pub hash: blake3::Hash,
+    /// Transaction fee rate
+    pub rate: FeeRate,
+    /// Serialized transaction size, in bytes
+    pub size: usize,
+    /// Nullifiers revealed by the transaction
+    pub nullifiers: Vec<Nullifier>,
+    /// Last block height the transaction can be included at, if bounded
+    pub valid_until: Option<u64>,
+}
+
+/// Current [`Mempool`] statistics
+#[derive(Clone, Debug)]
+pub struct MempoolStats {
+    /// Number of pending transactions
+    pub count: usize,
+    /// Total size of pending transactions, in bytes
+    pub size: usize,
+    /// Max total size of pending transactions, in bytes
+    pub max_size: usize,
+    /// Lowest pending transaction fee rate
+    pub min_rate: Option<FeeRate>,
+    /// Highest pending transaction fee rate
+    pub max_rate: Option<FeeRate>,
+}
+
+/// Index over the pending transactions store, prioritising them by their
+/// fee rate. When its size cap is reached, lowest fee rate transactions
+/// get evicted, and transactions revealing the same nullifiers as pending
+/// ones can replace them by paying a higher fee rate.
+pub struct Mempool {
+    /// Pending transactions entries, keyed by their hash
+    entries: HashMap<blake3::Hash, MempoolEntry>,
+    /// Pending transactions ordered by their fee rate, lowest first
+    order: BTreeSet<(FeeRate, [u8; 32])>,
+    /// Pending transactions keyed by the nullifiers they reveal
+    nullifiers: HashMap<[u8; 32], blake3::Hash>,
+    /// Total size of pending transactions, in bytes
+    size: usize,
+    /// Max total size of pending transactions, in bytes
+    max_size: usize,
+    /// Contract calls decoders
+    decoders: Vec<CallDecoder>,
+}
+
+impl Mempool {
+    pub fn new(max_size: usize, decoders: Vec<CallDecoder>) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeSet::new(),
+            nullifiers: HashMap::new(),
+            size: 0,
+            max_size,
+            decoders,
+        }
+    }
+
+    /// Generate the entry of provided transaction, using the gas it used
+    /// when it was verified.
+    pub fn entry(&self, tx: &Transaction, gas: u64) -> MempoolEntry {
+        let bytes = serialize(tx);
+        MempoolEntry {
+            hash: blake3::hash(&bytes),
+            rate: FeeRate { fee: self.fee_paid(tx), gas },
+            size: bytes.len(),
+            nullifiers: self.revealed_nullifiers(tx),
+            valid_until: tx.valid_until,
+        }
+    }
+
+    /// Retrieve the fee paid by provided transaction in its fee call.
+    /// Transactions without a fee call pay zero.
+    pub fn fee_paid(&self, tx: &Transaction) -> u64 {
+        for call in &tx.calls {
+            for decoder in &self.decoders {
+                if call.data.contract_id != decoder.contract_id {
+                    continue
+                }
+
+                if let Some(fee) = (decoder.fee_paid)(&call.data.data) {
+                    return fee
+                }
+            }
+        }
+
+        0
+    }
+
+    /// Retrieve the nullifiers revealed by provided transaction's contract calls.
+    /// Calls that can't be decoded are skipped, as they will fail verification anyway.
+    pub fn revealed_nullifiers(&self, tx: &Transaction) -> Vec<Nullifier> {
+        let mut nullifiers = vec![];
+        for call in &tx.calls {
+            for decoder in &self.decoders {
+                if call.data.contract_id != decoder.contract_id {
+                    continue
+                }
+
+                if let Some(v) = (decoder.nullifiers)(&call.data.data) {
+                    nullifiers.extend(v);
+                }
+            }
+        }
+
+        nullifiers
+    }
+
+    /// Check if given transaction hash exists in the mempool.
+    pub fn contains(&self, hash: &blake3::Hash) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    /// Retrieve the entry of given transaction hash.
+    pub fn get(&self, hash: &blake3::Hash) -> Option<&MempoolEntry> {
+        self.entries.get(hash)
+    }
+
+    /// Retrieve the pending transactions revealing any of given nullifiers.
+    pub fn conflicts(&self, nullifiers: &[Nullifier]) -> Vec<blake3::Hash> {
+        let mut conflicts = vec![];
+        for nullifier in nullifiers {
+            if let Some(hash) = self.nullifiers.get(&nullifier.to_bytes()) {
+                if !conflicts.contains(hash) {
+                    conflicts.push(*hash);
+                }
+            }
+        }
+
+        conflicts
+    }
+
+    /// Check if a transaction revealing given nullifiers, with at most given
+    /// fee rate, could be added to the mempool, before verifying its state
+    /// transition. Transactions revealing the same nullifier more than once
+    /// can never be valid, while ones conflicting with pending transactions
+    /// must pay a higher fee rate than each of them to be able to replace them.
+    /// Since the gas a transaction uses is only known after verifying it, the
+    /// rate is an upper bound, so only transactions [`Mempool::admit`] would
+    /// reject anyway are rejected.
+    pub fn check_conflicts(&self, nullifiers: &[Nullifier], rate: FeeRate) -> Result<()> {
+        let mut seen = HashSet::with_capacity(nullifiers.len());
+        for nullifier in nullifiers {
+            if !seen.insert(nullifier.to_bytes()) {
+                return Err(TxVerifyFailed::DuplicateNullifier(format!("{:?}", nullifier)).into())
+            }
+        }
+
+        for hash in self.conflicts(nullifiers) {
+            if !rate.exceeds(&self.entries[&hash].rate) {
+                return Err(TxVerifyFailed::ReplacementFeeTooLow(hash.to_string()).into())
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Retrieve the pending transactions that can't be included at given
+    /// block height or after it.
+    pub fn expired(&self, height: u64) -> Vec<blake3::Hash> {
+        self.entries
+            .values()
+            .filter(|e| matches!(e.valid_until, Some(valid_until) if height > valid_until))
+            .map(|e| e.hash)
+            .collect()
+    }
+
+    /// Check if provided entry can be added to the mempool. On success, returns
+    /// the hashes of pending transactions that must be removed for it, which are
+    /// the ones it replaces by fee, along with the ones evicted to stay under
+    /// the size cap.
+    pub fn admit(&self, entry: &MempoolEntry) -> Result<Vec<blake3::Hash>> {
+        // Entry must pay a higher fee rate than every transaction it conflicts with
+        let mut removed = self.conflicts(&entry.nullifiers);
+        for hash in &removed {
+            if !entry.rate.exceeds(&self.entries[hash].rate) {
+                return Err(TxVerifyFailed::ReplacementFeeTooLow(hash.to_string()).into())
+            }
+        }
+
+        // Evict lowest fee rate transactions until entry fits
+        let mut size = self.size + entry.size;
+        for hash in &removed {
+            size -= self.entries[hash].size;
+        }
+        for (rate, hash) in &self.order {
+            if size <= self.max_size {
+                break
+            }
+
+            let hash = blake3::Hash::from(*hash);
+            if removed.contains(&hash) {
+                continue
+            }
+
+            if !entry.rate.exceeds(rate) {
+                return Err(TxVerifyFailed::MempoolFull.into())
+            }
+
+            size -= self.entries[&hash].size;
+            removed.push(hash);
+        }
+
+        if size > self.max_size {
+            return Err(TxVerifyFailed::MempoolFull.into())
+        }
+
+        Ok(removed)
+    }
+
+    /// Insert provided entry, replacing any existing one for the same transaction.
+    pub fn insert(&mut self, entry: MempoolEntry) {
+        self.remove(&entry.hash);
+
+        for nullifier in &entry.nullifiers {
+            self.nullifiers.insert(nullifier.to_bytes(), entry.hash);
+        }
+        self.order.insert((entry.rate, *entry.hash.as_bytes()));
+        self.size += entry.size;
+        self.entries.insert(entry.hash, entry);
+    }
+
+    /// Remove the entry of given transaction hash, if it exists.
+    pub fn remove(&mut self, hash: &blake3::Hash) -> Option<MempoolEntry> {
+        let entry = self.entries.remove(hash)?;
+
+        for nullifier in &entry.nullifiers {
+            if self.nullifiers.get(&nullifier.to_bytes()) == Some(hash) {
+                self.nullifiers.remove(&nullifier.to_bytes());
+            }
+        }
+        self.order.remove(&(entry.rate, *hash.as_bytes()));
+        self.size -= entry.size;
+
+        Some(entry)
+    }
+
+    /// Remove all entries.
+    pub fn clear(&mut self) {
+        *self = Self::new(self.max_size, std::mem::take(&mut self.decoders));
+    }
+
+    /// Sort given transaction hashes by their fee rate, highest first.
+    /// Hashes not tracked by the mempool are placed last, in their original order.
+    pub fn sort_by_priority(&self, hashes: &mut [blake3::Hash]) {
+        hashes.sort_by(|a, b| {
+            let a = self.entries.get(a).map(|e| e.rate);
+            let b = self.entries.get(b).map(|e| e.rate);
+            b.cmp(&a)
+        });
+    }
+
+    /// Iterate over the mempool entries, highest fee rate first.
+    pub fn iter(&self) -> impl Iterator<Item = &MempoolEntry> {
+        self.order.iter().rev().map(|(_, hash)| &self.entries[&blake3::Hash::from(*hash)])
+    }
+
+    /// Retrieve current mempool statistics.
+    pub fn stats(&self) -> MempoolStats {
+        MempoolStats {
+            count: self.entries.len(),
+            size: self.size,
+            max_size: self.max_size,
+            min_rate: self.order.first().map(|(rate, _)| *rate),
+            max_rate: self.order.last().map(|(rate, _)| *rate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use darkfi_sdk::pasta::pallas;
+
+    use super::*;
+
+    fn entry(byte: u8, fee: u64, gas: u64, size: usize, nullifiers: &[u64]) -> MempoolEntry {
+        MempoolEntry {
+            hash: blake3::hash(&[byte]),
+            rate: FeeRate { fee, gas },
+            size,
+            nullifiers: nullifiers
+                .iter()
+                .map(|n| Nullifier::from(pallas::Base::from(*n)))
+                .collect(),
+            valid_until: None,
+        }
+    }
+
+    #[test]
+    fn mempool_priority_and_eviction() -> Result<()> {
+        let mut mempool = Mempool::new(100, vec![]);
+
+        let low = entry(0, 10, 10, 40, &[0]);
+        let high = entry(1, 30, 10, 40, &[1]);
+        mempool.insert(low.clone());
+        mempool.insert(high.clone());
+
+        let mut hashes = vec![low.hash, high.hash];
+        mempool.sort_by_priority(&mut hashes);
+        assert_eq!(hashes, vec![high.hash, low.hash]);
+
+        // A higher paying transaction evicts the lowest paying one
+        let mid = entry(2, 20, 10, 40, &[2]);
+        assert_eq!(mempool.admit(&mid)?, vec![low.hash]);
+
+        // A lower paying transaction can't get in
+        assert!(mempool.admit(&entry(3, 5, 10, 40, &[3])).is_err());
+
+        // Conflicting transactions must pay a higher rate to replace
+        assert!(mempool.admit(&entry(4, 30, 10, 10, &[1])).is_err());
+        assert_eq!(mempool.admit(&entry(5, 31, 10, 10, &[1]))?, vec![high.hash]);
+
+        mempool.remove(&low.hash);
+        let stats = mempool.stats();
+        assert_eq!((stats.count, stats.size), (1, 40));
+        assert!(mempool.conflicts(&low.nullifiers).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn mempool_conflict_detection() -> Result<()> {
+        let mut mempool = Mempool::new(100, vec![]);
+        let pending = entry(0, 10, 10, 40, &[0, 1]);
+        mempool.insert(pending.clone());
+
+        // Duplicate nullifiers within the same transaction are rejected
+        let nullifier = Nullifier::from(pallas::Base::from(2));
+        assert!(mempool
+            .check_conflicts(&[nullifier, nullifier], FeeRate { fee: 100, gas: 1 })
+            .is_err());
+
+        // Conflicting transactions must pay a higher fee rate to be considered
+        let nullifiers = vec![Nullifier::from(pallas::Base::from(1))];
+        assert_eq!(mempool.conflicts(&nullifiers), vec![pending.hash]);
+        assert!(mempool.check_conflicts(&nullifiers, FeeRate { fee: 10, gas: 10 }).is_err());
+        assert!(mempool.check_conflicts(&nullifiers, FeeRate { fee: 11, gas: 10 }).is_ok());
+
+        // A higher fee using proportionally more gas is not a higher rate,
+        // while the same fee using less gas is.
+        assert!(mempool.check_conflicts(&nullifiers, FeeRate { fee: 20, gas: 20 }).is_err());
+        assert!(mempool.check_conflicts(&nullifiers, FeeRate { fee: 10, gas: 5 }).is_ok());
+
+        // Non conflicting transactions pass regardless of their fee rate
+        assert!(mempool.check_conflicts(&[nullifier], FeeRate { fee: 0, gas: 1 }).is_ok());
+
+        // Expired transactions are reported
+        let mut expiring = entry(1, 10, 10, 10, &[3]);
+        expiring.valid_until = Some(5);
+        mempool.insert(expiring.clone());
+        assert!(mempool.expired(5).is_empty());
+        assert_eq!(mempool.expired(6), vec![expiring.hash]);
+
+        Ok(())
+    }
+}
@@ -30,9 +30,10 @@ use smol::lock::RwLock;
 use crate::{
     blockchain::{
         block_store::{BlockDifficulty, BlockInfo},
-        Blockchain, BlockchainOverlay,
+        Blockchain, BlockchainOverlay, Header,
     },
     error::TxVerifyFailed,
+    runtime::trace::ExecutionTrace,
     tx::Transaction,
     util::time::TimeKeeper,
     Error, Result,
@@ -52,15 +53,21 @@ pub mod pid;
 /// Verification functions
 pub mod verification;
 use verification::{
-    verify_block, verify_genesis_block, verify_producer_transaction, verify_proposal,
-    verify_transactions,
+    trace_transaction, verify_block, verify_genesis_block, verify_producer_transaction,
+    verify_proposal, verify_transaction_gas, verify_transactions,
 };
 
 /// Fee calculation helpers
 pub mod fees;
+use fees::min_gas_use;
 
 /// Validation functions
 pub mod validation;
+use validation::validate_pow_header;
+
+/// Fee prioritised pending transactions index
+pub mod mempool;
+use mempool::{CallDecoder, FeeRate};
 
 /// Helper utilities
 pub mod utils;
@@ -90,6 +97,8 @@ pub struct ValidatorConfig {
     pub pos_testing_mode: bool,
     /// Flag to enable tx fee verification
     pub verify_fees: bool,
+    /// Contract calls decoders, used by the mempool
+    pub call_decoders: Vec<CallDecoder>,
 }
 
 impl ValidatorConfig {
@@ -104,6 +113,7 @@ impl ValidatorConfig {
         faucet_pubkeys: Vec<PublicKey>,
         pos_testing_mode: bool,
         verify_fees: bool,
+        call_decoders: Vec<CallDecoder>,
     ) -> Self {
         Self {
             time_keeper,
@@ -115,6 +125,7 @@ impl ValidatorConfig {
             faucet_pubkeys,
             pos_testing_mode,
             verify_fees,
+            call_decoders,
         }
     }
 }
@@ -162,6 +173,10 @@ impl Validator {
             .await?;
         };
 
+        // Update the contracts state leaves the deployments modified,
+        // so blocks only have to recompute the trees they touch
+        overlay.lock().unwrap().contracts.update_state()?;
+
         // Write the changes to the actual chain db
         overlay.lock().unwrap().overlay.lock().unwrap().apply()?;
 
@@ -173,6 +188,7 @@ impl Validator {
             config.pow_target,
             config.pow_fixed_difficulty,
             pos_testing_mode,
+            config.call_decoders,
         )?;
 
         // Create the actual state
@@ -189,12 +205,14 @@ impl Validator {
     }
 
     /// The node retrieves a transaction, validates its state transition,
-    /// and appends it to the pending txs store.
+    /// and appends it to the pending txs store, if the mempool admits it.
     pub async fn append_tx(&self, tx: &Transaction) -> Result<()> {
-        let tx_hash = blake3::hash(&serialize_async(tx).await);
+        let tx_bytes = serialize_async(tx).await;
+        let tx_hash = blake3::hash(&tx_bytes);
 
-        // Check if we have already seen this tx
-        let tx_in_txstore = self.blockchain.transactions.contains(&tx_hash)?;
+        // Check if we have already seen this tx, including pruned ones
+        let tx_in_txstore = self.blockchain.transactions.contains(&tx_hash)? ||
+            self.blockchain.pruned_txs.contains(&tx_hash)?;
         let tx_in_pending_txs_store = self.blockchain.pending_txs.contains(&tx_hash)?;
 
         if tx_in_txstore || tx_in_pending_txs_store {
@@ -202,51 +220,89 @@ impl Validator {
             return Err(TxVerifyFailed::AlreadySeenTx(tx_hash.to_string()).into())
         }
 
+        // Generate a time keeper for current slot
+        let time_keeper = self.consensus.time_keeper.current();
+
+        // Check the transaction can currently be included in a block
+        if !tx.is_valid_at(time_keeper.verifying_slot) {
+            info!(target: "validator::append_tx", "Transaction is outside of its validity window");
+            return Err(TxVerifyFailed::OutsideValidityWindow(time_keeper.verifying_slot).into())
+        }
+
+        // Check the transaction doesn't conflict with pending ones before
+        // verifying its state transition, unless it can replace them by fee.
+        // Its gas is not known yet, so we use the lower bound of it, giving
+        // the highest fee rate the transaction can have.
+        let min_gas =
+            if self.verify_fees { min_gas_use(tx.signatures.len(), tx_bytes.len()) } else { 1 };
+        let mempool = self.consensus.mempool.read().await;
+        let rate = FeeRate { fee: mempool.fee_paid(tx), gas: min_gas };
+        let conflicts = mempool.check_conflicts(&mempool.revealed_nullifiers(tx), rate);
+        drop(mempool);
+        if let Err(e) = conflicts {
+            info!(target: "validator::append_tx", "Transaction conflicts with pending transactions: {}", e);
+            return Err(e)
+        }
+
         // Verify state transition
         info!(target: "validator::append_tx", "Starting state transition validation");
-        let tx_vec = [tx.clone()];
-        let mut valid = false;
+        let mut gas_used = None;
+        let mut valid_forks = vec![];
 
         // Grab a lock over current consensus forks state
         let mut forks = self.consensus.forks.write().await;
 
-        // Generate a time keeper for current slot
-        let time_keeper = self.consensus.time_keeper.current();
-
         // If node participates in consensus and holds any forks, iterate over them
         // to verify transaction validity in their overlays
-        for fork in forks.iter_mut() {
+        for (index, fork) in forks.iter().enumerate() {
             // Clone forks' overlay
             let overlay = fork.overlay.lock().unwrap().full_clone()?;
 
             // Verify transaction
-            let erroneous_txs = verify_transactions(&overlay, &time_keeper, &tx_vec, false).await?;
-            if !erroneous_txs.is_empty() {
+            let Some(gas) = verify_transaction_gas(&overlay, &time_keeper, tx, false).await? else {
                 continue
-            }
-            valid = true;
-
-            // Store transaction hash in forks' mempool
-            fork.mempool.push(tx_hash);
+            };
+            gas_used = Some(gas);
+            valid_forks.push(index);
         }
 
         // Verify transaction against canonical state
         let overlay = BlockchainOverlay::new(&self.blockchain)?;
-        let erroneous_txs = verify_transactions(&overlay, &time_keeper, &tx_vec, false).await?;
-        if erroneous_txs.is_empty() {
-            valid = true
+        if let Some(gas) = verify_transaction_gas(&overlay, &time_keeper, tx, false).await? {
+            gas_used = Some(gas);
         }
 
-        // Drop forks lock
-        drop(forks);
-
         // Return error if transaction is not valid for canonical or any fork
-        if !valid {
-            return Err(TxVerifyFailed::ErroneousTxs(erroneous_txs).into())
+        let Some(gas_used) = gas_used else {
+            return Err(TxVerifyFailed::ErroneousTxs(vec![tx.clone()]).into())
+        };
+
+        // Check the mempool admits the transaction, and remove
+        // the pending transactions it replaces or evicts.
+        let mut mempool = self.consensus.mempool.write().await;
+        let entry = mempool.entry(tx, gas_used);
+        let removed = mempool.admit(&entry)?;
+        if !removed.is_empty() {
+            info!(target: "validator::append_tx", "Removing {} replaced or evicted transactions", removed.len());
+            let removed_txs: Vec<Transaction> =
+                self.blockchain.pending_txs.get(&removed, false)?.into_iter().flatten().collect();
+            self.blockchain.remove_pending_txs(&removed_txs)?;
+            for hash in &removed {
+                mempool.remove(hash);
+            }
+            for fork in forks.iter_mut() {
+                fork.mempool.retain(|x| !removed.contains(x));
+            }
+        }
+
+        // Store transaction hash in the mempool of the forks it's valid for
+        for index in valid_forks {
+            forks[index].mempool.push(tx_hash);
         }
 
         // Add transaction to pending txs store
-        self.blockchain.add_pending_txs(&tx_vec)?;
+        self.blockchain.add_pending_txs(&[tx.clone()])?;
+        mempool.insert(entry);
         info!(target: "validator::append_tx", "Appended tx to pending txs store");
 
         Ok(())
@@ -263,8 +319,9 @@ impl Validator {
             return Ok(())
         }
 
-        // Grab a lock over current consensus forks state
+        // Grab a lock over current consensus forks state and the mempool
         let mut forks = self.consensus.forks.write().await;
+        let mut mempool = self.consensus.mempool.write().await;
 
         // Generate a time keeper for current slot
         let time_keeper = self.consensus.time_keeper.current();
@@ -272,8 +329,19 @@ impl Validator {
         let mut removed_txs = vec![];
         for tx in pending_txs {
             let tx_hash = &blake3::hash(&serialize_async(&tx).await);
-            let tx_vec = [tx.clone()];
-            let mut valid = false;
+
+            // Expired transactions can never be included again, so we
+            // drop them without verifying their state transition.
+            if tx.is_expired(time_keeper.verifying_slot) {
+                for fork in forks.iter_mut() {
+                    fork.mempool.retain(|x| x != tx_hash);
+                }
+                mempool.remove(tx_hash);
+                removed_txs.push(tx);
+                continue
+            }
+
+            let mut gas_used = None;
 
             // If node participates in consensus and holds any forks, iterate over them
             // to verify transaction validity in their overlays
@@ -282,10 +350,10 @@ impl Validator {
                 let overlay = fork.overlay.lock().unwrap().full_clone()?;
 
                 // Verify transaction
-                let erroneous_txs =
-                    verify_transactions(&overlay, &time_keeper, &tx_vec, false).await?;
-                if erroneous_txs.is_empty() {
-                    valid = true;
+                if let Some(gas) =
+                    verify_transaction_gas(&overlay, &time_keeper, &tx, false).await?
+                {
+                    gas_used = Some(gas);
                     continue
                 }
 
@@ -295,18 +363,27 @@ impl Validator {
 
             // Verify transaction against canonical state
             let overlay = BlockchainOverlay::new(&self.blockchain)?;
-            let erroneous_txs = verify_transactions(&overlay, &time_keeper, &tx_vec, false).await?;
-            if erroneous_txs.is_empty() {
-                valid = true
+            if let Some(gas) = verify_transaction_gas(&overlay, &time_keeper, &tx, false).await? {
+                gas_used = Some(gas);
             }
 
-            // Remove pending transaction if it's not valid for canonical or any fork
-            if !valid {
-                removed_txs.push(tx)
+            match gas_used {
+                // Refresh the transaction mempool entry, as its gas
+                // usage might have changed along with the state.
+                Some(gas) => {
+                    let entry = mempool.entry(&tx, gas);
+                    mempool.insert(entry);
+                }
+                // Remove pending transaction if it's not valid for canonical or any fork
+                None => {
+                    mempool.remove(tx_hash);
+                    removed_txs.push(tx);
+                }
             }
         }
 
-        // Drop forks lock
+        // Drop forks and mempool locks
+        drop(mempool);
         drop(forks);
 
         if removed_txs.is_empty() {
@@ -319,6 +396,69 @@ impl Validator {
         Ok(())
     }
 
+    /// The node removes provided finalized transactions from the pending txs store,
+    /// along with the pending transactions they conflict with, as their nullifiers
+    /// are now spent, and the ones that can't be included from given block height on.
+    /// Unlike [`Validator::purge_pending_txs`], remaining pending transactions are
+    /// not verified again.
+    pub async fn purge_finalized_txs(&self, txs: &[Transaction], height: u64) -> Result<()> {
+        // Grab a lock over current consensus forks state and the mempool
+        let mut forks = self.consensus.forks.write().await;
+        let mut mempool = self.consensus.mempool.write().await;
+
+        // Find the pending transactions affected by the finalized ones
+        let mut removed = vec![];
+        for tx in txs {
+            removed.push(tx.hash()?);
+            for hash in mempool.conflicts(&mempool.revealed_nullifiers(tx)) {
+                if !removed.contains(&hash) {
+                    removed.push(hash);
+                }
+            }
+        }
+        for hash in mempool.expired(height) {
+            if !removed.contains(&hash) {
+                removed.push(hash);
+            }
+        }
+
+        // Remove them from the forks and the mempool
+        for fork in forks.iter_mut() {
+            fork.mempool.retain(|x| !removed.contains(x));
+        }
+        for hash in &removed {
+            mempool.remove(hash);
+        }
+
+        // Drop forks and mempool locks
+        drop(mempool);
+        drop(forks);
+
+        // Finalized transactions might have never been in our pending txs store,
+        // so we only remove the ones we know of.
+        let removed_txs: Vec<Transaction> =
+            self.blockchain.pending_txs.get(&removed, false)?.into_iter().flatten().collect();
+        if removed_txs.is_empty() {
+            return Ok(())
+        }
+        info!(target: "validator::purge_finalized_txs", "Removing {} finalized or conflicting transactions...", removed_txs.len());
+        self.blockchain.remove_pending_txs(&removed_txs)?;
+
+        Ok(())
+    }
+
+    /// Remove provided transactions from the pending txs store and the mempool.
+    pub async fn remove_pending_txs(&self, txs: &[Transaction]) -> Result<()> {
+        self.blockchain.remove_pending_txs(txs)?;
+
+        let mut mempool = self.consensus.mempool.write().await;
+        for tx in txs {
+            mempool.remove(&tx.hash()?);
+        }
+
+        Ok(())
+    }
+
     /// The node retrieves a block and tries to add it if it doesn't
     /// already exists.
     pub async fn append_block(&self, block: &BlockInfo) -> Result<()> {
@@ -447,9 +587,8 @@ impl Validator {
         debug!(target: "validator::add_blocks", "Applying overlay changes");
         overlay.lock().unwrap().overlay.lock().unwrap().apply()?;
 
-        // Purge pending erroneous txs since canonical state has been changed
-        self.blockchain.remove_pending_txs(&removed_txs)?;
-        self.purge_pending_txs().await?;
+        // Purge pending txs affected by the canonical state change
+        self.purge_finalized_txs(&removed_txs, previous.header.height + 1).await?;
 
         // Update PoW module
         *self.consensus.module.write().await = module;
@@ -457,6 +596,30 @@ impl Validator {
         Ok(())
     }
 
+    /// Validate a sequence of [`Header`] extending our canonical blockchain, without
+    /// applying anything, and return the cummulative difficulty its last header reaches.
+    /// Used during sync to select the heaviest chain before retrieving its blocks.
+    /// Verified headers are tracked by the PoW module, so their proof of work is
+    /// not recomputed when their blocks get applied.
+    pub async fn validate_headers(&self, headers: &[Header]) -> Result<BigUint> {
+        // Retrieve last header
+        let mut previous = self.blockchain.last_block()?.header;
+
+        // Create a PoW module to validate each header
+        let mut module = self.consensus.module.read().await.clone();
+
+        for header in headers {
+            validate_pow_header(header, &previous, &module)?;
+
+            // Update PoW module
+            module.append(header.timestamp.0, &module.next_difficulty()?);
+
+            previous = header.clone();
+        }
+
+        Ok(module.cummulative_difficulty)
+    }
+
     /// Validate a set of [`Transaction`] in sequence and apply them if all are valid.
     /// In case any of the transactions fail, they will be returned to the caller.
     /// The function takes a boolean called `write` which tells it to actually write
@@ -502,6 +665,34 @@ impl Validator {
         Ok(())
     }
 
+    /// Execute given [`Transaction`] against the canonical state with tracing enabled,
+    /// without writing anything to the database. Returns the execution traces of the
+    /// transaction calls, along with the error the execution failed with, if any.
+    pub async fn trace_transaction(
+        &self,
+        tx: &Transaction,
+        verifying_slot: u64,
+    ) -> Result<(Vec<ExecutionTrace>, Option<Error>)> {
+        debug!(target: "validator::trace_transaction", "Instantiating BlockchainOverlay");
+        let overlay = BlockchainOverlay::new(&self.blockchain)?;
+
+        // Generate a time keeper using transaction verifying slot
+        let current_time_keeper = &self.consensus.time_keeper;
+        let time_keeper = TimeKeeper::new(
+            current_time_keeper.genesis_ts,
+            current_time_keeper.epoch_length,
+            current_time_keeper.slot_time,
+            verifying_slot,
+        );
+
+        let result = trace_transaction(&overlay, &time_keeper, tx).await;
+
+        // Traces are never written to the database
+        overlay.lock().unwrap().overlay.lock().unwrap().purge_new_trees()?;
+
+        result
+    }
+
     /// Append to canonical state received slot.
     /// This should be only used for test purposes.
     pub async fn receive_test_slot(&self, slot: &Slot) -> Result<()> {
@@ -17,9 +17,10 @@
  */
 
 use std::{
+    collections::{HashSet, VecDeque},
     sync::{
         atomic::{AtomicBool, AtomicU32, Ordering},
-        Arc,
+        Arc, Mutex,
     },
     thread,
     time::Instant,
@@ -37,7 +38,7 @@ use smol::channel::Receiver;
 use crate::{
     blockchain::{
         block_store::{BlockDifficulty, BlockInfo},
-        Blockchain, BlockchainOverlayPtr,
+        Blockchain, BlockchainOverlayPtr, Header,
     },
     util::{ringbuffer::RingBuffer, time::Timestamp},
     validator::utils::median,
@@ -73,6 +74,109 @@ const CUT_END: usize = 660;
 const BLOCKCHAIN_TIMESTAMP_CHECK_WINDOW: usize = 60;
 /// Time limit in the future of what blocks can be
 const BLOCK_FUTURE_TIME_LIMIT: u64 = 60 * 60 * 2;
+/// Max number of RandomX verifier VMs to keep cached
+const MAX_RANDOMX_VMS: usize = 4;
+/// Max number of verified header hashes to keep track of
+const MAX_VERIFIED_HEADERS: usize = 20000;
+
+/// Wrapper over a RandomX verifier VM, so it can be cached.
+struct CachedRandomXVM(RandomXVM);
+
+// SAFETY: The VM is only accessed through the `RandomXHasher` mutex,
+// so it is never used by more than one thread at a time.
+unsafe impl Send for CachedRandomXVM {}
+
+/// RandomX verifier VM keyed by a seed(previous block hash), computing
+/// the output hashes of headers extending that block. The VM is guarded
+/// by a mutex, so the hasher can be shared between threads.
+pub struct RandomXHasher {
+    /// Previous block hash the VM is keyed by
+    seed: blake3::Hash,
+    /// The RandomX verifier VM
+    vm: Mutex<CachedRandomXVM>,
+}
+
+impl RandomXHasher {
+    /// Setup a new RandomX verifier VM for provided seed.
+    pub fn new(seed: blake3::Hash) -> Result<Self> {
+        let verifier_setup = Instant::now();
+        let flags = RandomXFlags::default();
+        let cache = RandomXCache::new(flags, seed.as_bytes())
+            .map_err(|e| Error::PoWRandomXSetupFailed(e.to_string()))?;
+        let vm = RandomXVM::new(flags, &cache)
+            .map_err(|e| Error::PoWRandomXSetupFailed(e.to_string()))?;
+        debug!(target: "validator::pow::randomx_hasher", "[VERIFIER] Setup time: {:?}", verifier_setup.elapsed());
+
+        Ok(Self { seed, vm: Mutex::new(CachedRandomXVM(vm)) })
+    }
+
+    /// Seed the VM is keyed by.
+    pub fn seed(&self) -> &blake3::Hash {
+        &self.seed
+    }
+
+    /// Compute the RandomX output hash of provided header, which must
+    /// extend the seed block, so it can be checked against the mine target.
+    pub fn header_hash(&self, header: &Header) -> Result<BigUint> {
+        let header_hash = header.hash()?;
+        let out_hash = self.vm.lock().unwrap().0.hash(header_hash.as_bytes());
+        Ok(BigUint::from_bytes_be(&out_hash))
+    }
+}
+
+/// Proof of work verification state, shared between all clones of a
+/// [`PoWModule`]. RandomX VMs are cached per seed(previous block hash),
+/// so they are not rebuilt for every header extending the same block,
+/// and hashes of headers whose proof of work has been verified are kept,
+/// so it is not recomputed when the same header is validated again, i.e.
+/// a synced header when its block gets applied. VMs are built and used
+/// outside of the verifier lock, so verifications don't block each other.
+#[derive(Default)]
+pub struct PoWVerifier {
+    /// Cached RandomX VMs
+    vms: VecDeque<Arc<RandomXHasher>>,
+    /// Verified header hashes
+    verified: HashSet<blake3::Hash>,
+    /// Verified header hashes, in insertion order
+    verified_order: VecDeque<blake3::Hash>,
+}
+
+impl PoWVerifier {
+    /// Retrieve the cached VM of provided seed, if it exists.
+    fn hasher(&self, seed: &blake3::Hash) -> Option<Arc<RandomXHasher>> {
+        self.vms.iter().find(|vm| vm.seed() == seed).cloned()
+    }
+
+    /// Cache provided VM, returning the one already cached for
+    /// its seed instead, if it was built concurrently.
+    fn insert_hasher(&mut self, hasher: Arc<RandomXHasher>) -> Arc<RandomXHasher> {
+        if let Some(cached) = self.hasher(hasher.seed()) {
+            return cached
+        }
+        if self.vms.len() >= MAX_RANDOMX_VMS {
+            self.vms.pop_front();
+        }
+        self.vms.push_back(hasher.clone());
+        hasher
+    }
+
+    /// Check if provided header hash proof of work has been verified.
+    fn is_verified(&self, hash: &blake3::Hash) -> bool {
+        self.verified.contains(hash)
+    }
+
+    /// Mark provided header hash proof of work as verified.
+    fn insert_verified(&mut self, hash: blake3::Hash) {
+        if !self.verified.insert(hash) {
+            return
+        }
+        self.verified_order.push_back(hash);
+        if self.verified_order.len() > MAX_VERIFIED_HEADERS {
+            let oldest = self.verified_order.pop_front().unwrap();
+            self.verified.remove(&oldest);
+        }
+    }
+}
 
 /// This struct represents the information required by the PoW algorithm
 #[derive(Clone)]
@@ -90,6 +194,8 @@ pub struct PoWModule {
     /// access(optimization), since its always same as
     /// difficulties buffer last.
     pub cummulative_difficulty: BigUint,
+    /// Proof of work verification state
+    pub verifier: Arc<Mutex<PoWVerifier>>,
 }
 
 impl PoWModule {
@@ -114,7 +220,39 @@ impl PoWModule {
             assert!(diff > &BigUint::zero());
         }
 
-        Ok(Self { target, fixed_difficulty, timestamps, difficulties, cummulative_difficulty })
+        Ok(Self {
+            target,
+            fixed_difficulty,
+            timestamps,
+            difficulties,
+            cummulative_difficulty,
+            verifier: Arc::new(Mutex::new(PoWVerifier::default())),
+        })
+    }
+
+    /// Rebuild the ring buffers at given block height, from the blockchain
+    /// difficulties up to it, keeping the module configuration and verification
+    /// state. Used to validate blocks forking from an earlier one.
+    pub fn at_height(&self, blockchain: &Blockchain, height: u64) -> Result<Self> {
+        let mut timestamps = RingBuffer::<u64, BUF_SIZE>::new();
+        let mut difficulties = RingBuffer::<BigUint, BUF_SIZE>::new();
+        let mut cummulative_difficulty = BigUint::zero();
+        let start = (height + 1).saturating_sub(BUF_SIZE as u64);
+        let heights: Vec<u64> = (start..=height).collect();
+        for difficulty in blockchain.difficulties.get(&heights, false)?.into_iter().flatten() {
+            timestamps.push(difficulty.timestamp);
+            difficulties.push(difficulty.cummulative_difficulty.clone());
+            cummulative_difficulty = difficulty.cummulative_difficulty;
+        }
+
+        Ok(Self {
+            target: self.target,
+            fixed_difficulty: self.fixed_difficulty.clone(),
+            timestamps,
+            difficulties,
+            cummulative_difficulty,
+            verifier: self.verifier.clone(),
+        })
     }
 
     /// Compute the next mining difficulty, based on current ring buffers.
@@ -232,22 +370,38 @@ impl PoWModule {
 
     /// Verify provided block corresponds to next mine target
     pub fn verify_block_hash(&self, block: &BlockInfo) -> Result<()> {
-        // Then we verify the proof of work:
-        let verifier_setup = Instant::now();
+        self.verify_header_hash(&block.header)
+    }
+
+    /// Verify provided header corresponds to next mine target.
+    /// Since a block's hash is its header hash, this can be used
+    /// to verify the proof of work before having the block body.
+    /// Headers that have already been verified are skipped, since
+    /// a header hash commits to its whole chain.
+    pub fn verify_header_hash(&self, header: &Header) -> Result<()> {
+        let header_hash = header.hash()?;
+        let verifier = self.verifier.lock().unwrap();
+        if verifier.is_verified(&header_hash) {
+            return Ok(())
+        }
+        let hasher = verifier.hasher(&header.previous);
+        drop(verifier);
 
         // Grab the next mine target
         let target = self.next_mine_target()?;
 
-        // Setup verifier
-        let flags = RandomXFlags::default();
-        let cache = RandomXCache::new(flags, block.header.previous.as_bytes()).unwrap();
-        let vm = RandomXVM::new(flags, &cache).unwrap();
-        debug!(target: "validator::pow::verify_block", "[VERIFIER] Setup time: {:?}", verifier_setup.elapsed());
+        // Setup the VM of the header seed, if it's not cached
+        let hasher = match hasher {
+            Some(hasher) => hasher,
+            None => {
+                let hasher = Arc::new(RandomXHasher::new(header.previous)?);
+                self.verifier.lock().unwrap().insert_hasher(hasher)
+            }
+        };
 
-        // Compute the output hash
+        // Then we verify the proof of work:
         let verification_time = Instant::now();
-        let out_hash = vm.hash(block.hash()?.as_bytes());
-        let out_hash = BigUint::from_bytes_be(&out_hash);
+        let out_hash = hasher.header_hash(header)?;
 
         // Verify hash is less than the expected mine target
         if out_hash > target {
@@ -255,6 +409,8 @@ impl PoWModule {
         }
         debug!(target: "validator::pow::verify_block", "[VERIFIER] Verification time: {:?}", verification_time.elapsed());
 
+        self.verifier.lock().unwrap().insert_verified(header_hash);
+
         Ok(())
     }
 
@@ -392,7 +548,7 @@ mod tests {
         process::Command,
     };
 
-    use darkfi_sdk::num_traits::Num;
+    use darkfi_sdk::{num_traits::Num, pasta::pallas};
     use num_bigint::BigUint;
 
     use crate::{
@@ -456,4 +612,37 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_verifier_cache() -> Result<()> {
+        // Default setup
+        let sled_db = sled::Config::new().temporary(true).open()?;
+        let blockchain = Blockchain::new(&sled_db)?;
+        let module = PoWModule::new(blockchain, DEFAULT_TEST_DIFFICULTY_TARGET, None)?;
+        let genesis_block = BlockInfo::default();
+
+        // Verify two headers extending the same block
+        let mut header = BlockInfo::default().header;
+        header.previous = genesis_block.hash()?;
+        module.verify_header_hash(&header)?;
+        header.nonce = pallas::Base::from(1);
+        module.verify_header_hash(&header)?;
+
+        // They share the same cached VM and are both marked as verified
+        let verifier = module.verifier.lock().unwrap();
+        assert_eq!(verifier.vms.len(), 1);
+        assert!(verifier.is_verified(&header.hash()?));
+        drop(verifier);
+
+        // Module clones share the verification state
+        let clone = module.clone();
+        assert_eq!(clone.verifier.lock().unwrap().verified.len(), 2);
+
+        // Headers extending different blocks use their own VM
+        header.previous = header.hash()?;
+        clone.verify_header_hash(&header)?;
+        assert_eq!(module.verifier.lock().unwrap().vms.len(), 2);
+
+        Ok(())
+    }
 }
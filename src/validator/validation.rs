@@ -23,7 +23,7 @@ use darkfi_sdk::{
 use num_bigint::BigUint;
 
 use crate::{
-    blockchain::{BlockInfo, Blockchain},
+    blockchain::{BlockInfo, Blockchain, Header},
     validator::{pid::slot_pid_output, pow::PoWModule},
     Error, Result,
 };
@@ -62,29 +62,9 @@ pub fn validate_pow_block(
 ) -> Result<()> {
     let error = Err(Error::BlockIsInvalid(block.hash()?.to_string()));
 
-    // Check block version (1)
-    if block.header.version != 1 {
-        return error
-    }
-
-    // Check previous hash (2)
+    // Check block header (1-5)
+    validate_pow_header(&block.header, &previous.header, module)?;
     let previous_hash = previous.hash()?;
-    if block.header.previous != previous_hash {
-        return error
-    }
-
-    // Check heights are incremental (3)
-    if block.header.height != previous.header.height + 1 {
-        return error
-    }
-
-    // Check timestamp validity (4)
-    if !module.verify_timestamp_by_median(block.header.timestamp.0) {
-        return error
-    }
-
-    // Check block hash corresponds to next one (5)
-    module.verify_block_hash(block)?;
 
     // Verify slots vector contains single slot (6)
     if block.slots.len() != 1 {
@@ -113,6 +93,41 @@ pub fn validate_pow_block(
     Ok(())
 }
 
+/// A PoW block header is considered valid when the following rules apply:
+///     1. Header version is equal to 1
+///     2. Parent hash is equal to the hash of the previous header
+///     3. Header height increments previous header height by 1
+///     4. Timestamp is valid based on PoWModule validation
+///     5. Header hash is valid based on PoWModule validation
+/// These are the block rules that don't require its body, so header
+/// chains can be validated before their blocks are retrieved.
+pub fn validate_pow_header(header: &Header, previous: &Header, module: &PoWModule) -> Result<()> {
+    let error = Err(Error::BlockIsInvalid(header.hash()?.to_string()));
+
+    // Check header version (1)
+    if header.version != 1 {
+        return error
+    }
+
+    // Check previous hash (2)
+    if header.previous != previous.hash()? {
+        return error
+    }
+
+    // Check heights are incremental (3)
+    if header.height != previous.height + 1 {
+        return error
+    }
+
+    // Check timestamp validity (4)
+    if !module.verify_timestamp_by_median(header.timestamp.0) {
+        return error
+    }
+
+    // Check header hash corresponds to next one (5)
+    module.verify_header_hash(header)
+}
+
 /// A PoW slot is considered valid when the following rules apply:
 ///     1. Id increments previous slot id by 1
 ///     2. Forks extend previous block hash
@@ -37,12 +37,12 @@ use smol::io::Cursor;
 use crate::{
     blockchain::{BlockInfo, BlockchainOverlayPtr},
     error::TxVerifyFailed,
-    runtime::vm_runtime::Runtime,
+    runtime::{trace::ExecutionTrace, vm_runtime::Runtime},
     tx::{Transaction, MAX_TX_CALLS, MIN_TX_CALLS},
     util::time::TimeKeeper,
     validator::{
         consensus::{Consensus, Fork, Proposal, TXS_CAP},
-        fees::{circuit_gas_use, PALLAS_SCHNORR_SIGNATURE_FEE},
+        fees::{circuit_gas_use, min_gas_use},
         pow::PoWModule,
         validation::validate_block,
     },
@@ -50,7 +50,8 @@ use crate::{
     Error, Result,
 };
 
-/// Verify given genesis [`BlockInfo`], and apply it to the provided overlay
+/// Verify given genesis [`BlockInfo`], and apply it to the provided overlay.
+/// Its contracts state root is not verified, since the genesis block is hardcoded.
 pub async fn verify_genesis_block(
     overlay: &BlockchainOverlayPtr,
     time_keeper: &TimeKeeper,
@@ -184,6 +185,12 @@ pub async fn verify_block(
         return Err(TxVerifyFailed::ErroneousTxs(erroneous_txs).into())
     }
 
+    // Verify block state matches the contracts state after applying it
+    if let Err(e) = verify_block_state(overlay, block) {
+        overlay.lock().unwrap().overlay.lock().unwrap().purge_new_trees()?;
+        return Err(e)
+    }
+
     // Insert block
     overlay.lock().unwrap().add_block(block)?;
 
@@ -191,6 +198,73 @@ pub async fn verify_block(
     Ok(())
 }
 
+/// Verify the contracts state root committed to by given [`BlockInfo`] header
+/// matches the one of provided overlay, which must already contain the block
+/// changes. Only the state trees the overlay modified get recomputed. On
+/// mismatch, the trees whose leaf changed are reported, as the first
+/// divergent one is among them.
+pub fn verify_block_state(overlay: &BlockchainOverlayPtr, block: &BlockInfo) -> Result<()> {
+    let (state_root, changed) = overlay.lock().unwrap().contracts.update_state()?;
+    if block.header.state_root != state_root {
+        let block_hash = block.hash()?.to_string();
+        let trees: Vec<String> =
+            changed.into_iter().map(|x| blake3::Hash::from(x).to_hex().to_string()).collect();
+        let trees = trees.join(", ");
+        error!(target: "validator::verification::verify_block_state", "Block {} state diverges, modified contract state trees: {}", block_hash, trees);
+        return Err(Error::BlockStateDiverged(block_hash, trees))
+    }
+
+    Ok(())
+}
+
+/// Apply provided transactions of an unsigned [`BlockInfo`] over given overlay,
+/// the same way [`verify_block`] applies them, and add the ones that applied
+/// to the block, along with the contracts state root they result in. Block
+/// producers use it so the state root is derived from a single verification
+/// pass. Erroneous transactions, excluding the producer(first) one, get dropped,
+/// and valid ones get added, in the provided order, until the block reaches the
+/// configured transactions cap.
+pub async fn apply_block_transactions(
+    overlay: &BlockchainOverlayPtr,
+    time_keeper: &TimeKeeper,
+    block: &mut BlockInfo,
+    mut txs: Vec<Transaction>,
+    pos_testing_mode: bool,
+) -> Result<()> {
+    if txs.is_empty() {
+        return Err(Error::BlockContainsNoTransactions(block.hash()?.to_string()))
+    }
+
+    // Insert last block slot so transactions can be applied against
+    overlay.lock().unwrap().slots.insert(&[block.slots.last().unwrap().clone()])?;
+
+    // Apply proposal transaction.
+    // For PoS blocks(version 2) apply if not in PoS testing mode.
+    if block.header.version != 2 || !pos_testing_mode {
+        verify_producer_transaction(overlay, time_keeper, &txs[0], block.header.version).await?;
+    }
+
+    // Apply the rest transactions in batches filling the free block slots,
+    // so erroneous ones don't take the place of valid ones, until the
+    // configured cap is reached or no transactions are left
+    let mut remaining = txs.split_off(1).into_iter();
+    while txs.len() < TXS_CAP {
+        let batch: Vec<Transaction> = remaining.by_ref().take(TXS_CAP - txs.len()).collect();
+        if batch.is_empty() {
+            break
+        }
+        let erroneous_txs = verify_transactions(overlay, time_keeper, &batch, false).await?;
+        txs.extend(batch.into_iter().filter(|x| !erroneous_txs.contains(x)));
+    }
+    block.append_txs(txs)?;
+
+    // Set the contracts state root the block results in
+    let (state_root, _) = overlay.lock().unwrap().contracts.update_state()?;
+    block.header.state_root = state_root;
+
+    Ok(())
+}
+
 /// Verify block proposer signature, using the proposal transaction signature as signing key
 /// over blocks header hash.
 pub fn verify_producer_signature(block: &BlockInfo, public_key: &PublicKey) -> Result<()> {
@@ -309,6 +383,7 @@ pub async fn verify_producer_transaction(
     debug!(target: "validator::verification::verify_producer_transaction", "Executing \"apply\" call");
     runtime.apply(&state_update)?;
     debug!(target: "validator::verification::verify_producer_transaction", "Successfully executed \"apply\" call");
+    let events = runtime.take_events();
 
     // When we're done executing over the tx's contract call, we now move on with verification.
     // First we verify the signatures as that's cheaper, and then finally we verify the ZK proofs.
@@ -334,6 +409,10 @@ pub async fn verify_producer_transaction(
     }
 
     debug!(target: "validator::verification::verify_producer_transaction", "ZK proof verification successful");
+
+    // Store the events emitted by the call
+    overlay.lock().unwrap().events.insert(time_keeper.verifying_slot, &tx_hash, &events)?;
+
     debug!(target: "validator::verification::verify_producer_transaction", "Proposal transaction {} verified successfully", tx_hash);
 
     Ok(signature_public_key)
@@ -347,6 +426,28 @@ pub async fn verify_transaction(
     tx: &Transaction,
     verifying_keys: &mut HashMap<[u8; 32], HashMap<String, VerifyingKey>>,
     verify_fee: bool,
+) -> Result<u64> {
+    execute_transaction(overlay, time_keeper, tx, verifying_keys, verify_fee, None).await
+}
+
+/// Move the execution trace recorded by given [`Runtime`] into `traces`,
+/// if tracing was requested.
+fn collect_trace(traces: &mut Option<&mut Vec<ExecutionTrace>>, runtime: &mut Runtime) {
+    if let Some(traces) = traces {
+        traces.extend(runtime.take_trace());
+    }
+}
+
+/// Shared implementation of [`verify_transaction`] and [`trace_transaction`].
+/// When `traces` is provided, every instantiated runtime records its execution
+/// trace into it, including the runtime that failed, if any.
+async fn execute_transaction(
+    overlay: &BlockchainOverlayPtr,
+    time_keeper: &TimeKeeper,
+    tx: &Transaction,
+    verifying_keys: &mut HashMap<[u8; 32], HashMap<String, VerifyingKey>>,
+    verify_fee: bool,
+    mut traces: Option<&mut Vec<ExecutionTrace>>,
 ) -> Result<u64> {
     let tx_hash = tx.hash()?;
     debug!(target: "validator::verification::verify_transaction", "Validating transaction {}", tx_hash);
@@ -354,6 +455,16 @@ pub async fn verify_transaction(
     // Gas accumulator
     let mut gas_used = 0;
 
+    // Verify the transaction can be included at the block height being verified
+    if !tx.is_valid_at(time_keeper.verifying_slot) {
+        error!(
+            target: "validator::verification::verify_transaction",
+            "[VALIDATOR] Transaction {} is not valid at height {} (valid from {:?} until {:?})",
+            tx_hash, time_keeper.verifying_slot, tx.valid_from, tx.valid_until,
+        );
+        return Err(TxVerifyFailed::OutsideValidityWindow(time_keeper.verifying_slot).into())
+    }
+
     // Verify calls indexes integrity
     if verify_fee {
         dark_forest_leaf_vec_integrity_check(
@@ -396,6 +507,9 @@ pub async fn verify_transaction(
     // We'll also take note of all the circuits in a Vec so we can calculate their verification cost.
     let mut circuits_to_verify = vec![];
 
+    // Events emitted by the calls, stored once the transaction is verified
+    let mut events = vec![];
+
     // Iterate over all calls to get the metadata
     for (idx, call) in tx.calls.iter().enumerate() {
         // Transaction must not contain a reward call, Money::PoWReward(0x08) or Consensus::Proposal(0x02)
@@ -418,9 +532,16 @@ pub async fn verify_transaction(
 
         let mut runtime =
             Runtime::new(&wasm, overlay.clone(), call.data.contract_id, time_keeper.clone())?;
+        if traces.is_some() {
+            runtime.enable_trace();
+        }
 
         debug!(target: "validator::verification::verify_transaction", "Executing \"metadata\" call");
-        let metadata = runtime.metadata(&payload)?;
+        let metadata = runtime.metadata(&payload);
+        if metadata.is_err() {
+            collect_trace(&mut traces, &mut runtime);
+        }
+        let metadata = metadata?;
 
         // Decode the metadata retrieved from the execution
         let mut decoder = Cursor::new(&metadata);
@@ -466,13 +587,17 @@ pub async fn verify_transaction(
         // After getting the metadata, we run the "exec" function with the same runtime
         // and the same payload.
         debug!(target: "validator::verification::verify_transaction", "Executing \"exec\" call");
-        let state_update = runtime.exec(&payload)?;
-        debug!(target: "validator::verification::verify_transaction", "Successfully executed \"exec\" call");
-
-        // If that was successful, we apply the state update in the ephemeral overlay.
-        debug!(target: "validator::verification::verify_transaction", "Executing \"apply\" call");
-        runtime.apply(&state_update)?;
+        let result = runtime.exec(&payload).and_then(|state_update| {
+            debug!(target: "validator::verification::verify_transaction", "Successfully executed \"exec\" call");
+
+            // If that was successful, we apply the state update in the ephemeral overlay.
+            debug!(target: "validator::verification::verify_transaction", "Executing \"apply\" call");
+            runtime.apply(&state_update)
+        });
+        collect_trace(&mut traces, &mut runtime);
+        result?;
         debug!(target: "validator::verification::verify_transaction", "Successfully executed \"apply\" call");
+        events.extend(runtime.take_events());
 
         // If this call is supposed to deploy a new contract, we have to instantiate
         // a new `Runtime` and run its deploy function.
@@ -491,8 +616,13 @@ pub async fn verify_transaction(
                 deploy_cid,
                 time_keeper.clone(),
             )?;
+            if traces.is_some() {
+                deploy_runtime.enable_trace();
+            }
 
-            deploy_runtime.deploy(&deploy_params.ix)?;
+            let result = deploy_runtime.deploy(&deploy_params.ix);
+            collect_trace(&mut traces, &mut deploy_runtime);
+            result?;
 
             // Append the used gas
             gas_used += deploy_runtime.gas_used();
@@ -505,8 +635,7 @@ pub async fn verify_transaction(
 
     if verify_fee {
         // The signature fee is tx_size + fixed_sig_fee * n_signatures
-        gas_used += (PALLAS_SCHNORR_SIGNATURE_FEE * tx.signatures.len() as u64) +
-            serialize_async(tx).await.len() as u64;
+        gas_used += min_gas_use(tx.signatures.len(), serialize_async(tx).await.len());
 
         // The ZK circuit fee is calculated using a function in validator/fees.rs
         for zkbin in circuits_to_verify.iter() {
@@ -561,11 +690,41 @@ pub async fn verify_transaction(
     }
 
     debug!(target: "validator::verification::verify_transaction", "ZK proof verification successful");
+
+    // Store the events emitted by the calls
+    overlay.lock().unwrap().events.insert(time_keeper.verifying_slot, &tx_hash, &events)?;
+
     debug!(target: "validator::verification::verify_transaction", "Transaction {} verified successfully", tx_hash);
 
     Ok(gas_used)
 }
 
+/// Verify a single [`Transaction`] and apply it to the provided overlay if it's valid.
+/// Returns the gas it used, or `None` if it failed verification, in which case the
+/// overlay changes are reverted.
+pub async fn verify_transaction_gas(
+    overlay: &BlockchainOverlayPtr,
+    time_keeper: &TimeKeeper,
+    tx: &Transaction,
+    verify_fee: bool,
+) -> Result<Option<u64>> {
+    // Map of ZK proof verifying keys for the transaction
+    let mut vks: HashMap<[u8; 32], HashMap<String, VerifyingKey>> = HashMap::new();
+    for call in &tx.calls {
+        vks.insert(call.data.contract_id.to_bytes(), HashMap::new());
+    }
+
+    overlay.lock().unwrap().checkpoint();
+    match verify_transaction(overlay, time_keeper, tx, &mut vks, verify_fee).await {
+        Ok(gas) => Ok(Some(gas)),
+        Err(e) => {
+            warn!(target: "validator::verification::verify_transaction_gas", "Transaction verification failed: {}", e);
+            overlay.lock().unwrap().revert_to_checkpoint()?;
+            Ok(None)
+        }
+    }
+}
+
 /// Verify a set of [`Transaction`] in sequence and apply them if all are valid.
 /// In case any of the transactions fail, they will be returned to the caller.
 pub async fn verify_transactions(
@@ -614,6 +773,30 @@ pub async fn verify_transactions(
     Ok(erroneous_txs)
 }
 
+/// Verify given [`Transaction`] like [`verify_transaction`] does, with tracing enabled,
+/// applying it to the provided overlay. Returns the execution traces of all instantiated
+/// runtimes, along with the error the verification failed with, if any. Fees are not
+/// verified, as the traced transaction is not required to be fee-paying.
+pub async fn trace_transaction(
+    overlay: &BlockchainOverlayPtr,
+    time_keeper: &TimeKeeper,
+    tx: &Transaction,
+) -> Result<(Vec<ExecutionTrace>, Option<Error>)> {
+    debug!(target: "validator::verification::trace_transaction", "Tracing transaction {}", tx.hash()?);
+
+    // Map of ZK proof verifying keys for the transaction
+    let mut vks: HashMap<[u8; 32], HashMap<String, VerifyingKey>> = HashMap::new();
+    for call in &tx.calls {
+        vks.insert(call.data.contract_id.to_bytes(), HashMap::new());
+    }
+
+    let mut traces = vec![];
+    let result =
+        execute_transaction(overlay, time_keeper, tx, &mut vks, false, Some(&mut traces)).await;
+
+    Ok((traces, result.err()))
+}
+
 /// Verify given [`Proposal`] against provided consensus state
 pub async fn verify_proposal(
     consensus: &Consensus,
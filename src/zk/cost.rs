@@ -0,0 +1,283 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Static cost estimation of zkas circuits.
+//!
+//! The row estimates follow the layouts of the chips configured in
+//! [`super::vm::VmConfig`]. They assume every region is laid out one after
+//! another, so they are an upper bound of what `floor_planner::V1` ends up
+//! using, and the resulting `k` is conservative. Use `MockProver` to find the
+//! exact minimum.
+
+use darkfi_sdk::crypto::constants::{
+    fixed_bases::{NUM_WINDOWS, NUM_WINDOWS_SHORT},
+    MERKLE_DEPTH_ORCHARD,
+};
+use halo2_gadgets::{
+    poseidon::primitives::{P128Pow5T3, Spec},
+    sinsemilla::primitives::K as SINSEMILLA_K,
+};
+use halo2_proofs::pasta::pallas;
+
+use crate::zkas::{types::HeapType, LitType, Opcode, VarType, ZkBinary};
+
+/// Rows reserved by halo2 at the end of the table for blinding factors.
+/// This depends on the maximum number of queries on a single advice
+/// column, so we use a conservative value covering the VM chips.
+const BLINDING_ROWS: usize = 10;
+
+/// Number of 3-bit windows of the 64-bit `NativeRangeCheckChip`
+const RANGE_64_WINDOWS: usize = 22;
+
+/// Number of 3-bit windows of the 253-bit `NativeRangeCheckChip`
+const RANGE_253_WINDOWS: usize = 85;
+
+/// Number of rows used by the running sum decomposition of a value
+/// into `num_windows` windows.
+const fn decomposition_rows(num_windows: usize) -> usize {
+    num_windows + 1
+}
+
+/// Rows used by the `EccChip` complete addition region
+const ECC_COMPLETE_ADD_ROWS: usize = 2;
+
+/// Rows used by the `EccChip` variable-base scalar multiplication, which
+/// runs the double-and-add over the hi/lo halves of the scalar in parallel,
+/// then finishes with complete additions and the overflow check.
+const ECC_MUL_VAR_ROWS: usize = 165;
+
+/// Rows used by a single level of the Sinsemilla-based `MerkleChip`:
+/// the 10-bit words of the 520-bit message, plus the decomposition and
+/// conditional swap regions.
+const MERKLE_LAYER_ROWS: usize = 520 / SINSEMILLA_K + 5;
+
+/// Rows used by a single `Pow5Chip` permutation.
+/// One row for the initial state, one per full round, and one per pair
+/// of partial rounds.
+fn poseidon_permutation_rows() -> usize {
+    let full_rounds = <P128Pow5T3 as Spec<pallas::Base, 3, 2>>::full_rounds();
+    let partial_rounds = <P128Pow5T3 as Spec<pallas::Base, 3, 2>>::partial_rounds();
+    1 + full_rounds + partial_rounds / 2
+}
+
+/// Estimate the number of rows used when witnessing a value of the
+/// given type into the circuit.
+pub fn witness_rows(typ: &VarType) -> usize {
+    match typ {
+        // Witnessed as a point in a single row
+        VarType::EcPoint | VarType::EcNiPoint => 1,
+        // Loaded into an advice cell
+        VarType::Base => 1,
+        // Scalars, Merkle paths, and integers are only witnessed on use,
+        // which is accounted for in the opcode that consumes them.
+        _ => 0,
+    }
+}
+
+/// Estimate the number of rows used by executing an opcode with the given
+/// arguments, following the chip each opcode is dispatched to in the VM.
+/// `literals` is the literal heap of the circuit the opcode belongs to.
+pub fn opcode_rows(
+    opcode: &Opcode,
+    args: &[(HeapType, usize)],
+    literals: &[(LitType, String)],
+) -> usize {
+    match opcode {
+        Opcode::Noop => 0,
+
+        Opcode::EcAdd => ECC_COMPLETE_ADD_ROWS,
+
+        // Full-width fixed-base multiplication: scalar decomposition into
+        // windows, one row per window for the multiplication, and a final
+        // complete addition.
+        Opcode::EcMul => NUM_WINDOWS + (NUM_WINDOWS + 1) + ECC_COMPLETE_ADD_ROWS,
+
+        // Base field element decomposition is range-checked with canonicity
+        // checks on top of the full-width multiplication.
+        Opcode::EcMulBase => decomposition_rows(NUM_WINDOWS) + NUM_WINDOWS + 10,
+
+        // Short multiplication with a sign row
+        Opcode::EcMulShort => {
+            decomposition_rows(NUM_WINDOWS_SHORT) +
+                (NUM_WINDOWS_SHORT + 1) +
+                ECC_COMPLETE_ADD_ROWS +
+                2
+        }
+
+        Opcode::EcMulVarBase => ECC_MUL_VAR_ROWS,
+
+        // These just return a cell of the point
+        Opcode::EcGetX | Opcode::EcGetY => 0,
+
+        // Initial state, then one absorption row and a permutation per
+        // `RATE` elements of the message.
+        Opcode::PoseidonHash => {
+            let blocks = (args.len() + 1) / 2;
+            1 + blocks * (1 + poseidon_permutation_rows())
+        }
+
+        Opcode::MerkleRoot => MERKLE_DEPTH_ORCHARD * MERKLE_LAYER_ROWS,
+
+        // The length constant, a permutation of the initial state and one
+        // per `RATE` elements after the first, and an addition per element.
+        Opcode::ConstrainEncryptedInstance => {
+            let elements = args.len().saturating_sub(2);
+            let blocks = ((elements + 1) / 2).max(1);
+            1 + blocks * poseidon_permutation_rows() + elements
+        }
+
+        Opcode::BaseAdd | Opcode::BaseMul | Opcode::BaseSub => 1,
+
+        // Advice assignment of the literal
+        Opcode::WitnessBase => 1,
+
+        // The bit width is given as a literal, which the VM checks to be
+        // either 64 or 253.
+        Opcode::RangeCheck => {
+            let bits = match args.first() {
+                Some((HeapType::Lit, idx)) => literals.get(*idx).and_then(|l| l.1.parse().ok()),
+                _ => None,
+            };
+
+            match bits {
+                Some(64u64) => decomposition_rows(RANGE_64_WINDOWS),
+                _ => decomposition_rows(RANGE_253_WINDOWS),
+            }
+        }
+
+        // `a`, `b` and `a_offset` row plus the range check of `a_offset`
+        Opcode::LessThanStrict | Opcode::LessThanLoose => 1 + decomposition_rows(RANGE_253_WINDOWS),
+
+        Opcode::BoolCheck => 1,
+
+        Opcode::CondSelect | Opcode::ZeroCondSelect => 1,
+
+        // Copy constraints don't use any rows
+        Opcode::ConstrainEqualBase | Opcode::ConstrainEqualPoint | Opcode::ConstrainInstance => 0,
+
+        Opcode::DebugPrint => 0,
+    }
+}
+
+/// Cost estimation of a compiled zkas circuit
+#[derive(Debug, Clone)]
+pub struct CircuitCost {
+    /// Estimated rows used by each opcode, in order of execution
+    pub opcode_rows: Vec<(Opcode, usize)>,
+    /// Estimated rows used by witnessing values and loading constants
+    pub witness_rows: usize,
+    /// Estimated total rows used by the circuit's regions
+    pub total_rows: usize,
+    /// Minimum `k` needed to fit the regions and lookup tables
+    pub min_k: u32,
+    /// Gas used to verify a proof of this circuit
+    pub gas: u64,
+}
+
+impl CircuitCost {
+    /// Estimate the cost of the given circuit.
+    /// This function assumes that the zkbin was properly decoded.
+    pub fn measure(zkbin: &ZkBinary) -> Self {
+        let opcode_rows: Vec<(Opcode, usize)> = zkbin
+            .opcodes
+            .iter()
+            .map(|(op, args)| (*op, opcode_rows(op, args, &zkbin.literals)))
+            .collect();
+
+        // The VM always witnesses a constant `1` used for short multiplication.
+        let witness_rows = 1 + zkbin.witnesses.iter().map(witness_rows).sum::<usize>();
+
+        let total_rows = witness_rows + opcode_rows.iter().map(|(_, rows)| rows).sum::<usize>();
+
+        // The Sinsemilla generator table is always loaded, and it shares
+        // its column with the lookup range check table.
+        let needed = total_rows.max(1 << SINSEMILLA_K) + BLINDING_ROWS;
+        let min_k = needed.next_power_of_two().trailing_zeros();
+
+        Self { opcode_rows, witness_rows, total_rows, min_k, gas: circuit_gas_use(zkbin) }
+    }
+}
+
+/// Calculate the gas use for verifying a given zkas circuit.
+/// This function assumes that the zkbin was properly decoded.
+pub fn circuit_gas_use(zkbin: &ZkBinary) -> u64 {
+    let mut accumulator: u64 = 0;
+
+    // Constants each with a cost of 10
+    accumulator += 10 * zkbin.constants.len() as u64;
+
+    // Literals each with a cost of 10 (for now there's only 1 type of literal)
+    accumulator += 10 * zkbin.literals.len() as u64;
+
+    // Witnesses have cost by type
+    for witness in &zkbin.witnesses {
+        let cost = match witness {
+            VarType::Dummy => unreachable!(),
+            VarType::EcPoint => 20,
+            VarType::EcFixedPoint => unreachable!(),
+            VarType::EcFixedPointShort => unreachable!(),
+            VarType::EcFixedPointBase => unreachable!(),
+            VarType::EcNiPoint => 20,
+            VarType::Base => 10,
+            VarType::BaseArray => unreachable!(),
+            VarType::Scalar => 20,
+            VarType::ScalarArray => unreachable!(),
+            VarType::MerklePath => 10 * MERKLE_DEPTH_ORCHARD as u64,
+            VarType::Uint32 => 10,
+            VarType::Uint64 => 10,
+            VarType::Any => 10,
+        };
+
+        accumulator += cost;
+    }
+
+    // Opcodes depending on how heavy they are
+    for opcode in &zkbin.opcodes {
+        let cost = match opcode.0 {
+            Opcode::Noop => unreachable!(),
+            Opcode::EcAdd => 30,
+            Opcode::EcMul => 30,
+            Opcode::EcMulBase => 30,
+            Opcode::EcMulShort => 30,
+            Opcode::EcMulVarBase => 30,
+            Opcode::EcGetX => 5,
+            Opcode::EcGetY => 5,
+            Opcode::PoseidonHash => 20 + 10 * opcode.1.len() as u64,
+            Opcode::MerkleRoot => 10 * MERKLE_DEPTH_ORCHARD as u64,
+            Opcode::BaseAdd => 15,
+            Opcode::BaseMul => 15,
+            Opcode::BaseSub => 15,
+            Opcode::WitnessBase => 10,
+            Opcode::RangeCheck => 60,
+            Opcode::LessThanStrict => 100,
+            Opcode::LessThanLoose => 100,
+            Opcode::BoolCheck => 20,
+            Opcode::CondSelect => 10,
+            Opcode::ZeroCondSelect => 10,
+            Opcode::ConstrainEqualBase => 10,
+            Opcode::ConstrainEqualPoint => 20,
+            Opcode::ConstrainInstance => 10,
+            Opcode::ConstrainEncryptedInstance => 20 + 20 * opcode.1.len() as u64,
+            Opcode::DebugPrint => 100,
+        };
+
+        accumulator += cost;
+    }
+
+    accumulator
+}
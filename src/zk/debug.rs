@@ -21,9 +21,18 @@ use log::error;
 
 #[cfg(feature = "tinyjson")]
 use {
+    darkfi_sdk::{
+        crypto::{constants::MERKLE_DEPTH_ORCHARD, MerkleNode},
+        pasta::{
+            arithmetic::CurveAffine,
+            group::{ff::PrimeField, prime::PrimeCurveAffine, Curve},
+        },
+    },
+    halo2_proofs::circuit::Value,
     std::{collections::HashMap, fs::File, io::Write, path::Path},
-    tinyjson::JsonValue::{
-        Array as JsonArray, Number as JsonNum, Object as JsonObj, String as JsonStr,
+    tinyjson::{
+        JsonValue,
+        JsonValue::{Array as JsonArray, Number as JsonNum, Object as JsonObj, String as JsonStr},
     },
 };
 
@@ -36,7 +45,20 @@ pub fn export_witness_json<P: AsRef<Path>>(
     output_path: P,
     prover_witnesses: &Vec<Witness>,
     public_inputs: &Vec<pallas::Base>,
-) {
+) -> Result<()> {
+    let json = witness_json(prover_witnesses, public_inputs)?;
+    let mut output = File::create(output_path)?;
+    output.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(feature = "tinyjson")]
+/// Serialize the prover witnesses and public inputs into the witness.json
+/// format read by [`import_witness_json`].
+pub fn witness_json(
+    prover_witnesses: &[Witness],
+    public_inputs: &[pallas::Base],
+) -> Result<String> {
     let mut witnesses = Vec::new();
     for witness in prover_witnesses {
         let mut value_json = HashMap::new();
@@ -59,6 +81,14 @@ pub fn export_witness_json<P: AsRef<Path>>(
                     w1
                 });
             }
+            Witness::Uint64(value) => {
+                // Exported as a string since JSON numbers are not able to
+                // represent the whole u64 range.
+                value.map(|w1| {
+                    value_json.insert("Uint64".to_string(), JsonStr(w1.to_string()));
+                    w1
+                });
+            }
             Witness::MerklePath(value) => {
                 let mut path = Vec::new();
                 value.map(|w1| {
@@ -69,7 +99,23 @@ pub fn export_witness_json<P: AsRef<Path>>(
                 });
                 value_json.insert("MerklePath".to_string(), JsonArray(path));
             }
-            _ => unimplemented!(),
+            Witness::EcPoint(value) | Witness::EcNiPoint(value) => {
+                let mut coords = Vec::new();
+                value.map(|w1| {
+                    let coordinates = w1.to_affine().coordinates().unwrap();
+                    coords.push(JsonStr(format!("{:?}", coordinates.x())));
+                    coords.push(JsonStr(format!("{:?}", coordinates.y())));
+                    w1
+                });
+                value_json.insert(witness.name().to_string(), JsonArray(coords));
+            }
+            Witness::EcFixedPoint(_) => {
+                error!("Unsupported witness type: {}", witness.name());
+                return Err(Error::ZkasDecoderError(format!(
+                    "Unsupported witness type: {}",
+                    witness.name()
+                )))
+            }
         }
         witnesses.push(JsonObj(value_json));
     }
@@ -85,10 +131,197 @@ pub fn export_witness_json<P: AsRef<Path>>(
         ("witnesses".to_string(), witnesses_json),
         ("instances".to_string(), instances_json),
     ]));
-    // This is a debugging method. We don't care about .expect() crashing.
-    let json = witness_json.format().expect("cannot create debug json");
-    let mut output = File::create(output_path).expect("cannot write file");
-    output.write_all(json.as_bytes()).expect("write failed");
+
+    Ok(witness_json.format()?)
+}
+
+#[cfg(feature = "tinyjson")]
+/// Parse a field element from its `Debug` representation, i.e. big-endian
+/// hex prefixed with `0x`, as written by [`export_witness_json`].
+fn parse_field<F: PrimeField<Repr = [u8; 32]>>(value: &JsonValue) -> Result<F> {
+    let Some(hex) = value.get::<String>() else {
+        return Err(Error::ParseFailed("Field element is not a string"))
+    };
+
+    let Some(hex) = hex.strip_prefix("0x") else {
+        return Err(Error::ParseFailed("Field element is not prefixed with 0x"))
+    };
+
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(Error::ParseFailed("Field element is not 32 bytes of hex"))
+    }
+
+    let mut repr = [0u8; 32];
+    for (i, byte) in repr.iter_mut().rev().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)?;
+    }
+
+    match F::from_repr(repr).into() {
+        Some(v) => Ok(v),
+        None => Err(Error::ParseFailed("Field element is not canonical")),
+    }
+}
+
+#[cfg(feature = "tinyjson")]
+/// Parse an affine point given as a `[x, y]` array of coordinates
+fn parse_point(value: &JsonValue) -> Result<pallas::Point> {
+    let Some(coords) = value.get::<Vec<JsonValue>>() else {
+        return Err(Error::ParseFailed("Point is not an array"))
+    };
+
+    if coords.len() != 2 {
+        return Err(Error::ParseFailed("Point must be given as [x, y]"))
+    }
+
+    let x = parse_field(&coords[0])?;
+    let y = parse_field(&coords[1])?;
+
+    match Option::<pallas::Affine>::from(pallas::Affine::from_xy(x, y)) {
+        Some(v) => Ok(v.to_curve()),
+        None => Err(Error::ParseFailed("Point is not on the curve")),
+    }
+}
+
+#[cfg(feature = "tinyjson")]
+/// Parse an unsigned integer given either as a JSON number or as a
+/// decimal string.
+fn parse_uint(value: &JsonValue) -> Result<u64> {
+    if let Some(v) = value.get::<f64>() {
+        if v.fract() != 0.0 || *v < 0.0 || *v >= u64::MAX as f64 {
+            return Err(Error::ParseFailed("Integer is out of range"))
+        }
+        return Ok(*v as u64)
+    }
+
+    if let Some(v) = value.get::<String>() {
+        return Ok(v.parse()?)
+    }
+
+    Err(Error::ParseFailed("Integer is not a number or string"))
+}
+
+#[cfg(feature = "tinyjson")]
+/// Import the prover witnesses and public inputs from a witness.json
+/// in the format written by [`export_witness_json`].
+pub fn import_witness_json(json: &str) -> Result<(Vec<Witness>, Vec<pallas::Base>)> {
+    let json: JsonValue = json.parse()?;
+
+    let Some(witnesses_json) = json["witnesses"].get::<Vec<JsonValue>>() else {
+        return Err(Error::ParseFailed("Missing witnesses array"))
+    };
+
+    let Some(instances_json) = json["instances"].get::<Vec<JsonValue>>() else {
+        return Err(Error::ParseFailed("Missing instances array"))
+    };
+
+    let mut witnesses = Vec::with_capacity(witnesses_json.len());
+    for witness in witnesses_json {
+        let Some(witness) = witness.get::<HashMap<String, JsonValue>>() else {
+            return Err(Error::ParseFailed("Witness is not an object"))
+        };
+
+        let Some((typ, value)) = witness.iter().next() else {
+            return Err(Error::ParseFailed("Witness is empty"))
+        };
+
+        if witness.len() != 1 {
+            return Err(Error::ParseFailed("Witness must have exactly one type"))
+        }
+
+        let witness = match typ.as_str() {
+            "EcPoint" => Witness::EcPoint(Value::known(parse_point(value)?)),
+            "EcNiPoint" => Witness::EcNiPoint(Value::known(parse_point(value)?)),
+            "Base" => Witness::Base(Value::known(parse_field(value)?)),
+            "Scalar" => Witness::Scalar(Value::known(parse_field(value)?)),
+            "Uint32" => {
+                let Ok(v) = u32::try_from(parse_uint(value)?) else {
+                    return Err(Error::ParseFailed("Uint32 is out of range"))
+                };
+                Witness::Uint32(Value::known(v))
+            }
+            "Uint64" => Witness::Uint64(Value::known(parse_uint(value)?)),
+            "MerklePath" => {
+                let Some(nodes) = value.get::<Vec<JsonValue>>() else {
+                    return Err(Error::ParseFailed("MerklePath is not an array"))
+                };
+
+                if nodes.len() != MERKLE_DEPTH_ORCHARD {
+                    return Err(Error::ParseFailed("MerklePath has wrong length"))
+                }
+
+                let mut path = [MerkleNode::from(pallas::Base::zero()); MERKLE_DEPTH_ORCHARD];
+                for (node, value) in path.iter_mut().zip(nodes) {
+                    *node = MerkleNode::from(parse_field::<pallas::Base>(value)?);
+                }
+
+                Witness::MerklePath(Value::known(path))
+            }
+            _ => return Err(Error::ParseFailed("Unsupported witness type")),
+        };
+
+        witnesses.push(witness);
+    }
+
+    let mut instances = Vec::with_capacity(instances_json.len());
+    for instance in instances_json {
+        instances.push(parse_field(instance)?);
+    }
+
+    Ok((witnesses, instances))
+}
+
+#[cfg(feature = "tinyjson")]
+/// Create a witness.json template for the given circuit, with a zero
+/// (or generator, for points) placeholder value for each witness and
+/// public input.
+pub fn witness_json_template(binary: &zkas::ZkBinary) -> Result<String> {
+    let zero = || JsonStr(format!("{:?}", pallas::Base::zero()));
+
+    let mut witnesses = Vec::with_capacity(binary.witnesses.len());
+    for witness in &binary.witnesses {
+        let value = match witness {
+            zkas::VarType::EcPoint | zkas::VarType::EcNiPoint => {
+                let coordinates = pallas::Affine::generator().coordinates().unwrap();
+                JsonArray(vec![
+                    JsonStr(format!("{:?}", coordinates.x())),
+                    JsonStr(format!("{:?}", coordinates.y())),
+                ])
+            }
+            zkas::VarType::Base => zero(),
+            zkas::VarType::Scalar => JsonStr(format!("{:?}", pallas::Scalar::zero())),
+            zkas::VarType::MerklePath => JsonArray(vec![zero(); MERKLE_DEPTH_ORCHARD]),
+            zkas::VarType::Uint32 => JsonNum(0.0),
+            zkas::VarType::Uint64 => JsonStr("0".to_string()),
+            x => {
+                error!("Unsupported witness type: {:?}", x);
+                return Err(Error::ZkasDecoderError(format!("Unsupported witness type: {:?}", x)))
+            }
+        };
+
+        witnesses.push(JsonObj(HashMap::from([(witness.name().to_string(), value)])));
+    }
+
+    let instances = vec![zero(); public_inputs_count(&binary.opcodes)];
+
+    let witness_json = JsonObj(HashMap::from([
+        ("witnesses".to_string(), JsonArray(witnesses)),
+        ("instances".to_string(), JsonArray(instances)),
+    ]));
+
+    Ok(witness_json.format()?)
+}
+
+/// Count the public inputs constrained by the given opcodes
+fn public_inputs_count(opcodes: &[(zkas::Opcode, Vec<(zkas::types::HeapType, usize)>)]) -> usize {
+    opcodes
+        .iter()
+        .map(|(opcode, args)| match opcode {
+            zkas::Opcode::ConstrainInstance => 1,
+            // The key and the nonce are followed by the encrypted elements
+            zkas::Opcode::ConstrainEncryptedInstance => args.len().saturating_sub(2),
+            _ => 0,
+        })
+        .sum()
 }
 
 /// Call this before `Proof::create()` to perform type checks on the witnesses and check
@@ -132,12 +365,7 @@ pub fn zkas_type_checks(
     }
 
     // Count number of public instances
-    let mut instances_count = 0;
-    for opcode in &circuit.opcodes {
-        if let (zkas::Opcode::ConstrainInstance, _) = opcode {
-            instances_count += 1;
-        }
-    }
+    let instances_count = public_inputs_count(&circuit.opcodes);
     if instances.len() != instances_count {
         error!(
             "Wrong number of public inputs. Should be {}, but instead got {}.",
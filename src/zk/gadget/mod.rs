@@ -36,3 +36,6 @@ pub mod cond_select;
 
 /// Conditional selection based on lhs (will output lhs if lhs==0, otherwise rhs)
 pub mod zero_cond;
+
+/// Poseidon duplex sponge encryption
+pub mod poseidon_encrypt;
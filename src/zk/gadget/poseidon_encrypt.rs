@@ -0,0 +1,235 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use halo2_gadgets::poseidon::{
+    primitives::P128Pow5T3, PoseidonInstructions, Pow5Chip as PoseidonChip, StateWord,
+};
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter},
+    pasta::Fp,
+    plonk::{Advice, Column, Error},
+};
+
+use super::arithmetic::{ArithChip, ArithInstruction};
+
+/// Encrypt the given plaintext cells with a Poseidon duplex sponge, and
+/// return the ciphertext cells. This is the in-circuit counterpart of
+/// `darkfi_sdk::crypto::poseidon_encrypt`.
+///
+/// `advice` must have equality enabled, and the circuit must have a fixed
+/// column enabled for constants, as the message length is loaded as one.
+pub fn poseidon_encrypt(
+    poseidon_chip: &PoseidonChip<Fp, 3, 2>,
+    arith_chip: &ArithChip<Fp>,
+    advice: Column<Advice>,
+    mut layouter: impl Layouter<Fp>,
+    key: AssignedCell<Fp, Fp>,
+    nonce: AssignedCell<Fp, Fp>,
+    plaintext: &[AssignedCell<Fp, Fp>],
+) -> Result<Vec<AssignedCell<Fp, Fp>>, Error> {
+    let len = layouter.assign_region(
+        || "load message length",
+        |mut region| {
+            region.assign_advice_from_constant(
+                || "message length",
+                advice,
+                0,
+                Fp::from(plaintext.len() as u64),
+            )
+        },
+    )?;
+
+    let mut state: [AssignedCell<Fp, Fp>; 3] = [key, nonce, len];
+    state = permute_state(poseidon_chip, &mut layouter, state)?;
+
+    let mut ciphertext = Vec::with_capacity(plaintext.len());
+    for (i, chunk) in plaintext.chunks(2).enumerate() {
+        if i > 0 {
+            state = permute_state(poseidon_chip, &mut layouter, state)?;
+        }
+
+        for (j, m) in chunk.iter().enumerate() {
+            let c = arith_chip.add(layouter.namespace(|| "absorb element"), &state[j], m)?;
+            state[j] = c.clone();
+            ciphertext.push(c);
+        }
+    }
+
+    Ok(ciphertext)
+}
+
+/// Apply the Poseidon permutation to the given state cells
+fn permute_state(
+    poseidon_chip: &PoseidonChip<Fp, 3, 2>,
+    layouter: &mut impl Layouter<Fp>,
+    state: [AssignedCell<Fp, Fp>; 3],
+) -> Result<[AssignedCell<Fp, Fp>; 3], Error> {
+    let state = state.map(StateWord::from);
+    let state = <PoseidonChip<Fp, 3, 2> as PoseidonInstructions<Fp, P128Pow5T3, 3, 2>>::permute(
+        poseidon_chip,
+        &mut layouter.namespace(|| "poseidon permutation"),
+        &state,
+    )?;
+
+    Ok(state.map(AssignedCell::from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zk::{assign_free_advice, gadget::arithmetic::ArithConfig};
+    use darkfi_sdk::crypto::poseidon_encrypt as native_poseidon_encrypt;
+    use halo2_gadgets::poseidon::Pow5Config as PoseidonConfig;
+    use halo2_proofs::{
+        arithmetic::Field,
+        circuit::{floor_planner, Value},
+        dev::MockProver,
+        plonk::{Circuit, ConstraintSystem, Instance as InstanceColumn},
+    };
+    use rand::rngs::OsRng;
+
+    #[derive(Clone)]
+    struct EncryptCircuitConfig {
+        primary: Column<InstanceColumn>,
+        advices: [Column<Advice>; 5],
+        poseidon_config: PoseidonConfig<Fp, 3, 2>,
+        arith_config: ArithConfig,
+    }
+
+    #[derive(Default)]
+    struct EncryptCircuit {
+        key: Value<Fp>,
+        nonce: Value<Fp>,
+        plaintext: Vec<Value<Fp>>,
+    }
+
+    impl Circuit<Fp> for EncryptCircuit {
+        type Config = EncryptCircuitConfig;
+        type FloorPlanner = floor_planner::V1;
+        type Params = ();
+
+        fn without_witnesses(&self) -> Self {
+            Self {
+                key: Value::unknown(),
+                nonce: Value::unknown(),
+                plaintext: vec![Value::unknown(); self.plaintext.len()],
+            }
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let advices = [
+                meta.advice_column(),
+                meta.advice_column(),
+                meta.advice_column(),
+                meta.advice_column(),
+                meta.advice_column(),
+            ];
+            for advice in advices.iter() {
+                meta.enable_equality(*advice);
+            }
+
+            let primary = meta.instance_column();
+            meta.enable_equality(primary);
+
+            let constants = meta.fixed_column();
+            meta.enable_constant(constants);
+
+            let rc_a = [meta.fixed_column(), meta.fixed_column(), meta.fixed_column()];
+            let rc_b = [meta.fixed_column(), meta.fixed_column(), meta.fixed_column()];
+
+            let poseidon_config = PoseidonChip::configure::<P128Pow5T3>(
+                meta,
+                advices[1..4].try_into().unwrap(),
+                advices[4],
+                rc_a,
+                rc_b,
+            );
+
+            let arith_config = ArithChip::configure(meta, advices[0], advices[1], advices[2]);
+
+            EncryptCircuitConfig { primary, advices, poseidon_config, arith_config }
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            let poseidon_chip = PoseidonChip::construct(config.poseidon_config);
+            let arith_chip = ArithChip::construct(config.arith_config);
+
+            let key =
+                assign_free_advice(layouter.namespace(|| "load key"), config.advices[0], self.key)?;
+            let nonce = assign_free_advice(
+                layouter.namespace(|| "load nonce"),
+                config.advices[0],
+                self.nonce,
+            )?;
+
+            let mut plaintext = vec![];
+            for m in &self.plaintext {
+                plaintext.push(assign_free_advice(
+                    layouter.namespace(|| "load plaintext"),
+                    config.advices[0],
+                    *m,
+                )?);
+            }
+
+            let ciphertext = poseidon_encrypt(
+                &poseidon_chip,
+                &arith_chip,
+                config.advices[0],
+                layouter.namespace(|| "poseidon_encrypt"),
+                key,
+                nonce,
+                &plaintext,
+            )?;
+
+            for (i, c) in ciphertext.iter().enumerate() {
+                layouter.constrain_instance(c.cell(), config.primary, i)?;
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn poseidon_encrypt_chip() {
+        let key = Fp::random(&mut OsRng);
+        let nonce = Fp::random(&mut OsRng);
+
+        for len in 1..6 {
+            let plaintext: Vec<Fp> = (0..len).map(|_| Fp::random(&mut OsRng)).collect();
+            let ciphertext = native_poseidon_encrypt(key, nonce, &plaintext);
+
+            let circuit = EncryptCircuit {
+                key: Value::known(key),
+                nonce: Value::known(nonce),
+                plaintext: plaintext.iter().map(|m| Value::known(*m)).collect(),
+            };
+
+            let prover = MockProver::run(8, &circuit, vec![ciphertext.clone()]).unwrap();
+            prover.assert_satisfied();
+
+            // Ciphertext under a different nonce must not verify
+            let wrong = native_poseidon_encrypt(key, nonce + Fp::ONE, &plaintext);
+            let prover = MockProver::run(8, &circuit, vec![wrong]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+    }
+}
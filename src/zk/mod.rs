@@ -31,14 +31,20 @@ pub mod gadget;
 pub mod proof;
 pub use proof::{Proof, ProvingKey, VerifyingKey};
 
+/// Circuit row and gas cost estimation
+pub mod cost;
+
+/// Native evaluation of circuits without halo2
+pub mod native;
+
 /// Trace computation of intermediate values in circuit
 mod tracer;
 pub use tracer::DebugOpValue;
 
 mod debug;
-#[cfg(feature = "tinyjson")]
-pub use debug::export_witness_json;
 pub use debug::zkas_type_checks;
+#[cfg(feature = "tinyjson")]
+pub use debug::{export_witness_json, import_witness_json, witness_json, witness_json_template};
 
 pub mod halo2 {
     pub use halo2_proofs::{
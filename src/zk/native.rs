@@ -0,0 +1,450 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Native evaluation of zkas circuits.
+//!
+//! This interprets the opcodes of a [`ZkBinary`] directly over the Pallas
+//! curve and its fields, without going through halo2. It computes the same
+//! values the [`ZkCircuit`](super::ZkCircuit) tracer records for each opcode,
+//! and checks the constraints each opcode enforces in the VM. It's meant to
+//! be used as a reference implementation when testing the VM.
+
+use darkfi_sdk::{
+    bridgetree::{Hashable, Level},
+    crypto::{
+        constants::{NullifierK, OrchardFixedBasesFull, ValueCommitV, MERKLE_DEPTH_ORCHARD},
+        poseidon_encrypt,
+        util::{mod_r_p, poseidon_hash},
+        MerkleNode,
+    },
+    pasta::{
+        arithmetic::{Coordinates, CurveAffine},
+        group::{
+            ff::{Field, PrimeField},
+            Curve, Group,
+        },
+        pallas,
+    },
+};
+use halo2_gadgets::ecc::chip::FixedPoint;
+use halo2_proofs::circuit::Value;
+use log::error;
+
+use super::{debug::zkas_type_checks, DebugOpValue, Witness, ZkCircuit};
+use crate::{
+    zkas::{LitType, Opcode, ZkBinary},
+    Error, Result,
+};
+
+/// Native counterpart of the VM heap variables
+#[derive(Clone, Debug)]
+enum NativeVar {
+    EcPoint(pallas::Point),
+    EcNiPoint(pallas::Point),
+    EcFixedPoint(pallas::Point),
+    EcFixedPointShort(pallas::Point),
+    EcFixedPointBase(pallas::Point),
+    Base(pallas::Base),
+    Scalar(pallas::Scalar),
+    MerklePath([pallas::Base; MERKLE_DEPTH_ORCHARD]),
+    Uint32(u32),
+    Uint64(u64),
+}
+
+macro_rules! heap_get {
+    ($heap:expr, $idx:expr, $variant:ident) => {{
+        let idx = $idx;
+        match $heap.get(idx) {
+            Some(NativeVar::$variant(v)) => *v,
+            x => {
+                error!(target: "zk::native", "Invalid heap variable {:?} at index {}", x, idx);
+                return Err(Error::ZkasEvalError(format!(
+                    "Expected {} at heap index {}",
+                    stringify!($variant),
+                    idx
+                )))
+            }
+        }
+    }};
+}
+
+/// Result of natively evaluating a circuit
+#[derive(Clone, Debug)]
+pub struct NativeEvaluation {
+    /// Value computed by each opcode, in the form recorded by `ZkTracer`
+    pub opvalues: Vec<DebugOpValue>,
+    /// Indexes of the opcodes whose constraints are not satisfied
+    pub failures: Vec<usize>,
+}
+
+impl NativeEvaluation {
+    /// Returns `true` if all constraints of the circuit are satisfied
+    pub fn is_satisfied(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Extract a known witness value
+fn known<T: Copy>(value: &Value<T>, idx: usize) -> Result<T> {
+    let mut ret = None;
+    value.map(|v| ret = Some(v));
+    ret.ok_or_else(|| Error::ZkasEvalError(format!("Unknown value for witness {}", idx)))
+}
+
+/// Returns `true` if the given field element fits in `bits` bits
+fn fits_bits(value: &pallas::Base, bits: usize) -> bool {
+    let repr = value.to_repr();
+    repr.iter().enumerate().all(|(i, byte)| {
+        let lo = i * 8;
+        if lo + 8 <= bits {
+            true
+        } else if lo >= bits {
+            *byte == 0
+        } else {
+            byte >> (bits - lo) == 0
+        }
+    })
+}
+
+/// Affine coordinates of a point, with the identity as `(0, 0)` like the
+/// ECC chip represents it.
+fn coordinates(point: &pallas::Point) -> (pallas::Base, pallas::Base) {
+    let coords: Option<Coordinates<pallas::Affine>> = point.to_affine().coordinates().into();
+    coords.map_or((pallas::Base::ZERO, pallas::Base::ZERO), |c| (*c.x(), *c.y()))
+}
+
+/// Compute the Merkle root of `leaf` at position `pos` with the given
+/// authentication path, as the VM `MerkleChip` does.
+fn merkle_root(
+    pos: u32,
+    path: &[pallas::Base; MERKLE_DEPTH_ORCHARD],
+    leaf: pallas::Base,
+) -> pallas::Base {
+    let mut node = MerkleNode::from(leaf);
+    for (l, sibling) in path.iter().enumerate() {
+        let sibling = MerkleNode::from(*sibling);
+        let level = Level::from(l as u8);
+        node = if pos & (1 << l) == 0 {
+            MerkleNode::combine(level, &node, &sibling)
+        } else {
+            MerkleNode::combine(level, &sibling, &node)
+        };
+    }
+
+    node.inner()
+}
+
+/// Evaluate the given circuit natively with the given witnesses and public
+/// inputs. Returns an error if the witnesses don't match the circuit, or if
+/// the circuit can't be executed by the VM. Unsatisfied constraints are not
+/// errors, and are instead reported in [`NativeEvaluation::failures`].
+///
+/// Note that the values computed by opcodes after the first failure are
+/// not necessarily the ones the VM assigns, as they are unconstrained.
+pub fn evaluate(
+    zkbin: &ZkBinary,
+    witnesses: &[Witness],
+    instances: &[pallas::Base],
+) -> Result<NativeEvaluation> {
+    zkas_type_checks(&ZkCircuit::new(witnesses.to_vec(), zkbin), zkbin, instances)?;
+
+    let mut heap = vec![];
+
+    for (_, constant) in &zkbin.constants {
+        let var = match constant.as_str() {
+            "VALUE_COMMIT_VALUE" => NativeVar::EcFixedPointShort(ValueCommitV.generator().into()),
+            "VALUE_COMMIT_RANDOM" => {
+                NativeVar::EcFixedPoint(OrchardFixedBasesFull::ValueCommitR.generator().into())
+            }
+            "NULLIFIER_K" => NativeVar::EcFixedPointBase(NullifierK.generator().into()),
+            x => {
+                error!(target: "zk::native", "Invalid constant name: {}", x);
+                return Err(Error::ZkasEvalError(format!("Invalid constant name: {}", x)))
+            }
+        };
+        heap.push(var);
+    }
+
+    let mut litheap = Vec::with_capacity(zkbin.literals.len());
+    for (typ, literal) in &zkbin.literals {
+        match (typ, literal.parse::<u64>()) {
+            (LitType::Uint64, Ok(v)) => litheap.push(v),
+            _ => {
+                error!(target: "zk::native", "Invalid literal: {:?}", literal);
+                return Err(Error::ZkasEvalError(format!("Invalid literal: {}", literal)))
+            }
+        }
+    }
+
+    for (i, witness) in witnesses.iter().enumerate() {
+        let var = match witness {
+            Witness::EcPoint(w) => NativeVar::EcPoint(known(w, i)?),
+            Witness::EcNiPoint(w) => {
+                let point = known(w, i)?;
+                if bool::from(point.is_identity()) {
+                    return Err(Error::ZkasEvalError(format!("Witness {} is the identity", i)))
+                }
+                NativeVar::EcNiPoint(point)
+            }
+            Witness::EcFixedPoint(_) => {
+                return Err(Error::ZkasEvalError("Unable to witness EcFixedPoint".to_string()))
+            }
+            Witness::Base(w) => NativeVar::Base(known(w, i)?),
+            Witness::Scalar(w) => NativeVar::Scalar(known(w, i)?),
+            Witness::MerklePath(w) => NativeVar::MerklePath(known(w, i)?.map(|n| n.inner())),
+            Witness::Uint32(w) => NativeVar::Uint32(known(w, i)?),
+            Witness::Uint64(w) => NativeVar::Uint64(known(w, i)?),
+        };
+        heap.push(var);
+    }
+
+    let mut opvalues = Vec::with_capacity(zkbin.opcodes.len());
+    let mut failures = vec![];
+    let mut literals_offset = 0;
+    let mut public_inputs_offset = 0;
+
+    for (i, (opcode, args)) in zkbin.opcodes.iter().enumerate() {
+        let idx = |n: usize| -> Result<usize> {
+            match args.get(n) {
+                Some((_, v)) => Ok(*v),
+                None => {
+                    Err(Error::ZkasEvalError(format!("Missing argument {} of opcode {}", n, i)))
+                }
+            }
+        };
+
+        let mut fail = |cond: bool| {
+            if !cond {
+                failures.push(i);
+            }
+        };
+
+        let ret = match opcode {
+            Opcode::EcAdd => {
+                let lhs = heap_get!(heap, idx(0)?, EcPoint);
+                let rhs = heap_get!(heap, idx(1)?, EcPoint);
+                Some(NativeVar::EcPoint(lhs + rhs))
+            }
+
+            Opcode::EcMul => {
+                let scalar = heap_get!(heap, idx(0)?, Scalar);
+                let base = heap_get!(heap, idx(1)?, EcFixedPoint);
+                Some(NativeVar::EcPoint(base * scalar))
+            }
+
+            Opcode::EcMulVarBase => {
+                let scalar = heap_get!(heap, idx(0)?, Base);
+                let base = heap_get!(heap, idx(1)?, EcNiPoint);
+                Some(NativeVar::EcPoint(base * mod_r_p(scalar)))
+            }
+
+            Opcode::EcMulBase => {
+                let scalar = heap_get!(heap, idx(0)?, Base);
+                let base = heap_get!(heap, idx(1)?, EcFixedPointBase);
+                Some(NativeVar::EcPoint(base * mod_r_p(scalar)))
+            }
+
+            Opcode::EcMulShort => {
+                // The magnitude is decomposed into 64 bits, with a positive sign.
+                let magnitude = heap_get!(heap, idx(0)?, Base);
+                let base = heap_get!(heap, idx(1)?, EcFixedPointShort);
+                fail(fits_bits(&magnitude, 64));
+                Some(NativeVar::EcPoint(base * mod_r_p(magnitude)))
+            }
+
+            Opcode::EcGetX => {
+                let point = heap_get!(heap, idx(0)?, EcPoint);
+                Some(NativeVar::Base(coordinates(&point).0))
+            }
+
+            Opcode::EcGetY => {
+                let point = heap_get!(heap, idx(0)?, EcPoint);
+                Some(NativeVar::Base(coordinates(&point).1))
+            }
+
+            Opcode::PoseidonHash => {
+                let mut message = Vec::with_capacity(args.len());
+                for (_, arg) in args {
+                    message.push(heap_get!(heap, *arg, Base));
+                }
+
+                macro_rules! vla {
+                    ($($num:tt)*) => {
+                        match message.len() {
+                            $($num => poseidon_hash::<$num>(message.try_into().unwrap()),)*
+                            x => {
+                                error!(target: "zk::native", "Unsupported poseidon hash for {} elements", x);
+                                return Err(Error::ZkasEvalError(format!(
+                                    "Unsupported poseidon hash for {} elements", x
+                                )))
+                            }
+                        }
+                    };
+                }
+
+                Some(NativeVar::Base(vla!(1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16)))
+            }
+
+            Opcode::MerkleRoot => {
+                let pos = heap_get!(heap, idx(0)?, Uint32);
+                let path = heap_get!(heap, idx(1)?, MerklePath);
+                let leaf = heap_get!(heap, idx(2)?, Base);
+                Some(NativeVar::Base(merkle_root(pos, &path, leaf)))
+            }
+
+            Opcode::BaseAdd => {
+                let lhs = heap_get!(heap, idx(0)?, Base);
+                let rhs = heap_get!(heap, idx(1)?, Base);
+                Some(NativeVar::Base(lhs + rhs))
+            }
+
+            Opcode::BaseMul => {
+                let lhs = heap_get!(heap, idx(0)?, Base);
+                let rhs = heap_get!(heap, idx(1)?, Base);
+                Some(NativeVar::Base(lhs * rhs))
+            }
+
+            Opcode::BaseSub => {
+                let lhs = heap_get!(heap, idx(0)?, Base);
+                let rhs = heap_get!(heap, idx(1)?, Base);
+                Some(NativeVar::Base(lhs - rhs))
+            }
+
+            Opcode::WitnessBase => {
+                let Some(lit) = litheap.get(literals_offset) else {
+                    return Err(Error::ZkasEvalError(format!("Missing literal for opcode {}", i)))
+                };
+                literals_offset += 1;
+                Some(NativeVar::Base(pallas::Base::from(*lit)))
+            }
+
+            Opcode::RangeCheck => {
+                let Some(lit) = litheap.get(literals_offset) else {
+                    return Err(Error::ZkasEvalError(format!("Missing literal for opcode {}", i)))
+                };
+                literals_offset += 1;
+
+                let value = heap_get!(heap, idx(1)?, Base);
+                match *lit {
+                    64 | 253 => fail(fits_bits(&value, *lit as usize)),
+                    x => {
+                        error!(target: "zk::native", "Unsupported bit-range {} for range_check", x);
+                        return Err(Error::ZkasEvalError(format!(
+                            "Unsupported bit-range {} for range_check",
+                            x
+                        )))
+                    }
+                }
+                None
+            }
+
+            Opcode::LessThanStrict | Opcode::LessThanLoose => {
+                // Both `a` and `a + 2^253 - b` (minus one if loose) are
+                // range checked to 253 bits.
+                let a = heap_get!(heap, idx(0)?, Base);
+                let b = heap_get!(heap, idx(1)?, Base);
+                let two_pow_m = pallas::Base::from(2).pow([253, 0, 0, 0]);
+                let mut a_offset = a + (two_pow_m - b);
+                if *opcode == Opcode::LessThanLoose {
+                    a_offset -= pallas::Base::ONE;
+                }
+                fail(fits_bits(&a, 253) && fits_bits(&a_offset, 253));
+                None
+            }
+
+            Opcode::BoolCheck => {
+                let w = heap_get!(heap, idx(0)?, Base);
+                fail(w == pallas::Base::ZERO || w == pallas::Base::ONE);
+                None
+            }
+
+            Opcode::CondSelect => {
+                let cond = heap_get!(heap, idx(0)?, Base);
+                let lhs = heap_get!(heap, idx(1)?, Base);
+                let rhs = heap_get!(heap, idx(2)?, Base);
+                fail(cond == pallas::Base::ZERO || cond == pallas::Base::ONE);
+                Some(NativeVar::Base(if cond == pallas::Base::ONE { lhs } else { rhs }))
+            }
+
+            Opcode::ZeroCondSelect => {
+                let lhs = heap_get!(heap, idx(0)?, Base);
+                let rhs = heap_get!(heap, idx(1)?, Base);
+                Some(NativeVar::Base(if lhs == pallas::Base::ZERO { lhs } else { rhs }))
+            }
+
+            Opcode::ConstrainEqualBase => {
+                let lhs = heap_get!(heap, idx(0)?, Base);
+                let rhs = heap_get!(heap, idx(1)?, Base);
+                fail(lhs == rhs);
+                None
+            }
+
+            Opcode::ConstrainEqualPoint => {
+                let lhs = heap_get!(heap, idx(0)?, EcPoint);
+                let rhs = heap_get!(heap, idx(1)?, EcPoint);
+                fail(lhs == rhs);
+                None
+            }
+
+            Opcode::ConstrainInstance => {
+                let var = heap_get!(heap, idx(0)?, Base);
+                fail(instances.get(public_inputs_offset) == Some(&var));
+                public_inputs_offset += 1;
+                None
+            }
+
+            Opcode::ConstrainEncryptedInstance => {
+                let key = heap_get!(heap, idx(0)?, Base);
+                let nonce = heap_get!(heap, idx(1)?, Base);
+                let mut plaintext = Vec::with_capacity(args.len());
+                for i in 2..args.len() {
+                    plaintext.push(heap_get!(heap, idx(i)?, Base));
+                }
+
+                for c in poseidon_encrypt(key, nonce, &plaintext) {
+                    fail(instances.get(public_inputs_offset) == Some(&c));
+                    public_inputs_offset += 1;
+                }
+                None
+            }
+
+            Opcode::DebugPrint => None,
+
+            Opcode::Noop => {
+                error!(target: "zk::native", "Unsupported opcode");
+                return Err(Error::ZkasEvalError("Unsupported opcode".to_string()))
+            }
+        };
+
+        let opvalue = match &ret {
+            Some(NativeVar::EcPoint(p)) => {
+                let (x, y) = coordinates(p);
+                DebugOpValue::EcPoint(x, y)
+            }
+            Some(NativeVar::Base(v)) => DebugOpValue::Base(*v),
+            _ => DebugOpValue::Void,
+        };
+        opvalues.push(opvalue);
+
+        if let Some(var) = ret {
+            heap.push(var);
+        }
+    }
+
+    Ok(NativeEvaluation { opvalues, failures })
+}
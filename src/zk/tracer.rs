@@ -22,7 +22,7 @@ use darkfi_sdk::{crypto::constants::OrchardFixedBases, pasta::pallas};
 use halo2_gadgets::ecc as ecc_gadget;
 use halo2_proofs::{arithmetic::Field, circuit::AssignedCell};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum DebugOpValue {
     EcPoint(pallas::Base, pallas::Base),
     Base(pallas::Base),
@@ -59,6 +59,7 @@ use super::{
         cond_select::{ConditionalSelectChip, ConditionalSelectConfig},
         less_than::{LessThanChip, LessThanConfig},
         native_range_check::{NativeRangeCheckChip, NativeRangeCheckConfig},
+        poseidon_encrypt::poseidon_encrypt,
         small_range_check::{SmallRangeCheckChip, SmallRangeCheckConfig},
         zero_cond::{ZeroCondChip, ZeroCondConfig},
     },
@@ -331,7 +332,8 @@ impl Circuit<pallas::Base> for ZkCircuit {
             });
 
         // Conditions on which we enable the Poseidon hash chip
-        let init_poseidon = opcodes.contains(&Opcode::PoseidonHash);
+        let init_poseidon = opcodes.contains(&Opcode::PoseidonHash) ||
+            opcodes.contains(&Opcode::ConstrainEncryptedInstance);
 
         // Conditions on which we enable the Sinsemilla and Merkle chips
         let init_sinsemilla = opcodes.contains(&Opcode::MerkleRoot);
@@ -339,7 +341,8 @@ impl Circuit<pallas::Base> for ZkCircuit {
         // Conditions on which we enable the base field Arithmetic chip
         let init_arithmetic = opcodes.contains(&Opcode::BaseAdd) ||
             opcodes.contains(&Opcode::BaseSub) ||
-            opcodes.contains(&Opcode::BaseMul);
+            opcodes.contains(&Opcode::BaseMul) ||
+            opcodes.contains(&Opcode::ConstrainEncryptedInstance);
 
         // Conditions on which we enable the native range check chips
         // TODO: Separate 253 and 64.
@@ -1194,6 +1197,42 @@ impl Circuit<pallas::Base> for ZkCircuit {
                     self.tracer.push_void();
                 }
 
+                Opcode::ConstrainEncryptedInstance => {
+                    trace!(
+                        target: "zk::vm",
+                        "Executing `ConstrainEncryptedInstance{:?}` opcode",
+                        opcode.1,
+                    );
+                    let args = &opcode.1;
+
+                    let mut vars: Vec<AssignedCell<Fp, Fp>> = vec![];
+                    for idx in args {
+                        vars.push(heap[idx.1].clone().try_into()?);
+                    }
+
+                    let ciphertext = poseidon_encrypt(
+                        &config.poseidon_chip().unwrap(),
+                        arith_chip.as_ref().unwrap(),
+                        config.witness,
+                        layouter.namespace(|| "poseidon encrypt"),
+                        vars[0].clone(),
+                        vars[1].clone(),
+                        &vars[2..],
+                    )?;
+
+                    for c in ciphertext {
+                        layouter.constrain_instance(
+                            c.cell(),
+                            config.primary,
+                            public_inputs_offset,
+                        )?;
+
+                        public_inputs_offset += 1;
+                    }
+
+                    self.tracer.push_void();
+                }
+
                 Opcode::DebugPrint => {
                     trace!(target: "zk::vm", "Executing `DebugPrint{:?}` opcode", opcode.1);
                     let args = &opcode.1;
@@ -17,6 +17,7 @@
  */
 
 use std::{
+    collections::{HashMap, HashSet},
     io::{stdin, stdout, Read, Result, Write},
     str::Chars,
 };
@@ -92,7 +93,6 @@ impl Analyzer {
             }
 
             // Edge-cases for some opcodes
-            #[allow(clippy::single_match)]
             match &statement.opcode {
                 Opcode::RangeCheck => {
                     if let Arg::Lit(arg0) = &statement.rhs[0] {
@@ -112,6 +112,16 @@ impl Analyzer {
                     }
                 }
 
+                Opcode::ConstrainEncryptedInstance => {
+                    if statement.rhs.len() < 3 {
+                        return Err(self.error.abort(
+                            "Expected a key, a nonce, and at least one element to encrypt.",
+                            statement.line,
+                            1,
+                        ))
+                    }
+                }
+
                 _ => {}
             }
 
@@ -456,6 +466,85 @@ impl Analyzer {
         None
     }
 
+    /// Find constants, witnesses, and heap variables which are never used
+    /// as an argument by any statement. They still cost rows and gas, but
+    /// have no effect on the circuit. Must be called after `analyze_types()`.
+    pub fn find_dead_vars(&self) -> Vec<Var> {
+        let mut used = HashSet::new();
+        for stmt in &self.statements {
+            for arg in &stmt.rhs {
+                if let Arg::Var(v) = arg {
+                    used.insert(v.name.as_str());
+                }
+            }
+        }
+
+        let mut dead = vec![];
+
+        for c in &self.constants {
+            if !used.contains(c.name.as_str()) {
+                dead.push(Var::Constant(c.clone()));
+            }
+        }
+
+        for w in &self.witnesses {
+            if !used.contains(w.name.as_str()) {
+                dead.push(Var::Witness(w.clone()));
+            }
+        }
+
+        for stmt in &self.statements {
+            if let Some(lhs) = &stmt.lhs {
+                if !used.contains(lhs.name.as_str()) {
+                    dead.push(Var::Variable(lhs.clone()));
+                }
+            }
+        }
+
+        dead
+    }
+
+    /// Find assignments computing the same opcode over the same arguments
+    /// as an earlier assignment, e.g. the same `poseidon_hash` computed
+    /// twice. Variables assigned by duplicates are treated as aliases of
+    /// the original, so chains of duplicated computations are found as well.
+    /// Returns pairs of `(original, duplicate)` statements. Must be called
+    /// after `analyze_types()`.
+    pub fn find_duplicates(&self) -> Vec<(Statement, Statement)> {
+        // Maps variables assigned by duplicates to the original variable
+        let mut aliases: HashMap<&str, &str> = HashMap::new();
+        let mut seen: HashMap<(Opcode, Vec<String>), &Statement> = HashMap::new();
+        let mut duplicates = vec![];
+
+        for stmt in &self.statements {
+            let Some(lhs) = &stmt.lhs else { continue };
+
+            let args: Vec<String> = stmt
+                .rhs
+                .iter()
+                .map(|arg| match arg {
+                    Arg::Var(v) => {
+                        aliases.get(v.name.as_str()).unwrap_or(&v.name.as_str()).to_string()
+                    }
+                    Arg::Lit(l) => format!("#{}", l.name),
+                    Arg::Func(_) => unreachable!(),
+                })
+                .collect();
+
+            match seen.get(&(stmt.opcode, args.clone())) {
+                Some(original) => {
+                    aliases.insert(&lhs.name, &original.lhs.as_ref().unwrap().name);
+                    duplicates.push(((*original).clone(), stmt.clone()));
+                }
+                None => {
+                    seen.insert((stmt.opcode, args), stmt);
+                }
+            }
+        }
+
+        duplicates
+    }
+
     pub fn analyze_semantic(&mut self) -> Result<()> {
         let mut heap = vec![];
 
@@ -93,6 +93,11 @@ pub enum Opcode {
     /// Constrain a Base field element to a circuit's public input
     ConstrainInstance = 0xf0,
 
+    /// Encrypt N Base field elements with a Poseidon duplex sponge given a
+    /// key and a nonce, and constrain the ciphertext to the circuit's next
+    /// N public inputs
+    ConstrainEncryptedInstance = 0xf1,
+
     /// Debug a variable's value in the ZK circuit table.
     DebugPrint = 0xff,
 }
@@ -122,6 +127,7 @@ impl Opcode {
             "constrain_equal_base" => Some(Self::ConstrainEqualBase),
             "constrain_equal_point" => Some(Self::ConstrainEqualPoint),
             "constrain_instance" => Some(Self::ConstrainInstance),
+            "constrain_encrypted_instance" => Some(Self::ConstrainEncryptedInstance),
             "debug" => Some(Self::DebugPrint),
             _ => None,
         }
@@ -151,6 +157,7 @@ impl Opcode {
             0xe0 => Some(Self::ConstrainEqualBase),
             0xe1 => Some(Self::ConstrainEqualPoint),
             0xf0 => Some(Self::ConstrainInstance),
+            0xf1 => Some(Self::ConstrainEncryptedInstance),
             0xff => Some(Self::DebugPrint),
             _ => None,
         }
@@ -181,6 +188,7 @@ impl Opcode {
             Self::ConstrainEqualBase => "constrain_equal_base",
             Self::ConstrainEqualPoint => "constrain_equal_point",
             Self::ConstrainInstance => "constrain_instance",
+            Self::ConstrainEncryptedInstance => "constrain_encrypted_instance",
             Self::DebugPrint => "debug",
         }
     }
@@ -245,6 +253,9 @@ impl Opcode {
 
             Opcode::ConstrainInstance => (vec![], vec![VarType::Base]),
 
+            // The key and the nonce, followed by the plaintext
+            Opcode::ConstrainEncryptedInstance => (vec![], vec![VarType::BaseArray]),
+
             Opcode::DebugPrint => (vec![], vec![VarType::Any]),
         }
     }
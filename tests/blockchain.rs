@@ -17,7 +17,9 @@
  */
 
 use darkfi::{
-    blockchain::{BlockInfo, Blockchain, BlockchainOverlay, Header},
+    blockchain::{
+        BlockInfo, Blockchain, BlockchainOverlay, ContractEvent, ContractMigration, Header,
+    },
     validator::{
         pid::slot_pid_output,
         pow::PoWModule,
@@ -27,6 +29,7 @@ use darkfi::{
 };
 use darkfi_sdk::{
     blockchain::{expected_reward, PidOutput, PreviousSlot, Slot, POS_START},
+    crypto::ContractId,
     pasta::{group::ff::Field, pallas},
 };
 
@@ -194,3 +197,167 @@ fn blockchain_add_pos_blocks() -> Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn contract_state_range() -> Result<()> {
+    let blockchain = Blockchain::new(&sled::Config::new().temporary(true).open()?)?;
+    let contract_id = ContractId::from(pallas::Base::from(42));
+
+    // Write some records to disk
+    let overlay = BlockchainOverlay::new(&blockchain)?;
+    let tree = overlay.lock().unwrap().contracts.init(&contract_id, "state")?;
+    {
+        let lock = overlay.lock().unwrap();
+        let mut db = lock.overlay.lock().unwrap();
+        for key in [b"a1", b"a2", b"a4", b"b1"] {
+            db.insert(&tree, key, b"disk")?;
+        }
+        db.apply()?;
+    }
+
+    // Modify them in a new overlay, without applying the changes
+    let overlay = BlockchainOverlay::new(&blockchain)?;
+    let lock = overlay.lock().unwrap();
+    lock.contracts.lookup(&contract_id, "state")?;
+    {
+        let mut db = lock.overlay.lock().unwrap();
+        db.insert(&tree, b"a3", b"overlay")?;
+        db.insert(&tree, b"a4", b"overlay")?;
+        db.remove(&tree, b"a2")?;
+    }
+
+    let records = lock.contracts.range(&tree, b"a", Some(b"b"), 10)?;
+    let expected: Vec<(Vec<u8>, Vec<u8>)> = vec![
+        (b"a1".to_vec(), b"disk".to_vec()),
+        (b"a3".to_vec(), b"overlay".to_vec()),
+        (b"a4".to_vec(), b"overlay".to_vec()),
+    ];
+    assert_eq!(records, expected);
+
+    // Paging
+    let records = lock.contracts.range(&tree, b"a", Some(b"b"), 2)?;
+    assert_eq!(records, expected[..2]);
+    let records = lock.contracts.range(&tree, b"a30", Some(b"b"), 2)?;
+    assert_eq!(records, expected[2..]);
+
+    // Unbounded
+    let records = lock.contracts.range(&tree, b"a4", None, 10)?;
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[1], (b"b1".to_vec(), b"disk".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn contract_events() -> Result<()> {
+    let blockchain = Blockchain::new(&sled::Config::new().temporary(true).open()?)?;
+    let contract_a = ContractId::from(pallas::Base::from(42));
+    let contract_b = ContractId::from(pallas::Base::from(43));
+    let (topic_1, topic_2) = ([1u8; 32], [2u8; 32]);
+
+    let event =
+        |contract_id, topic, data: &[u8]| ContractEvent { contract_id, topic, data: data.to_vec() };
+
+    let tx_1 = blake3::hash(b"tx_1");
+    let tx_2 = blake3::hash(b"tx_2");
+    let tx_3 = blake3::hash(b"tx_3");
+    let tx_1_events = vec![event(contract_a, topic_2, b"a2"), event(contract_b, topic_1, b"b1")];
+    let tx_2_events = vec![event(contract_a, topic_1, b"a1")];
+
+    // Store the events through an overlay
+    let overlay = BlockchainOverlay::new(&blockchain)?;
+    {
+        let lock = overlay.lock().unwrap();
+        lock.events.insert(1, &tx_1, &tx_1_events)?;
+        lock.events.insert(2, &tx_2, &tx_2_events)?;
+        lock.events.insert(3, &tx_3, &[])?;
+        assert_eq!(lock.events.get(&[tx_2], true)?[0], Some(tx_2_events.clone()));
+        lock.overlay.lock().unwrap().apply()?;
+    }
+
+    // Transactions without events are not stored
+    assert_eq!(blockchain.events.len(), 2);
+    assert!(blockchain.events.get(&[tx_3], true).is_err());
+    assert_eq!(blockchain.events.get(&[tx_1], true)?[0], Some(tx_1_events));
+
+    // Lookups by contract and topic
+    let records = blockchain.events.get_by_topic(&contract_a, None, 0, u64::MAX, 10)?;
+    assert_eq!(records, vec![(1, tx_1), (2, tx_2)]);
+    let records = blockchain.events.get_by_topic(&contract_a, Some(&topic_1), 0, u64::MAX, 10)?;
+    assert_eq!(records, vec![(2, tx_2)]);
+    let records = blockchain.events.get_by_topic(&contract_a, None, 2, u64::MAX, 10)?;
+    assert_eq!(records, vec![(2, tx_2)]);
+    let records = blockchain.events.get_by_topic(&contract_a, None, 0, u64::MAX, 1)?;
+    assert_eq!(records, vec![(1, tx_1)]);
+    let records = blockchain.events.get_by_topic(&contract_b, Some(&topic_2), 0, u64::MAX, 10)?;
+    assert!(records.is_empty());
+
+    // Height ranges are inclusive
+    let records = blockchain.events.get_by_topic(&contract_a, None, 0, 1, 10)?;
+    assert_eq!(records, vec![(1, tx_1)]);
+    let records = blockchain.events.get_by_topic(&contract_a, Some(&topic_2), 2, 2, 10)?;
+    assert!(records.is_empty());
+    let records = blockchain.events.get_by_topic(&contract_b, Some(&topic_1), 1, 1, 10)?;
+    assert_eq!(records, vec![(1, tx_1)]);
+
+    Ok(())
+}
+
+#[test]
+fn contract_migrations() -> Result<()> {
+    let blockchain = Blockchain::new(&sled::Config::new().temporary(true).open()?)?;
+    let contract_id = ContractId::from(pallas::Base::from(42));
+
+    let migration = |previous: &[u8], current: &[u8], height| ContractMigration {
+        previous_bincode: *blake3::hash(previous).as_bytes(),
+        bincode: *blake3::hash(current).as_bytes(),
+        height,
+    };
+    let v1_v2 = migration(b"v1", b"v2", 5);
+    let v2_v3 = migration(b"v2", b"v3", 9);
+
+    // Record the migrations through an overlay
+    let overlay = BlockchainOverlay::new(&blockchain)?;
+    {
+        let lock = overlay.lock().unwrap();
+        assert!(lock.migrations.get(&contract_id)?.is_empty());
+        lock.migrations.append(&contract_id, v1_v2.clone())?;
+        lock.migrations.append(&contract_id, v2_v3.clone())?;
+        lock.overlay.lock().unwrap().apply()?;
+    }
+
+    // Migrations are kept in order
+    assert_eq!(blockchain.migrations.get(&contract_id)?, vec![v1_v2, v2_v3]);
+    let other = ContractId::from(pallas::Base::from(43));
+    assert!(blockchain.migrations.get(&other)?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn contract_state_sizes() -> Result<()> {
+    let blockchain = Blockchain::new(&sled::Config::new().temporary(true).open()?)?;
+    let contract_id = ContractId::from(pallas::Base::from(42));
+
+    // Track the state size through an overlay
+    let overlay = BlockchainOverlay::new(&blockchain)?;
+    {
+        let lock = overlay.lock().unwrap();
+        assert_eq!(lock.state_sizes.get(&contract_id)?, 0);
+        assert_eq!(lock.state_sizes.update(&contract_id, 100)?, 100);
+        assert_eq!(lock.state_sizes.update(&contract_id, -30)?, 70);
+        // Freeing more bytes than tracked never underflows
+        assert_eq!(lock.state_sizes.update(&contract_id, -1000)?, 0);
+        assert_eq!(lock.state_sizes.update(&contract_id, 42)?, 42);
+
+        // Nothing is written until the overlay is applied
+        assert_eq!(blockchain.state_sizes.get(&contract_id)?, 0);
+        lock.overlay.lock().unwrap().apply()?;
+    }
+
+    assert_eq!(blockchain.state_sizes.get(&contract_id)?, 42);
+    let other = ContractId::from(pallas::Base::from(43));
+    assert_eq!(blockchain.state_sizes.get(&other)?, 0);
+
+    Ok(())
+}
@@ -0,0 +1,230 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! End-to-end tests of the light client, syncing and verifying headers,
+//! block transactions and contracts state proofs served by a full node.
+
+use darkfi::{
+    blockchain::{BlockInfo, Blockchain, BlockchainOverlay, Header, LightClient},
+    tx::Transaction,
+    Error, Result,
+};
+use darkfi_sdk::{
+    crypto::ContractId,
+    num_bigint::BigUint,
+    pasta::{group::ff::Field, pallas},
+};
+
+const POW_TARGET: usize = 10;
+
+/// Generate the next PoW block, extending provided one.
+fn next_block(previous: &BlockInfo, txs: Vec<Transaction>) -> Result<BlockInfo> {
+    let mut timestamp = previous.header.timestamp;
+    timestamp.add(1);
+    let header = Header::new(
+        previous.hash()?,
+        previous.header.epoch,
+        previous.header.height + 1,
+        timestamp,
+        pallas::Base::ZERO,
+    );
+
+    let mut block = BlockInfo::new_empty(header, vec![]);
+    block.append_txs(txs)?;
+    Ok(block)
+}
+
+#[test]
+fn light_client() -> Result<()> {
+    let difficulty = || Some(BigUint::from(1u64));
+
+    // Full node, holding a contract state tree with a few records
+    let full = Blockchain::new(&sled::Config::new().temporary(true).open()?)?;
+    let genesis = BlockInfo::default();
+    full.add_block(&genesis)?;
+
+    let overlay = BlockchainOverlay::new(&full)?;
+    let contract_id = ContractId::from(pallas::Base::from(42));
+    let ptr = overlay.lock().unwrap().contracts.init(&contract_id, "records")?;
+    for key in [1u8, 3, 5] {
+        overlay.lock().unwrap().overlay.lock().unwrap().insert(&ptr, &[key], &[key, key])?;
+    }
+
+    // Only the modified state tree gets recomputed
+    let (state_root, changed) = overlay.lock().unwrap().contracts.update_state()?;
+    assert_eq!(changed, vec![ptr]);
+    assert_eq!(overlay.lock().unwrap().contracts.update_state()?, (state_root, vec![]));
+
+    // Generate a few blocks, the last one committing to the state
+    let mut blocks = vec![];
+    let mut previous = genesis.clone();
+    for i in 0..3 {
+        let mut block = next_block(&previous, vec![Transaction::default(); i + 1])?;
+        if i == 2 {
+            block.header.state_root = state_root;
+        }
+        overlay.lock().unwrap().add_block(&block)?;
+        blocks.push(block.clone());
+        previous = block;
+    }
+    overlay.lock().unwrap().overlay.lock().unwrap().apply()?;
+    let headers: Vec<_> = blocks.iter().map(|x| x.header.clone()).collect();
+
+    // The genesis header must match the network one
+    let db = sled::Config::new().temporary(true).open()?;
+    let wrong_genesis = blake3::hash(b"Never skip brain day.");
+    assert!(matches!(
+        LightClient::new(&db, &genesis.header, &wrong_genesis, POW_TARGET, difficulty()),
+        Err(Error::GenesisHeaderMismatch)
+    ));
+    let mut client =
+        LightClient::new(&db, &genesis.header, &genesis.hash()?, POW_TARGET, difficulty())?;
+
+    // Headers not extending our last one are rejected, without writing anything
+    assert!(client.add_headers(&headers[1..]).is_err());
+    assert_eq!(client.last_header()?, genesis.header);
+
+    // Sync the headers
+    client.add_headers(&headers[..1])?;
+    client.add_headers(&headers[1..])?;
+    assert_eq!(client.last_header()?, headers[2]);
+
+    // Blocks transactions are verified against their header
+    client.verify_block_txs(2, &blocks[1].txs)?;
+    assert!(matches!(client.verify_block_txs(2, &blocks[2].txs), Err(Error::BlockTxsMismatch(_))));
+
+    // State proofs are verified against the header of their height
+    let proof = |key: &[u8]| {
+        let overlay = BlockchainOverlay::new(&full)?;
+        let lock = overlay.lock().unwrap();
+        lock.contracts.state_proof(&contract_id, "records", key, 3)
+    };
+
+    // Membership
+    let membership = proof(&[3])?;
+    assert_eq!(client.verify_state(&contract_id, "records", &[3], &membership)?, Some(vec![3, 3]));
+
+    // Absence, before, between and after the records
+    for key in [0u8, 2, 4, 6] {
+        let absence = proof(&[key])?;
+        assert_eq!(client.verify_state(&contract_id, "records", &[key], &absence)?, None);
+    }
+
+    // Proofs against another header or tree, or tampered ones, are rejected
+    let mut moved = membership.clone();
+    moved.height = 2;
+    assert!(client.verify_state(&contract_id, "records", &[3], &moved).is_err());
+    assert!(client.verify_state(&contract_id, "other", &[3], &membership).is_err());
+    let mut tampered = membership.clone();
+    tampered.record.as_mut().unwrap().value = vec![4];
+    assert!(client.verify_state(&contract_id, "records", &[3], &tampered).is_err());
+
+    // Trees not committed to yet can't be proven
+    let overlay = BlockchainOverlay::new(&full)?;
+    overlay.lock().unwrap().contracts.init(&contract_id, "other")?;
+    let lock = overlay.lock().unwrap();
+    assert!(lock.contracts.state_proof(&contract_id, "other", &[3], 3).is_err());
+    drop(lock);
+
+    // Only the written records get updated, matching a tree built from scratch
+    // with the same trees history, as it defines their leaves indexes
+    overlay.lock().unwrap().overlay.lock().unwrap().insert(&ptr, &[2], &[2, 2])?;
+    overlay.lock().unwrap().overlay.lock().unwrap().remove(&ptr, &[5])?;
+    let (new_root, changed) = overlay.lock().unwrap().contracts.update_state()?;
+    assert_ne!(new_root, state_root);
+    assert_eq!(changed.len(), 2);
+    assert!(changed.contains(&ptr));
+    let lock = overlay.lock().unwrap();
+    let proof = lock.contracts.state_proof(&contract_id, "records", &[2], 3)?;
+    assert_eq!(proof.record.unwrap().value, vec![2, 2]);
+    drop(lock);
+
+    let scratch = Blockchain::new(&sled::Config::new().temporary(true).open()?)?;
+    let overlay = BlockchainOverlay::new(&scratch)?;
+    let lock = overlay.lock().unwrap();
+    let ptr = lock.contracts.init(&contract_id, "records")?;
+    for key in [3u8, 2, 1] {
+        lock.overlay.lock().unwrap().insert(&ptr, &[key], &[key, key])?;
+    }
+    lock.contracts.update_state()?;
+    lock.contracts.init(&contract_id, "other")?;
+    assert_eq!(lock.contracts.update_state()?.0, new_root);
+
+    Ok(())
+}
+
+#[test]
+fn light_client_reorg() -> Result<()> {
+    let genesis = BlockInfo::default();
+    let db = sled::Config::new().temporary(true).open()?;
+    let mut client = LightClient::new(
+        &db,
+        &genesis.header,
+        &genesis.hash()?,
+        POW_TARGET,
+        Some(BigUint::from(1u64)),
+    )?;
+
+    // Generate a chain of given length extending provided block, its
+    // blocks containing given number of transactions, so forks differ
+    let chain = |from: &BlockInfo, length: usize, txs: usize| -> Result<Vec<BlockInfo>> {
+        let mut blocks = vec![];
+        let mut previous = from.clone();
+        for _ in 0..length {
+            let block = next_block(&previous, vec![Transaction::default(); txs])?;
+            blocks.push(block.clone());
+            previous = block;
+        }
+        Ok(blocks)
+    };
+    let headers =
+        |blocks: &[BlockInfo]| -> Vec<Header> { blocks.iter().map(|x| x.header.clone()).collect() };
+
+    // Sync our chain
+    let ours = chain(&genesis, 3, 1)?;
+    client.add_headers(&headers(&ours))?;
+    assert_eq!(client.last_header()?, ours[2].header);
+    let difficulty = client.cummulative_difficulty().clone();
+
+    // Headers not extending any stored header are rejected
+    let orphans = chain(&ours[2], 2, 1)?;
+    assert!(matches!(client.add_headers(&headers(&orphans[1..])), Err(Error::HeadersNotExtending)));
+
+    // A fork not heavier than our chain is rejected, without writing anything
+    let lighter = chain(&genesis, 3, 2)?;
+    assert!(matches!(client.add_headers(&headers(&lighter)), Err(Error::HeadersForkNotHeavier)));
+    assert_eq!(client.last_header()?, ours[2].header);
+    assert_eq!(client.header(1)?, ours[0].header);
+    assert_eq!(client.cummulative_difficulty(), &difficulty);
+
+    // A heavier fork replaces our headers after the fork point
+    let heavier = chain(&ours[0], 3, 2)?;
+    client.add_headers(&headers(&heavier))?;
+    assert_eq!(client.header(1)?, ours[0].header);
+    assert_eq!(client.header(2)?, heavier[0].header);
+    assert_eq!(client.last_header()?, heavier[2].header);
+    assert!(client.cummulative_difficulty() > &difficulty);
+
+    // Our previous chain can no longer be extended, while the new one can
+    let stale = chain(&ours[2], 1, 1)?;
+    assert!(matches!(client.add_headers(&headers(&stale)), Err(Error::HeadersNotExtending)));
+    client.add_headers(&headers(&chain(&heavier[2], 1, 1)?))?;
+    assert_eq!(client.last_header()?.height, 5);
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi::{
+    blockchain::{Blockchain, BlockchainOverlay},
+    runtime::native::NativeRuntime,
+    util::time::{TimeKeeper, Timestamp},
+    Error, Result,
+};
+use darkfi_sdk::{
+    crypto::ContractId,
+    db::{db_get, db_init, db_lookup, db_set},
+    define_contract,
+    error::{ContractError, ContractResult},
+    pasta::pallas,
+    util::{get_verifying_slot, set_return_data},
+};
+
+// A counter contract, linked natively into the test binary
+define_contract!(
+    init: init_contract,
+    exec: process_instruction,
+    apply: process_update,
+    metadata: get_metadata
+);
+
+const COUNTER_TREE: &str = "counter";
+const COUNTER_KEY: &[u8] = b"value";
+
+fn init_contract(cid: ContractId, _ix: &[u8]) -> ContractResult {
+    if db_lookup(cid, COUNTER_TREE).is_err() {
+        db_init(cid, COUNTER_TREE)?;
+    }
+
+    Ok(())
+}
+
+fn get_metadata(_cid: ContractId, _ix: &[u8]) -> ContractResult {
+    set_return_data(&get_verifying_slot().to_le_bytes())
+}
+
+fn process_instruction(cid: ContractId, ix: &[u8]) -> ContractResult {
+    let increment = ix.first().ok_or(ContractError::Custom(1))?;
+    if *increment == u8::MAX {
+        panic!("Increment too large")
+    }
+
+    let db = db_lookup(cid, COUNTER_TREE)?;
+    let current = db_get(db, COUNTER_KEY)?.map_or(0, |v| v[0]);
+    set_return_data(&[current + increment])
+}
+
+fn process_update(cid: ContractId, update: &[u8]) -> ContractResult {
+    let db = db_lookup(cid, COUNTER_TREE)?;
+    db_set(db, COUNTER_KEY, update)
+}
+
+#[test]
+fn native_contract_execution() -> Result<()> {
+    let blockchain = Blockchain::new(&sled::Config::new().temporary(true).open()?)?;
+    let overlay = BlockchainOverlay::new(&blockchain)?;
+    let contract_id = ContractId::from(pallas::Base::from(42));
+    let time_keeper = TimeKeeper::new(Timestamp::current_time(), 10, 90, 7);
+
+    let mut runtime =
+        NativeRuntime::new(NATIVE_CONTRACT, overlay.clone(), contract_id, time_keeper.clone())?;
+    runtime.deploy(&[])?;
+
+    // Host functions see the same environment as WASM contracts
+    assert_eq!(runtime.metadata(&[])?, 7_u64.to_le_bytes());
+
+    let update = runtime.exec(&[5])?;
+    assert_eq!(update, vec![5]);
+    runtime.apply(&update)?;
+
+    // State written by the contract lives in the overlay
+    let mut runtime =
+        NativeRuntime::new(NATIVE_CONTRACT, overlay.clone(), contract_id, time_keeper)?;
+    assert_eq!(runtime.exec(&[3])?, vec![8]);
+    let size = overlay.lock().unwrap().state_sizes.get(&contract_id)?;
+    assert_eq!(size, (COUNTER_KEY.len() + 1) as u64);
+
+    // Contract errors are returned like in WASM
+    assert!(matches!(runtime.exec(&[]), Err(Error::ContractError(ContractError::Custom(1)))));
+
+    // Contract panics are caught at the section boundary, and the
+    // runtime stays usable afterwards
+    assert!(matches!(
+        runtime.exec(&[u8::MAX]),
+        Err(Error::ContractPanic(msg)) if msg == "Increment too large"
+    ));
+    assert_eq!(runtime.exec(&[3])?, vec![8]);
+
+    // Missing sections can't be called
+    assert!(runtime.view(&[]).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,80 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Tests of canonical state snapshots export and import.
+
+use std::fs::OpenOptions;
+
+use darkfi::{
+    blockchain::{export_snapshot, import_snapshot, verify_snapshot, BlockInfo, Blockchain},
+    tx::Transaction,
+    Error, Result,
+};
+
+#[test]
+fn snapshot() -> Result<()> {
+    // Create a temporary blockchain containing a block and a pending transaction
+    let blockchain = Blockchain::new(&sled::Config::new().temporary(true).open()?)?;
+    let block = BlockInfo::default();
+    blockchain.add_block(&block)?;
+    blockchain.add_pending_txs(&[Transaction::default()])?;
+    blockchain.sled_db.open_tree(b"contract_state")?.insert(b"key", b"value")?;
+    let genesis = block.hash()?;
+
+    // Export its state into a snapshot file
+    let path = std::env::temp_dir().join(format!("darkfi_snapshot_test_{}", std::process::id()));
+    let header = export_snapshot(&blockchain, &path)?;
+    assert_eq!(header.height, block.header.height);
+    assert_eq!(header.block, genesis);
+    assert_eq!(verify_snapshot(&path)?, header);
+
+    // Importing using a wrong trusted hash or another network's genesis
+    // block fails, without writing anything
+    let imported = Blockchain::new(&sled::Config::new().temporary(true).open()?)?;
+    let wrong_hash = blake3::hash(b"Never skip brain day.");
+    assert!(import_snapshot(&imported, &path, &wrong_hash, &genesis).is_err());
+    assert!(matches!(
+        import_snapshot(&imported, &path, &header.state_hash, &wrong_hash),
+        Err(Error::SnapshotInvalid(_))
+    ));
+    assert!(imported.is_empty());
+
+    // Import it into an empty blockchain
+    assert_eq!(import_snapshot(&imported, &path, &header.state_hash, &genesis)?, header);
+    assert_eq!(imported.last()?, blockchain.last()?);
+    assert_eq!(imported.len(), blockchain.len());
+    let value = imported.sled_db.open_tree(b"contract_state")?.get(b"key")?;
+    assert_eq!(value.as_deref(), Some(b"value".as_slice()));
+
+    // Pending transactions are not part of the snapshot
+    assert!(imported.get_pending_txs()?.is_empty());
+
+    // A non empty blockchain can't import snapshots
+    assert!(matches!(
+        import_snapshot(&imported, &path, &header.state_hash, &genesis),
+        Err(Error::SnapshotDatabaseNotEmpty)
+    ));
+
+    // A tampered snapshot fails verification
+    let mut file = OpenOptions::new().append(true).open(&path)?;
+    std::io::Write::write_all(&mut file, &[0])?;
+    assert!(verify_snapshot(&path).is_err());
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
@@ -0,0 +1,130 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Tests of the transaction validity window and its versioned encoding.
+
+use std::collections::HashMap;
+
+use darkfi::{
+    blockchain::{Blockchain, BlockchainOverlay},
+    error::TxVerifyFailed,
+    tx::{Transaction, TX_VERSION_LEGACY, TX_VERSION_VALIDITY_WINDOW},
+    util::time::{TimeKeeper, Timestamp},
+    validator::verification::verify_transaction,
+    Error, Result,
+};
+use darkfi_serial::{deserialize, deserialize_async, serialize, serialize_async};
+
+const VALID_FROM: u64 = 5;
+const VALID_UNTIL: u64 = 10;
+
+fn windowed_tx() -> Transaction {
+    Transaction {
+        valid_from: Some(VALID_FROM),
+        valid_until: Some(VALID_UNTIL),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn tx_version_encoding() -> Result<()> {
+    // Transactions without a window keep the legacy encoding
+    let legacy = Transaction::default();
+    assert_eq!(legacy.version(), TX_VERSION_LEGACY);
+    assert_eq!(serialize(&legacy), vec![0, 0, 0]);
+    assert_eq!(deserialize::<Transaction>(&serialize(&legacy))?, legacy);
+
+    // Transactions with a window are versioned, and roundtrip both ways
+    let tx = windowed_tx();
+    assert_eq!(tx.version(), TX_VERSION_VALIDITY_WINDOW);
+    let bytes = serialize(&tx);
+    assert_eq!(bytes[..2], [0, TX_VERSION_VALIDITY_WINDOW]);
+    assert_eq!(deserialize::<Transaction>(&bytes)?, tx);
+    smol::block_on(async {
+        assert_eq!(serialize_async(&tx).await, bytes);
+        assert_eq!(deserialize_async::<Transaction>(&bytes).await.unwrap(), tx);
+    });
+
+    let tx = Transaction { valid_until: Some(VALID_UNTIL), ..Default::default() };
+    assert_eq!(deserialize::<Transaction>(&serialize(&tx))?, tx);
+
+    // A versioned encoding without a window is not canonical
+    let bytes = [0, TX_VERSION_VALIDITY_WINDOW, 0, 0, 0, 0, 0];
+    assert!(deserialize::<Transaction>(&bytes).is_err());
+
+    // Unknown versions are rejected
+    let bytes = [0, TX_VERSION_VALIDITY_WINDOW + 1, 0, 0, 0, 0, 0];
+    assert!(deserialize::<Transaction>(&bytes).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn tx_validity_window() {
+    let tx = windowed_tx();
+
+    // Both bounds are inclusive
+    assert!(!tx.is_valid_at(VALID_FROM - 1));
+    assert!(tx.is_valid_at(VALID_FROM));
+    assert!(tx.is_valid_at(VALID_UNTIL));
+    assert!(!tx.is_valid_at(VALID_UNTIL + 1));
+
+    // The transaction expires once the last height it's valid at has passed
+    assert!(!tx.is_expired(VALID_FROM - 1));
+    assert!(!tx.is_expired(VALID_UNTIL));
+    assert!(tx.is_expired(VALID_UNTIL + 1));
+
+    // Unbounded transactions are always valid and never expire
+    let tx = Transaction::default();
+    assert!(tx.is_valid_at(0) && tx.is_valid_at(u64::MAX));
+    assert!(!tx.is_expired(u64::MAX));
+}
+
+#[test]
+fn tx_validity_window_verification() -> Result<()> {
+    let blockchain = Blockchain::new(&sled::Config::new().temporary(true).open()?)?;
+    let overlay = BlockchainOverlay::new(&blockchain)?;
+    let tx = windowed_tx();
+
+    smol::block_on(async {
+        for height in [0, VALID_FROM - 1, VALID_UNTIL + 1] {
+            let time_keeper = TimeKeeper::new(Timestamp::current_time(), 10, 90, height);
+            let result =
+                verify_transaction(&overlay, &time_keeper, &tx, &mut HashMap::new(), false).await;
+            assert!(matches!(
+                result,
+                Err(Error::TxVerifyFailed(TxVerifyFailed::OutsideValidityWindow(h))) if h == height
+            ));
+        }
+
+        // At the bounds, verification moves past the window check, and fails
+        // on the transaction not having any calls instead
+        for height in [VALID_FROM, VALID_UNTIL] {
+            let time_keeper = TimeKeeper::new(Timestamp::current_time(), 10, 90, height);
+            let result =
+                verify_transaction(&overlay, &time_keeper, &tx, &mut HashMap::new(), false).await;
+            assert!(!matches!(
+                result,
+                Err(Error::TxVerifyFailed(TxVerifyFailed::OutsideValidityWindow(_)))
+            ));
+            assert!(result.is_err());
+        }
+    });
+
+    Ok(())
+}
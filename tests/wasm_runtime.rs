@@ -0,0 +1,436 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Tests of the WASM runtime host functions, using small contracts
+//! written in the WebAssembly text format.
+
+use darkfi::{
+    blockchain::{Blockchain, BlockchainOverlay, BlockchainOverlayPtr},
+    runtime::{
+        gas_schedule::GAS_SCHEDULE_V2,
+        trace::{DbAccessKind, TraceStep},
+        vm_runtime::Runtime,
+    },
+    util::time::{TimeKeeper, Timestamp},
+    Error, Result,
+};
+use darkfi_sdk::{crypto::ContractId, error::ContractError, pasta::pallas};
+use darkfi_serial::serialize;
+
+/// Value the storage contract writes on deploy
+const STORED_VALUE: u64 = 42;
+
+/// Render bytes as a WAT string literal
+fn wat_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("\\{:02x}", b)).collect()
+}
+
+/// Host functions used by the test contracts
+const IMPORTS: &str = r#"
+    (import "env" "db_init_" (func $db_init (param i32 i32) (result i64)))
+    (import "env" "db_lookup_" (func $db_lookup (param i32 i32) (result i64)))
+    (import "env" "db_get_" (func $db_get (param i32 i32) (result i64)))
+    (import "env" "db_set_" (func $db_set (param i32 i32) (result i64)))
+    (import "env" "get_object_bytes_" (func $get_object_bytes (param i32 i32) (result i64)))
+    (import "env" "set_return_data_" (func $set_return_data (param i32 i32) (result i64)))
+    (import "env" "call_contract_view_" (func $call_contract_view (param i32 i32) (result i64)))
+"#;
+
+/// A contract storing a `u64` in its `state` tree on deploy, and
+/// returning it from its `__view` section. If the first byte of the
+/// view payload is `1`, it tries to overwrite the value instead. Its
+/// `__update` section stores the same value under a second key.
+///
+/// The payload of every section is at offset 40 of the memory, after the
+/// serialized contract ID and the payload length.
+fn storage_contract(cid: &ContractId) -> Vec<u8> {
+    format!(
+        r#"(module
+    {imports}
+    (memory (export "memory") 1)
+    ;; db_init/db_lookup arguments: contract ID and tree name
+    (data (i32.const 1024) "{cid}\05state")
+    ;; db_set arguments: db handle, key, and value
+    (data (i32.const 1100) "\00\00\00\00\01k\08{value}")
+    ;; db_get arguments: db handle and key
+    (data (i32.const 1200) "\00\00\00\00\01k")
+    ;; db_set arguments for the second key
+    (data (i32.const 1300) "\00\00\00\00\01l\08{value}")
+
+    (func (export "__initialize") (param i32) (result i64)
+        (local $db i64)
+        (local.set $db (call $db_init (i32.const 1024) (i32.const 38)))
+        (if (i64.lt_s (local.get $db) (i64.const 0)) (then (return (local.get $db))))
+        (i32.store (i32.const 1100) (i32.wrap_i64 (local.get $db)))
+        (call $db_set (i32.const 1100) (i32.const 15)))
+
+    (func (export "__update") (param i32) (result i64)
+        (local $db i64)
+        (local.set $db (call $db_lookup (i32.const 1024) (i32.const 38)))
+        (if (i64.lt_s (local.get $db) (i64.const 0)) (then (return (local.get $db))))
+        (i32.store (i32.const 1300) (i32.wrap_i64 (local.get $db)))
+        (call $db_set (i32.const 1300) (i32.const 15)))
+
+    (func (export "__view") (param i32) (result i64)
+        (local $db i64)
+        (local $obj i64)
+        (local.set $db (call $db_lookup (i32.const 1024) (i32.const 38)))
+        (if (i64.lt_s (local.get $db) (i64.const 0)) (then (return (local.get $db))))
+        (if (i32.eq (i32.load8_u (i32.const 40)) (i32.const 1)) (then
+            (i32.store (i32.const 1100) (i32.wrap_i64 (local.get $db)))
+            (return (call $db_set (i32.const 1100) (i32.const 15)))))
+        (i32.store (i32.const 1200) (i32.wrap_i64 (local.get $db)))
+        (local.set $obj (call $db_get (i32.const 1200) (i32.const 6)))
+        (if (i64.lt_s (local.get $obj) (i64.const 0)) (then (return (local.get $obj))))
+        (drop (call $get_object_bytes (i32.const 2048) (i32.wrap_i64 (local.get $obj))))
+        (call $set_return_data (i32.const 2048) (i32.const 8)))
+)"#,
+        imports = IMPORTS,
+        cid = wat_bytes(&serialize(cid)),
+        value = wat_bytes(&STORED_VALUE.to_le_bytes()),
+    )
+    .into_bytes()
+}
+
+/// A contract whose `__entrypoint` calls the `__view` section of `callee`,
+/// forwarding the first byte of its own payload, and returns the `u64`
+/// the callee returned.
+fn caller_contract(callee: &ContractId) -> Vec<u8> {
+    format!(
+        r#"(module
+    {imports}
+    (memory (export "memory") 1)
+    ;; call_contract_view arguments: callee contract ID and a 1-byte payload
+    (data (i32.const 1024) "{callee}\01\00")
+
+    (func (export "__entrypoint") (param i32) (result i64)
+        (local $obj i64)
+        (i32.store8 (i32.const 1057) (i32.load8_u (i32.const 40)))
+        (local.set $obj (call $call_contract_view (i32.const 1024) (i32.const 34)))
+        (if (i64.lt_s (local.get $obj) (i64.const 0)) (then (return (local.get $obj))))
+        (drop (call $get_object_bytes (i32.const 2048) (i32.wrap_i64 (local.get $obj))))
+        (call $set_return_data (i32.const 2048) (i32.const 8)))
+)"#,
+        imports = IMPORTS,
+        callee = wat_bytes(&serialize(callee)),
+    )
+    .into_bytes()
+}
+
+/// Value the upgraded storage contract writes on deploy
+const UPGRADED_VALUE: u64 = 7;
+
+/// An upgraded version of the storage contract. Its `__migrate` section
+/// copies the value stored by the previous version under the `old` key,
+/// and its `__initialize` section overwrites the stored value.
+fn upgraded_storage_contract(cid: &ContractId) -> Vec<u8> {
+    format!(
+        r#"(module
+    {imports}
+    (memory (export "memory") 1)
+    ;; db_init/db_lookup arguments: contract ID and tree name
+    (data (i32.const 1024) "{cid}\05state")
+    ;; db_set arguments: db handle, key, and value
+    (data (i32.const 1100) "\00\00\00\00\01k\08{value}")
+    ;; db_get arguments: db handle and key
+    (data (i32.const 1200) "\00\00\00\00\01k")
+    ;; db_set arguments: db handle, `old` key, and the value read by db_get
+    (data (i32.const 1300) "\00\00\00\00\03old\08")
+
+    (func (export "__initialize") (param i32) (result i64)
+        (local $db i64)
+        (local.set $db (call $db_lookup (i32.const 1024) (i32.const 38)))
+        (if (i64.lt_s (local.get $db) (i64.const 0)) (then
+            (local.set $db (call $db_init (i32.const 1024) (i32.const 38)))))
+        (if (i64.lt_s (local.get $db) (i64.const 0)) (then (return (local.get $db))))
+        (i32.store (i32.const 1100) (i32.wrap_i64 (local.get $db)))
+        (call $db_set (i32.const 1100) (i32.const 15)))
+
+    (func (export "__migrate") (param i32) (result i64)
+        (local $db i64)
+        (local $obj i64)
+        (local.set $db (call $db_lookup (i32.const 1024) (i32.const 38)))
+        (if (i64.lt_s (local.get $db) (i64.const 0)) (then (return (local.get $db))))
+        (i32.store (i32.const 1200) (i32.wrap_i64 (local.get $db)))
+        (local.set $obj (call $db_get (i32.const 1200) (i32.const 6)))
+        (if (i64.lt_s (local.get $obj) (i64.const 0)) (then (return (local.get $obj))))
+        (drop (call $get_object_bytes (i32.const 1309) (i32.wrap_i64 (local.get $obj))))
+        (i32.store (i32.const 1300) (i32.wrap_i64 (local.get $db)))
+        (call $db_set (i32.const 1300) (i32.const 17)))
+)"#,
+        imports = IMPORTS,
+        cid = wat_bytes(&serialize(cid)),
+        value = wat_bytes(&UPGRADED_VALUE.to_le_bytes()),
+    )
+    .into_bytes()
+}
+
+fn setup() -> Result<(BlockchainOverlayPtr, TimeKeeper)> {
+    let blockchain = Blockchain::new(&sled::Config::new().temporary(true).open()?)?;
+    let overlay = BlockchainOverlay::new(&blockchain)?;
+    let time_keeper = TimeKeeper::new(Timestamp::current_time(), 10, 90, 0);
+    Ok((overlay, time_keeper))
+}
+
+/// Deploy the storage contract under the given ID
+/// Read a value of the storage contract's `state` tree
+fn read_state(overlay: &BlockchainOverlayPtr, cid: &ContractId, key: &[u8]) -> Result<Vec<u8>> {
+    let lock = overlay.lock().unwrap();
+    let tree = lock.contracts.lookup(cid, "state")?;
+    let value = lock.overlay.lock().unwrap().get(&tree, key)?.unwrap();
+    Ok(value.to_vec())
+}
+
+fn deploy_storage(
+    overlay: &BlockchainOverlayPtr,
+    time_keeper: &TimeKeeper,
+    cid: ContractId,
+) -> Result<Runtime> {
+    let mut runtime =
+        Runtime::new(&storage_contract(&cid), overlay.clone(), cid, time_keeper.clone())?;
+    runtime.deploy(&[])?;
+    Ok(runtime)
+}
+
+#[test]
+fn view_call() -> Result<()> {
+    let (overlay, time_keeper) = setup()?;
+    let callee_id = ContractId::from(pallas::Base::from(1));
+    let caller_id = ContractId::from(pallas::Base::from(2));
+    deploy_storage(&overlay, &time_keeper, callee_id)?;
+
+    // Gas used by the view section on its own
+    let mut callee = Runtime::new(
+        &storage_contract(&callee_id),
+        overlay.clone(),
+        callee_id,
+        time_keeper.clone(),
+    )?;
+    let gas_limit = callee.gas_remaining();
+    assert_eq!(callee.view(&[0], 1, gas_limit)?, STORED_VALUE.to_le_bytes());
+    let view_gas = gas_limit - callee.gas_remaining();
+    assert!(view_gas > 0);
+
+    // The caller gets the callee's return data, and pays for its gas
+    let mut caller = Runtime::new(
+        &caller_contract(&callee_id),
+        overlay.clone(),
+        caller_id,
+        time_keeper.clone(),
+    )?;
+    assert_eq!(caller.exec(&[0])?, STORED_VALUE.to_le_bytes());
+    assert!(caller.gas_used() > view_gas);
+
+    // The callee can't write to its state from the view section, and the
+    // failure is handed back to the caller
+    let mut callee = Runtime::new(
+        &storage_contract(&callee_id),
+        overlay.clone(),
+        callee_id,
+        time_keeper.clone(),
+    )?;
+    assert!(matches!(
+        callee.view(&[1], 1, gas_limit),
+        Err(Error::ContractError(ContractError::CallerAccessDenied))
+    ));
+
+    let mut caller = Runtime::new(
+        &caller_contract(&callee_id),
+        overlay.clone(),
+        caller_id,
+        time_keeper.clone(),
+    )?;
+    assert!(matches!(
+        caller.exec(&[1]),
+        Err(Error::ContractError(ContractError::CallContractFailed))
+    ));
+
+    // Neither attempt changed the stored value
+    let mut caller = Runtime::new(&caller_contract(&callee_id), overlay, caller_id, time_keeper)?;
+    assert_eq!(caller.exec(&[0])?, STORED_VALUE.to_le_bytes());
+
+    Ok(())
+}
+
+#[test]
+fn view_call_gas_limit() -> Result<()> {
+    let (overlay, time_keeper) = setup()?;
+    let callee_id = ContractId::from(pallas::Base::from(1));
+    deploy_storage(&overlay, &time_keeper, callee_id)?;
+
+    // The callee can't use more gas than the caller has left
+    let mut callee = Runtime::new(&storage_contract(&callee_id), overlay, callee_id, time_keeper)?;
+    assert!(callee.view(&[0], 1, 10).is_err());
+    assert_eq!(callee.gas_remaining(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn view_call_trace() -> Result<()> {
+    let (overlay, time_keeper) = setup()?;
+    let callee_id = ContractId::from(pallas::Base::from(1));
+    let caller_id = ContractId::from(pallas::Base::from(2));
+    deploy_storage(&overlay, &time_keeper, callee_id)?;
+
+    // Nothing is recorded unless tracing is enabled
+    let mut caller = Runtime::new(
+        &caller_contract(&callee_id),
+        overlay.clone(),
+        caller_id,
+        time_keeper.clone(),
+    )?;
+    caller.exec(&[0])?;
+    assert!(caller.take_trace().is_none());
+
+    let mut caller = Runtime::new(&caller_contract(&callee_id), overlay, caller_id, time_keeper)?;
+    caller.enable_trace();
+    caller.exec(&[0])?;
+    let trace = caller.take_trace().unwrap();
+    assert_eq!(trace.contract_id, caller_id);
+
+    // The caller section is wrapped in start and end steps
+    let steps = &trace.steps;
+    assert!(
+        matches!(&steps[0], TraceStep::SectionStart { section, .. } if section == "__entrypoint")
+    );
+    assert!(matches!(
+        steps.last().unwrap(),
+        TraceStep::SectionEnd { section, result: Ok(0), .. } if section == "__entrypoint"
+    ));
+
+    // The view call is recorded as a host call, followed by the nested callee trace
+    let idx = steps
+        .iter()
+        .position(|step| matches!(step, TraceStep::HostCall { function: "call_contract_view", .. }))
+        .unwrap();
+    let TraceStep::HostCall { gas_before, gas_after, ret: Some(ret), .. } = &steps[idx] else {
+        panic!("Expected a host call step")
+    };
+    assert!(gas_before > gas_after);
+    assert!(*ret >= 0);
+
+    let view = steps
+        .iter()
+        .find_map(|step| match step {
+            TraceStep::View(view) => Some(view),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(view.contract_id, callee_id);
+    assert!(
+        matches!(&view.steps[0], TraceStep::SectionStart { section, .. } if section == "__view")
+    );
+
+    // The callee's read of its stored value is recorded
+    let read = view
+        .steps
+        .iter()
+        .find_map(|step| match step {
+            TraceStep::DbAccess { kind: DbAccessKind::Read, contract_id, value, .. } => {
+                Some((contract_id, value))
+            }
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(*read.0, callee_id);
+    assert_eq!(*read.1, Some(blake3::hash(&STORED_VALUE.to_le_bytes())));
+
+    // Tracing stays enabled, starting from an empty trace
+    assert!(caller.take_trace().unwrap().steps.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn migrate() -> Result<()> {
+    let (overlay, time_keeper) = setup()?;
+    let cid = ContractId::from(pallas::Base::from(1));
+    deploy_storage(&overlay, &time_keeper, cid)?;
+    assert!(overlay.lock().unwrap().migrations.get(&cid)?.is_empty());
+
+    // The upgrade migrates the state left by the previous version,
+    // before initializing on top of it
+    let upgraded = upgraded_storage_contract(&cid);
+    let mut runtime = Runtime::new(&upgraded, overlay.clone(), cid, time_keeper.clone())?;
+    runtime.deploy(&[])?;
+    assert_eq!(read_state(&overlay, &cid, b"old")?, STORED_VALUE.to_le_bytes());
+    assert_eq!(read_state(&overlay, &cid, b"k")?, UPGRADED_VALUE.to_le_bytes());
+    assert_eq!(overlay.lock().unwrap().wasm_bincode.get(cid)?, upgraded);
+
+    let migrations = overlay.lock().unwrap().migrations.get(&cid)?;
+    assert_eq!(migrations.len(), 1);
+    assert_eq!(migrations[0].previous_bincode, *blake3::hash(&storage_contract(&cid)).as_bytes());
+    assert_eq!(migrations[0].bincode, *blake3::hash(&upgraded).as_bytes());
+    assert_eq!(migrations[0].height, time_keeper.verifying_slot);
+
+    // Redeploying the same bincode doesn't migrate again
+    let mut runtime = Runtime::new(&upgraded, overlay.clone(), cid, time_keeper)?;
+    runtime.deploy(&[])?;
+    assert_eq!(overlay.lock().unwrap().migrations.get(&cid)?.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn storage_fee() -> Result<()> {
+    let cid = ContractId::from(pallas::Base::from(1));
+    let height = GAS_SCHEDULE_V2.activation_height;
+
+    // Deploy the storage contract right before and at the activation
+    // of storage fees
+    let (overlay, _) = setup()?;
+    let time_keeper = TimeKeeper::new(Timestamp::current_time(), 10, 90, height - 1);
+    let gas_before = deploy_storage(&overlay, &time_keeper, cid)?.gas_used();
+
+    let (overlay, _) = setup()?;
+    let time_keeper = TimeKeeper::new(Timestamp::current_time(), 10, 90, height);
+    let gas_after = deploy_storage(&overlay, &time_keeper, cid)?.gas_used();
+
+    // The key and value written on deploy are charged a fee for every byte
+    let written = (b"k".len() + STORED_VALUE.to_le_bytes().len()) as u64;
+    assert_eq!(overlay.lock().unwrap().state_sizes.get(&cid)?, written);
+    assert_eq!(gas_after - gas_before, written * GAS_SCHEDULE_V2.storage_per_byte);
+
+    Ok(())
+}
+
+#[test]
+fn db_set_gas() -> Result<()> {
+    let cid = ContractId::from(pallas::Base::from(1));
+    let (overlay, _) = setup()?;
+    let time_keeper =
+        TimeKeeper::new(Timestamp::current_time(), 10, 90, GAS_SCHEDULE_V2.activation_height);
+    let mut runtime = deploy_storage(&overlay, &time_keeper, cid)?;
+
+    // Writing a new key is charged for the bytes it adds to the state
+    let gas = runtime.gas_used();
+    runtime.apply(&[])?;
+    let gas_insert = runtime.gas_used() - gas;
+
+    // Overwriting it with a value of the same size adds no bytes
+    let gas = runtime.gas_used();
+    runtime.apply(&[])?;
+    let gas_overwrite = runtime.gas_used() - gas;
+
+    let written = (b"l".len() + STORED_VALUE.to_le_bytes().len()) as u64;
+    assert_eq!(overlay.lock().unwrap().state_sizes.get(&cid)?, 2 * written);
+    assert_eq!(gas_insert - gas_overwrite, written * GAS_SCHEDULE_V2.storage_per_byte);
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::fs;
+
+use halo2_proofs::dev::MockProver;
+
+use darkfi::{
+    zk::{cost::CircuitCost, import_witness_json, vm::ZkCircuit, witness_json_template},
+    zkas::{Analyzer, Compiler, Lexer, Parser, ZkBinary},
+    Result,
+};
+
+/// Compile a zkas source file into its decoded binary
+fn compile(path: &str) -> Result<ZkBinary> {
+    let source = fs::read_to_string(path)?.replace('\t', "    ").replace("\r\n", "\n");
+
+    let lexer = Lexer::new(path, source.chars());
+    let tokens = lexer.lex()?;
+
+    let parser = Parser::new(path, source.chars(), tokens);
+    let (namespace, k, constants, witnesses, statements) = parser.parse()?;
+
+    let mut analyzer = Analyzer::new(path, source.chars(), constants, witnesses, statements);
+    analyzer.analyze_types()?;
+
+    let compiler = Compiler::new(
+        path,
+        source.chars(),
+        namespace,
+        k,
+        analyzer.constants,
+        analyzer.witnesses,
+        analyzer.statements,
+        analyzer.literals,
+        false,
+    );
+
+    ZkBinary::decode(&compiler.compile()?)
+}
+
+/// The cost estimate is used to pick `k` for circuits that are not
+/// written by hand (see the `zkvm_native` fuzz target), so it has to
+/// be an upper bound of what the VM really lays out. We check that
+/// every circuit we ship synthesizes with the estimated `k`, and that
+/// the estimate does not exceed the `k` the circuit was written for.
+#[test]
+fn circuit_cost_min_k() -> Result<()> {
+    std::env::set_var("ZKAS_SILENT", "1");
+
+    let mut paths: Vec<String> = fs::read_dir("proof")?
+        .map(|entry| entry.unwrap().path().to_string_lossy().to_string())
+        .filter(|path| path.ends_with(".zk"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    for path in paths {
+        let zkbin = compile(&path)?;
+        let cost = CircuitCost::measure(&zkbin);
+
+        assert!(
+            cost.min_k <= zkbin.k,
+            "{}: estimated k={} exceeds declared k={} ({} rows)",
+            path,
+            cost.min_k,
+            zkbin.k,
+            cost.total_rows
+        );
+
+        // Synthesis fails with `NotEnoughRowsAvailable` if the regions
+        // don't fit, regardless of whether the witnesses satisfy the
+        // constraints, so the placeholder values are enough here.
+        let (witnesses, instances) = import_witness_json(&witness_json_template(&zkbin)?)?;
+        let circuit = ZkCircuit::new(witnesses, &zkbin);
+        if let Err(e) = MockProver::run(cost.min_k, &circuit, vec![instances]) {
+            panic!("{}: circuit does not fit estimated k={}: {}", path, cost.min_k, e)
+        }
+    }
+
+    Ok(())
+}
@@ -30,13 +30,15 @@ use halo2_proofs::{
     pasta::{group::Curve, pallas},
 };
 use rand::rngs::OsRng;
+use tinyjson::JsonValue;
 
 use darkfi::{
     zk::{
+        import_witness_json,
         proof::{ProvingKey, VerifyingKey},
         vm::ZkCircuit,
         vm_heap::{empty_witnesses, Witness},
-        Proof,
+        witness_json, Proof,
     },
     zkas::ZkBinary,
     Result,
@@ -132,3 +134,55 @@ fn zkvm_opcodes() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn zkvm_witness_json_roundtrip() -> Result<()> {
+    let mut tree = MerkleTree::new(100);
+    tree.append(MerkleNode::from(pallas::Base::random(&mut OsRng)));
+    let leaf_pos = tree.mark().unwrap();
+    let merkle_path = tree.witness(leaf_pos, 0).unwrap();
+    let pubkey = PublicKey::from_secret(SecretKey::random(&mut OsRng)).inner();
+
+    let witnesses = vec![
+        Witness::Base(Value::known(pallas::Base::random(&mut OsRng))),
+        Witness::Scalar(Value::known(pallas::Scalar::random(&mut OsRng))),
+        Witness::Uint32(Value::known(u32::MAX)),
+        Witness::Uint64(Value::known(u64::MAX)),
+        Witness::MerklePath(Value::known(merkle_path.try_into().unwrap())),
+        Witness::EcNiPoint(Value::known(pubkey)),
+    ];
+    let public_inputs = vec![pallas::Base::random(&mut OsRng), pallas::Base::ZERO];
+
+    // Witnesses and public inputs survive a roundtrip through
+    // witness.json, as used by zkrunner
+    let json = witness_json(&witnesses, &public_inputs)?;
+    let (imported_witnesses, imported_inputs) = import_witness_json(&json)?;
+    assert_eq!(imported_inputs, public_inputs);
+    assert_eq!(
+        witness_json(&imported_witnesses, &imported_inputs)?.parse::<JsonValue>()?,
+        json.parse::<JsonValue>()?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn zkvm_witness_json_bounds() -> Result<()> {
+    // Fixed points can't be exported, and must not bring down the caller
+    let fixed = Witness::EcFixedPoint(Value::known(
+        PublicKey::from_secret(SecretKey::random(&mut OsRng)).inner(),
+    ));
+    assert!(witness_json(&[fixed], &[]).is_err());
+
+    // JSON numbers are doubles, and 2^64 is the first one past u64::MAX
+    let json = r#"{"witnesses": [{"Uint64": 18446744073709551616}], "instances": []}"#;
+    assert!(import_witness_json(json).is_err());
+
+    let json = r#"{"witnesses": [{"Uint64": "18446744073709551615"}], "instances": []}"#;
+    assert_eq!(import_witness_json(json)?.0.len(), 1);
+
+    let json = r#"{"witnesses": [{"Uint32": 4294967296}], "instances": []}"#;
+    assert!(import_witness_json(json).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_sdk::crypto::{poseidon_decrypt, poseidon_encrypt, poseidon_hash};
+use halo2_proofs::{arithmetic::Field, circuit::Value, dev::MockProver, pasta::pallas};
+use rand::rngs::OsRng;
+
+use darkfi::{
+    zk::{
+        native::evaluate,
+        proof::{ProvingKey, VerifyingKey},
+        vm::ZkCircuit,
+        vm_heap::{empty_witnesses, Witness},
+        Proof,
+    },
+    zkas::ZkBinary,
+    Result,
+};
+
+#[test]
+fn zkvm_poseidon_encrypt() -> Result<()> {
+    let bincode = include_bytes!("../proof/poseidon_encrypt.zk.bin");
+    let zkbin = ZkBinary::decode(bincode)?;
+
+    let key = pallas::Base::random(&mut OsRng);
+    let nonce = pallas::Base::random(&mut OsRng);
+    let values = [pallas::Base::from(42), pallas::Base::from(69), pallas::Base::random(&mut OsRng)];
+
+    let prover_witnesses = vec![
+        Witness::Base(Value::known(key)),
+        Witness::Base(Value::known(nonce)),
+        Witness::Base(Value::known(values[0])),
+        Witness::Base(Value::known(values[1])),
+        Witness::Base(Value::known(values[2])),
+    ];
+
+    let ciphertext = poseidon_encrypt(key, nonce, &values);
+    assert_eq!(poseidon_decrypt(key, nonce, &ciphertext), values);
+
+    let mut public_inputs = vec![nonce, poseidon_hash([key])];
+    public_inputs.extend_from_slice(&ciphertext);
+
+    let native = evaluate(&zkbin, &prover_witnesses, &public_inputs)?;
+    assert!(native.is_satisfied());
+
+    let circuit = ZkCircuit::new(prover_witnesses.clone(), &zkbin);
+    let mockprover = MockProver::run(zkbin.k, &circuit, vec![public_inputs.clone()])?;
+    mockprover.assert_satisfied();
+
+    // A ciphertext of different values must not verify
+    let mut wrong_inputs = public_inputs.clone();
+    wrong_inputs[2] += pallas::Base::ONE;
+    let mockprover = MockProver::run(zkbin.k, &circuit, vec![wrong_inputs.clone()])?;
+    assert!(mockprover.verify().is_err());
+    assert!(!evaluate(&zkbin, &prover_witnesses, &wrong_inputs)?.is_satisfied());
+
+    let proving_key = ProvingKey::build(zkbin.k, &circuit);
+    let proof = Proof::create(&proving_key, &[circuit], &public_inputs, &mut OsRng)?;
+
+    let verifier_witnesses = empty_witnesses(&zkbin)?;
+    let circuit = ZkCircuit::new(verifier_witnesses, &zkbin);
+    let verifying_key = VerifyingKey::build(zkbin.k, &circuit);
+    proof.verify(&verifying_key, &public_inputs)?;
+
+    Ok(())
+}